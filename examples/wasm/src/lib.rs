@@ -0,0 +1,73 @@
+// The ray tracer in a browser. No bindings crate, just a few exported functions: index.html
+// calls start() once, then render_next() every animation frame and copies the RGBA image at
+// image() onto a canvas, so the picture fills in tile by tile from the middle. Clicking the
+// canvas asks pick() which object is under the mouse.
+use std::cell::RefCell;
+use raytracer_test::colorspace::Encoding;
+use raytracer_test::render::{render_tile, Renderer, Settings, TileOrder};
+use raytracer_test::scenes::Builtin;
+use raytracer_test::Scene;
+
+const TILE_SIZE: u32 = 32;
+
+struct State {
+    scene: Scene,
+    settings: Settings,
+    tiles: Vec<(u32, u32)>,
+    next: usize,
+    // RGBA, the layout canvas ImageData wants
+    image: Vec<u8>,
+}
+
+thread_local! {
+    static STATE: RefCell<Option<State>> = const { RefCell::new(None) };
+}
+
+#[no_mangle]
+pub extern "C" fn start(width: u32, height: u32, samples_per_pixel: u32) {
+    let settings = Settings { width, height, samples_per_pixel, ..Builtin::Default.settings() };
+    let scene = Builtin::Default.build(0.0, settings.aspect_ratio());
+    // the middle of the picture first, that's where the spheres are
+    let tiles = TileOrder::Spiral.tiles(&settings, TILE_SIZE);
+    let image = vec![0; (width * height * 4) as usize];
+    STATE.with(|s| *s.borrow_mut() = Some(State { scene, settings, tiles, next: 0, image }));
+}
+
+// renders one more tile, 0 once the image is done
+#[no_mangle]
+pub extern "C" fn render_next() -> u32 {
+    STATE.with(|s| {
+        let mut s = s.borrow_mut();
+        let Some(state) = s.as_mut() else { return 0 };
+        let Some(&(x, y)) = state.tiles.get(state.next) else { return 0 };
+        state.next += 1;
+
+        let tile = render_tile(&state.scene, &state.settings, x, y, TILE_SIZE);
+        let rgb = tile.data.to_rgb8(Encoding::default());
+        let (w, h) = (tile.data.width(), tile.data.height());
+        for j in 0..h {
+            for i in 0..w {
+                let src = ((j * w + i) * 3) as usize;
+                let dst = (((y + j) * state.settings.width + x + i) * 4) as usize;
+                state.image[dst..dst + 3].copy_from_slice(&rgb[src..src + 3]);
+                state.image[dst + 3] = 255;
+            }
+        }
+        1
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn image() -> *const u8 {
+    STATE.with(|s| s.borrow().as_ref().map_or(std::ptr::null(), |state| state.image.as_ptr()))
+}
+
+// index of the object under pixel (x, y), -1 for the background
+#[no_mangle]
+pub extern "C" fn pick(x: u32, y: u32) -> i32 {
+    STATE.with(|s| {
+        let s = s.borrow();
+        let Some(state) = s.as_ref() else { return -1 };
+        Renderer::new().pick(&state.scene, &state.settings, x, y).map_or(-1, |hit| hit.object as i32)
+    })
+}
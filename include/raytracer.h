@@ -0,0 +1,70 @@
+/*
+ * C interface to the ray tracer, implemented in src/ffi.rs. Keep the two in step: this file is
+ * written by hand.
+ *
+ * Build the library with
+ *
+ *     cargo rustc --lib --release --features ffi --crate-type cdylib
+ *
+ * (or --crate-type staticlib) and link against target/release/libraytracer_test.
+ *
+ * Materials get an index when they're created, which objects then refer to. Functions return
+ * 0, or that index, on success and -1 on failure; rt_last_error() says why. A scene can be
+ * changed and rendered again as often as needed, but only from one thread at a time.
+ *
+ *     RtScene *scene = rt_scene_new();
+ *     int ground = rt_material_lambertian(scene, 0.5, 0.5, 0.5);
+ *     rt_scene_add_sphere(scene, 0, -1000, 0, 1000, ground);
+ *     double from[3] = {13, 2, 3}, at[3] = {0, 0, 0}, up[3] = {0, 1, 0};
+ *     rt_scene_set_camera(scene, from, at, up, 20, 0.1, 10);
+ *     uint8_t *rgb = malloc(400 * 300 * 3);
+ *     if (rt_render(scene, 400, 300, 100, 50, 0, rgb) != 0)
+ *         fprintf(stderr, "%s\n", rt_last_error());
+ *     rt_scene_free(scene);
+ */
+#ifndef RAYTRACER_H
+#define RAYTRACER_H
+
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct RtScene RtScene;
+
+/* Why the last failing call on this thread failed, valid until the next failure. */
+const char *rt_last_error(void);
+
+RtScene *rt_scene_new(void);
+/* Null is ignored. */
+void rt_scene_free(RtScene *scene);
+
+int rt_material_lambertian(RtScene *scene, double r, double g, double b);
+int rt_material_metal(RtScene *scene, double r, double g, double b, double fuzz);
+int rt_material_dielectric(RtScene *scene, double ir);
+int rt_material_light(RtScene *scene, double r, double g, double b);
+
+/* A negative radius turns a dielectric sphere inside out, for an air bubble in glass. */
+int rt_scene_add_sphere(RtScene *scene, double x, double y, double z, double radius, int material);
+/* A hollow sphere, 0 < inner < outer, for glass bubbles and bowls. */
+int rt_scene_add_shell(RtScene *scene, double x, double y, double z, double outer, double inner, int material);
+/* A Wavefront .obj file, path is UTF-8. */
+int rt_scene_add_mesh(RtScene *scene, const char *path, int material);
+
+/* lookfrom, lookat and vup are three doubles each, vfov is in degrees. */
+int rt_scene_set_camera(RtScene *scene, const double *lookfrom, const double *lookat, const double *vup,
+                        double vfov, double aperture, double focus_dist);
+/* A flat color instead of the default sky gradient. */
+int rt_scene_set_background(RtScene *scene, double r, double g, double b);
+
+/* Writes width * height gamma corrected 8-bit RGB pixels to rgb, rows from the top, and
+ * returns once the image is done. */
+int rt_render(RtScene *scene, uint32_t width, uint32_t height, uint32_t samples_per_pixel,
+              uint32_t max_depth, uint64_t seed, uint8_t *rgb);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
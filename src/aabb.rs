@@ -0,0 +1,64 @@
+use crate::transform::{self, Matrix};
+use crate::{Float, Point3, Ray};
+
+// An axis-aligned box, what Hit::bounding_box puts around an object: everything the object
+// can be hit at is inside it, `min` being the corner with the smallest coordinates
+#[derive(Copy, Clone, Debug)]
+pub struct Aabb {
+    pub min: Point3,
+    pub max: Point3,
+}
+
+impl Aabb {
+    // between any two opposite corners
+    pub fn new(a: Point3, b: Point3) -> Aabb {
+        Aabb {
+            min: Point3::new(a.x().min(b.x()), a.y().min(b.y()), a.z().min(b.z())),
+            max: Point3::new(a.x().max(b.x()), a.y().max(b.y()), a.z().max(b.z())),
+        }
+    }
+
+    // the smallest box around the points, None when there are none
+    pub fn around(points: impl IntoIterator<Item = Point3>) -> Option<Aabb> {
+        points.into_iter().map(|p| Aabb::new(p, p)).reduce(Aabb::surrounding)
+    }
+
+    // the smallest box around both
+    pub fn surrounding(self, other: Aabb) -> Aabb {
+        Aabb::new(
+            Point3::new(self.min.x().min(other.min.x()), self.min.y().min(other.min.y()), self.min.z().min(other.min.z())),
+            Point3::new(self.max.x().max(other.max.x()), self.max.y().max(other.max.y()), self.max.z().max(other.max.z())),
+        )
+    }
+
+    pub fn corners(&self) -> [Point3; 8] {
+        std::array::from_fn(|i| Point3::new(
+            if i & 1 == 0 { self.min.x() } else { self.max.x() },
+            if i & 2 == 0 { self.min.y() } else { self.max.y() },
+            if i & 4 == 0 { self.min.z() } else { self.max.z() },
+        ))
+    }
+
+    // the box around this one once `m` has moved, turned or scaled it
+    pub fn transformed(&self, m: &Matrix) -> Aabb {
+        Aabb::around(self.corners().map(|p| transform::point(m, p))).expect("A box has corners")
+    }
+
+    // Whether `r` goes through the box between t_min and t_max, by the slabs between each
+    // pair of opposite faces
+    pub fn hit(&self, r: &Ray, t_min: Float, t_max: Float) -> bool {
+        let (o, d) = (r.origin(), r.direction());
+        let (mut t0, mut t1) = (t_min, t_max);
+        for axis in 0..3 {
+            let inv = 1.0 / d[axis];
+            let (a, b) = ((self.min[axis] - o[axis]) * inv, (self.max[axis] - o[axis]) * inv);
+            let (a, b) = if inv < 0.0 { (b, a) } else { (a, b) };
+            t0 = t0.max(a);
+            t1 = t1.min(b);
+            if t1 < t0 {
+                return false;
+            }
+        }
+        true
+    }
+}
@@ -0,0 +1,87 @@
+use std::path::{Path, PathBuf};
+use crate::error::{RendererError, Result};
+use crate::film::Film;
+use crate::framebuffer::Framebuffer;
+use crate::Float;
+
+// what has to be the same for two renders to be averaged, from the image metadata
+const MUST_MATCH: [&str; 7] = ["SceneHash", "MaxDepth", "PathLimits", "Time", "Camera", "Integrator", "WorkingSpace"];
+
+// Averages renders of the same scene made with different seeds into one with less noise,
+// each weighted by its sample count. The sample counts, seeds and everything that has to
+// match come from the metadata the renderer writes into PNG and EXR files. The result's
+// metadata adds the samples up and lists the seeds, so it can be added to again later.
+#[derive(Default)]
+pub struct Accumulator {
+    // the first render, for its metadata and AOVs, and all of them added up
+    image: Option<(Framebuffer, Film)>,
+    // the file the settings were first seen in, for the errors
+    first: PathBuf,
+    samples: u64,
+    seeds: Vec<String>,
+}
+
+impl Accumulator {
+    pub fn new() -> Accumulator {
+        Accumulator::default()
+    }
+
+    pub fn add(&mut self, path: &Path, image: Framebuffer) -> Result<()> {
+        let samples: u64 = image.metadata_value("Samples")
+            .and_then(|s| s.parse().ok())
+            .filter(|&s| s > 0)
+            .ok_or_else(|| RendererError::Unsupported(format!("{}: no sample count in the metadata, only PNG and EXR \
+                                                               renders of this renderer can be merged", path.display())))?;
+        let seeds: Vec<String> = image.metadata_value("Seed").unwrap_or_default()
+            .split(',')
+            .map(|s| s.trim().to_string())
+            .filter(|s| !s.is_empty())
+            .collect();
+
+        let Some((total, film)) = &mut self.image else {
+            let mut film = Film::new(image.width(), image.height());
+            film.splat_image(&image, samples as Float);
+            self.first = path.to_path_buf();
+            self.samples = samples;
+            self.seeds = seeds;
+            self.image = Some((image, film));
+            return Ok(());
+        };
+        if (total.width(), total.height()) != (image.width(), image.height()) {
+            return Err(RendererError::SizeMismatch((total.width(), total.height()), (image.width(), image.height())));
+        }
+        for key in MUST_MATCH {
+            if total.metadata_value(key) != image.metadata_value(key) {
+                return Err(RendererError::Unsupported(format!("{}: {} is {}, in {} it's {}", path.display(), key,
+                                                              image.metadata_value(key).unwrap_or("not set"),
+                                                              self.first.display(),
+                                                              total.metadata_value(key).unwrap_or("not set"))));
+            }
+        }
+        // the same seed gives the same noise, averaging it in again makes nothing better
+        if let Some(seed) = seeds.iter().find(|s| self.seeds.contains(s)) {
+            log::warn!("{}: seed {} is in the merge already, its noise counts twice", path.display(), seed);
+        }
+
+        // every render counts as often as it has samples
+        film.splat_image(&image, samples as Float);
+        self.samples += samples;
+        self.seeds.extend(seeds);
+        Ok(())
+    }
+
+    pub fn samples(&self) -> u64 {
+        self.samples
+    }
+
+    // The merged image, None when nothing was added. Normals and depth come from the first
+    // render, they're traced through the pixel centers and come out the same every time.
+    pub fn finish(self) -> Option<Framebuffer> {
+        let (mut image, film) = self.image?;
+        film.resolve_into(&mut image);
+        image.metadata.retain(|(key, _)| key != "Samples" && key != "Seed" && key != "RenderDuration");
+        image.metadata.push(("Samples".to_string(), self.samples.to_string()));
+        image.metadata.push(("Seed".to_string(), self.seeds.join(",")));
+        Some(image)
+    }
+}
@@ -0,0 +1,215 @@
+use std::sync::Arc;
+use serde::{Deserialize, Serialize};
+use crate::aabb::Aabb;
+use crate::error::Result;
+use crate::hit::{rounding_error, HitRecord};
+use crate::scene_file::Exporter;
+use crate::transform::{self, Matrix, Transform};
+use crate::{Float, Hit, Ray, Vec3};
+
+// Where an object is at a point in time. Rotations are in degrees around the x, then y, then
+// z axis, and like the uniform scale they pivot around the world origin, so model objects
+// around the origin and move them into place with `translate`.
+#[derive(Copy, Clone, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Keyframe {
+    pub time: Float,
+    #[serde(default)]
+    pub translate: [Float; 3],
+    #[serde(default)]
+    pub rotate: [Float; 3],
+    #[serde(default = "one")]
+    pub scale: Float,
+}
+
+fn one() -> Float {
+    1.0
+}
+
+// Keyframes sorted by time, linearly interpolated in between and held before the first and
+// after the last one
+#[derive(Clone, Debug)]
+pub struct Track {
+    keys: Vec<Keyframe>,
+}
+
+impl Track {
+    pub fn new(mut keys: Vec<Keyframe>) -> Track {
+        keys.sort_by(|a, b| a.time.total_cmp(&b.time));
+        Track {
+            keys
+        }
+    }
+
+    pub fn keys(&self) -> &[Keyframe] {
+        &self.keys
+    }
+
+    pub fn at(&self, time: Float) -> Keyframe {
+        let next = self.keys.partition_point(|k| k.time <= time);
+        if next == 0 {
+            return self.keys[0];
+        }
+        if next == self.keys.len() {
+            return self.keys[next - 1];
+        }
+        let (a, b) = (&self.keys[next - 1], &self.keys[next]);
+        let f = (time - a.time) / (b.time - a.time);
+        let lerp = |x: Float, y: Float| x + f * (y - x);
+        let lerp3 = |x: [Float; 3], y: [Float; 3]| [lerp(x[0], y[0]), lerp(x[1], y[1]), lerp(x[2], y[2])];
+        Keyframe {
+            time,
+            translate: lerp3(a.translate, b.translate),
+            rotate: lerp3(a.rotate, b.rotate),
+            scale: lerp(a.scale, b.scale),
+        }
+    }
+
+    // Around `local` wherever the track takes it between time0 and time1. However it's turned
+    // about the origin, each point stays as far from it, scaled, so the box goes round the
+    // ball that reaches that far; the move and the scale are straight lines between the
+    // keyframes, so the balls at those and at the ends cover the rest.
+    pub fn bounds(&self, local: Aabb, time0: Float, time1: Float) -> Aabb {
+        let reach = reach(&local);
+        self.keys.iter()
+            .map(|k| k.time)
+            .filter(|&t| t > time0 && t < time1)
+            .chain([time0, time1])
+            .map(|t| {
+                let k = self.at(t);
+                let r = k.scale.abs() * reach;
+                let center = Vec3::new(k.translate[0], k.translate[1], k.translate[2]);
+                Aabb::new(center - Vec3::new(r, r, r), center + Vec3::new(r, r, r))
+            })
+            .reduce(Aabb::surrounding)
+            .expect("There are always the ends")
+    }
+
+    pub fn matrix(&self, time: Float) -> Matrix {
+        let k = self.at(time);
+        let rotation = transform::mul(
+            &transform::rotate(k.rotate[2], Vec3::new(0.0, 0.0, 1.0)),
+            &transform::mul(
+                &transform::rotate(k.rotate[1], Vec3::new(0.0, 1.0, 0.0)),
+                &transform::rotate(k.rotate[0], Vec3::new(1.0, 0.0, 0.0)),
+            ),
+        );
+        let t = k.translate;
+        transform::mul(
+            &transform::translate(Vec3::new(t[0], t[1], t[2])),
+            &transform::mul(&rotation, &transform::scale(Vec3::new(k.scale, k.scale, k.scale))),
+        )
+    }
+}
+
+// An object moved by a keyframe track, evaluated at the time each ray was sent out. Rays are
+// brought into the object's own space, so anything that can be hit can be animated.
+#[derive(Debug)]
+pub struct Animated {
+    object: Arc<dyn Hit>,
+    track: Track,
+}
+
+impl Animated {
+    pub fn new(object: Arc<dyn Hit>, track: Track) -> Animated {
+        Animated {
+            object,
+            track
+        }
+    }
+}
+
+impl Hit for Animated {
+    fn hit(&self, r: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord> {
+        // a zero scale makes the object vanish
+        let to_world = Transform::new(self.track.matrix(r.time()))?;
+        let to_local = to_world.inverse();
+        let local = r.transformed(&to_local);
+
+        // the direction isn't normalized, so t is the same in both spaces
+        let mut rec = self.object.hit(&local, t_min, t_max)?;
+        rec.p = to_world.point(rec.p);
+        // the object's own error, scaled along, plus what the transform adds
+        rec.error = self.track.at(r.time()).scale.abs() * rec.error + rounding_error(rec.p.max_abs());
+        rec.normal = to_world.normal(rec.normal).normalized();
+        Some(rec)
+    }
+
+    fn bounding_box(&self, time0: Float, time1: Float) -> Option<Aabb> {
+        Some(self.track.bounds(self.object.bounding_box(time0, time1)?, time0, time1))
+    }
+
+    fn export(&self, out: &mut Exporter) -> Result<()> {
+        let first = out.object_count();
+        self.object.export(out)?;
+        out.animate_since(first, self.track.keys());
+        Ok(())
+    }
+}
+
+// A transform that changes while the shutter is open, for motion blur of things that turn as
+// well as move: a propeller or a car's wheels. It's `start` when the shutter opens at `open`
+// and `end` when it closes at `close`. In between the matrices aren't blended as they are, a
+// turning object would shrink halfway: the move, the turn and the scale are each interpolated
+// on their own, the turn the short way round, so it has to be less than half a turn.
+#[derive(Clone, Debug)]
+pub struct Motion {
+    start: Matrix,
+    end: Matrix,
+    open: Float,
+    close: Float,
+    parts: [(Vec3, [Float; 4], Matrix); 2],
+}
+
+impl Motion {
+    // None if either matrix is singular
+    pub fn new(start: Matrix, end: Matrix, open: Float, close: Float) -> Option<Motion> {
+        Some(Motion {
+            start,
+            end,
+            open,
+            close,
+            parts: [transform::decompose(&start)?, transform::decompose(&end)?],
+        })
+    }
+
+    pub fn start(&self) -> &Matrix {
+        &self.start
+    }
+
+    pub fn end(&self) -> &Matrix {
+        &self.end
+    }
+
+    // where it is at `time`, None where the scale passes through 0
+    pub fn at(&self, time: Float) -> Option<Transform> {
+        let f = if self.close > self.open { ((time - self.open) / (self.close - self.open)).clamp(0.0, 1.0) } else { 0.0 };
+        if f == 0.0 {
+            return Transform::new(self.start);
+        }
+        let [(t0, q0, s0), (t1, q1, s1)] = &self.parts;
+        let mut rest = *s0;
+        for (row, end) in rest.iter_mut().zip(s1) {
+            for (v, e) in row.iter_mut().zip(end) {
+                *v += f * (e - *v);
+            }
+        }
+        Transform::new(transform::compose(*t0 + f * (*t1 - *t0), transform::slerp(*q0, *q1, f), &rest))
+    }
+
+    // Around `local` all the way from start to end. As in Track::bounds the turn is covered
+    // by a ball, whose radius the scale in between can't take past that at either end.
+    pub fn bounds(&self, local: Aabb) -> Aabb {
+        let [(t0, _, s0), (t1, _, s1)] = &self.parts;
+        let r = local.corners().into_iter()
+            .flat_map(|c| [transform::vector(s0, c).length(), transform::vector(s1, c).length()])
+            .fold(0.0, Float::max);
+        let ball = |center: Vec3| Aabb::new(center - Vec3::new(r, r, r), center + Vec3::new(r, r, r));
+        ball(*t0).surrounding(ball(*t1))
+    }
+}
+
+// how far the box's furthest point is from the origin
+fn reach(b: &Aabb) -> Float {
+    b.corners().into_iter().map(Vec3::length).fold(0.0, Float::max)
+}
@@ -0,0 +1,289 @@
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use crate::error::{RendererError, Result};
+use crate::framebuffer::Framebuffer;
+use crate::material::Lambertian;
+use crate::mesh::Mesh;
+use crate::occlusion::Occlusion;
+use crate::{metadata, overlay, random};
+use crate::render::{ray_color, Renderer, Settings};
+use crate::scene::Scene;
+use crate::scene_file::{mesh_transform, ObjectDesc, SceneFile};
+use crate::triangle::Triangle;
+use crate::visibility::TraceGroup;
+use crate::{Color, Float, Vec3};
+
+// Baking into texture space: instead of what the camera sees, each texel of a mesh's texture
+// gets what falls on the point of the mesh its UVs put there, for lightmaps in game engines.
+// The mesh stays in the scene and shadows itself like any other object. Texels no triangle
+// covers are black, apart from a few around the edges of the UV islands that get the color
+// next to them (`padding`), so filtering in the engine doesn't bleed black into the seams.
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "native", derive(clap::ValueEnum))]
+pub enum BakeMode {
+    // the light arriving at the surface, as a white diffuse surface would reflect it; the
+    // engine multiplies in the albedo
+    Lighting,
+    // ambient occlusion, see occlusion::Occlusion
+    Occlusion,
+    // the world space normal, 0.5 * (n + 1)
+    Normal,
+    // nothing rendered but the UV layout itself, for finding unwrap problems: the triangles'
+    // edges in white over their faces, blue where the UVs keep the mesh's orientation, red
+    // where they mirror it, yellow where they overlap (see layout)
+    Layout,
+}
+
+impl BakeMode {
+    pub fn name(self) -> &'static str {
+        match self {
+            BakeMode::Lighting => "lighting",
+            BakeMode::Occlusion => "occlusion",
+            BakeMode::Normal => "normal",
+            BakeMode::Layout => "layout",
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Bake {
+    pub mode: BakeMode,
+    // of the texture
+    pub width: u32,
+    pub height: u32,
+    // per texel, spread over the hemisphere above it
+    pub samples: u32,
+    pub max_depth: u64,
+    pub seed: u64,
+    // how many texels the UV islands are grown by
+    pub padding: u32,
+    // what counts as blocking for BakeMode::Occlusion
+    pub distance: Float,
+}
+
+// The triangles of the scene file's mesh called `name`, placed like in the scene
+pub fn mesh_triangles(file: &SceneFile, name: &str) -> Result<Vec<Triangle>> {
+    let desc = file.objects.iter().find(|o| o.name() == Some(name))
+        .ok_or_else(|| RendererError::Scene(format!("the scene has no object named '{}'", name)))?;
+    let ObjectDesc::Mesh { file: path, transform, up, unit_scale, .. } = desc else {
+        return Err(RendererError::Unsupported(format!("'{}' isn't a mesh, only meshes have UVs to bake into", name)));
+    };
+    // the material doesn't matter for baking, only where the triangles are
+    let mut mesh = Mesh::load_obj(&file.base_dir.join(path), Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5))))?;
+    if let Some(t) = mesh_transform(transform.as_ref(), *up, *unit_scale) {
+        mesh = mesh.transformed(&t);
+    }
+    Ok(mesh.into_triangles())
+}
+
+// Bakes `triangles`, which should be in `scene`, into a texture. The renderer's threads and
+// cancel are used, nothing else of it.
+pub fn bake(renderer: &Renderer, scene: &Scene, triangles: &[Triangle], bake: &Bake) -> Framebuffer {
+    let (width, height) = (bake.width, bake.height);
+    if bake.mode == BakeMode::Layout {
+        let mut data = layout(triangles, width, height);
+        data.metadata.push(("Bake".to_string(), bake.mode.name().to_string()));
+        return data;
+    }
+    let texels = cover(triangles, width, height);
+    let data = Mutex::new(Framebuffer::new(width, height));
+    let bake_row = |y: u32| {
+        for x in 0..width {
+            let Some((i, b1, b2)) = texels[(y * width + x) as usize] else { continue };
+            random::reseed(random::pixel_seed(bake.seed, x, y));
+            let mut rec = triangles[i].surface(b1, b2);
+            rec.error = rec.error.max(scene.epsilon());
+            let color = match bake.mode {
+                BakeMode::Lighting => {
+                    let mut sum = Color::default();
+                    for _ in 0..bake.samples {
+                        let mut direction = rec.normal + Vec3::rand_in_unit_sphere().normalized();
+                        if direction.near_zero() {
+                            direction = rec.normal;
+                        }
+                        let r = rec.spawn_ray(direction.normalized()).with_time(scene.time).with_group(TraceGroup::DIFFUSE);
+                        sum += ray_color(&r, scene, bake.max_depth);
+                    }
+                    sum / bake.samples as Float
+                }
+                BakeMode::Occlusion => {
+                    let open = Occlusion::new(bake.distance, bake.samples).at(&rec, &scene.world, scene.time);
+                    Color::new(open, open, open)
+                }
+                BakeMode::Normal => 0.5 * (rec.normal + Color::new(1.0, 1.0, 1.0)),
+                BakeMode::Layout => unreachable!("layouts are drawn, not baked"),
+            };
+            data.lock().unwrap().set(x, y, color, rec.normal, 0.0);
+        }
+        log::debug!("row {} of {} baked", y + 1, height);
+    };
+
+    if renderer.threads() == 1 {
+        (0..height).take_while(|_| !renderer.is_cancelled()).for_each(bake_row);
+    } else {
+        let next_row = AtomicU32::new(0);
+        thread::scope(|s| {
+            for _ in 0..renderer.threads() {
+                s.spawn(|| loop {
+                    let y = next_row.fetch_add(1, Ordering::Relaxed);
+                    if y >= height || renderer.is_cancelled() {
+                        break;
+                    }
+                    bake_row(y);
+                });
+            }
+        });
+    }
+
+    let mut data = data.into_inner().unwrap();
+    let mut filled: Vec<bool> = texels.iter().map(Option::is_some).collect();
+    for _ in 0..bake.padding {
+        grow(&mut data, &mut filled);
+    }
+    let settings = Settings { width, height, samples_per_pixel: bake.samples, max_depth: bake.max_depth, seed: bake.seed, ..Settings::default() };
+    data.metadata = metadata::render_metadata(scene, &settings, None);
+    // the camera has nothing to do with it
+    data.metadata.retain(|(key, _)| key != "Camera");
+    data.metadata.push(("Bake".to_string(), bake.mode.name().to_string()));
+    data
+}
+
+// For each texel, the triangle whose UVs cover its center and the barycentric coordinates
+// there. Where triangles overlap in UV space the first one wins, baking needs UVs that don't.
+fn cover(triangles: &[Triangle], width: u32, height: u32) -> Vec<Option<(usize, Float, Float)>> {
+    let mut texels = vec![None; (width * height) as usize];
+    let mut overlapping = 0;
+    for (i, tri) in triangles.iter().enumerate() {
+        let [(u0, v0), (u1, v1), (u2, v2)] = tri.uv();
+        let (e1, e2) = ((u1 - u0, v1 - v0), (u2 - u0, v2 - v0));
+        let det = e1.0 * e2.1 - e1.1 * e2.0;
+        if det.abs() < 1.0e-12 {
+            continue;
+        }
+        // texel rows go down from the top, v goes up from the bottom
+        let (w, h) = (width as Float, height as Float);
+        let columns = |a: Float, b: Float| ((a * w - 0.5).floor().max(0.0) as u32, ((b * w - 0.5).ceil().max(0.0) as u32).min(width - 1));
+        let rows = |a: Float, b: Float| (((1.0 - b) * h - 0.5).floor().max(0.0) as u32, (((1.0 - a) * h - 0.5).ceil().max(0.0) as u32).min(height - 1));
+        let (x0, x1) = columns(u0.min(u1).min(u2), u0.max(u1).max(u2));
+        let (y0, y1) = rows(v0.min(v1).min(v2), v0.max(v1).max(v2));
+        for y in y0..=y1 {
+            for x in x0..=x1 {
+                let d = ((x as Float + 0.5) / w - u0, 1.0 - (y as Float + 0.5) / h - v0);
+                let b1 = (d.0 * e2.1 - d.1 * e2.0) / det;
+                let b2 = (e1.0 * d.1 - e1.1 * d.0) / det;
+                if b1 < 0.0 || b2 < 0.0 || b1 + b2 > 1.0 {
+                    continue;
+                }
+                match &mut texels[(y * width + x) as usize] {
+                    // texels right on the edge between two triangles don't count
+                    Some(_) if b1.min(b2).min(1.0 - b1 - b2) > 1.0e-6 => overlapping += 1,
+                    Some(_) => {}
+                    texel => *texel = Some((i, b1, b2)),
+                }
+            }
+        }
+    }
+    if overlapping > 0 {
+        log::warn!("{} texels are covered by more than one triangle, the mesh's UVs overlap", overlapping);
+    }
+    texels
+}
+
+// The UV layout of `triangles` on a texture, see BakeMode::Layout. Faces are filled at the
+// texel centers they cover like for baking; what's wrong with the UVs is logged as well.
+pub fn layout(triangles: &[Triangle], width: u32, height: u32) -> Framebuffer {
+    let mut data = Framebuffer::new(width, height);
+    let (w, h) = (width as Float, height as Float);
+    // how many faces cover each texel, and which way the last of them was turned
+    let mut covered = vec![(0u32, false); (width * height) as usize];
+    let (mut mirrored, mut outside, mut degenerate) = (0, 0, 0);
+    for tri in triangles {
+        let [(u0, v0), (u1, v1), (u2, v2)] = tri.uv();
+        let (e1, e2) = ((u1 - u0, v1 - v0), (u2 - u0, v2 - v0));
+        let det = e1.0 * e2.1 - e1.1 * e2.0;
+        if tri.uv().iter().any(|&(u, v)| !(0.0..=1.0).contains(&u) || !(0.0..=1.0).contains(&v)) {
+            outside += 1;
+        }
+        if det.abs() < 1.0e-12 {
+            degenerate += 1;
+            continue;
+        }
+        // UVs going round the other way from the vertices
+        let flipped = det < 0.0;
+        if flipped {
+            mirrored += 1;
+        }
+        let lo = (u0.min(u1).min(u2), v0.min(v1).min(v2));
+        let hi = (u0.max(u1).max(u2), v0.max(v1).max(v2));
+        let (x0, x1) = ((lo.0 * w - 0.5).floor().max(0.0) as u32, ((hi.0 * w - 0.5).ceil().max(0.0) as u32).min(width - 1));
+        let (y0, y1) = (((1.0 - hi.1) * h - 0.5).floor().max(0.0) as u32, (((1.0 - lo.1) * h - 0.5).ceil().max(0.0) as u32).min(height - 1));
+        for y in y0..=y1 {
+            for x in x0..=x1 {
+                let d = ((x as Float + 0.5) / w - u0, 1.0 - (y as Float + 0.5) / h - v0);
+                let b1 = (d.0 * e2.1 - d.1 * e2.0) / det;
+                let b2 = (e1.0 * d.1 - e1.1 * d.0) / det;
+                // texels right on a shared edge would count as overlaps
+                if b1.min(b2).min(1.0 - b1 - b2) > 1.0e-6 {
+                    let texel = &mut covered[(y * width + x) as usize];
+                    *texel = (texel.0 + 1, flipped);
+                }
+            }
+        }
+    }
+    let overlapping = covered.iter().filter(|(n, _)| *n > 1).count();
+    for (c, &(n, flipped)) in data.beauty.iter_mut().zip(&covered) {
+        *c = match n {
+            0 => Color::new(0.02, 0.02, 0.02),
+            1 if flipped => Color::new(0.5, 0.05, 0.05),
+            1 => Color::new(0.05, 0.1, 0.5),
+            _ => Color::new(0.8, 0.7, 0.05),
+        };
+    }
+    let texel = |(u, v): (Float, Float)| (u * w, (1.0 - v) * h);
+    for tri in triangles {
+        let uv = tri.uv();
+        for k in 0..3 {
+            overlay::line(&mut data, texel(uv[k]), texel(uv[(k + 1) % 3]), Color::new(1.0, 1.0, 1.0));
+        }
+    }
+    if mirrored > 0 {
+        log::warn!("{} of {} triangles have mirrored UVs", mirrored, triangles.len());
+    }
+    if outside > 0 {
+        log::warn!("{} triangles have UVs outside 0 to 1, they're cut off", outside);
+    }
+    if degenerate > 0 {
+        log::warn!("{} triangles have no area in UV space", degenerate);
+    }
+    if overlapping > 0 {
+        log::warn!("{} texels are covered by more than one triangle, the mesh's UVs overlap", overlapping);
+    }
+    data
+}
+
+// Fills the empty texels next to filled ones with the average of those, one texel further out
+fn grow(data: &mut Framebuffer, filled: &mut [bool]) {
+    let (width, height) = (data.width() as i64, data.height() as i64);
+    let mut added = Vec::new();
+    for y in 0..height {
+        for x in 0..width {
+            if filled[(y * width + x) as usize] {
+                continue;
+            }
+            let neighbours: Vec<usize> = (-1..=1).flat_map(|dy| (-1..=1).map(move |dx| (x + dx, y + dy)))
+                .filter(|&(nx, ny)| (0..width).contains(&nx) && (0..height).contains(&ny))
+                .map(|(nx, ny)| (ny * width + nx) as usize)
+                .filter(|&n| filled[n])
+                .collect();
+            if !neighbours.is_empty() {
+                let sum = neighbours.iter().fold(Color::default(), |sum, &n| sum + data.beauty[n]);
+                added.push(((y * width + x) as usize, sum / neighbours.len() as Float));
+            }
+        }
+    }
+    for (i, color) in added {
+        data.beauty[i] = color;
+        filled[i] = true;
+    }
+}
@@ -0,0 +1,82 @@
+use crate::colorspace::WorkingSpace;
+use crate::{float::consts, Color, Float};
+
+// The light of a black body: what glowing things give off, from the deep red of embers through
+// the orange of candles and filament bulbs to the blue white of hot stars. Lights take their
+// color from it and fire in volumes (see volume::Volume) their color and brightness.
+
+// Stefan-Boltzmann over pi, in the units Cycles uses for blackbody intensity 1, so fire looks
+// as bright as it does there
+const STEFAN_BOLTZMANN: Float = 5.670373e-8 * 1.0e-6 / consts::PI;
+
+// The color of a black body at `kelvin` in linear sRGB, as bright as white light of luminance
+// 1: Planck's law over the visible wavelengths through the CIE color matching functions, in
+// the analytic fit of Wyman, Sloan and Shirley. Colors sRGB can't show lose their negative
+// parts.
+pub fn color(kelvin: Float) -> Color {
+    // in nanometers, the second radiation constant to go with them
+    let c2 = 1.4387769e7;
+    let lobe = |x: Float, mu: Float, s1: Float, s2: Float| {
+        let s = if x < mu { s1 } else { s2 };
+        (-0.5 * (x - mu) * (x - mu) / (s * s)).exp()
+    };
+    let (mut x, mut y, mut z) = (0.0, 0.0, 0.0);
+    for i in 0..=80 {
+        let l = 380.0 + 5.0 * i as Float;
+        let radiance = 1.0 / (l.powi(5) * ((c2 / (l * kelvin)).exp() - 1.0));
+        x += radiance * (1.056 * lobe(l, 599.8, 37.9, 31.0) + 0.362 * lobe(l, 442.0, 16.0, 26.7) - 0.065 * lobe(l, 501.1, 20.4, 26.2));
+        y += radiance * (0.821 * lobe(l, 568.8, 46.9, 40.5) + 0.286 * lobe(l, 530.9, 16.3, 31.1));
+        z += radiance * (1.217 * lobe(l, 437.0, 11.8, 36.0) + 0.681 * lobe(l, 459.0, 26.0, 13.8));
+    }
+    let (x, z) = (x / y, z / y);
+    Color::new(
+        (3.2406 * x - 1.5372 - 0.4986 * z).max(0.0),
+        (-0.9689 * x + 1.8758 + 0.0415 * z).max(0.0),
+        (0.0557 * x - 0.2040 + 1.0570 * z).max(0.0),
+    )
+}
+
+// How bright a black body at `kelvin` is, Stefan-Boltzmann's T⁴
+pub fn brightness(kelvin: Float) -> Float {
+    STEFAN_BOLTZMANN * kelvin * kelvin * kelvin * kelvin
+}
+
+// The colors of a black body worked out ahead, for looking them up at every shading point.
+// Between the temperatures of the table colors are interpolated, outside of it they're the
+// nearest end's.
+#[derive(Clone, Debug)]
+pub struct Table {
+    colors: Vec<Color>,
+}
+
+const TABLE_MIN: Float = 800.0;
+const TABLE_MAX: Float = 12000.0;
+const TABLE_STEP: Float = 100.0;
+
+impl Table {
+    pub fn new() -> Table {
+        let steps = ((TABLE_MAX - TABLE_MIN) / TABLE_STEP) as usize;
+        Table { colors: (0..=steps).map(|i| color(TABLE_MIN + i as Float * TABLE_STEP)).collect() }
+    }
+
+    // the colors in `space` rather than sRGB
+    pub fn in_space(mut self, space: WorkingSpace) -> Table {
+        for c in &mut self.colors {
+            *c = space.from_srgb(*c);
+        }
+        self
+    }
+
+    pub fn color(&self, kelvin: Float) -> Color {
+        let at = ((kelvin - TABLE_MIN) / TABLE_STEP).clamp(0.0, (self.colors.len() - 1) as Float);
+        let i = (at as usize).min(self.colors.len() - 2);
+        let f = at - i as Float;
+        (1.0 - f) * self.colors[i] + f * self.colors[i + 1]
+    }
+}
+
+impl Default for Table {
+    fn default() -> Table {
+        Table::new()
+    }
+}
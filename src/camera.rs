@@ -1,55 +1,357 @@
-use crate::{Point3, Ray, Vec3};
+use std::sync::Arc;
+use serde::{Deserialize, Serialize};
+use crate::error::Result;
+use crate::lens::{Lens, Prescription};
+use crate::onb::Onb;
+use crate::ray::Differentials;
+use crate::{float::consts, random, Float, Point3, Ray, Vec3};
 
+// How the directions around the camera are laid out on the image. The panoramic ones (all but
+// perspective) see every way from lookfrom, lookat in the middle of the image, and vfov can go
+// past 180 degrees; they have no lens, so aperture, focus and real lenses don't matter to them.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Projection {
+    // through a pinhole or thin lens onto a flat image, vfov up to 180 degrees
+    #[default]
+    Perspective,
+    // the sphere of directions seen from the pole behind the camera, which keeps shapes but not
+    // sizes: the further from lookat the bigger. Looking straight down with a vfov of 270
+    // degrees or so makes a "little planet", the ground a ball in the middle with the sky
+    // around it.
+    Stereographic,
+}
 
-#[derive(Copy, Clone)]
+#[derive(Clone)]
 pub struct Camera {
     origin: Point3,
+    lookat: Point3,
+    vup: Vec3,
+    vert_fov: Float,
+    aperture: Float,
+    focus_dist: Float,
     lower_left_corner: Point3,
     horizontal: Vec3,
     vertical: Vec3,
-    cu: Vec3,
-    cv: Vec3,
-    lens_radius: f64
+    // the lens is in the frame's uv plane
+    frame: Onb,
+    lens_radius: Float,
+    // the shutter opens at `time` and stays open for `shutter` seconds, on the bottom row of
+    // the image `readout` seconds later than on the top one
+    time: Float,
+    shutter: Float,
+    readout: Float,
+    // traced through instead of the thin lens when there is one, aperture doesn't matter then
+    lens: Option<Arc<Lens>>,
+    projection: Projection,
+    // how far past each edge of the frame the image goes, in get_ray's u and v, and in pixels
+    // for the metadata
+    overscan: (Float, Float),
+    overscan_pixels: (u32, u32),
 }
 
+// random points tried on the rear of a real lens before get_ray gives up on getting through
+const LENS_TRIES: u32 = 16;
+
 impl Camera {
     pub fn new(lookfrom: Point3,
                lookat: Point3,
                vup: Vec3,
-               vert_fov: f64,
-               aspect_ratio: f64,
-               aperture: f64,
-               focus_dist: f64) -> Camera {
+               vert_fov: Float,
+               aspect_ratio: Float,
+               aperture: Float,
+               focus_dist: Float) -> Camera {
         // Converting FOV into radians
-        let theta = std::f64::consts::PI / 180.0 * vert_fov;
+        let theta = consts::PI / 180.0 * vert_fov;
         let vph = 2.0 * (theta/2.0).tan();
         let vpw = aspect_ratio * vph;
 
-        let cw = (lookfrom - lookat).normalized();
-        let cu = vup.cross(cw).normalized();
-        let cv = cw.cross(cu);
+        let frame = Onb::new(lookfrom - lookat, vup);
 
-        let h = focus_dist * vpw * cu;
-        let v = focus_dist * vph * cv;
-        let llc = lookfrom - h/2.0 - v/2.0 - focus_dist * cw;
+        let h = focus_dist * vpw * frame.u();
+        let v = focus_dist * vph * frame.v();
+        let llc = lookfrom - h/2.0 - v/2.0 - focus_dist * frame.w();
 
         Camera {
             origin: lookfrom,
+            lookat,
+            vup,
+            vert_fov,
+            aperture,
+            focus_dist,
             horizontal: h,
             vertical: v,
-            cu,
-            cv,
+            frame,
             lower_left_corner: llc,
             lens_radius: aperture/2.0,
+            time: 0.0,
+            shutter: 0.0,
+            readout: 0.0,
+            lens: None,
+            projection: Projection::Perspective,
+            overscan: (0.0, 0.0),
+            overscan_pixels: (0, 0),
+        }
+    }
+
+    // the same lens and shutter looking from somewhere else
+    pub fn moved(&self, lookfrom: Point3, lookat: Point3) -> Camera {
+        let aspect_ratio = self.horizontal.length() / self.vertical.length();
+        Camera {
+            lens: self.lens.clone(),
+            overscan: self.overscan,
+            overscan_pixels: self.overscan_pixels,
+            ..Camera::new(lookfrom, lookat, self.vup, self.vert_fov, aspect_ratio, self.aperture, self.focus_dist)
+                .at_time(self.time)
+                .with_shutter(self.shutter)
+                .with_rolling_shutter(self.readout)
+                .with_projection(self.projection)
+        }
+    }
+
+    // Rays traced through the real lens of `prescription` instead of a thin lens, focused at
+    // focus_dist with the camera's field of view; `scale` is how many millimeters a scene unit is
+    pub fn with_lens(mut self, prescription: Prescription, scale: Float) -> Result<Camera> {
+        let aspect_ratio = self.horizontal.length() / self.vertical.length();
+        self.lens = Some(Arc::new(Lens::new(prescription, scale, self.focus_dist, self.vert_fov, aspect_ratio)?));
+        Ok(self)
+    }
+
+    // everything in focus: the thin lens closed and a real lens taken out, for previews
+    pub fn pinhole(mut self) -> Camera {
+        self.aperture = 0.0;
+        self.lens_radius = 0.0;
+        self.lens = None;
+        self
+    }
+
+    // The image of a `frame` sized render made bigger by `margin` pixels on the left and right
+    // and on the top and bottom, for a render that size: the frame lands on the same pixels in
+    // the middle of it, and the margins go on past its edges, through the lens or panorama the
+    // same way. Lets distortion, bloom or a shaking camera done afterwards pull
+    // the picture in from outside without black showing at the edges.
+    pub fn with_overscan(mut self, margin: (u32, u32), frame: (u32, u32)) -> Camera {
+        // the render's u is (x + rand) / (width - 1), see render::sample_ray
+        let extent = |m: u32, size: u32| m as Float / (size.max(2) - 1) as Float;
+        self.overscan = (extent(margin.0, frame.0), extent(margin.1, frame.1));
+        self.overscan_pixels = margin;
+        self
+    }
+
+    pub fn lens(&self) -> Option<&Lens> {
+        self.lens.as_deref()
+    }
+
+    pub fn with_projection(mut self, projection: Projection) -> Camera {
+        self.projection = projection;
+        self
+    }
+
+    pub fn projection(&self) -> Projection {
+        self.projection
+    }
+
+    // when the shutter opens, SceneBuilder sets it to the scene time
+    pub fn at_time(mut self, time: Float) -> Camera {
+        self.time = time;
+        self
+    }
+
+    // how long the shutter stays open, anything moving meanwhile gets motion blur
+    pub fn with_shutter(mut self, seconds: Float) -> Camera {
+        self.shutter = seconds;
+        self
+    }
+
+    // A rolling shutter, like the sensors of most CMOS cameras: the rows of the image are
+    // read out one after another from the top, the bottom one `readout` seconds after it, so
+    // each row sees the scene a little later than the one above. Fast motion comes out
+    // slanted and propellers bent, whatever the shutter.
+    pub fn with_rolling_shutter(mut self, readout: Float) -> Camera {
+        self.readout = readout;
+        self
+    }
+
+    // the parameters the camera was set up with, for the render metadata
+    pub fn describe(&self) -> String {
+        let mut description = format!("lookfrom={} lookat={} vfov={} aperture={} focus_dist={}",
+                                      self.origin, self.lookat, self.vert_fov, self.aperture, self.focus_dist);
+        if let Some(lens) = &self.lens {
+            description += &format!(" lens={}", lens.prescription().path().display());
+        }
+        if self.overscan_pixels != (0, 0) {
+            description += &format!(" overscan={}x{}", self.overscan_pixels.0, self.overscan_pixels.1);
+        }
+        description
+    }
+
+    pub fn lookfrom(&self) -> Point3 {
+        self.origin
+    }
+
+    pub fn lookat(&self) -> Point3 {
+        self.lookat
+    }
+
+    pub fn vup(&self) -> Vec3 {
+        self.vup
+    }
+
+    pub fn vert_fov(&self) -> Float {
+        self.vert_fov
+    }
+
+    pub fn aperture(&self) -> Float {
+        self.aperture
+    }
+
+    pub fn focus_dist(&self) -> Float {
+        self.focus_dist
+    }
+
+    pub fn get_ray(&self, u: Float, v: Float) -> Ray {
+        let (u, v) = self.in_frame(u, v);
+        let (disk, time) = self.sample(v);
+        if self.projection != Projection::Perspective {
+            return Ray::new(self.origin, self.panorama(u, v)).with_time(time);
         }
+        let Some(lens) = &self.lens else {
+            return self.ray_through(u, v, self.frame.local(self.lens_radius * disk), time);
+        };
+        // any ray that makes it through the lens, the one through the middle of the
+        // thin lens if none does
+        (0..LENS_TRIES)
+            .find_map(|i| {
+                let rear = if i == 0 { disk } else { Vec3::rand_in_unit_disk() };
+                self.lens_ray(lens, u, v, rear, time)
+            })
+            .map_or_else(|| self.ray_through(u, v, Vec3::default(), time), |(r, _)| r)
     }
 
-    pub fn get_ray(&self, u: f64, v: f64) -> Ray {
-        let rd = self.lens_radius * Vec3::rand_in_unit_disk();
-        let offset = self.cu * rd.x() + self.cv * rd.y();
+    // get_ray with differentials: the rays through (u + du, v) and (u, v + dv) from the same
+    // point on the lens at the same time, du and dv being one pixel across. Also the share of
+    // the light along the ray that gets to the film, 1 but through a real lens, where it's 0
+    // when the lens blocks the ray.
+    pub fn get_ray_differential(&self, u: Float, v: Float, du: Float, dv: Float) -> (Ray, Float) {
+        let (u, v) = self.in_frame(u, v);
+        let (du, dv) = ((1.0 + 2.0 * self.overscan.0) * du, (1.0 + 2.0 * self.overscan.1) * dv);
+        let (disk, time) = self.sample(v);
+        if self.projection != Projection::Perspective {
+            let (rx, ry) = (self.panorama(u + du, v), self.panorama(u, v + dv));
+            let r = Ray::new(self.origin, self.panorama(u, v)).with_time(time).with_differentials(Some(Differentials {
+                rx_origin: self.origin,
+                rx_direction: rx,
+                ry_origin: self.origin,
+                ry_direction: ry,
+            }));
+            return (r, 1.0);
+        }
+        let Some(lens) = &self.lens else {
+            let offset = self.frame.local(self.lens_radius * disk);
+            let rx = self.ray_through(u + du, v, offset, time);
+            let ry = self.ray_through(u, v + dv, offset, time);
+            let r = self.ray_through(u, v, offset, time).with_differentials(Some(Differentials {
+                rx_origin: rx.origin(),
+                rx_direction: rx.direction(),
+                ry_origin: ry.origin(),
+                ry_direction: ry.direction(),
+            }));
+            return (r, 1.0);
+        };
+        let Some((r, weight)) = self.lens_ray(lens, u, v, disk, time) else {
+            return (self.ray_through(u, v, Vec3::default(), time), 0.0);
+        };
+        // no differentials when the neighbours are blocked, near the edge of the stop
+        let differentials = self.lens_ray(lens, u + du, v, disk, time)
+            .zip(self.lens_ray(lens, u, v + dv, disk, time))
+            .map(|((rx, _), (ry, _))| Differentials {
+                rx_origin: rx.origin(),
+                rx_direction: rx.direction(),
+                ry_origin: ry.origin(),
+                ry_direction: ry.direction(),
+            });
+        (r.with_differentials(differentials), weight)
+    }
 
+    // Where a ray from the lens goes through the frame, in get_ray's u and v: (0, 0) bottom left
+    // and (1, 1) top right, past them in the overscan
+    pub fn screen(&self, r: &Ray) -> (Float, Float) {
+        if self.projection != Projection::Perspective {
+            return self.panorama_screen(r.direction());
+        }
+        let w = self.frame.w();
+        let t = (self.lower_left_corner - r.origin()).dot(w) / r.direction().dot(w);
+        let p = r.origin() + t * r.direction() - self.lower_left_corner;
+        (p.dot(self.horizontal) / self.horizontal.dot(self.horizontal), p.dot(self.vertical) / self.vertical.dot(self.vertical))
+    }
+
+    // where the image's (u, v) is on the frame, past 0 and 1 in the overscan
+    fn in_frame(&self, u: Float, v: Float) -> (Float, Float) {
+        let (ou, ov) = self.overscan;
+        ((1.0 + 2.0 * ou) * u - ou, (1.0 + 2.0 * ov) * v - ov)
+    }
+
+    // The direction through (u, v) of a panoramic projection, as a unit vector. Points on the
+    // image are (x, y) from its middle, tan(vfov / 4) at the top edge, so that the stereographic
+    // projection has the top and bottom edges vfov apart.
+    fn panorama(&self, u: Float, v: Float) -> Vec3 {
+        let (half_width, half_height) = self.panorama_size();
+        let (x, y) = ((2.0 * u - 1.0) * half_width, (2.0 * v - 1.0) * half_height);
+        match self.projection {
+            Projection::Perspective => unreachable!("perspective isn't panoramic"),
+            Projection::Stereographic => {
+                let r2 = x * x + y * y;
+                (2.0 * x * self.frame.u() + 2.0 * y * self.frame.v() - (1.0 - r2) * self.frame.w()) / (1.0 + r2)
+            }
+        }
+    }
+
+    // the other way round from panorama, straight behind the camera being infinitely far out
+    fn panorama_screen(&self, d: Vec3) -> (Float, Float) {
+        let d = d.normalized();
+        let (x, y) = match self.projection {
+            Projection::Perspective => unreachable!("perspective isn't panoramic"),
+            Projection::Stereographic => {
+                let forward = 1.0 - d.dot(self.frame.w());
+                (d.dot(self.frame.u()) / forward, d.dot(self.frame.v()) / forward)
+            }
+        };
+        let (half_width, half_height) = self.panorama_size();
+        (0.5 + 0.5 * x / half_width, 0.5 + 0.5 * y / half_height)
+    }
+
+    // how far the edges of the image are from its middle on the plane of panorama
+    fn panorama_size(&self) -> (Float, Float) {
+        let half_height = (self.vert_fov.to_radians() / 4.0).tan();
+        (half_height * self.horizontal.length() / self.vertical.length(), half_height)
+    }
+
+    // where on the lens, as a point in the unit disk, and when in the shutter interval of the
+    // row at `v` a ray starts
+    fn sample(&self, v: Float) -> (Vec3, Float) {
+        let disk = Vec3::rand_in_unit_disk();
+
+        let open = self.time + (1.0 - v).clamp(0.0, 1.0) * self.readout;
+        // no random number spent on an instant shutter, the same seed gives the same image
+        let time = if self.shutter > 0.0 {
+            open + random::gen::<Float>() * self.shutter
+        } else {
+            open
+        };
+        (disk, time)
+    }
+
+    fn ray_through(&self, u: Float, v: Float, offset: Vec3, time: Float) -> Ray {
         Ray::new(self.origin + offset,
                  self.lower_left_corner + u*self.horizontal + v*self.vertical - self.origin - offset
-        )
+        ).with_time(time)
+    }
+
+    // the lens's space is in millimeters with z toward the scene, the camera's frame has w
+    // pointing back
+    fn lens_ray(&self, lens: &Lens, u: Float, v: Float, rear: Vec3, time: Float) -> Option<(Ray, Float)> {
+        let (o, d, weight) = lens.ray(u, v, rear)?;
+        let to_world = |a: Vec3| self.frame.local(Vec3::new(a.x(), a.y(), -a.z()));
+        Some((Ray::new(self.origin + to_world(o) / lens.scale(), to_world(d)).with_time(time), weight))
     }
 }
\ No newline at end of file
@@ -0,0 +1,509 @@
+use std::path::PathBuf;
+use std::str::FromStr;
+use clap::{Parser, Subcommand};
+use raytracer_test::bake::BakeMode;
+use raytracer_test::colorspace::Display;
+use raytracer_test::integrator::Integrator;
+use raytracer_test::lpe::LightPath;
+use raytracer_test::output::{Collision, Format};
+use raytracer_test::overlay::Overlay;
+use raytracer_test::overrides::{Override, Sweep};
+use raytracer_test::render::{Preview, TileOrder};
+use raytracer_test::scenes::Builtin;
+use raytracer_test::transform::Up;
+use raytracer_test::{Float, Point3};
+use crate::sequence::FrameRange;
+
+#[derive(Parser)]
+#[command(about = "Testing and trying out raytracing!", args_conflicts_with_subcommands = true)]
+pub struct Args {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    #[arg(short, long, help = "Where to write the render, .mp4/.webm encode a frame sequence with ffmpeg [default: ./renders/render-<timestamp>.<ext>]")]
+    pub output: Option<PathBuf>,
+
+    #[arg(long, value_enum, help = "Output format, inferred from the file extension when not given")]
+    pub format: Option<Format>,
+
+    #[arg(long, value_enum, default_value_t = Collision::Overwrite, help = "What to do if the output file exists")]
+    pub on_exists: Collision,
+
+    #[arg(long = "16bit", help = "Write 16 bits per channel (PNG and TIFF)")]
+    pub sixteen_bit: bool,
+
+    #[arg(long, help = "Write half floats instead of 32-bit floats (EXR)")]
+    pub half: bool,
+
+    #[arg(long, help = "Write the beauty and each AOV and pass as a part of its own in a multi-part EXR, \
+                        instead of as channels of one part")]
+    pub exr_parts: bool,
+
+    #[arg(long, value_name = "CONFIG", help = "OpenColorIO config to show the renders through in the 8 and 16-bit formats, \
+                                               its scene_linear role naming the working space")]
+    pub ocio: Option<PathBuf>,
+
+    #[arg(long, requires = "ocio", help = "OCIO display/view or colorspace to show the renders in, \
+                                          the first view of the first display by default")]
+    pub view: Option<String>,
+
+    #[arg(long, value_enum, default_value_t = Display::Srgb, conflicts_with = "ocio",
+          help = "Display PNGs are made for: Display P3 or Rec. 2020 for wide gamut screens, hdr10 (PQ, 16-bit) \
+                  for HDR ones with 1 as 203 nits; anything but srgb is for PNG only")]
+    pub display: Display,
+
+    #[arg(long, default_value_t = 90, value_parser = clap::value_parser!(u8).range(0..=100),
+          help = "Quality of lossy formats (JPEG and WebP)")]
+    pub quality: u8,
+
+    #[arg(long, help = "Render an image sequence, e.g. 1..240 (use %04d in the output name for the frame number)")]
+    pub frames: Option<FrameRange>,
+
+    #[arg(long, default_value_t = 24.0, value_parser = fps, help = "Frames per second, converts frame numbers to animation time")]
+    pub fps: f64,
+
+    #[arg(long, default_value_t = 0.0, value_parser = shutter,
+          help = "Part of a frame the shutter stays open for motion blur, e.g. 0.5 for a 180° shutter")]
+    pub shutter: f64,
+
+    #[arg(long, default_value_t = 0.0, value_parser = shutter, value_name = "PART",
+          help = "Part of a frame a rolling shutter takes to read the image out from top to bottom, each row seeing the \
+                  scene later than the one above, for matching CMOS footage and its wobble on fast motion")]
+    pub rolling_shutter: f64,
+
+    #[arg(long, help = "Video bitrate passed on to ffmpeg, e.g. 8M")]
+    pub bitrate: Option<String>,
+
+    #[arg(long, help = "Write EXR tiles to disk as they finish instead of keeping the whole image in memory")]
+    pub stream: bool,
+
+    #[arg(long, default_value_t = 64, value_parser = clap::value_parser!(u32).range(1..),
+          help = "Tile size in pixels when streaming")]
+    pub tile_size: u32,
+
+    #[arg(long, value_delimiter = ',', allow_hyphen_values = true,
+          help = "Also write these exposures (in EV stops), e.g. -2,0,2")]
+    pub brackets: Vec<Float>,
+
+    #[arg(long, help = "Also write a contact sheet of the beauty, normal and depth passes")]
+    pub contact_sheet: bool,
+
+    #[arg(long, value_name = "SIZE", value_parser = clap::value_parser!(u32).range(16..),
+          help = "Also write a tone-mapped JPEG thumbnail no more than SIZE pixels across next to every image, \
+                  <name>_thumb.jpg, as soon as the image is written")]
+    pub thumbnail: Option<u32>,
+
+    #[arg(long, help = "Write a JSON report of timings, statistics and settings to this file")]
+    pub report: Option<PathBuf>,
+
+    #[arg(long, help = "Seed for the random numbers, the same seed gives the same image [default: 0]")]
+    pub seed: Option<u64>,
+
+    #[arg(long, help = "Scene file (.toml, .json, .pbrt or Mitsuba .xml), or builtin:<name> for one of the built-in scenes \
+                        (default, final, cornell, materials) [default: builtin:default]")]
+    pub scene: Option<SceneArg>,
+
+    #[arg(long, help = "Save the scene to a .toml or .json scene file instead of rendering it")]
+    pub save_scene: Option<PathBuf>,
+
+    #[arg(long, value_enum, value_name = "AXIS",
+          help = "Up axis the scene file was made with, z for Blender, 3ds Max and CAD exports, turned to y up as here")]
+    pub up: Option<Up>,
+
+    #[arg(long, value_parser = unit_scale, value_name = "SCALE",
+          help = "How long the scene file's unit is here, e.g. 0.001 for an export in millimetres into metres")]
+    pub unit_scale: Option<Float>,
+
+    #[arg(long, help = "Put the scene in a photo studio: a shadow-catching ground, a grey backdrop and key, fill and rim \
+                        lights around it (materials studio_key, studio_fill, studio_rim and studio_ground)")]
+    pub studio: bool,
+
+    #[arg(long, value_parser = pixel, value_name = "X,Y",
+          help = "Trace the samples of one pixel, counted from the top left, and print every bounce of their paths \
+                  instead of rendering")]
+    pub trace_pixel: Option<(u32, u32)>,
+
+    #[arg(long, requires = "trace_pixel", help = "Only print this sample of --trace-pixel, counting from 0")]
+    pub trace_sample: Option<u32>,
+
+    #[arg(long, global = true, value_parser = noise, value_name = "NOISE",
+          help = "Keep rendering in rounds of the scene's samples per pixel until the noise left is below this, \
+                  0.01 being about 1%")]
+    pub noise_threshold: Option<Float>,
+
+    #[arg(long, global = true, default_value_t = 4096, requires = "noise_threshold",
+          value_parser = clap::value_parser!(u32).range(1..),
+          help = "Samples per pixel to stop at with --noise-threshold even if the noise is still above it")]
+    pub max_samples: u32,
+
+    #[arg(long, global = true,
+          help = "Look out for samples that come out NaN, infinite or negative, leave them out and log the paths of the \
+                  first few")]
+    pub check_radiance: bool,
+
+    #[arg(long, value_enum, num_args = 0..=1, default_missing_value = "half", conflicts_with_all = ["save_scene", "watch"],
+          help = "Render a quick preview instead: half or a quarter of the size, far fewer samples and bounces and no \
+                  depth of field, the scene's own settings cut down [default: half]")]
+    pub preview: Option<Preview>,
+
+    #[arg(long, value_parser = overscan, value_name = "PERCENT", conflicts_with_all = ["save_scene", "watch"],
+          help = "Render this percentage of the width and height more past each edge of the frame, for distortion, \
+                  bloom or camera shake added afterwards to draw on")]
+    pub overscan: Option<Float>,
+
+    #[arg(long, help = "Render again whenever the scene file changes, a quick preview first and then the full render")]
+    pub watch: bool,
+
+    #[arg(long, conflicts_with_all = ["watch", "frames", "stream", "save_scene", "trace_pixel", "overscan"],
+          help = "Render a few samples at a time, writing the image after each round, and take changes on stdin while \
+                  it goes: exposure EV, fov DEGREES, sun DEGREES, an override like camera:lookfrom=[0,1,5], or quit")]
+    pub live: bool,
+
+    #[arg(long, value_enum, value_delimiter = ',', requires = "watch",
+          help = "Draw these over the quick previews of --watch: the boxes of the lights, the bounds of the objects, \
+                  where the camera looks with the horizon, and what's in focus with the focal plane")]
+    pub overlay: Vec<Overlay>,
+
+    #[arg(long, default_value_t = 1.0, value_parser = coc, value_name = "PIXELS",
+          help = "How wide, in pixels, the blur of a point can get for --overlay focus to count it as sharp")]
+    pub coc: Float,
+
+    #[arg(long, global = true, value_parser = clap::value_parser!(u32).range(1..),
+          help = "Number of render threads [default: one per core]")]
+    pub threads: Option<u32>,
+
+    #[arg(long, global = true, value_enum, default_value_t = Integrator::Path,
+          help = "What to render: path traced light, or a quick single sample look at the surfaces' normals, \
+                  positions, texture coordinates or facing for finding problems with the geometry")]
+    pub integrator: Integrator,
+
+    #[arg(long, global = true, value_parser = distance, help = "Distance that comes out black with --integrator depth [default: 0]")]
+    pub near: Option<Float>,
+
+    #[arg(long, global = true, value_parser = distance,
+          help = "Distance that comes out white with --integrator depth [default: twice the focus distance]")]
+    pub far: Option<Float>,
+
+    #[arg(long, global = true, conflicts_with_all = ["near", "far"],
+          help = "Write the distances themselves with --integrator depth, for EXR or PFM output")]
+    pub raw_depth: bool,
+
+    #[arg(long, global = true, value_parser = distance, value_name = "DISTANCE",
+          help = "Also render an ambient occlusion pass, counting what's within this distance of a surface as blocking. \
+                  EXR gets it as extra channels, other formats as a second image ending in _occlusion")]
+    pub occlusion: Option<Float>,
+
+    #[arg(long, global = true, default_value_t = 16, value_parser = clap::value_parser!(u32).range(1..),
+          help = "Rays per pixel for the occlusion pass")]
+    pub occlusion_samples: u32,
+
+    #[arg(long = "lpe", global = true, value_name = "NAME=EXPRESSION",
+          help = "Also render the light of the paths matching a light path expression into a pass of its own, e.g. \
+                  caustics=CD[ST]+L or reflections=CS.*, events being C(amera), D(iffuse), S(pecular), T(ransmission), V(olume) for fog, \
+                  L(ight) and B(ackground). EXR gets the pass as extra channels, other formats as an image ending in _NAME. \
+                  Can be given more than once")]
+    pub light_paths: Vec<LightPath>,
+
+    #[arg(long, global = true,
+          help = "Also render the light of each named light material into a pass of its own, light.NAME, and the \
+                  background's into one called background, for balancing the lights afterwards")]
+    pub light_passes: bool,
+
+    #[arg(long, global = true, value_enum, default_value_t = TileOrder::Scanline,
+          help = "Order tiles are rendered in when they're delivered one by one (--stream and serve), spiral starts from the \
+                  middle of the image, hilbert keeps neighbouring tiles together, random fills it in evenly")]
+    pub tile_order: TileOrder,
+
+    #[arg(long = "override", global = true, value_name = "TARGET=VALUE",
+          help = "Change the scene after loading it, e.g. material:glass.ir=1.33, object:ball.visible=false or camera:vfov=30, \
+                  can be given more than once")]
+    pub overrides: Vec<Override>,
+
+    #[arg(short, long, global = true, action = clap::ArgAction::Count, help = "Log more, -v for progress and scene details, -vv for everything")]
+    pub verbose: u8,
+
+    #[arg(short, long, global = true, conflicts_with = "verbose", help = "Only log errors")]
+    pub quiet: bool,
+}
+
+impl Args {
+    // what --up and --unit-scale turn a scene file by, see SceneFile::convert
+    pub fn conversion(&self) -> Option<(Up, Float)> {
+        (self.up.is_some() || self.unit_scale.is_some()).then(|| (self.up.unwrap_or_default(), self.unit_scale.unwrap_or(1.0)))
+    }
+}
+
+fn shutter(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(f) if (0.0..=1.0).contains(&f) => Ok(f),
+        _ => Err(format!("'{}' is not between 0 and 1", s)),
+    }
+}
+
+fn fps(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(f) if f > 0.0 && f.is_finite() => Ok(f),
+        _ => Err(format!("'{}' is not a frame rate above 0", s)),
+    }
+}
+
+fn pixel(s: &str) -> Result<(u32, u32), String> {
+    s.split_once(',')
+        .and_then(|(x, y)| Some((x.trim().parse().ok()?, y.trim().parse().ok()?)))
+        .ok_or_else(|| format!("'{}' is not a pixel, give it as X,Y", s))
+}
+
+fn noise(s: &str) -> Result<Float, String> {
+    match s.parse::<Float>() {
+        Ok(n) if n > 0.0 && n.is_finite() => Ok(n),
+        _ => Err(format!("'{}' is not a noise level above 0", s)),
+    }
+}
+
+fn unit_scale(s: &str) -> Result<Float, String> {
+    match s.parse::<Float>() {
+        Ok(n) if n > 0.0 && n.is_finite() => Ok(n),
+        _ => Err(format!("'{}' is not a scale above 0", s)),
+    }
+}
+
+fn coc(s: &str) -> Result<Float, String> {
+    match s.parse::<Float>() {
+        Ok(n) if n > 0.0 && n.is_finite() => Ok(n),
+        _ => Err(format!("'{}' is not a width in pixels above 0", s)),
+    }
+}
+
+fn overscan(s: &str) -> Result<Float, String> {
+    match s.parse::<Float>() {
+        Ok(p) if (0.0..=100.0).contains(&p) => Ok(p),
+        _ => Err(format!("'{}' is not a percentage from 0 to 100", s)),
+    }
+}
+
+fn distance(s: &str) -> Result<Float, String> {
+    match s.parse::<Float>() {
+        Ok(d) if d >= 0.0 && d.is_finite() => Ok(d),
+        _ => Err(format!("'{}' is not a distance", s)),
+    }
+}
+
+#[derive(Clone)]
+pub enum SceneArg {
+    File(PathBuf),
+    Builtin(Builtin),
+}
+
+impl FromStr for SceneArg {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<SceneArg, String> {
+        match s.strip_prefix("builtin:") {
+            Some(name) => Builtin::from_name(name).map(SceneArg::Builtin).ok_or_else(|| {
+                let names: Vec<&str> = Builtin::ALL.iter().map(|b| b.name()).collect();
+                format!("unknown built-in scene '{}', pick one of {}", name, names.join(", "))
+            }),
+            None => Ok(SceneArg::File(PathBuf::from(s))),
+        }
+    }
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    #[command(about = "Compare two renders (RMSE, PSNR and FLIP)")]
+    Diff(DiffArgs),
+    #[command(about = "Orbit the camera once around the scene, writing an image sequence or video")]
+    Turntable(TurntableArgs),
+    #[command(about = "Render scenes submitted over HTTP: POST /renders, then poll, stream tiles and fetch the image")]
+    Serve(ServeArgs),
+    #[command(name = "matpreview", about = "Render a material on a shader ball with fixed lighting, to compare material tweaks")]
+    MatPreview(MatPreviewArgs),
+    #[command(about = "Print what a scene is made of: object counts, meshes, material use and bounds, without rendering")]
+    Inspect(InspectArgs),
+    #[command(about = "Average renders of the same scene made with different seeds into one with less noise, \
+                       weighted by their sample counts")]
+    Accumulate(AccumulateArgs),
+    #[command(about = "Render the scene once for every value of a parameter, or every combination of several, \
+                       e.g. for comparing roughness or sample counts side by side")]
+    Sweep(SweepArgs),
+    #[command(about = "Render the lighting, occlusion or normals of a mesh into a texture laid out by its UVs, \
+                       e.g. lightmaps for game engines, or draw the UV layout itself")]
+    Bake(BakeArgs),
+    #[command(about = "Check that materials neither lose nor make up light: render each in a white furnace, \
+                       on a sphere and as the whole Cornell box, where the image should come out at 1. \
+                       Give materials of albedo 1 that don't glow, or the loss is theirs")]
+    Furnace(FurnaceArgs),
+}
+
+#[derive(clap::Args)]
+pub struct DiffArgs {
+    #[arg(help = "Reference image")]
+    pub reference: PathBuf,
+
+    #[arg(help = "Image to compare against the reference")]
+    pub test: PathBuf,
+
+    #[arg(long, help = "Write a FLIP error heatmap to this file")]
+    pub heatmap: Option<PathBuf>,
+}
+
+#[derive(clap::Args)]
+pub struct TurntableArgs {
+    #[arg(long, help = "Scene file or builtin:<name>, as for a normal render [default: builtin:default]")]
+    pub scene: Option<SceneArg>,
+
+    #[arg(short, long, help = "Where to write the frames (use %04d for the frame number), .mp4/.webm encode a video \
+                              [default: ./renders/render-<timestamp>-%04d.png]")]
+    pub output: Option<PathBuf>,
+
+    #[arg(long, default_value_t = 72, value_parser = clap::value_parser!(u32).range(1..),
+          help = "Number of frames in one full turn")]
+    pub frames: u32,
+
+    #[arg(long, value_parser = point, help = "Point to orbit around, e.g. 0,1,0 [default: the point the camera looks at]")]
+    pub target: Option<Point3>,
+
+    #[arg(long, default_value_t = 24.0, value_parser = fps, help = "Frames per second of the video")]
+    pub fps: f64,
+
+    #[arg(long, help = "Seed for the random numbers [default: 0]")]
+    pub seed: Option<u64>,
+}
+
+fn point(s: &str) -> Result<Point3, String> {
+    let v: Vec<Float> = s.split(',')
+        .map(|n| n.trim().parse().map_err(|_| format!("invalid number '{}'", n)))
+        .collect::<Result<_, _>>()?;
+    match v[..] {
+        [x, y, z] => Ok(Point3::new(x, y, z)),
+        _ => Err(format!("'{}' is not a point, write it as x,y,z", s)),
+    }
+}
+
+#[derive(clap::Args)]
+pub struct ServeArgs {
+    #[arg(long, default_value = "127.0.0.1:8080", help = "Address and port to listen on")]
+    pub bind: String,
+
+    #[arg(long, default_value_t = 32, value_parser = clap::value_parser!(u32).range(1..),
+          help = "Tile size in pixels, tiles are what clients receive as the render goes")]
+    pub tile_size: u32,
+}
+
+#[derive(clap::Args)]
+pub struct MatPreviewArgs {
+    #[arg(help = "File with the material (.toml or .json), laid out like the [textures] and [materials] tables of a \
+                  scene file; a whole scene file works too")]
+    pub file: PathBuf,
+
+    #[arg(short, long, help = "Name of the material to preview, needed when the file has more than one")]
+    pub material: Option<String>,
+
+    #[arg(short, long, help = "Where to write the preview [default: ./renders/render-<timestamp>.png]")]
+    pub output: Option<PathBuf>,
+
+    #[arg(long, default_value_t = 400, value_parser = clap::value_parser!(u32).range(2..),
+          help = "Width and height of the preview in pixels")]
+    pub size: u32,
+
+    #[arg(long, default_value_t = 64, value_parser = clap::value_parser!(u32).range(1..), help = "Samples per pixel")]
+    pub samples: u32,
+
+    #[arg(long, help = "Seed for the random numbers [default: 0]")]
+    pub seed: Option<u64>,
+}
+
+#[derive(clap::Args)]
+pub struct FurnaceArgs {
+    #[arg(help = "File with materials to test (.toml or .json), laid out like the [textures] and [materials] tables \
+                  of a scene file; the built-in materials when left out")]
+    pub file: Option<PathBuf>,
+
+    #[arg(short, long, requires = "file", help = "Name of the material to test, all of the file's when left out")]
+    pub material: Option<String>,
+
+    #[arg(long, default_value_t = 64, value_parser = clap::value_parser!(u32).range(2..),
+          help = "Width and height of the test renders in pixels")]
+    pub size: u32,
+
+    #[arg(long, default_value_t = 64, value_parser = clap::value_parser!(u32).range(1..), help = "Samples per pixel")]
+    pub samples: u32,
+
+    #[arg(long, default_value_t = 0.01, help = "Largest error of the mean allowed, as a fraction of it")]
+    pub tolerance: Float,
+
+    #[arg(long, help = "Seed for the random numbers [default: 0]")]
+    pub seed: Option<u64>,
+}
+
+#[derive(clap::Args)]
+pub struct InspectArgs {
+    #[arg(help = "Scene file or builtin:<name>, as for a normal render")]
+    pub scene: SceneArg,
+}
+
+#[derive(clap::Args)]
+pub struct AccumulateArgs {
+    #[arg(required = true, help = "Renders to merge, PNG or EXR files with the metadata the renderer writes")]
+    pub inputs: Vec<PathBuf>,
+
+    #[arg(short, long, help = "Where to write the merged image, EXR keeps the full precision")]
+    pub output: PathBuf,
+
+    #[arg(long, help = "Merge the inputs into the output file when it exists already, instead of replacing it")]
+    pub resume: bool,
+}
+
+#[derive(clap::Args)]
+pub struct SweepArgs {
+    #[arg(long, help = "Scene file or builtin:<name>, as for a normal render [default: builtin:default]")]
+    pub scene: Option<SceneArg>,
+
+    #[arg(long, required = true, value_name = "TARGET=VALUES",
+          help = "Values to render the scene with, written like --override with a list, render:samples_per_pixel=1,4,16,64, \
+                  or a range cut into steps, material:metal.fuzz=0..1:5. Given more than once, every combination is rendered")]
+    pub vary: Vec<Sweep>,
+
+    #[arg(short, long, help = "Where to write the renders, the values are added to the name, e.g. render_fuzz-0.25.png \
+                              [default: ./renders/render-<timestamp>.png]")]
+    pub output: Option<PathBuf>,
+
+    #[arg(long, help = "Also write all renders into one image, a row for each value of the last --vary")]
+    pub grid: Option<PathBuf>,
+
+    #[arg(long, help = "Seed for the random numbers [default: 0]")]
+    pub seed: Option<u64>,
+}
+
+#[derive(clap::Args)]
+pub struct BakeArgs {
+    #[arg(long, help = "Scene file the mesh is in")]
+    pub scene: PathBuf,
+
+    #[arg(long, help = "Name of the mesh to bake, a mesh object in the scene's objects list")]
+    pub object: String,
+
+    #[arg(long, value_enum, default_value_t = BakeMode::Lighting, help = "What to bake")]
+    pub mode: BakeMode,
+
+    #[arg(short, long, help = "Where to write the texture, EXR keeps the full range of the lighting \
+                              [default: ./renders/render-<timestamp>.png]")]
+    pub output: Option<PathBuf>,
+
+    #[arg(long, default_value_t = 1024, value_parser = clap::value_parser!(u32).range(2..),
+          help = "Width and height of the texture in pixels")]
+    pub size: u32,
+
+    #[arg(long, default_value_t = 64, value_parser = clap::value_parser!(u32).range(1..), help = "Samples per texel")]
+    pub samples: u32,
+
+    #[arg(long, default_value_t = 4, help = "Texels to grow the UV islands by, so filtering doesn't pull in black from \
+                                            around them")]
+    pub padding: u32,
+
+    #[arg(long, default_value_t = 1.0, help = "Distance within which objects block for --mode occlusion")]
+    pub distance: Float,
+
+    #[arg(long, help = "Seed for the random numbers [default: the scene's]")]
+    pub seed: Option<u64>,
+}
@@ -0,0 +1,123 @@
+use std::sync::Arc;
+use crate::aabb::Aabb;
+use crate::error::Result;
+use crate::hit::{rounding_error, HitRecord};
+use crate::scene_file::{ClipDesc, Exporter};
+use crate::{Float, Hit, Point3, Ray, Vec3};
+
+// Cuts away everything on the side of the plane its normal points to, for showing the inside
+// of a building or a mesh in section. With a cap the cut is closed off where it goes through a
+// solid object, in that object's material, as if the object had been sawn through.
+#[derive(Copy, Clone, Debug)]
+pub struct ClipPlane {
+    point: Point3,
+    normal: Vec3,
+    cap: bool,
+}
+
+impl ClipPlane {
+    pub fn new(point: Point3, normal: Vec3) -> ClipPlane {
+        ClipPlane {
+            point,
+            normal: normal.normalized(),
+            cap: false,
+        }
+    }
+
+    pub fn with_cap(mut self, cap: bool) -> ClipPlane {
+        self.cap = cap;
+        self
+    }
+
+    pub fn point(&self) -> Point3 {
+        self.point
+    }
+
+    pub fn normal(&self) -> Vec3 {
+        self.normal
+    }
+
+    pub fn cap(&self) -> bool {
+        self.cap
+    }
+}
+
+// An object seen only on the kept side of all the planes, see ClipPlane. SceneBuilder wraps
+// every object in the scene in one of these when there are clipping planes.
+#[derive(Debug)]
+pub struct Clipped {
+    object: Arc<dyn Hit>,
+    planes: Vec<ClipPlane>,
+}
+
+impl Clipped {
+    pub fn new(object: Arc<dyn Hit>, planes: Vec<ClipPlane>) -> Clipped {
+        Clipped {
+            object,
+            planes,
+        }
+    }
+}
+
+impl Hit for Clipped {
+    fn hit(&self, r: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord> {
+        // the stretch of the ray on the kept side, and the plane it comes in through
+        let (mut t_min, mut t_max) = (t_min, t_max);
+        let mut entry = None;
+        for plane in &self.planes {
+            let side = (r.origin() - plane.point).dot(plane.normal);
+            let toward = r.direction().dot(plane.normal);
+            if toward == 0.0 {
+                if side > 0.0 {
+                    return None;
+                }
+                continue;
+            }
+            let t = -side / toward;
+            if toward > 0.0 {
+                t_max = t_max.min(t);
+            } else if t > t_min {
+                t_min = t;
+                entry = Some(plane);
+            }
+        }
+        if t_min >= t_max {
+            return None;
+        }
+
+        let rec = self.object.hit(r, t_min, t_max)?;
+        // Seeing the inside of a surface first means the ray came in through the cut inside a
+        // solid, which is where the cap goes. Open surfaces get caps where they shouldn't.
+        match entry {
+            Some(plane) if plane.cap && !rec.front_face => {
+                let p = r.at(t_min);
+                let mut cap = HitRecord {
+                    p,
+                    normal: plane.normal,
+                    mat: rec.mat,
+                    t: t_min,
+                    u: 0.0,
+                    v: 0.0,
+                    front_face: true,
+                    error: rounding_error(p.max_abs()),
+                };
+                cap.set_face_normal(r, plane.normal);
+                Some(cap)
+            }
+            _ => Some(rec),
+        }
+    }
+
+    // the object's own, the caps are inside it and the cut only takes away
+    fn bounding_box(&self, time0: Float, time1: Float) -> Option<Aabb> {
+        self.object.bounding_box(time0, time1)
+    }
+
+    fn export(&self, out: &mut Exporter) -> Result<()> {
+        self.object.export(out)?;
+        for plane in &self.planes {
+            out.clip(ClipDesc::Plane { point: plane.point.to_array(), normal: plane.normal.to_array(), cap: plane.cap });
+        }
+        Ok(())
+    }
+}
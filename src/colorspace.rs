@@ -0,0 +1,266 @@
+use serde::{Deserialize, Serialize};
+use crate::ocio::View;
+use crate::{Color, Float};
+
+// The linear RGB space the renderer works in. The colors of the scene file are taken to be in
+// it, and so is the beauty that comes out. Everything the renderer brings in itself is
+// turned into it on the way in: image textures and environment maps, and the colors of
+// black bodies.
+//
+// sRGB has the primaries of Rec. 709 displays. ACEScg's (AP1) reach further into saturated
+// greens and reds, which keeps bounced light from colored surfaces closer to what a
+// spectral render gives, and it's what the compositing side of ACES pipelines expects.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WorkingSpace {
+    #[default]
+    Srgb,
+    Acescg,
+}
+
+// linear sRGB to ACEScg and back, white going from D65 to the ACES white by Bradford
+const SRGB_TO_ACESCG: [[Float; 3]; 3] = [
+    [0.6130974024, 0.3395231462, 0.0473794514],
+    [0.0701937225, 0.9163538791, 0.0134523985],
+    [0.0206155929, 0.1095697729, 0.8698146342],
+];
+const ACESCG_TO_SRGB: [[Float; 3]; 3] = [
+    [1.7050509927, -0.6217921207, -0.0832588720],
+    [-0.1302564175, 1.1408047366, -0.0105483191],
+    [-0.0240033568, -0.1289689761, 1.1529723329],
+];
+
+// the key of the image metadata that names the space, left out for sRGB
+const KEY: &str = "WorkingSpace";
+
+impl WorkingSpace {
+    pub fn name(self) -> &'static str {
+        match self {
+            WorkingSpace::Srgb => "srgb",
+            WorkingSpace::Acescg => "acescg",
+        }
+    }
+
+    // The space an image's beauty is in, from the metadata the renderer writes (see
+    // metadata::render_metadata). Images without it are sRGB.
+    pub fn of(metadata: &[(String, String)]) -> WorkingSpace {
+        match metadata.iter().find(|(key, _)| key == KEY).map(|(_, value)| value.as_str()) {
+            Some("acescg") => WorkingSpace::Acescg,
+            _ => WorkingSpace::Srgb,
+        }
+    }
+
+    // the metadata entry for images in this space, None for sRGB
+    pub fn metadata(self) -> Option<(String, String)> {
+        (self != WorkingSpace::Srgb).then(|| (KEY.to_string(), self.name().to_string()))
+    }
+
+    // a linear sRGB color in this space
+    pub fn from_srgb(self, c: Color) -> Color {
+        match self {
+            WorkingSpace::Srgb => c,
+            WorkingSpace::Acescg => mul(&SRGB_TO_ACESCG, c),
+        }
+    }
+
+    // a color in this space as linear sRGB, what displays show
+    pub fn to_srgb(self, c: Color) -> Color {
+        match self {
+            WorkingSpace::Srgb => c,
+            WorkingSpace::Acescg => mul(&ACESCG_TO_SRGB, c),
+        }
+    }
+
+    // a color in `from` in this space
+    pub fn convert(self, from: WorkingSpace, c: Color) -> Color {
+        if from == self { c } else { self.from_srgb(from.to_srgb(c)) }
+    }
+
+    // the CIE xy of the red, green and blue primaries and of white, as EXR files store them
+    pub fn chromaticities(self) -> [(f32, f32); 4] {
+        match self {
+            WorkingSpace::Srgb => [(0.64, 0.33), (0.30, 0.60), (0.15, 0.06), (0.3127, 0.3290)],
+            WorkingSpace::Acescg => [(0.713, 0.293), (0.165, 0.830), (0.128, 0.044), (0.32168, 0.33767)],
+        }
+    }
+
+    // the space with these chromaticities, None when it's neither
+    pub fn from_chromaticities(chromaticities: [(f32, f32); 4]) -> Option<WorkingSpace> {
+        [WorkingSpace::Srgb, WorkingSpace::Acescg].into_iter().find(|space| {
+            space.chromaticities().iter().zip(&chromaticities)
+                .all(|(a, b)| (a.0 - b.0).abs() < 1.0e-3 && (a.1 - b.1).abs() < 1.0e-3)
+        })
+    }
+}
+
+fn mul(m: &[[Float; 3]; 3], c: Color) -> Color {
+    Color::new(
+        m[0][0] * c[0] + m[0][1] * c[1] + m[0][2] * c[2],
+        m[1][0] * c[0] + m[1][1] * c[1] + m[1][2] * c[2],
+        m[2][0] * c[0] + m[2][1] * c[1] + m[2][2] * c[2],
+    )
+}
+
+// The displays the 8 and 16-bit formats are made for. sRGB is what they've always been:
+// sRGB's primaries with a gamma of 2, which every viewer takes for sRGB. The others are for
+// wide gamut and HDR screens and only PNG can say it's for them, by a cICP chunk (see
+// output::encode_png) and the "Display" metadata entry, which input::read_png goes by.
+//
+// Display P3 and Rec. 2020 keep saturated colors sRGB would clip, with the sRGB curve. HDR10 is
+// Rec. 2020 with the PQ curve of SMPTE ST 2084, in absolute brightness: 1 in the render is
+// paper white, 203 nits as BT.2408 has it, and highlights go on up to the 10000 nits PQ ends at.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "native", derive(clap::ValueEnum))]
+pub enum Display {
+    #[default]
+    Srgb,
+    P3,
+    Rec2020,
+    Hdr10,
+}
+
+// linear sRGB to linear Display P3 and Rec. 2020, all D65
+const SRGB_TO_P3: [[Float; 3]; 3] = [
+    [0.8224621209, 0.1775378791, 0.0],
+    [0.0331941989, 0.9668058011, 0.0],
+    [0.0170826307, 0.0723974407, 0.9105199286],
+];
+const SRGB_TO_REC2020: [[Float; 3]; 3] = [
+    [0.6274038959, 0.3292830384, 0.0433130657],
+    [0.0690972894, 0.9195403951, 0.0113623156],
+    [0.0163914389, 0.0880133079, 0.8955952532],
+];
+const P3_TO_SRGB: [[Float; 3]; 3] = [
+    [1.2249401763, -0.2249401763, 0.0],
+    [-0.0420569547, 1.0420569547, 0.0],
+    [-0.0196375546, -0.0786360456, 1.0982736002],
+];
+const REC2020_TO_SRGB: [[Float; 3]; 3] = [
+    [1.6604910021, -0.5876411388, -0.0728498633],
+    [-0.1245504745, 1.1328998971, -0.0083494226],
+    [-0.0181507634, -0.1005788980, 1.1187296614],
+];
+
+// nits of paper white, what 1 in the render is in HDR10
+const PAPER_WHITE: Float = 203.0;
+
+// the metadata key PNGs for other displays than sRGB name theirs under
+const DISPLAY_KEY: &str = "Display";
+
+impl Display {
+    pub fn name(self) -> &'static str {
+        match self {
+            Display::Srgb => "srgb",
+            Display::P3 => "p3",
+            Display::Rec2020 => "rec2020",
+            Display::Hdr10 => "hdr10",
+        }
+    }
+
+    // the display an image was made for, from its metadata, sRGB without an entry
+    pub fn of(metadata: &[(String, String)]) -> Display {
+        let name = metadata.iter().find(|(key, _)| key == DISPLAY_KEY).map(|(_, value)| value.as_str());
+        [Display::P3, Display::Rec2020, Display::Hdr10].into_iter()
+            .find(|d| Some(d.name()) == name)
+            .unwrap_or_default()
+    }
+
+    // the metadata entry for images made for this display, None for sRGB
+    pub fn metadata(self) -> Option<(String, String)> {
+        (self != Display::Srgb).then(|| (DISPLAY_KEY.to_string(), self.name().to_string()))
+    }
+
+    // the color primaries, transfer characteristics, matrix coefficients and full range flag
+    // of the PNG cICP chunk, as ITU-T H.273 numbers them; None for sRGB, whose gamma of 2
+    // doesn't have one
+    pub fn cicp(self) -> Option<[u8; 4]> {
+        match self {
+            Display::Srgb => None,
+            Display::P3 => Some([12, 13, 0, 1]),
+            Display::Rec2020 => Some([9, 13, 0, 1]),
+            Display::Hdr10 => Some([9, 16, 0, 1]),
+        }
+    }
+
+    // a linear sRGB color as this display's values from 0 to 1
+    pub fn encode(self, c: Color) -> Color {
+        let c = match self {
+            Display::Srgb => return Color::new(c[0].sqrt(), c[1].sqrt(), c[2].sqrt()),
+            Display::P3 => mul(&SRGB_TO_P3, c),
+            Display::Rec2020 | Display::Hdr10 => mul(&SRGB_TO_REC2020, c),
+        };
+        let curve = if self == Display::Hdr10 { pq } else { srgb_curve };
+        Color::new(curve(c[0].max(0.0)), curve(c[1].max(0.0)), curve(c[2].max(0.0)))
+    }
+
+    // the linear sRGB color this display's values are
+    pub fn decode(self, c: Color) -> Color {
+        let curve = match self {
+            Display::Srgb => return Color::new(c[0] * c[0], c[1] * c[1], c[2] * c[2]),
+            Display::Hdr10 => pq_inverse,
+            _ => srgb_curve_inverse,
+        };
+        let c = Color::new(curve(c[0]), curve(c[1]), curve(c[2]));
+        match self {
+            Display::P3 => mul(&P3_TO_SRGB, c),
+            _ => mul(&REC2020_TO_SRGB, c),
+        }
+    }
+}
+
+fn srgb_curve(v: Float) -> Float {
+    if v <= 0.0031308 { 12.92 * v } else { 1.055 * v.powf(1.0 / 2.4) - 0.055 }
+}
+
+fn srgb_curve_inverse(v: Float) -> Float {
+    if v <= 0.04045 { v / 12.92 } else { ((v + 0.055) / 1.055).powf(2.4) }
+}
+
+// the constants of ST 2084
+const PQ_M1: Float = 2610.0 / 16384.0;
+const PQ_M2: Float = 2523.0 / 4096.0 * 128.0;
+const PQ_C1: Float = 3424.0 / 4096.0;
+const PQ_C2: Float = 2413.0 / 4096.0 * 32.0;
+const PQ_C3: Float = 2392.0 / 4096.0 * 32.0;
+
+fn pq(v: Float) -> Float {
+    let y = (v * PAPER_WHITE / 10000.0).min(1.0).powf(PQ_M1);
+    ((PQ_C1 + PQ_C2 * y) / (1.0 + PQ_C3 * y)).powf(PQ_M2)
+}
+
+fn pq_inverse(v: Float) -> Float {
+    let e = v.max(0.0).powf(1.0 / PQ_M2);
+    ((e - PQ_C1).max(0.0) / (PQ_C2 - PQ_C3 * e)).powf(1.0 / PQ_M1) * 10000.0 / PAPER_WHITE
+}
+
+// How the 8 and 16-bit formats turn the beauty into values from 0 to 1: for a display, or
+// through an OCIO view, which the output is whatever the view makes it
+#[derive(Copy, Clone)]
+pub enum Encoding<'a> {
+    Display(Display),
+    View(&'a View),
+}
+
+impl Default for Encoding<'_> {
+    fn default() -> Self {
+        Encoding::Display(Display::Srgb)
+    }
+}
+
+impl Encoding<'_> {
+    // the display the values are for, sRGB for views
+    pub fn display(self) -> Display {
+        match self {
+            Encoding::Display(display) => display,
+            Encoding::View(_) => Display::Srgb,
+        }
+    }
+
+    // a color of the beauty, in `space`
+    pub fn encode(self, space: WorkingSpace, c: Color) -> Color {
+        match self {
+            Encoding::Display(display) => display.encode(space.to_srgb(c)),
+            Encoding::View(view) => view.apply(c),
+        }
+    }
+}
@@ -0,0 +1,99 @@
+use crate::framebuffer::Framebuffer;
+use crate::{Color, Float};
+
+// Rendering until the image is clean enough instead of for a fixed number of samples. The
+// render goes in rounds of the settings' samples per pixel, each with a seed of its own, and
+// stops once the noise left in the average of the rounds drops below `threshold`, or when
+// another round would go past `max_samples`.
+//
+// The noise is told from how much the rounds disagree: per pixel, the standard error of the
+// mean luminance over the rounds relative to the luminance, averaged over the image. 0.01 is
+// about 1% noise, hard to see in most scenes.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct NoiseThreshold {
+    pub threshold: Float,
+    pub max_samples: u32,
+}
+
+// fewer rounds don't say much about how they scatter
+pub const MIN_ROUNDS: u32 = 4;
+
+// dark pixels count as this bright, or the tiniest noise in them would look huge
+const DARKEST: Float = 0.01;
+
+impl NoiseThreshold {
+    pub fn new(threshold: Float, max_samples: u32) -> NoiseThreshold {
+        NoiseThreshold { threshold, max_samples }
+    }
+}
+
+// The rounds so far, averaged, and how they scatter around the average
+pub struct Convergence {
+    image: Option<Framebuffer>,
+    rounds: u32,
+    // sum of squared differences from the mean luminance, per pixel (Welford's)
+    m2: Vec<Float>,
+}
+
+impl Convergence {
+    pub fn new() -> Convergence {
+        Convergence { image: None, rounds: 0, m2: Vec::new() }
+    }
+
+    pub fn rounds(&self) -> u32 {
+        self.rounds
+    }
+
+    // Averages a round in. Normals and depth are the same every round, they come from the
+    // first one.
+    pub fn add(&mut self, round: Framebuffer) {
+        self.rounds += 1;
+        let Some(image) = &mut self.image else {
+            self.m2 = vec![0.0; round.beauty.len()];
+            self.image = Some(round);
+            return;
+        };
+        let weight = 1.0 / self.rounds as Float;
+        for ((mean, &c), m2) in image.beauty.iter_mut().zip(&round.beauty).zip(&mut self.m2) {
+            let before = luminance(*mean);
+            *mean = *mean + weight * (c - *mean);
+            *m2 += (luminance(c) - before) * (luminance(c) - luminance(*mean));
+        }
+        for (pass, other) in image.passes.iter_mut().zip(&round.passes) {
+            for (mean, &c) in pass.values.iter_mut().zip(&other.values) {
+                *mean = *mean + weight * (c - *mean);
+            }
+        }
+        if let (Some(occlusion), Some(other)) = (&mut image.occlusion, &round.occlusion) {
+            for (mean, &o) in occlusion.iter_mut().zip(other) {
+                *mean += weight * (o - *mean);
+            }
+        }
+    }
+
+    // the noise left in the average, see NoiseThreshold; infinite until there are two rounds
+    pub fn error(&self) -> Float {
+        let Some(image) = self.image.as_ref().filter(|_| self.rounds > 1) else {
+            return Float::INFINITY;
+        };
+        let n = self.rounds as Float;
+        let total: Float = image.beauty.iter().zip(&self.m2)
+            .map(|(&mean, &m2)| (m2 / (n - 1.0) / n).sqrt() / luminance(mean).max(DARKEST))
+            .sum();
+        total / image.beauty.len().max(1) as Float
+    }
+
+    pub fn finish(self) -> Option<Framebuffer> {
+        self.image
+    }
+}
+
+impl Default for Convergence {
+    fn default() -> Convergence {
+        Convergence::new()
+    }
+}
+
+fn luminance(c: Color) -> Float {
+    0.2126 * c[0] + 0.7152 * c[1] + 0.0722 * c[2]
+}
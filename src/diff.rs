@@ -0,0 +1,238 @@
+use crate::float::consts::{self, PI};
+use crate::error::{RendererError, Result};
+use crate::framebuffer::Framebuffer;
+use crate::{Color, Float};
+
+// Image comparison metrics. Everything is computed on display values (gamma applied and
+// clamped to 0..1), the same way the images are looked at.
+pub struct DiffResult {
+    pub rmse: Float,
+    pub psnr: Float,
+    pub flip: Float,
+    // per pixel FLIP error, row by row from the top
+    pub flip_map: Vec<Float>,
+}
+
+pub fn compare(a: &Framebuffer, b: &Framebuffer) -> Result<DiffResult> {
+    if (a.width(), a.height()) != (b.width(), b.height()) {
+        return Err(RendererError::SizeMismatch((a.width(), a.height()), (b.width(), b.height())));
+    }
+
+    let (da, db) = (display(a), display(b));
+    let mse = da.iter()
+        .zip(&db)
+        .map(|(x, y)| { let d = *x - *y; d.dot(d) / 3.0 })
+        .sum::<Float>() / da.len() as Float;
+    let rmse = mse.sqrt();
+    let psnr = if mse == 0.0 { Float::INFINITY } else { 10.0 * (1.0 / mse).log10() };
+
+    let flip_map = flip(&da, &db, a.width() as usize, a.height() as usize);
+    let flip = flip_map.iter().sum::<Float>() / flip_map.len() as Float;
+
+    Ok(DiffResult { rmse, psnr, flip, flip_map })
+}
+
+fn display(data: &Framebuffer) -> Vec<Color> {
+    data.beauty.iter()
+        .map(|c| Color::new(c[0].max(0.0).sqrt().min(1.0), c[1].max(0.0).sqrt().min(1.0), c[2].max(0.0).sqrt().min(1.0)))
+        .collect()
+}
+
+// Error heatmap (black → purple → orange → yellow) as an image the output writers can save
+pub fn heatmap(result: &DiffResult, width: u32, height: u32) -> Framebuffer {
+    const STOPS: [(Float, Float, Float); 5] = [
+        (0.0, 0.0, 0.0),
+        (0.34, 0.06, 0.43),
+        (0.73, 0.21, 0.33),
+        (0.98, 0.55, 0.04),
+        (0.99, 1.0, 0.64),
+    ];
+
+    let mut map = Framebuffer::new(width, height);
+    for (i, &e) in result.flip_map.iter().enumerate() {
+        let t = e.clamp(0.0, 1.0) * (STOPS.len() - 1) as Float;
+        let k = (t as usize).min(STOPS.len() - 2);
+        let f = t - k as Float;
+        let (a, b) = (STOPS[k], STOPS[k + 1]);
+        let c = Color::new(a.0 + f*(b.0 - a.0), a.1 + f*(b.1 - a.1), a.2 + f*(b.2 - a.2));
+        // squared so the writers' gamma gives back the colormap value
+        map.beauty[i] = c * c;
+    }
+    map
+}
+
+// -- LDR FLIP (Andersson et al. 2020) --
+
+const PIXELS_PER_DEGREE: Float = 67.0;  // 0.7m viewing distance from a 24" 4K monitor
+
+// sRGB (display) to linear
+fn srgb_to_linear(c: Float) -> Float {
+    if c <= 0.04045 { c / 12.92 } else { ((c + 0.055) / 1.055).powf(2.4) }
+}
+
+fn linear_to_xyz(c: Color) -> Color {
+    Color::new(
+        0.4124 * c[0] + 0.3576 * c[1] + 0.1805 * c[2],
+        0.2126 * c[0] + 0.7152 * c[1] + 0.0722 * c[2],
+        0.0193 * c[0] + 0.1192 * c[1] + 0.9505 * c[2],
+    )
+}
+
+fn xyz_to_linear(c: Color) -> Color {
+    Color::new(
+        3.2406 * c[0] - 1.5372 * c[1] - 0.4986 * c[2],
+        -0.9689 * c[0] + 1.8758 * c[1] + 0.0415 * c[2],
+        0.0557 * c[0] - 0.2040 * c[1] + 1.0570 * c[2],
+    )
+}
+
+const WHITE: (Float, Float, Float) = (0.950428545, 1.0, 1.088900371);
+
+fn xyz_to_ycxcz(c: Color) -> Color {
+    let (x, y, z) = (c[0] / WHITE.0, c[1] / WHITE.1, c[2] / WHITE.2);
+    Color::new(116.0 * y - 16.0, 500.0 * (x - y), 200.0 * (y - z))
+}
+
+fn ycxcz_to_xyz(c: Color) -> Color {
+    let y = (c[0] + 16.0) / 116.0;
+    let x = y + c[1] / 500.0;
+    let z = y - c[2] / 200.0;
+    Color::new(x * WHITE.0, y * WHITE.1, z * WHITE.2)
+}
+
+fn xyz_to_lab(c: Color) -> Color {
+    let f = |t: Float| if t > Float::powi(6.0 / 29.0, 3) { t.cbrt() } else { t / (3.0 * Float::powi(6.0 / 29.0, 2)) + 4.0 / 29.0 };
+    let (x, y, z) = (f(c[0] / WHITE.0), f(c[1] / WHITE.1), f(c[2] / WHITE.2));
+    Color::new(116.0 * y - 16.0, 500.0 * (x - y), 200.0 * (y - z))
+}
+
+fn hunt(lab: Color) -> Color {
+    Color::new(lab[0], 0.01 * lab[0] * lab[1], 0.01 * lab[0] * lab[2])
+}
+
+fn hyab(a: Color, b: Color) -> Float {
+    let d = a - b;
+    d[0].abs() + (d[1] * d[1] + d[2] * d[2]).sqrt()
+}
+
+// separable 2D convolution with clamped borders
+fn convolve(src: &[Float], w: usize, h: usize, kx: &[Float], ky: &[Float]) -> Vec<Float> {
+    let (rx, ry) = (kx.len() / 2, ky.len() / 2);
+    let mut tmp = vec![0.0; src.len()];
+    for y in 0..h {
+        for x in 0..w {
+            tmp[y * w + x] = kx.iter().enumerate()
+                .map(|(i, k)| k * src[y * w + (x + i).saturating_sub(rx).min(w - 1)])
+                .sum();
+        }
+    }
+    let mut out = vec![0.0; src.len()];
+    for y in 0..h {
+        for x in 0..w {
+            out[y * w + x] = ky.iter().enumerate()
+                .map(|(j, k)| k * tmp[(y + j).saturating_sub(ry).min(h - 1) * w + x])
+                .sum();
+        }
+    }
+    out
+}
+
+// contrast sensitivity function of one opponent channel as a normalized 1D kernel. The CSFs
+// are sums of Gaussians, so they separate into x and y
+fn csf_kernel(a1: Float, b1: Float, a2: Float, b2: Float) -> Vec<Float> {
+    let radius = (3.0 * (b1.max(b2) / (2.0 * PI * PI)).sqrt() * PIXELS_PER_DEGREE).ceil() as i64;
+    let g = |b: Float, x: Float| (PI / b).sqrt() * (-PI * PI * x * x / b).exp();
+    let k: Vec<Float> = (-radius..=radius)
+        .map(|i| {
+            let x = i as Float / PIXELS_PER_DEGREE;
+            a1 * g(b1, x) + a2 * g(b2, x)
+        })
+        .collect();
+    let sum: Float = k.iter().sum();
+    k.into_iter().map(|v| v / sum).collect()
+}
+
+// Gaussian derivative kernels for the edge (first derivative) and point (second derivative)
+// detectors, each with its positive and negative parts normalized
+fn feature_kernels() -> (Vec<Float>, Vec<Float>, Vec<Float>) {
+    let sigma = 0.5 * 0.082 * PIXELS_PER_DEGREE;
+    let radius = (3.0 * sigma).ceil() as i64;
+    let xs: Vec<Float> = (-radius..=radius).map(|i| i as Float).collect();
+
+    let gauss: Vec<Float> = xs.iter().map(|x| (-x * x / (2.0 * sigma * sigma)).exp()).collect();
+    let gsum: Float = gauss.iter().sum();
+    let gauss: Vec<Float> = gauss.iter().map(|g| g / gsum).collect();
+
+    let normalize = |k: Vec<Float>| -> Vec<Float> {
+        let pos: Float = k.iter().filter(|v| **v > 0.0).sum();
+        let neg: Float = -k.iter().filter(|v| **v < 0.0).sum::<Float>();
+        k.into_iter().map(|v| if v > 0.0 { v / pos } else if v < 0.0 { v / neg } else { 0.0 }).collect()
+    };
+    let d1 = normalize(xs.iter().zip(&gauss).map(|(x, g)| -x * g).collect());
+    let d2 = normalize(xs.iter().zip(&gauss).map(|(x, g)| (x * x / (sigma * sigma) - 1.0) * g).collect());
+    (gauss, d1, d2)
+}
+
+pub fn flip(reference: &[Color], test: &[Color], w: usize, h: usize) -> Vec<Float> {
+    const QC: Float = 0.7;
+    const PC: Float = 0.4;
+    const PT: Float = 0.95;
+    const QF: Float = 0.5;
+
+    let kernels = [
+        csf_kernel(1.0, 0.0047, 0.0, 1.0e-5),
+        csf_kernel(1.0, 0.0053, 0.0, 1.0e-5),
+        csf_kernel(34.1, 0.04, 13.5, 0.025),
+    ];
+    let (gauss, d1, d2) = feature_kernels();
+
+    let prepare = |img: &[Color]| {
+        let linear: Vec<Color> = img.iter().map(|c| Color::new(srgb_to_linear(c[0]), srgb_to_linear(c[1]), srgb_to_linear(c[2]))).collect();
+
+        // color: spatially filtered in YCxCz, then Hunt adjusted L*a*b*
+        let opponent: Vec<Color> = linear.iter().map(|&c| xyz_to_ycxcz(linear_to_xyz(c))).collect();
+        let mut filtered = vec![Color::default(); opponent.len()];
+        for (ch, k) in kernels.iter().enumerate() {
+            let channel: Vec<Float> = opponent.iter().map(|c| c[ch]).collect();
+            for (i, v) in convolve(&channel, w, h, k, k).into_iter().enumerate() {
+                filtered[i][ch] = v;
+            }
+        }
+        let color: Vec<Color> = filtered.iter()
+            .map(|&c| {
+                let rgb = xyz_to_linear(ycxcz_to_xyz(c));
+                let rgb = Color::new(rgb[0].clamp(0.0, 1.0), rgb[1].clamp(0.0, 1.0), rgb[2].clamp(0.0, 1.0));
+                hunt(xyz_to_lab(linear_to_xyz(rgb)))
+            })
+            .collect();
+
+        // features: edges and points of the normalized achromatic channel
+        let lum: Vec<Float> = opponent.iter().map(|c| (c[0] + 16.0) / 116.0).collect();
+        let magnitude = |a: Vec<Float>, b: Vec<Float>| -> Vec<Float> { a.iter().zip(&b).map(|(x, y)| (x * x + y * y).sqrt()).collect() };
+        let edges = magnitude(convolve(&lum, w, h, &d1, &gauss), convolve(&lum, w, h, &gauss, &d1));
+        let points = magnitude(convolve(&lum, w, h, &d2, &gauss), convolve(&lum, w, h, &gauss, &d2));
+        (color, edges, points)
+    };
+
+    let (color_r, edges_r, points_r) = prepare(reference);
+    let (color_t, edges_t, points_t) = prepare(test);
+
+    let green = hunt(xyz_to_lab(linear_to_xyz(Color::new(0.0, 1.0, 0.0))));
+    let blue = hunt(xyz_to_lab(linear_to_xyz(Color::new(0.0, 0.0, 1.0))));
+    let cmax = hyab(green, blue).powf(QC);
+
+    (0..w * h)
+        .map(|i| {
+            let de = hyab(color_r[i], color_t[i]).powf(QC);
+            let color_error = if de < PC * cmax {
+                PT / (PC * cmax) * de
+            } else {
+                PT + (de - PC * cmax) / (cmax - PC * cmax) * (1.0 - PT)
+            };
+
+            let feature = (edges_r[i] - edges_t[i]).abs().max((points_r[i] - points_t[i]).abs());
+            let feature_error = (feature / consts::SQRT_2).powf(QF);
+            color_error.powf(1.0 - feature_error)
+        })
+        .collect()
+}
@@ -0,0 +1,52 @@
+use crate::hit::{Hit, HitRecord, HittableList};
+use crate::visibility::TraceGroup;
+use crate::{stats, Float, Vec3};
+
+// Where dirt gathers and paint wears off, worked out from the geometry around a point as it's
+// shaded, for the mask of a Mix material. Crevices are where much of the hemisphere above the
+// surface is blocked within `distance`, like ambient occlusion (see occlusion::Occlusion).
+// Edges are where the object is thin below the surface: rays sent into it come out of it
+// again within `distance`, which happens along corners and ridges but not in the middle of a
+// face. Both give 0 for clean and 1 for as dirty or worn as it gets.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Dirt {
+    pub kind: DirtKind,
+    pub distance: Float,
+    pub samples: u32,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum DirtKind {
+    Crevices,
+    Edges,
+}
+
+impl Dirt {
+    pub fn new(kind: DirtKind, distance: Float, samples: u32) -> Dirt {
+        Dirt {
+            kind,
+            distance,
+            samples: samples.max(1),
+        }
+    }
+
+    pub fn at(&self, rec: &HitRecord, world: &HittableList, time: Float) -> Float {
+        // into the object for the edges, spawn_ray starts the probes on the side they go to
+        let normal = match self.kind {
+            DirtKind::Crevices => rec.normal,
+            DirtKind::Edges => -1.0 * rec.normal,
+        };
+        let blocked = (0..self.samples)
+            .filter(|_| {
+                let mut direction = normal + Vec3::rand_in_unit_sphere().normalized();
+                if direction.near_zero() {
+                    direction = normal;
+                }
+                let r = rec.spawn_ray(direction.normalized()).with_time(time).with_group(TraceGroup::DIFFUSE);
+                stats::count(&stats::RAYS);
+                world.hit(&r, 0.0, self.distance).is_some()
+            })
+            .count();
+        blocked as Float / self.samples as Float
+    }
+}
@@ -0,0 +1,54 @@
+use std::fmt::Display;
+use std::io;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+// Everything that can go wrong outside of the render itself: files that can't be read or
+// written, images that don't decode, scene descriptions that don't make sense
+#[derive(Error, Debug)]
+pub enum RendererError {
+    #[error("{}: {source}", path.display())]
+    Io { path: PathBuf, source: io::Error },
+    // an image file that could not be decoded
+    #[error("{}: {message}", path.display())]
+    Decode { path: PathBuf, message: String },
+    // the encoder of an output format gave up
+    #[error("{}: {message}", path.display())]
+    Encode { path: PathBuf, message: String },
+    // scene and mesh files that don't parse
+    #[error("{}: {message}", path.display())]
+    Parse { path: PathBuf, message: String },
+    // a scene file with values that can't be rendered, one problem per line
+    #[error("{}: invalid scene\n  {}", path.display(), problems.join("\n  "))]
+    Invalid { path: PathBuf, problems: Vec<String> },
+    // the scene parses but can't be built, an unknown material for instance
+    #[error("invalid scene: {0}")]
+    Scene(String),
+    #[error("image sizes differ: {}x{} and {}x{}", .0.0, .0.1, .1.0, .1.1)]
+    SizeMismatch((u32, u32), (u32, u32)),
+    #[error("{0}")]
+    Unsupported(String),
+    // stopped through a Cancel, whatever was done has been written out
+    #[error("the render was cancelled")]
+    Cancelled,
+}
+
+pub type Result<T> = std::result::Result<T, RendererError>;
+
+impl RendererError {
+    pub fn io(path: &Path, source: io::Error) -> RendererError {
+        RendererError::Io { path: path.to_path_buf(), source }
+    }
+
+    pub fn decode(path: &Path, message: impl Display) -> RendererError {
+        RendererError::Decode { path: path.to_path_buf(), message: message.to_string() }
+    }
+
+    pub fn encode(path: &Path, message: impl Display) -> RendererError {
+        RendererError::Encode { path: path.to_path_buf(), message: message.to_string() }
+    }
+
+    pub fn parse(path: &Path, message: impl Display) -> RendererError {
+        RendererError::Parse { path: path.to_path_buf(), message: message.to_string() }
+    }
+}
@@ -0,0 +1,195 @@
+// C interface for embedding the renderer, declared in include/raytracer.h. Scenes are opaque
+// handles, materials are referred to by the index they were given when created. Functions
+// return 0 (or an index) on success and -1 on failure, with the reason in rt_last_error().
+// Numbers are doubles on the C side whatever precision the renderer was built with.
+// Scene pointers have to come from rt_scene_new() and not be freed yet, other pointers are
+// described next to the function, here and in the header.
+#![allow(clippy::missing_safety_doc)]
+use std::cell::RefCell;
+use std::ffi::{c_char, c_int, CStr, CString};
+use std::path::Path;
+use std::sync::Arc;
+use crate::camera::Camera;
+use crate::colorspace::{Encoding, WorkingSpace};
+use crate::material::{Dielectric, DiffuseLight, Lambertian, Metal, Scatter};
+use crate::mesh::Mesh;
+use crate::render::{Renderer, Settings};
+use crate::scene::{Background, Environment, Scene};
+use crate::sphere::{Shell, Sphere};
+use crate::{Color, Float, HittableList, Point3, Vec3};
+
+pub struct RtScene {
+    world: HittableList,
+    materials: Vec<Arc<dyn Scatter>>,
+    // everything but the aspect ratio, which comes with the render size
+    camera: Option<(Point3, Point3, Vec3, Float, Float, Float)>,
+    background: Background,
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<CString> = RefCell::new(CString::default());
+}
+
+fn fail(message: impl Into<String>) -> c_int {
+    let message = CString::new(message.into().replace('\0', " ")).unwrap_or_default();
+    LAST_ERROR.with(|e| *e.borrow_mut() = message);
+    -1
+}
+
+fn vec3(v: *const f64) -> Vec3 {
+    // SAFETY: callers pass arrays of three doubles, as documented in the header
+    let v = unsafe { std::slice::from_raw_parts(v, 3) };
+    Vec3::new(v[0] as Float, v[1] as Float, v[2] as Float)
+}
+
+fn color(r: f64, g: f64, b: f64) -> Color {
+    Color::new(r as Float, g as Float, b as Float)
+}
+
+// why the last call on this thread failed, valid until the next failing call
+#[no_mangle]
+pub extern "C" fn rt_last_error() -> *const c_char {
+    LAST_ERROR.with(|e| e.borrow().as_ptr())
+}
+
+#[no_mangle]
+pub extern "C" fn rt_scene_new() -> *mut RtScene {
+    Box::into_raw(Box::new(RtScene {
+        world: HittableList::new(),
+        materials: Vec::new(),
+        camera: None,
+        background: Background::Sky,
+    }))
+}
+
+// the scene can't be used afterwards, null is ignored
+#[no_mangle]
+pub unsafe extern "C" fn rt_scene_free(scene: *mut RtScene) {
+    if !scene.is_null() {
+        drop(Box::from_raw(scene));
+    }
+}
+
+unsafe fn add_material(scene: *mut RtScene, material: Arc<dyn Scatter>) -> c_int {
+    let Some(scene) = scene.as_mut() else { return fail("scene is null") };
+    scene.materials.push(material);
+    (scene.materials.len() - 1) as c_int
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn rt_material_lambertian(scene: *mut RtScene, r: f64, g: f64, b: f64) -> c_int {
+    add_material(scene, Arc::new(Lambertian::new(color(r, g, b))))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn rt_material_metal(scene: *mut RtScene, r: f64, g: f64, b: f64, fuzz: f64) -> c_int {
+    add_material(scene, Arc::new(Metal::new(color(r, g, b), fuzz as Float)))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn rt_material_dielectric(scene: *mut RtScene, ir: f64) -> c_int {
+    if !(ir > 0.0 && ir.is_finite()) {
+        return fail(format!("index of refraction {} should be above 0", ir));
+    }
+    add_material(scene, Arc::new(Dielectric::new(ir as Float)))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn rt_material_light(scene: *mut RtScene, r: f64, g: f64, b: f64) -> c_int {
+    add_material(scene, Arc::new(DiffuseLight::new(color(r, g, b))))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn rt_scene_add_sphere(scene: *mut RtScene, x: f64, y: f64, z: f64, radius: f64, material: c_int) -> c_int {
+    let Some(scene) = scene.as_mut() else { return fail("scene is null") };
+    let Some(mat) = scene.materials.get(material as usize).filter(|_| material >= 0) else {
+        return fail(format!("unknown material {}", material));
+    };
+    if ![x, y, z, radius].iter().all(|v| v.is_finite()) || radius == 0.0 {
+        return fail(format!("sphere at ({}, {}, {}) with radius {} is invalid", x, y, z, radius));
+    }
+    scene.world.push(Arc::new(Sphere::new(Point3::new(x as Float, y as Float, z as Float), radius as Float, mat.clone())));
+    0
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn rt_scene_add_shell(scene: *mut RtScene, x: f64, y: f64, z: f64, outer: f64, inner: f64,
+                                            material: c_int) -> c_int {
+    let Some(scene) = scene.as_mut() else { return fail("scene is null") };
+    let Some(mat) = scene.materials.get(material as usize).filter(|_| material >= 0) else {
+        return fail(format!("unknown material {}", material));
+    };
+    if !([x, y, z, outer].iter().all(|v| v.is_finite()) && inner > 0.0 && inner < outer) {
+        return fail(format!("shell at ({}, {}, {}) with radii {} and {} is invalid", x, y, z, outer, inner));
+    }
+    let center = Point3::new(x as Float, y as Float, z as Float);
+    scene.world.push(Arc::new(Shell::new(center, outer as Float, inner as Float, mat.clone())));
+    0
+}
+
+// `path` is a NUL terminated UTF-8 string
+#[no_mangle]
+pub unsafe extern "C" fn rt_scene_add_mesh(scene: *mut RtScene, path: *const c_char, material: c_int) -> c_int {
+    let Some(scene) = scene.as_mut() else { return fail("scene is null") };
+    if path.is_null() {
+        return fail("path is null");
+    }
+    let Ok(path) = CStr::from_ptr(path).to_str() else { return fail("path is not UTF-8") };
+    let Some(mat) = scene.materials.get(material as usize).filter(|_| material >= 0) else {
+        return fail(format!("unknown material {}", material));
+    };
+    match Mesh::load_obj(Path::new(path), mat.clone()) {
+        Ok(mesh) => {
+            scene.world.push(Arc::new(mesh));
+            0
+        }
+        Err(e) => fail(e.to_string()),
+    }
+}
+
+// `lookfrom`, `lookat` and `vup` point to three doubles each
+#[no_mangle]
+pub unsafe extern "C" fn rt_scene_set_camera(scene: *mut RtScene, lookfrom: *const f64, lookat: *const f64, vup: *const f64,
+                                             vfov: f64, aperture: f64, focus_dist: f64) -> c_int {
+    let Some(scene) = scene.as_mut() else { return fail("scene is null") };
+    if lookfrom.is_null() || lookat.is_null() || vup.is_null() {
+        return fail("camera vector is null");
+    }
+    scene.camera = Some((vec3(lookfrom), vec3(lookat), vec3(vup), vfov as Float, aperture as Float, focus_dist as Float));
+    0
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn rt_scene_set_background(scene: *mut RtScene, r: f64, g: f64, b: f64) -> c_int {
+    let Some(scene) = scene.as_mut() else { return fail("scene is null") };
+    scene.background = Background::Color(color(r, g, b));
+    0
+}
+
+// `rgb` has room for width * height * 3 bytes. The image is written as gamma corrected 8-bit RGB, rows from the top.
+#[no_mangle]
+pub unsafe extern "C" fn rt_render(scene: *mut RtScene, width: u32, height: u32, samples_per_pixel: u32, max_depth: u32,
+                                   seed: u64, rgb: *mut u8) -> c_int {
+    let Some(rt) = scene.as_mut() else { return fail("scene is null") };
+    let Some((lookfrom, lookat, vup, vfov, aperture, focus_dist)) = rt.camera else {
+        return fail("the scene has no camera");
+    };
+    if width < 2 || height < 2 || samples_per_pixel == 0 {
+        return fail(format!("can't render {}x{} at {} samples per pixel", width, height, samples_per_pixel));
+    }
+    if rgb.is_null() {
+        return fail("output buffer is null");
+    }
+
+    let settings = Settings { width, height, samples_per_pixel, max_depth: max_depth as u64, seed, ..Settings::default() };
+    let camera = Camera::new(lookfrom, lookat, vup, vfov, settings.aspect_ratio(), aperture, focus_dist);
+    // the objects are shared with the render, the scene can be changed and rendered again
+    let environment = Environment::new(rt.background.clone());
+    let scene = Scene { world: rt.world.clone(), camera, environment, time: 0.0, material_names: Vec::new(), fog: None,
+                        working_space: WorkingSpace::Srgb, scale: None };
+    let data = Renderer::new().render(&scene, &settings);
+
+    let pixels = data.to_rgb8(Encoding::default());
+    std::ptr::copy_nonoverlapping(pixels.as_ptr(), rgb, pixels.len());
+    0
+}
@@ -0,0 +1,58 @@
+use crate::framebuffer::Framebuffer;
+use crate::{Color, Float};
+
+// An image added up out of whole renders of the same scene: per pixel the sum of the weighted
+// colors and the sum of the weights, a render counting as much as it has samples. Merging
+// renders (see accumulate) and rendering a few samples at a time (live) both go through it.
+// `resolve` divides the sums out into the beauty of a framebuffer, which writes it to the
+// output formats.
+#[derive(Clone, Debug)]
+pub struct Film {
+    width: u32,
+    height: u32,
+    sums: Vec<Color>,
+    weights: Vec<Float>,
+}
+
+impl Film {
+    pub fn new(width: u32, height: u32) -> Film {
+        let size = width as usize * height as usize;
+        Film {
+            width,
+            height,
+            sums: vec![Color::default(); size],
+            weights: vec![0.0; size],
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    // every pixel of `image`'s beauty as `weight` samples, e.g. a render of that many samples
+    // per pixel
+    pub fn splat_image(&mut self, image: &Framebuffer, weight: Float) {
+        for (i, &c) in image.beauty.iter().enumerate().take(self.sums.len()) {
+            self.sums[i] += weight * c;
+            self.weights[i] += weight;
+        }
+    }
+
+    // The weighted average of each pixel into `data`'s beauty, black where nothing landed;
+    // the AOVs, passes and metadata are left as they are
+    pub fn resolve_into(&self, data: &mut Framebuffer) {
+        for ((c, &sum), &weight) in data.beauty.iter_mut().zip(&self.sums).zip(&self.weights) {
+            *c = if weight > 0.0 { sum / weight } else { Color::default() };
+        }
+    }
+
+    pub fn resolve(&self) -> Framebuffer {
+        let mut data = Framebuffer::new(self.width, self.height);
+        self.resolve_into(&mut data);
+        data
+    }
+}
@@ -0,0 +1,12 @@
+// The scalar the renderer computes in. f64 by default; the f32 feature switches the whole
+// pipeline to single precision, which is faster and plenty for most scenes, but large or far
+// away geometry starts to show artifacts.
+#[cfg(not(feature = "f32"))]
+pub type Float = f64;
+#[cfg(not(feature = "f32"))]
+pub use std::f64::consts;
+
+#[cfg(feature = "f32")]
+pub type Float = f32;
+#[cfg(feature = "f32")]
+pub use std::f32::consts;
@@ -0,0 +1,189 @@
+use std::sync::Arc;
+use crate::hit::{Hit, HittableList};
+use crate::material::Scatter;
+use crate::onb::Onb;
+use crate::sphere::Sphere;
+use crate::visibility::TraceGroup;
+use crate::{float::consts, random, stats, Color, Float, Point3, Ray, Vec3};
+
+// Fog of the same thickness everywhere, filling the scene or a box of it, for the beams of
+// light that come through windows and between leaves. Only light that scatters once in the
+// fog on its way from a light to the path is counted, which is what the beams are made of;
+// light scattering in it more often only brightens it a little overall.
+//
+// Along each stretch of a path the fog picks a point on every light and a distance with
+// equiangular sampling: evenly in the angle the stretch covers as seen from that point, so
+// most of the distances land where the stretch passes close to the light and the beams come
+// out clean after a few samples per pixel. The lights are the spheres and triangles of the
+// scene that give off light, as long as they're not in groups or moving.
+#[derive(Clone, Debug)]
+pub struct Fog {
+    // how much of the light is lost per scene unit
+    pub density: Float,
+    // the share of what's lost that's scattered rather than absorbed
+    pub albedo: Color,
+    // the Henyey-Greenstein g: 0 scatters every way alike, towards 1 more and more straight on
+    pub anisotropy: Float,
+    // the corners of the box the fog is in. Without it the fog goes on forever and nothing of
+    // the background makes it through.
+    pub bounds: Option<(Point3, Point3)>,
+    lights: Vec<FogLight>,
+}
+
+#[derive(Clone, Debug)]
+pub enum FogLight {
+    Sphere(Point3, Float, Arc<dyn Scatter>),
+    Triangle([Point3; 3], Arc<dyn Scatter>),
+}
+
+impl FogLight {
+    fn material(&self) -> &Arc<dyn Scatter> {
+        match self {
+            FogLight::Sphere(_, _, mat) | FogLight::Triangle(_, mat) => mat,
+        }
+    }
+
+    // The point equiangular sampling aims at: the center of a sphere, a point picked evenly
+    // over a triangle. Its texture coordinates go along for the triangle.
+    fn aim(&self) -> (Point3, Float, Float) {
+        match self {
+            FogLight::Sphere(center, _, _) => (*center, 0.0, 0.0),
+            FogLight::Triangle([v0, v1, v2], _) => {
+                let r1 = random::gen::<Float>().sqrt();
+                let r2 = random::gen::<Float>();
+                let (b1, b2) = (r1 * (1.0 - r2), r1 * r2);
+                (*v0 + b1 * (*v1 - *v0) + b2 * (*v2 - *v0), b1, b2)
+            }
+        }
+    }
+
+    // The light coming to `x` from the light, aimed at `aimed`: the direction, how far away
+    // the light is that way and what it gives off over the probability of the direction. A
+    // sphere is sampled over the cone it fills as seen from `x`, so none of its points are
+    // picked on the far side.
+    fn arriving(&self, x: Point3, (aimed, u, v): (Point3, Float, Float)) -> Option<(Vec3, Float, Color)> {
+        match self {
+            FogLight::Sphere(center, radius, mat) => {
+                let to_center = *center - x;
+                let d2 = to_center.dot(to_center);
+                let r2 = radius * radius;
+                if d2 <= r2 {
+                    return None;
+                }
+                let cos_max = (1.0 - r2 / d2).sqrt();
+                let cos = 1.0 - random::gen::<Float>() * (1.0 - cos_max);
+                let sin = (1.0 - cos * cos).max(0.0).sqrt();
+                let phi = 2.0 * consts::PI * random::gen::<Float>();
+                let wi = Onb::from_w(to_center).local(Vec3::new(sin * phi.cos(), sin * phi.sin(), cos));
+                let distance = d2.sqrt() * cos - (r2 - d2 * sin * sin).max(0.0).sqrt();
+                let p = x + distance * wi;
+                let (u, v) = Sphere::uv((p - *center) / radius.abs());
+                Some((wi, distance, 2.0 * consts::PI * (1.0 - cos_max) * mat.emitted(u, v, p)))
+            }
+            FogLight::Triangle([v0, v1, v2], mat) => {
+                let to_light = aimed - x;
+                let distance2 = to_light.dot(to_light);
+                let distance = distance2.sqrt();
+                let wi = to_light / distance;
+                let cross = (*v1 - *v0).cross(*v2 - *v0);
+                let cos = cross.normalized().dot(wi).abs();
+                (cos > 0.0).then(|| (wi, distance, (0.5 * cross.length() * cos / distance2) * mat.emitted(u, v, aimed)))
+            }
+        }
+    }
+}
+
+impl Fog {
+    pub fn new(density: Float, albedo: Color, anisotropy: Float) -> Fog {
+        Fog { density, albedo, anisotropy, bounds: None, lights: Vec::new() }
+    }
+
+    pub fn with_bounds(mut self, a: Point3, b: Point3) -> Fog {
+        let lo = Point3::new(a.x().min(b.x()), a.y().min(b.y()), a.z().min(b.z()));
+        let hi = Point3::new(a.x().max(b.x()), a.y().max(b.y()), a.z().max(b.z()));
+        self.bounds = Some((lo, hi));
+        self
+    }
+
+    // the lights the beams come from, SceneBuilder finds them
+    pub fn with_lights(mut self, lights: Vec<FogLight>) -> Fog {
+        self.lights = lights;
+        self
+    }
+
+    pub fn lights(&self) -> &[FogLight] {
+        &self.lights
+    }
+
+    // what's left of the light coming along `r` from `t_max`
+    pub fn transmittance(&self, r: &Ray, t_max: Float) -> Float {
+        let length = r.direction().length();
+        match self.inside(r.origin(), r.direction() / length, t_max * length) {
+            Some((s0, s1)) => (-self.density * (s1 - s0)).exp(),
+            None => 1.0,
+        }
+    }
+
+    // The light the fog scatters into `r` before `t_max`, one estimate for each light, passed
+    // to `each` with the light's material
+    pub fn in_scattered(&self, r: &Ray, t_max: Float, world: &HittableList, mut each: impl FnMut(&Arc<dyn Scatter>, Color)) {
+        let length = r.direction().length();
+        let (o, d) = (r.origin(), r.direction() / length);
+        let Some((s0, s1)) = self.inside(o, d, t_max * length) else { return };
+        for light in &self.lights {
+            let aimed = light.aim();
+            // the distance along the ray closest to the point aimed at and how close it gets
+            let delta = (aimed.0 - o).dot(d);
+            let closest = (o + delta * d - aimed.0).length();
+            if closest < 1.0e-6 {
+                continue;
+            }
+            let theta_a = ((s0 - delta) / closest).atan();
+            let theta_b = ((s1 - delta) / closest).atan();
+            let theta = theta_a + random::gen::<Float>() * (theta_b - theta_a);
+            let s = delta + closest * theta.tan();
+            let pdf = closest / ((theta_b - theta_a) * (closest * closest + (s - delta) * (s - delta)));
+            if pdf.is_nan() || pdf <= 0.0 {
+                continue;
+            }
+            let x = o + s * d;
+            let Some((wi, distance, emitted)) = light.arriving(x, aimed) else { continue };
+            if emitted.near_zero() {
+                continue;
+            }
+
+            stats::count(&stats::RAYS);
+            let shadow = Ray::new(x, wi).with_time(r.time()).with_group(TraceGroup::DIFFUSE);
+            if world.hit(&shadow, 0.0, distance * (1.0 - 1.0e-4)).is_some() {
+                continue;
+            }
+            let in_fog = self.inside(x, wi, distance).map_or(0.0, |(a, b)| b - a);
+            let transmittance = (-self.density * (s - s0 + in_fog)).exp();
+            let phase = henyey_greenstein(self.anisotropy, wi.dot(d));
+            each(light.material(), (self.density * phase * transmittance / pdf) * self.albedo * emitted);
+        }
+    }
+
+    // the part of the ray from `o` along the unit `d` up to `s_max` that's in the fog, as
+    // distances from `o`
+    fn inside(&self, o: Point3, d: Vec3, s_max: Float) -> Option<(Float, Float)> {
+        let (mut s0, mut s1): (Float, Float) = (0.0, s_max);
+        if let Some((lo, hi)) = self.bounds {
+            for axis in 0..3 {
+                let inv = 1.0 / d[axis];
+                let (a, b) = ((lo[axis] - o[axis]) * inv, (hi[axis] - o[axis]) * inv);
+                let (a, b) = if inv < 0.0 { (b, a) } else { (a, b) };
+                s0 = s0.max(a);
+                s1 = s1.min(b);
+            }
+        }
+        (s0 < s1).then_some((s0, s1))
+    }
+}
+
+// how much of the light going on along `cos` of the angle from where it was headed is
+// scattered that way, per steradian
+fn henyey_greenstein(g: Float, cos: Float) -> Float {
+    let denom = 1.0 + g * g - 2.0 * g * cos;
+    (1.0 - g * g) / (4.0 * consts::PI * denom * denom.sqrt())
+}
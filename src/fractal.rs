@@ -0,0 +1,357 @@
+use std::sync::Arc;
+use serde::{Deserialize, Serialize};
+use crate::aabb::Aabb;
+use crate::error::Result;
+use crate::hit::{rounding_error, HitRecord};
+use crate::material::Scatter;
+use crate::onb::Onb;
+use crate::scene_file::{Exporter, ObjectDesc};
+use crate::sphere::Sphere;
+use crate::{float::consts, Float, Hit, Point3, Ray, Vec3};
+
+// Shapes made by repeating a rule `depth` times, each level adding detail a third the size of
+// the last: lots of surface from a few numbers, for showcase scenes and for seeing how the
+// renderer copes with that many primitives. None of them is turned into triangles, each is
+// intersected its own way, so even deep ones cost little memory:
+//
+// - the Menger sponge is a cube with the middle of every face and the center taken out, again
+//   and again on what's left; rays walk the grid of its smallest cubes
+// - the sphereflake is a sphere with nine spheres a third its size around it, and nine around
+//   each of those, away from the one they sit on; whole branches are skipped by the rays that
+//   miss the sphere around them
+// - the Mandelbulb is the power 8 bulb, found by sphere tracing its distance estimate, `depth`
+//   being the iterations of the formula. Rays follow it from the outside only, it's meant for
+//   opaque materials.
+#[derive(Copy, Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FractalKind {
+    Menger,
+    Sphereflake,
+    Mandelbulb,
+}
+
+impl FractalKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            FractalKind::Menger => "menger",
+            FractalKind::Sphereflake => "sphereflake",
+            FractalKind::Mandelbulb => "mandelbulb",
+        }
+    }
+
+    // deeper than this takes too long to build or shows nothing new at any sensible resolution
+    pub fn max_depth(self) -> u32 {
+        match self {
+            FractalKind::Menger => 7,
+            FractalKind::Sphereflake => 5,
+            FractalKind::Mandelbulb => 32,
+        }
+    }
+
+    // from the center to the furthest any part of it gets, for a fractal of `size`
+    pub fn extent(self, size: Float) -> Float {
+        match self {
+            // the spheres around a sphere and all theirs stay within twice its radius
+            FractalKind::Sphereflake => 2.0 * size,
+            FractalKind::Menger | FractalKind::Mandelbulb => size,
+        }
+    }
+}
+
+// the Mandelbulb fits in a sphere this big in the space of its formula
+const BULB_RADIUS: Float = 1.2;
+const BULB_POWER: Float = 8.0;
+const BULB_STEPS: u32 = 400;
+
+// `size` is half the width of the sponge, the radius of the sphereflake's biggest sphere and
+// the radius of the sphere the bulb fits in
+#[derive(Debug)]
+pub struct Fractal {
+    kind: FractalKind,
+    depth: u32,
+    center: Point3,
+    size: Float,
+    mat: Arc<dyn Scatter>,
+    // the sphereflake's spheres, None for the others
+    flake: Option<Flake>,
+}
+
+impl Fractal {
+    pub fn new(kind: FractalKind, depth: u32, center: Point3, size: Float, m: Arc<dyn Scatter>) -> Fractal {
+        let flake = (kind == FractalKind::Sphereflake)
+            .then(|| Flake::new(center, size, Vec3::new(0.0, 1.0, 0.0), depth, &m));
+        Fractal { kind, depth, center, size, mat: m, flake }
+    }
+
+    // Walks the grid of the smallest cubes along the ray until it goes from a cube that's
+    // there to one that isn't or the other way around, so rays inside glass find their way
+    // out through the holes too
+    fn hit_sponge(&self, r: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord> {
+        let cells = 3usize.pow(self.depth);
+        let cell = 2.0 * self.size / cells as Float;
+        let lo = self.center - Vec3::new(self.size, self.size, self.size);
+        let (o, d) = (r.origin(), r.direction());
+
+        // where the ray is inside the sponge's box, and the side it comes in through
+        let (mut t_in, mut t_out, mut side) = (Float::NEG_INFINITY, Float::INFINITY, 0);
+        for a in 0..3 {
+            if d[a] == 0.0 {
+                if o[a] < lo[a] || o[a] > lo[a] + 2.0 * self.size {
+                    return None;
+                }
+                continue;
+            }
+            let (t0, t1) = ((lo[a] - o[a]) / d[a], (lo[a] + 2.0 * self.size - o[a]) / d[a]);
+            if t0.min(t1) > t_in {
+                (t_in, side) = (t0.min(t1), a);
+            }
+            t_out = t_out.min(t0.max(t1));
+        }
+        if t_in > t_out || t_out < t_min || t_in > t_max {
+            return None;
+        }
+
+        let start = t_in.max(t_min);
+        let q = (r.at(start) - lo) / cell;
+        let mut at = [0, 1, 2].map(|a| (q[a].floor() as i64).clamp(0, cells as i64 - 1));
+        let step = [0, 1, 2].map(|a| if d[a] > 0.0 { 1 } else { -1 });
+        let mut inside = solid(at, self.depth);
+        if t_in >= t_min && inside {
+            return Some(self.face(r, t_in, side, -step[side] as Float));
+        }
+        let mut t_next = [0, 1, 2].map(|a| {
+            if d[a] == 0.0 {
+                Float::INFINITY
+            } else {
+                let next = at[a] + if step[a] > 0 { 1 } else { 0 };
+                (lo[a] + next as Float * cell - o[a]) / d[a]
+            }
+        });
+        let delta = [0, 1, 2].map(|a| (cell / d[a]).abs());
+        loop {
+            let a = (0..3).fold(0, |best, a| if t_next[a] < t_next[best] { a } else { best });
+            let t = t_next[a];
+            if t > t_max {
+                return None;
+            }
+            at[a] += step[a];
+            if at[a] < 0 || at[a] >= cells as i64 {
+                // out of the box, through its side if the ray was inside a cube
+                return inside.then(|| self.face(r, t, a, step[a] as Float));
+            }
+            let next = solid(at, self.depth);
+            if next != inside {
+                // the side of the cube the ray goes into or comes out of, facing out of the cube
+                let sign = if next { -step[a] } else { step[a] } as Float;
+                return Some(self.face(r, t, a, sign));
+            }
+            inside = next;
+            t_next[a] += delta[a];
+        }
+    }
+
+    // a hit on a side of a cube, facing `sign` along axis `a`
+    fn face(&self, r: &Ray, t: Float, a: usize, sign: Float) -> HitRecord {
+        let mut normal = Vec3::new(0.0, 0.0, 0.0);
+        normal[a] = sign;
+        // the texture spreads over the whole sponge, seen along the axis
+        let q = (r.at(t) - self.center) / (2.0 * self.size);
+        let (b, c) = ((a + 1) % 3, (a + 2) % 3);
+        let mut rec = HitRecord {
+            p: r.at(t),
+            normal,
+            mat: self.mat.clone(),
+            t,
+            u: q[b] + 0.5,
+            v: q[c] + 0.5,
+            front_face: false,
+            error: rounding_error(self.center.max_abs() + self.size),
+        };
+        rec.set_face_normal(r, normal);
+        rec
+    }
+
+    // Sphere tracing: steps along the ray as far as the distance estimate says is empty, until
+    // that's below `eps` or the ray leaves the bulb's sphere
+    fn hit_bulb(&self, r: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord> {
+        let (near, far) = through_sphere(r, self.center, self.size)?;
+        let end = far.min(t_max);
+        let mut t = near.max(t_min);
+        let length = r.direction().length();
+        let eps = 1.0e-4 * self.size;
+        for _ in 0..BULB_STEPS {
+            if t > end {
+                return None;
+            }
+            let p = r.at(t);
+            let distance = self.bulb_distance(p);
+            if distance < eps {
+                let normal = Vec3::new(
+                    self.bulb_distance(p + Vec3::new(eps, 0.0, 0.0)) - self.bulb_distance(p - Vec3::new(eps, 0.0, 0.0)),
+                    self.bulb_distance(p + Vec3::new(0.0, eps, 0.0)) - self.bulb_distance(p - Vec3::new(0.0, eps, 0.0)),
+                    self.bulb_distance(p + Vec3::new(0.0, 0.0, eps)) - self.bulb_distance(p - Vec3::new(0.0, 0.0, eps)),
+                );
+                // straight at the center where the estimate is flat
+                let normal = if normal.near_zero() { p - self.center } else { normal }.normalized();
+                let (u, v) = sphere_uv(normal);
+                let mut rec = HitRecord {
+                    p,
+                    normal,
+                    mat: self.mat.clone(),
+                    t,
+                    u,
+                    v,
+                    front_face: false,
+                    // the surface is only found to within eps, and the estimate is low by a
+                    // few times near it, spawned rays start well clear of it
+                    error: 8.0 * eps + rounding_error(self.center.max_abs() + self.size),
+                };
+                rec.set_face_normal(r, normal);
+                return Some(rec);
+            }
+            t += distance / length;
+        }
+        None
+    }
+
+    // how far p is from the bulb at least, in world units
+    fn bulb_distance(&self, p: Point3) -> Float {
+        let scale = self.size / BULB_RADIUS;
+        let c = (p - self.center) / scale;
+        let mut z = c;
+        let (mut dr, mut radius) = (1.0, z.length());
+        for _ in 0..self.depth.max(1) {
+            radius = z.length();
+            if radius > 2.0 || radius == 0.0 {
+                break;
+            }
+            // y is the axis of the bulb, so it stands upright
+            let theta = (z.y() / radius).acos() * BULB_POWER;
+            let phi = z.z().atan2(z.x()) * BULB_POWER;
+            dr = radius.powf(BULB_POWER - 1.0) * BULB_POWER * dr + 1.0;
+            let zr = radius.powf(BULB_POWER);
+            z = zr * Vec3::new(theta.sin() * phi.cos(), theta.cos(), theta.sin() * phi.sin()) + c;
+        }
+        if radius == 0.0 {
+            return 0.0;
+        }
+        scale * 0.5 * radius.ln() * radius / dr
+    }
+}
+
+impl Hit for Fractal {
+    fn hit(&self, r: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord> {
+        match (self.kind, &self.flake) {
+            (_, Some(flake)) => flake.hit(r, t_min, t_max),
+            (FractalKind::Mandelbulb, None) => self.hit_bulb(r, t_min, t_max),
+            _ => self.hit_sponge(r, t_min, t_max),
+        }
+    }
+
+    fn bounding_box(&self, _time0: Float, _time1: Float) -> Option<Aabb> {
+        let r = self.kind.extent(self.size);
+        Some(Aabb::new(self.center - Vec3::new(r, r, r), self.center + Vec3::new(r, r, r)))
+    }
+
+    fn export(&self, out: &mut Exporter) -> Result<()> {
+        let material = out.material(&self.mat)?;
+        out.object(ObjectDesc::Fractal {
+            name: None,
+            shape: self.kind,
+            depth: self.depth,
+            center: self.center.to_array(),
+            size: self.size,
+            material,
+            hidden: false,
+            keyframes: Vec::new(),
+            visible_to: None,
+        });
+        Ok(())
+    }
+}
+
+// whether the sponge keeps the smallest cube `at`: it doesn't when at any level the cube is in
+// the middle of two axes or more
+fn solid(at: [i64; 3], depth: u32) -> bool {
+    let mut at = at;
+    for _ in 0..depth {
+        if at.iter().filter(|&&i| i % 3 == 1).count() >= 2 {
+            return false;
+        }
+        at = at.map(|i| i / 3);
+    }
+    true
+}
+
+// where the ray goes into and out of the sphere, None if it misses it
+fn through_sphere(r: &Ray, center: Point3, radius: Float) -> Option<(Float, Float)> {
+    let oc = r.origin() - center;
+    let a = r.direction().dot(r.direction());
+    let half_b = oc.dot(r.direction());
+    let c = oc.dot(oc) - radius * radius;
+    let discriminant = half_b * half_b - a * c;
+    if discriminant < 0.0 {
+        return None;
+    }
+    let sqrtd = discriminant.sqrt();
+    Some(((-half_b - sqrtd) / a, (-half_b + sqrtd) / a))
+}
+
+// like a sphere's, around the Y axis and from pole to pole
+fn sphere_uv(n: Vec3) -> (Float, Float) {
+    let theta = (-n.y()).acos();
+    let phi = (-n.z()).atan2(n.x()) + consts::PI;
+    (phi / (2.0 * consts::PI), theta / consts::PI)
+}
+
+// A sphere of the sphereflake and the ones around it
+#[derive(Debug)]
+struct Flake {
+    sphere: Sphere,
+    center: Point3,
+    radius: Float,
+    children: Vec<Flake>,
+}
+
+impl Flake {
+    // `up` points away from the sphere this one sits on; six children go around its equator
+    // and three higher up, between them
+    fn new(center: Point3, radius: Float, up: Vec3, depth: u32, m: &Arc<dyn Scatter>) -> Flake {
+        let children = if depth == 0 {
+            Vec::new()
+        } else {
+            let frame = Onb::from_w(up);
+            let around = (0..6).map(|i| (0.0, i as Float * consts::PI / 3.0));
+            let above = (0..3).map(|i| (consts::PI / 3.0, (2.0 * i as Float + 0.5) * consts::PI / 3.0));
+            around.chain(above)
+                .map(|(elevation, azimuth)| {
+                    let direction = frame.local(Vec3::new(
+                        elevation.cos() * azimuth.cos(),
+                        elevation.cos() * azimuth.sin(),
+                        elevation.sin(),
+                    ));
+                    let r = radius / 3.0;
+                    Flake::new(center + (radius + r) * direction, r, direction, depth - 1, m)
+                })
+                .collect()
+        };
+        Flake { sphere: Sphere::new(center, radius, m.clone()), center, radius, children }
+    }
+
+    fn hit(&self, r: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord> {
+        if !self.children.is_empty() {
+            match through_sphere(r, self.center, FractalKind::Sphereflake.extent(self.radius)) {
+                Some((near, far)) if far >= t_min && near <= t_max => {}
+                _ => return None,
+            }
+        }
+        let mut closest = self.sphere.hit(r, t_min, t_max);
+        for child in &self.children {
+            let t = closest.as_ref().map_or(t_max, |rec| rec.t);
+            if let Some(rec) = child.hit(r, t_min, t) {
+                closest = Some(rec);
+            }
+        }
+        closest
+    }
+}
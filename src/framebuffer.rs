@@ -0,0 +1,218 @@
+use crate::colorspace::{Encoding, WorkingSpace};
+use crate::{Color, Float, Vec3};
+
+// The most pixels an image may have, 16384 by 16384. A framebuffer that size already takes
+// several GB, renders and images read in are checked against it before one is made.
+pub const MAX_PIXELS: usize = 16384 * 16384;
+
+// HDR image the renderer accumulates into, along with the auxiliary outputs (AOVs)
+// gathered from the primary ray of each pixel
+#[derive(Clone)]
+pub struct Framebuffer {
+    width: u32,
+    height: u32,
+    pub beauty: Vec<Color>,
+    pub normal: Vec<Vec3>,
+    pub depth: Vec<Float>,
+    // None unless the render was asked for it, see occlusion::Occlusion
+    pub occlusion: Option<Vec<Float>>,
+    // the light of the paths each light path expression picked, see lpe::LightPath
+    pub passes: Vec<Pass>,
+    // render settings embedded into the formats that support it
+    pub metadata: Vec<(String, String)>,
+}
+
+impl Framebuffer {
+    pub fn new(width: u32, height: u32) -> Framebuffer {
+        // in usize, width * height can be more than a u32 holds
+        let size = width as usize * height as usize;
+        Framebuffer {
+            width,
+            height,
+            beauty: vec![Color::default(); size],
+            normal: vec![Vec3::default(); size],
+            depth: vec![Float::INFINITY; size],
+            occlusion: None,
+            passes: Vec::new(),
+            metadata: Vec::new(),
+        }
+    }
+
+    // with a black pass for each name
+    pub fn with_passes(mut self, names: impl IntoIterator<Item = impl Into<String>>) -> Framebuffer {
+        let size = self.beauty.len();
+        self.passes = names.into_iter()
+            .map(|name| Pass { name: name.into(), values: vec![Color::default(); size] })
+            .collect();
+        self
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn metadata_value(&self, key: &str) -> Option<&str> {
+        self.metadata.iter().find(|(k, _)| k == key).map(|(_, v)| v.as_str())
+    }
+
+    // (x, y) are in image space, the row y=0 being the top of the image
+    fn index(&self, x: u32, y: u32) -> usize {
+        y as usize * self.width as usize + x as usize
+    }
+
+    pub fn set(&mut self, x: u32, y: u32, color: Color, normal: Vec3, depth: Float) {
+        let i = self.index(x, y);
+        self.beauty[i] = color;
+        self.normal[i] = normal;
+        self.depth[i] = depth;
+    }
+
+    // the pass is made, open everywhere, when the first pixel is set
+    pub fn set_occlusion(&mut self, x: u32, y: u32, occlusion: Float) {
+        let i = self.index(x, y);
+        let size = self.beauty.len();
+        self.occlusion.get_or_insert_with(|| vec![1.0; size])[i] = occlusion;
+    }
+
+    // one color per pass, in the order they were made
+    pub fn set_passes(&mut self, x: u32, y: u32, colors: &[Color]) {
+        let i = self.index(x, y);
+        for (pass, &color) in self.passes.iter_mut().zip(colors) {
+            pass.values[i] = color;
+        }
+    }
+
+    pub fn pass(&self, name: &str) -> Option<&Pass> {
+        self.passes.iter().find(|p| p.name == name)
+    }
+
+
+    // copy with the beauty and the passes of light scaled by 2^ev, AOVs untouched
+    pub fn exposed(&self, ev: Float) -> Framebuffer {
+        let scale = Float::powf(2.0, ev);
+        Framebuffer {
+            width: self.width,
+            height: self.height,
+            beauty: self.beauty.iter().map(|&c| scale * c).collect(),
+            normal: self.normal.clone(),
+            depth: self.depth.clone(),
+            occlusion: self.occlusion.clone(),
+            passes: self.passes.iter()
+                .map(|p| Pass { name: p.name.clone(), values: p.values.iter().map(|&c| scale * c).collect() })
+                .collect(),
+            metadata: self.metadata.clone(),
+        }
+    }
+
+    // box filtered copy, `factor` times smaller on each side
+    pub fn downsampled(&self, factor: u32) -> Framebuffer {
+        let mut small = Framebuffer::new(self.width / factor, self.height / factor)
+            .with_passes(self.passes.iter().map(|p| p.name.as_str()));
+        let n = (factor * factor) as Float;
+        for y in 0..small.height {
+            for x in 0..small.width {
+                let mut color = Color::default();
+                let mut normal = Vec3::default();
+                let mut depth = 0.0;
+                let mut occlusion = 0.0;
+                let mut passes = vec![Color::default(); self.passes.len()];
+                for j in 0..factor {
+                    for i in 0..factor {
+                        let k = self.index(x*factor + i, y*factor + j);
+                        color += self.beauty[k];
+                        normal += self.normal[k];
+                        depth += self.depth[k];
+                        occlusion += self.occlusion.as_ref().map_or(1.0, |o| o[k]);
+                        for (sum, pass) in passes.iter_mut().zip(&self.passes) {
+                            *sum += pass.values[k];
+                        }
+                    }
+                }
+                small.set(x, y, color / n, normal / n, depth / n);
+                if self.occlusion.is_some() {
+                    small.set_occlusion(x, y, occlusion / n);
+                }
+                let passes: Vec<Color> = passes.into_iter().map(|c| c / n).collect();
+                small.set_passes(x, y, &passes);
+            }
+        }
+        small
+    }
+
+    // keeps the top `height` rows and drops the rest
+    pub fn truncate(&mut self, height: u32) {
+        let size = self.width as usize * height.min(self.height) as usize;
+        self.height = height.min(self.height);
+        self.beauty.truncate(size);
+        self.normal.truncate(size);
+        self.depth.truncate(size);
+        if let Some(occlusion) = &mut self.occlusion {
+            occlusion.truncate(size);
+        }
+        for pass in &mut self.passes {
+            pass.values.truncate(size);
+        }
+    }
+
+    // copies `other` into this image with its top left corner at (x, y), making the passes
+    // this one doesn't have yet
+    pub fn blit(&mut self, other: &Framebuffer, x: u32, y: u32) {
+        let size = self.beauty.len();
+        let passes: Vec<usize> = other.passes.iter()
+            .map(|pass| match self.passes.iter().position(|p| p.name == pass.name) {
+                Some(i) => i,
+                None => {
+                    self.passes.push(Pass { name: pass.name.clone(), values: vec![Color::default(); size] });
+                    self.passes.len() - 1
+                }
+            })
+            .collect();
+        for j in 0..other.height.min(self.height.saturating_sub(y)) {
+            for i in 0..other.width.min(self.width.saturating_sub(x)) {
+                let k = other.index(i, j);
+                self.set(x + i, y + j, other.beauty[k], other.normal[k], other.depth[k]);
+                if let Some(occlusion) = &other.occlusion {
+                    self.set_occlusion(x + i, y + j, occlusion[k]);
+                }
+                let at = self.index(x + i, y + j);
+                for (pass, &to) in other.passes.iter().zip(&passes) {
+                    self.passes[to].values[at] = pass.values[k];
+                }
+            }
+        }
+    }
+
+    // 8-bit RGB of the beauty, row by row from the top, out of whichever working space the
+    // metadata names
+    pub fn to_rgb8(&self, encoding: Encoding) -> Vec<u8> {
+        self.encoded(encoding).flat_map(|c| [0, 1, 2].map(|i| (256.0 * c[i].clamp(0.0, 0.999)) as u8)).collect()
+    }
+
+    pub fn to_rgb16(&self, encoding: Encoding) -> Vec<u16> {
+        self.encoded(encoding).flat_map(|c| [0, 1, 2].map(|i| (65536.0 * c[i].clamp(0.0, 0.99999)) as u16)).collect()
+    }
+
+    // the beauty from 0 to 1 the way the 8 and 16-bit formats store it
+    fn encoded<'a>(&'a self, encoding: Encoding<'a>) -> impl Iterator<Item = Color> + 'a {
+        let space = WorkingSpace::of(&self.metadata);
+        self.beauty.iter().map(move |&c| encoding.encode(space, c))
+    }
+}
+
+// Light sorted out by an expression, a color per pixel like the beauty
+#[derive(Clone)]
+pub struct Pass {
+    pub name: String,
+    pub values: Vec<Color>,
+}
+
+// A finished rectangle of the image, (x, y) being its top left pixel
+pub struct Tile {
+    pub x: u32,
+    pub y: u32,
+    pub data: Framebuffer,
+}
@@ -0,0 +1,93 @@
+// White furnace tests of materials: everything in the scene made of one material, lit by a
+// uniform white sky of radiance 1. A material that neither absorbs nor adds light, albedo 1
+// and no emission, hands on all the light it gets, so however many times it bounces the image
+// comes out at 1 everywhere; where it doesn't the material loses or makes up energy. New
+// materials can be checked here before they go into scenes, with the `furnace` command or
+// with `measure` from a test.
+use std::sync::Arc;
+use crate::camera::Camera;
+use crate::material::Scatter;
+use crate::render::{Renderer, Settings};
+use crate::scene::{Background, Scene, SceneBuilder};
+use crate::scenes::{cornell_camera, cornell_geometry};
+use crate::{Color, Float, Point3, Vec3};
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Furnace {
+    // a lone sphere filling the view, the light bounces off it once or, inside glass, a few times
+    Sphere,
+    // the Cornell box, light included, where it goes round many surfaces before getting out of
+    // the open front
+    Cornell,
+}
+
+impl Furnace {
+    pub const ALL: [Furnace; 2] = [Furnace::Sphere, Furnace::Cornell];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Furnace::Sphere => "sphere",
+            Furnace::Cornell => "cornell",
+        }
+    }
+
+    pub fn scene(self, material: Arc<dyn Scatter>, aspect_ratio: Float) -> Scene {
+        let builder = match self {
+            Furnace::Sphere => {
+                let lookfrom = Point3::new(0.0, 0.0, 3.0);
+                // the corners of a square image just inside the sphere's outline
+                let cam = Camera::new(lookfrom, Point3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0), 28.0,
+                                      aspect_ratio, 0.0, 3.0);
+                SceneBuilder::new()
+                    .add_sphere(Point3::new(0.0, 0.0, 0.0), 1.0).with_material(material)
+                    .set_camera(cam)
+            }
+            Furnace::Cornell => cornell_geometry(SceneBuilder::new(), [&material; 4]).set_camera(cornell_camera(aspect_ratio)),
+        };
+        builder
+            .set_background(Background::Color(Color::new(1.0, 1.0, 1.0)))
+            .build()
+            .expect("The furnace scenes are valid")
+    }
+}
+
+// what the furnace tests render with, a path cut short being lost energy too
+pub fn settings(size: u32, samples_per_pixel: u32, seed: u64) -> Settings {
+    Settings {
+        width: size,
+        height: size,
+        samples_per_pixel,
+        max_depth: 64,
+        seed,
+        ..Settings::default()
+    }
+}
+
+// The mean of a furnace render of `material`, 1 for a material that keeps the energy
+pub fn measure(renderer: &Renderer, furnace: Furnace, material: Arc<dyn Scatter>, settings: &Settings) -> Float {
+    let data = renderer.render(&furnace.scene(material, settings.aspect_ratio()), settings);
+    data.beauty.iter().map(|c| (c.x() + c.y() + c.z()) / 3.0).sum::<Float>() / data.beauty.len() as Float
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::{Dielectric, Lambertian, Metal};
+
+    #[test]
+    fn lossless_materials_keep_their_energy() {
+        let white = Color::new(1.0, 1.0, 1.0);
+        let materials: [(&str, Arc<dyn Scatter>); 3] = [
+            ("lambertian", Arc::new(Lambertian::new(white))),
+            ("mirror", Arc::new(Metal::new(white, 0.0))),
+            ("glass", Arc::new(Dielectric::new(1.5))),
+        ];
+        let (renderer, settings) = (Renderer::new(), settings(16, 16, 1));
+        for (name, material) in materials {
+            for furnace in Furnace::ALL {
+                let mean = measure(&renderer, furnace, material.clone(), &settings);
+                assert!((mean - 1.0).abs() < 0.02, "{} in the {} furnace comes out at {}", name, furnace.name(), mean);
+            }
+        }
+    }
+}
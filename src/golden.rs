@@ -0,0 +1,131 @@
+// Golden-image regression testing: small fixed-seed renders compared against reference images
+// stored in tests/golden. Set UPDATE_GOLDEN=1 when running the tests to (re)write the references
+// after an intentional change to the output.
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use crate::colorspace::Encoding;
+use crate::framebuffer::Framebuffer;
+use crate::output::{self, BitDepth};
+use crate::render::{Renderer, Settings};
+use crate::scenes::default_scene;
+use crate::Float;
+
+// mean squared error (on 0..1 values) above which a render no longer matches its reference
+const TOLERANCE: f64 = 1.0e-4;
+
+pub struct GoldenScene {
+    pub name: &'static str,
+    pub time: Float,
+    pub settings: Settings,
+}
+
+impl GoldenScene {
+    pub fn new(name: &'static str, time: Float) -> GoldenScene {
+        GoldenScene {
+            name,
+            time,
+            settings: Settings {
+                width: 60,
+                height: 40,
+                samples_per_pixel: 16,
+                max_depth: 10,
+                seed: 1,
+                ..Settings::default()
+            },
+        }
+    }
+
+    pub fn reference_path(&self) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden").join(format!("{}.png", self.name))
+    }
+
+    pub fn render(&self) -> Framebuffer {
+        Renderer::new().render(&default_scene(self.time, self.settings.aspect_ratio()), &self.settings)
+    }
+
+    // renders the scene and compares it to the reference, returning the error
+    pub fn check(&self) -> Result<f64, String> {
+        let data = self.render();
+        let path = self.reference_path();
+
+        if std::env::var_os("UPDATE_GOLDEN").is_some() {
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            output::write_png(&path, &data, BitDepth::Eight, Encoding::default()).map_err(|e| e.to_string())?;
+            return Ok(0.0);
+        }
+
+        let (width, height, reference) = read_png(&path)?;
+        if (width, height) != (data.width(), data.height()) {
+            return Err(format!("{}: size {}x{} does not match the reference {}x{}",
+                               self.name, data.width(), data.height(), width, height));
+        }
+        let error = mse(&data.to_rgb8(Encoding::default()), &reference);
+        if error > TOLERANCE {
+            return Err(format!("{}: MSE {:.6} is over the tolerance {}", self.name, error, TOLERANCE));
+        }
+        Ok(error)
+    }
+}
+
+pub fn mse(a: &[u8], b: &[u8]) -> f64 {
+    let sum: f64 = a.iter()
+        .zip(b)
+        .map(|(&x, &y)| ((x as f64 - y as f64) / 255.0).powi(2))
+        .sum();
+    sum / a.len() as f64
+}
+
+// 8-bit RGB PNG as written by output::write_png
+pub fn read_png(path: &Path) -> Result<(u32, u32, Vec<u8>), String> {
+    let file = File::open(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let mut reader = png::Decoder::new(file).read_info().map_err(|e| e.to_string())?;
+    let mut buf = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buf).map_err(|e| e.to_string())?;
+    buf.truncate(info.buffer_size());
+    Ok((info.width, info.height, buf))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render::TileOrder;
+    use crate::sink::MemorySink;
+
+    #[test]
+    fn default_scene() {
+        GoldenScene::new("default", 0.0).check().unwrap();
+    }
+
+    #[test]
+    fn default_scene_orbited() {
+        // camera moved around to the far side of the spheres
+        GoldenScene::new("default_orbited", 10.0).check().unwrap();
+    }
+
+    #[test]
+    fn renders_are_repeatable() {
+        let scene = GoldenScene::new("default", 0.0);
+        assert_eq!(mse(&scene.render().to_rgb8(Encoding::default()), &scene.render().to_rgb8(Encoding::default())), 0.0);
+    }
+
+    #[test]
+    fn renders_dont_depend_on_threads() {
+        // pixels are seeded by where they are, not by which thread or tile they came in
+        let golden = GoldenScene::new("default", 0.0);
+        let (settings, rgb) = (golden.settings, |data: &Framebuffer| data.to_rgb8(Encoding::default()));
+        let scene = || crate::scenes::default_scene(golden.time, settings.aspect_ratio());
+        let one = rgb(&Renderer::new().with_threads(1).render(&scene(), &settings));
+        assert_eq!(mse(&one, &rgb(&Renderer::new().with_threads(4).render(&scene(), &settings))), 0.0);
+
+        let renderer = Renderer::new().with_threads(4).with_tile_order(TileOrder::Spiral);
+        let mut tiled = Framebuffer::new(settings.width, settings.height);
+        for tile in renderer.render_tiles(scene(), settings, 8) {
+            tiled.blit(&tile.data, tile.x, tile.y);
+        }
+        assert_eq!(mse(&one, &rgb(&tiled)), 0.0);
+
+        let mut sink = MemorySink::new();
+        renderer.render_to(&scene(), &settings, 8, &mut sink).unwrap();
+        assert_eq!(mse(&one, &rgb(&sink.into_image().unwrap())), 0.0);
+    }
+}
@@ -0,0 +1,116 @@
+use std::sync::Arc;
+use crate::aabb::Aabb;
+use crate::animation::Motion;
+use crate::error::Result;
+use crate::hit::{rounding_error, HitRecord, HittableList};
+use crate::scene_file::{Exporter, ObjectDesc};
+use crate::transform::Transform;
+use crate::{Float, Hit, Ray, Vec3};
+
+// Objects that move, hide and get copied as one, a car or a chess set. Groups nest, each
+// placed by its own transform inside the one around it. Instances share the objects of the
+// group they're made from and only have a transform of their own, so a hundred chairs cost
+// the memory of one.
+#[derive(Clone, Debug)]
+pub struct Group {
+    name: Option<String>,
+    objects: Arc<HittableList>,
+    // from the group's space to the one it sits in
+    transform: Option<Transform>,
+    // in place of the transform, while the shutter is open
+    motion: Option<Motion>,
+}
+
+impl Group {
+    pub fn new(objects: HittableList) -> Group {
+        Group {
+            name: None,
+            objects: Arc::new(objects),
+            transform: None,
+            motion: None,
+        }
+    }
+
+    pub fn with_name(mut self, name: impl Into<String>) -> Group {
+        self.name = Some(name.into());
+        self
+    }
+
+    pub fn with_transform(mut self, transform: Transform) -> Group {
+        self.transform = Some(transform);
+        self
+    }
+
+    // moving from the transform to another one while the shutter is open, see Motion
+    pub fn with_motion(mut self, motion: Motion) -> Group {
+        self.motion = Some(motion);
+        self
+    }
+
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    pub fn objects(&self) -> &HittableList {
+        &self.objects
+    }
+
+    // The group's objects once more, sharing them, placed by `transform` alone: the group's
+    // own transform isn't part of the copy
+    pub fn instance(&self, transform: Option<Transform>) -> Group {
+        Group {
+            name: None,
+            objects: self.objects.clone(),
+            transform,
+            motion: None,
+        }
+    }
+}
+
+impl Hit for Group {
+    fn hit(&self, r: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord> {
+        let to_world = match (&self.motion, &self.transform) {
+            // a zero scale in between makes the group vanish
+            (Some(motion), _) => &motion.at(r.time())?,
+            (None, Some(transform)) => transform,
+            (None, None) => return self.objects.hit(r, t_min, t_max),
+        };
+        // the direction isn't normalized, so t is the same in both spaces
+        let mut rec = self.objects.hit(&r.transformed(&to_world.inverse()), t_min, t_max)?;
+        rec.p = to_world.point(rec.p);
+        // the object's own error, grown by the largest scale along an axis, plus what the
+        // transform adds
+        let scale = [Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0), Vec3::new(0.0, 0.0, 1.0)]
+            .map(|axis| to_world.vector(axis).length())
+            .into_iter()
+            .fold(0.0, Float::max);
+        rec.error = scale * rec.error + rounding_error(rec.p.max_abs());
+        rec.normal = to_world.normal(rec.normal).normalized();
+        Some(rec)
+    }
+
+    fn bounding_box(&self, time0: Float, time1: Float) -> Option<Aabb> {
+        let local = self.objects.bounding_box(time0, time1)?;
+        Some(match (&self.motion, &self.transform) {
+            (Some(motion), _) => motion.bounds(local),
+            (None, Some(transform)) => local.transformed(transform.matrix()),
+            (None, None) => local,
+        })
+    }
+
+    fn export(&self, out: &mut Exporter) -> Result<()> {
+        let first = out.object_count();
+        self.objects.export(out)?;
+        let objects = out.take_since(first);
+        out.object(ObjectDesc::Group {
+            name: self.name.clone(),
+            objects,
+            transform: self.motion.as_ref().map(Motion::start).or(self.transform.as_ref().map(Transform::matrix)).copied(),
+            end_transform: self.motion.as_ref().map(|m| *m.end()),
+            hidden: false,
+            keyframes: Vec::new(),
+            visible_to: None,
+        });
+        Ok(())
+    }
+}
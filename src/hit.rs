@@ -1,14 +1,30 @@
-use std::rc::Rc;
+use std::fmt::{self, Debug, Formatter};
 use std::sync::Arc;
+use crate::aabb::Aabb;
+use crate::ray::Differentials;
 use crate::{Point3, Ray, Vec3};
+use crate::error::{RendererError, Result};
 use crate::material::Scatter;
+use crate::scene_file::Exporter;
+use crate::Float;
 
 pub struct HitRecord {
     pub p: Point3,
     pub normal: Vec3,
     pub mat: Arc<dyn Scatter>,
-    pub t: f64,
+    pub t: Float,
+    // surface coordinates, for textures
+    pub u: Float,
+    pub v: Float,
     pub front_face: bool,
+    // how far p can be off the true surface in any coordinate, see rounding_error
+    pub error: Float,
+}
+
+// Bound on the rounding error of a point computed from coordinates up to `magnitude`, with
+// room for the handful of operations that go into an intersection
+pub fn rounding_error(magnitude: Float) -> Float {
+    64.0 * Float::EPSILON * magnitude
 }
 
 impl HitRecord {
@@ -20,26 +36,112 @@ impl HitRecord {
             -1.0 * outward_normal
         }
     }
+
+    // A ray leaving the surface at p. Its origin is pushed along the normal, to the side the
+    // ray goes, by more than p's error, so it can neither hit the surface it starts from again
+    // (shadow acne) nor start out on the wrong side of it (light leaks). Unlike a fixed
+    // minimum distance this holds up at any scene scale.
+    pub fn spawn_ray(&self, direction: Vec3) -> Ray {
+        let n = self.normal;
+        let offset = self.error * (n.x().abs() + n.y().abs() + n.z().abs()) * n;
+        let origin = if direction.dot(n) > 0.0 { self.p + offset } else { self.p - offset };
+        Ray::new(origin, direction)
+    }
+
+    // Differentials for a ray bounced off a smooth surface, `scatter` being what turns an
+    // incoming unit direction into the outgoing one. The neighbouring rays are followed onto
+    // the tangent plane and sent off from there like the ray itself; the curvature of the
+    // surface is left out.
+    pub fn scatter_differentials(&self, r_in: &Ray, scatter: impl Fn(Vec3) -> Vec3) -> Option<Differentials> {
+        let d = r_in.differentials()?;
+        let (dx, dy) = r_in.footprint(self.p, self.normal)?;
+        Some(Differentials {
+            rx_origin: self.p + dx,
+            rx_direction: scatter(d.rx_direction.normalized()),
+            ry_origin: self.p + dy,
+            ry_direction: scatter(d.ry_direction.normalized()),
+        })
+    }
+}
+
+pub trait Hit : Send + Sync + Debug {
+    fn hit(&self, r: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord>;
+
+    // A box around everything the object can be hit at by rays sent out between time0 and
+    // time1, for skipping it when a ray misses the box. None for the objects with no end to
+    // them, and for those whose bounds aren't known, which any ray has to be tried against.
+    fn bounding_box(&self, time0: Float, time1: Float) -> Option<Aabb>;
+
+    // adds the object's description to a scene file being written
+    fn export(&self, _out: &mut Exporter) -> Result<()> {
+        Err(RendererError::Unsupported(format!("{} can't be saved to a scene file", std::any::type_name::<Self>())))
+    }
 }
 
-pub trait Hit : Send + Sync {
-    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord>;
+// The objects of a scene, or of a group of them. Objects are shared, so the same one can also
+// sit in a light list or inside an instance, and cloning the list doesn't copy them.
+#[derive(Clone, Default)]
+pub struct HittableList {
+    objects: Vec<Arc<dyn Hit>>,
 }
 
-pub type World = Vec<Box<dyn Hit>>;
+impl HittableList {
+    pub fn new() -> HittableList {
+        HittableList::default()
+    }
+
+    pub fn push(&mut self, object: Arc<dyn Hit>) {
+        self.objects.push(object);
+    }
+
+    pub fn objects(&self) -> &[Arc<dyn Hit>] {
+        &self.objects
+    }
 
-impl Hit for World {
-    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+    // the closest hit and the index of the object it's on
+    pub fn hit_object(&self, r: &Ray, t_min: Float, t_max: Float) -> Option<(usize, HitRecord)> {
         let mut tmp_rec = None;
         let mut closest_so_far = t_max;  // only stors hit record of the closest obj
 
-        for object in self {
+        for (i, object) in self.objects.iter().enumerate() {
             if let Some(rec) = object.hit(r, t_min, closest_so_far) {
                 closest_so_far = rec.t;
-                tmp_rec = Some(rec);
+                tmp_rec = Some((i, rec));
             }
         }
 
         tmp_rec
     }
-}
\ No newline at end of file
+
+    pub fn len(&self) -> usize {
+        self.objects.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.objects.is_empty()
+    }
+}
+
+// just the objects, the scene hash in the render metadata is taken from this
+impl Debug for HittableList {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(&self.objects).finish()
+    }
+}
+
+impl Hit for HittableList {
+    fn hit(&self, r: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord> {
+        self.hit_object(r, t_min, t_max).map(|(_, rec)| rec)
+    }
+
+    // around all the objects, None when there are none or one of them has no box
+    fn bounding_box(&self, time0: Float, time1: Float) -> Option<Aabb> {
+        self.objects.iter()
+            .map(|object| object.bounding_box(time0, time1))
+            .reduce(|a, b| Some(a?.surrounding(b?)))?
+    }
+
+    fn export(&self, out: &mut Exporter) -> Result<()> {
+        self.objects.iter().try_for_each(|object| object.export(out))
+    }
+}
@@ -0,0 +1,305 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read};
+use std::path::Path;
+use crate::colorspace::{Display, WorkingSpace};
+use crate::error::RendererError;
+use crate::framebuffer::{Framebuffer, Pass, MAX_PIXELS};
+use crate::output::Format;
+use crate::{Color, Float};
+
+// Loads an image written by the renderer (or any other tool) back into linear radiance.
+// 8/16-bit formats are assumed to carry the same gamma of 2 the writers apply, or for PNG the
+// display its metadata names.
+pub fn read_image(path: &Path) -> crate::error::Result<Framebuffer> {
+    match Format::from_path(path) {
+        Some(Format::Png) => read_png(path),
+        Some(Format::Exr) => read_exr(path),
+        Some(Format::Hdr) => read_hdr(path),
+        Some(Format::Pfm) => read_pfm(path),
+        Some(Format::Ppm) => read_ppm(path),
+        _ => return Err(RendererError::Unsupported(format!("{}: unsupported image format", path.display()))),
+    }.map_err(|e| RendererError::decode(path, e))
+}
+
+fn from_gamma(width: u32, height: u32, values: impl Iterator<Item = Float>) -> Framebuffer {
+    let values: Vec<Float> = values.collect();
+    let mut data = Framebuffer::new(width, height);
+    for (i, c) in values.chunks(3).enumerate() {
+        data.beauty[i] = Color::new(c[0] * c[0], c[1] * c[1], c[2] * c[2]);
+    }
+    data
+}
+
+// The number of pixels of a header's width and height, checked before anything that size is
+// allocated
+fn check_dimensions(width: u32, height: u32) -> Result<usize, String> {
+    if width == 0 || height == 0 {
+        return Err(format!("the image is {}x{}, it has no pixels", width, height));
+    }
+    match (width as usize).checked_mul(height as usize) {
+        Some(pixels) if pixels <= MAX_PIXELS => Ok(pixels),
+        _ => Err(format!("the image is {}x{}, too big to read", width, height)),
+    }
+}
+
+// Checks that the pixels after a PPM or PFM header are all there and nothing more: three
+// samples of `sample_size` bytes for each of the width times height pixels
+fn check_size(width: u32, height: u32, sample_size: usize, found: usize) -> Result<(), String> {
+    let expected = check_dimensions(width, height)? * 3 * sample_size;
+    if found != expected {
+        return Err(format!("expected {} bytes of pixels, found {}", expected, found));
+    }
+    Ok(())
+}
+
+fn read_png(path: &Path) -> Result<Framebuffer, String> {
+    let mut decoder = png::Decoder::new(File::open(path).map_err(|e| e.to_string())?);
+    // palette and grey images are expanded to RGB
+    decoder.set_transformations(png::Transformations::EXPAND);
+    let mut reader = decoder.read_info().map_err(|e| e.to_string())?;
+    let mut buf = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buf).map_err(|e| e.to_string())?;
+    buf.truncate(info.buffer_size());
+
+    let channels = info.color_type.samples();
+    let samples: Vec<Float> = match info.bit_depth {
+        png::BitDepth::Sixteen => buf.chunks(2).map(|b| u16::from_be_bytes([b[0], b[1]]) as Float / 65535.0).collect(),
+        _ => buf.iter().map(|&b| b as Float / 255.0).collect(),
+    };
+    // grey(+alpha) has one color sample, RGB(A) three, alpha is dropped
+    let rgb: Vec<Float> = samples.chunks(channels).flat_map(|p| if channels < 3 { [p[0], p[0], p[0]] } else { [p[0], p[1], p[2]] }).collect();
+    let mut data = Framebuffer::new(info.width, info.height);
+    // the render settings, see metadata::render_metadata
+    data.metadata = reader.info().uncompressed_latin1_text.iter()
+        .map(|chunk| (chunk.keyword.clone(), chunk.text.clone()))
+        .collect();
+    // the pixels are for the display the metadata names, sRGB with a gamma of 2 without one,
+    // whatever the render was in; they go back into its working space
+    let (display, space) = (Display::of(&data.metadata), WorkingSpace::of(&data.metadata));
+    for (c, v) in data.beauty.iter_mut().zip(rgb.chunks(3)) {
+        *c = space.from_srgb(display.decode(Color::new(v[0], v[1], v[2])));
+    }
+    Ok(data)
+}
+
+fn read_ppm(path: &Path) -> Result<Framebuffer, String> {
+    let mut r = BufReader::new(File::open(path).map_err(|e| e.to_string())?);
+    let header = read_tokens(&mut r, 4)?;
+    if header[0] != "P6" {
+        return Err("only binary (P6) PPM is supported".to_string());
+    }
+    let parse = |s: &str| s.parse::<u32>().map_err(|e| e.to_string());
+    let (width, height, max) = (parse(&header[1])?, parse(&header[2])?, parse(&header[3])?);
+
+    if max == 0 || max > 65535 {
+        return Err(format!("the maximum value {} should be from 1 to 65535", max));
+    }
+
+    let mut buf = Vec::new();
+    r.read_to_end(&mut buf).map_err(|e| e.to_string())?;
+    check_size(width, height, if max > 255 { 2 } else { 1 }, buf.len())?;
+    let samples: Vec<Float> = if max > 255 {
+        buf.chunks(2).map(|b| u16::from_be_bytes([b[0], b[1]]) as Float / max as Float).collect()
+    } else {
+        buf.iter().map(|&b| b as Float / max as Float).collect()
+    };
+    Ok(from_gamma(width, height, samples.into_iter()))
+}
+
+fn read_pfm(path: &Path) -> Result<Framebuffer, String> {
+    let mut r = BufReader::new(File::open(path).map_err(|e| e.to_string())?);
+    let header = read_tokens(&mut r, 4)?;
+    if header[0] != "PF" {
+        return Err("only color (PF) PFM is supported".to_string());
+    }
+    let width: u32 = header[1].parse().map_err(|_| "bad width")?;
+    let height: u32 = header[2].parse().map_err(|_| "bad height")?;
+    let scale: Float = header[3].parse().map_err(|_| "bad scale")?;
+
+    let mut buf = Vec::new();
+    r.read_to_end(&mut buf).map_err(|e| e.to_string())?;
+    check_size(width, height, 4, buf.len())?;
+    let values: Vec<Float> = buf.chunks(4)
+        .map(|b| {
+            let b = [b[0], b[1], b[2], b[3]];
+            (if scale < 0.0 { f32::from_le_bytes(b) } else { f32::from_be_bytes(b) }) as Float
+        })
+        .collect();
+
+    // rows are stored bottom to top
+    let mut data = Framebuffer::new(width, height);
+    for (row, line) in values.chunks(3 * width as usize).rev().enumerate() {
+        for (x, c) in line.chunks(3).enumerate() {
+            data.beauty[row * width as usize + x] = Color::new(c[0], c[1], c[2]);
+        }
+    }
+    Ok(data)
+}
+
+fn read_hdr(path: &Path) -> Result<Framebuffer, String> {
+    let mut r = BufReader::new(File::open(path).map_err(|e| e.to_string())?);
+
+    // header lines up to an empty line, then the resolution line
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if r.read_line(&mut line).map_err(|e| e.to_string())? == 0 {
+            return Err("missing resolution line".to_string());
+        }
+        if line.trim().is_empty() {
+            break;
+        }
+    }
+    line.clear();
+    r.read_line(&mut line).map_err(|e| e.to_string())?;
+    let res: Vec<&str> = line.split_whitespace().collect();
+    if res.len() != 4 || res[0] != "-Y" || res[2] != "+X" {
+        return Err(format!("unsupported orientation '{}'", line.trim()));
+    }
+    let height: u32 = res[1].parse().map_err(|_| "bad height")?;
+    let width: u32 = res[3].parse().map_err(|_| "bad width")?;
+    check_dimensions(width, height)?;
+
+    let mut buf = Vec::new();
+    r.read_to_end(&mut buf).map_err(|e| e.to_string())?;
+    let pixels = decode_rgbe(&buf, width as usize, height as usize)?;
+
+    let mut data = Framebuffer::new(width, height);
+    for (i, p) in pixels.iter().enumerate() {
+        data.beauty[i] = if p[3] == 0 {
+            Color::default()
+        } else {
+            let f = Float::powi(2.0, p[3] as i32 - 136);
+            Color::new(p[0] as Float * f, p[1] as Float * f, p[2] as Float * f)
+        };
+    }
+    Ok(data)
+}
+
+// scanlines are either flat RGBE or the "new" run-length encoding that starts with 2 2 hi lo
+fn decode_rgbe(buf: &[u8], width: usize, height: usize) -> Result<Vec<[u8; 4]>, String> {
+    let truncated = || "truncated pixel data".to_string();
+    // no more than the data could hold, a run being 2 bytes for up to 127 pixels of one of
+    // the 4 channels, so a small file claiming a big image isn't allocated for up front
+    let mut pixels = Vec::with_capacity((width * height).min(buf.len() * 16));
+    let mut pos = 0;
+
+    for _ in 0..height {
+        let rle = (8..32768).contains(&width) && buf.len() >= pos + 4
+            && buf[pos] == 2 && buf[pos+1] == 2 && ((buf[pos+2] as usize) << 8 | buf[pos+3] as usize) == width;
+        if !rle {
+            for _ in 0..width {
+                let p = buf.get(pos..pos+4).ok_or_else(truncated)?;
+                pixels.push([p[0], p[1], p[2], p[3]]);
+                pos += 4;
+            }
+            continue;
+        }
+
+        pos += 4;
+        let mut line = vec![[0u8; 4]; width];
+        for channel in 0..4 {
+            let mut x = 0;
+            while x < width {
+                let count = *buf.get(pos).ok_or_else(truncated)? as usize;
+                pos += 1;
+                if count > 128 {
+                    let value = *buf.get(pos).ok_or_else(truncated)?;
+                    pos += 1;
+                    for _ in 0..count - 128 {
+                        line.get_mut(x).ok_or_else(truncated)?[channel] = value;
+                        x += 1;
+                    }
+                } else {
+                    for _ in 0..count {
+                        line.get_mut(x).ok_or_else(truncated)?[channel] = *buf.get(pos).ok_or_else(truncated)?;
+                        pos += 1;
+                        x += 1;
+                    }
+                }
+            }
+        }
+        pixels.extend(line);
+    }
+    Ok(pixels)
+}
+
+// every channel the renderer writes, in one part or in parts of their own (see
+// output::ExrLayout), those that are missing keep their defaults
+fn read_exr(path: &Path) -> Result<Framebuffer, String> {
+    use exr::prelude::*;
+
+    let image = read().no_deep_data().largest_resolution_level().all_channels().all_layers().all_attributes()
+        .from_file(path)
+        .map_err(|e| e.to_string())?;
+    let layers = image.layer_data;
+    // the beauty's part comes first, with the metadata
+    let first = layers.first().ok_or("the file has no parts")?;
+    let mut data = Framebuffer::new(first.size.width() as u32, first.size.height() as u32);
+    for channel in layers.iter().filter(|l| l.size == first.size).flat_map(|l| &l.channel_data.list) {
+        let values = channel.sample_data.values_as_f32().map(|v| v as Float);
+        match channel.name.to_string().as_str() {
+            "R" => values.zip(&mut data.beauty).for_each(|(v, c)| c[0] = v),
+            "G" => values.zip(&mut data.beauty).for_each(|(v, c)| c[1] = v),
+            "B" => values.zip(&mut data.beauty).for_each(|(v, c)| c[2] = v),
+            "normal.X" => values.zip(&mut data.normal).for_each(|(v, n)| n[0] = v),
+            "normal.Y" => values.zip(&mut data.normal).for_each(|(v, n)| n[1] = v),
+            "normal.Z" => values.zip(&mut data.normal).for_each(|(v, n)| n[2] = v),
+            "depth.Z" => values.zip(&mut data.depth).for_each(|(v, d)| *d = v),
+            "occlusion.Y" => data.occlusion = Some(values.collect()),
+            name => {
+                // the light path passes, "caustics.R" and so on
+                let Some((pass, i)) = name.rsplit_once('.').and_then(|(pass, c)| Some((pass, "RGB".find(c)?))) else {
+                    continue;
+                };
+                if data.pass(pass).is_none() {
+                    data.passes.push(Pass { name: pass.to_string(), values: vec![Color::default(); data.beauty.len()] });
+                }
+                let pass = data.passes.iter_mut().find(|p| p.name == pass).unwrap();
+                values.zip(&mut pass.values).for_each(|(v, c)| c[i] = v);
+            }
+        }
+    }
+    let mut metadata: Vec<(String, String)> = first.attributes.other.iter()
+        .filter_map(|(key, value)| match value {
+            AttributeValue::Text(text) => Some((key.to_string(), text.to_string())),
+            _ => None,
+        })
+        .collect();
+    // ACEScg files from other apps only say so by their primaries
+    let space = image.attributes.chromaticities
+        .and_then(|c| WorkingSpace::from_chromaticities([c.red, c.green, c.blue, c.white].map(|v| (v.0, v.1))));
+    if let Some(entry) = space.and_then(WorkingSpace::metadata).filter(|(key, _)| !metadata.iter().any(|(k, _)| k == key)) {
+        metadata.push(entry);
+    }
+    metadata.sort();
+    data.metadata = metadata;
+    Ok(data)
+}
+
+// whitespace separated header fields of the netpbm formats, skipping # comments
+fn read_tokens(r: &mut impl BufRead, count: usize) -> Result<Vec<String>, String> {
+    let mut tokens = Vec::new();
+    let mut token = String::new();
+    let mut byte = [0u8; 1];
+    let mut comment = false;
+
+    while tokens.len() < count {
+        if r.read(&mut byte).map_err(|e| e.to_string())? == 0 {
+            return Err("truncated header".to_string());
+        }
+        let c = byte[0] as char;
+        if comment {
+            comment = c != '\n';
+        } else if c == '#' {
+            comment = true;
+        } else if c.is_ascii_whitespace() {
+            if !token.is_empty() {
+                tokens.push(std::mem::take(&mut token));
+            }
+        } else {
+            token.push(c);
+        }
+    }
+    Ok(tokens)
+}
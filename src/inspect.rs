@@ -0,0 +1,306 @@
+use std::collections::{BTreeMap, HashMap};
+use std::mem::size_of;
+use std::path::PathBuf;
+use std::sync::Arc;
+use crate::aabb::Aabb;
+use crate::animation::Track;
+use crate::error::{RendererError, Result};
+use crate::instancer;
+use crate::material::Scatter;
+use crate::mesh::Mesh;
+use crate::scene_file::{self, LodDesc, MaskDesc, MaterialDesc, ObjectDesc, SceneFile, TextureDesc};
+use crate::transform::{self, Matrix};
+use crate::vdb::Grid;
+use crate::{Float, Point3};
+
+// What a scene is made of, for finding out why it renders slowly or looks wrong without
+// rendering it. Animated objects are counted where they are at time 0.
+#[derive(Debug, Default)]
+pub struct SceneInfo {
+    pub spheres: usize,
+    pub shells: usize,
+    // single triangles, not the ones in meshes
+    pub triangles: usize,
+    // sponges, sphereflakes and bulbs, each intersected as one
+    pub fractals: usize,
+    // smoke and fire out of grid files, also one each
+    pub volumes: usize,
+    // file and triangle count of each mesh, in scene order
+    pub meshes: Vec<(PathBuf, usize)>,
+    pub animated: usize,
+    // groups drawn and instances of them, scattered copies included, their objects are
+    // counted with the rest
+    pub groups: usize,
+    pub instances: usize,
+    // kind of each material and how many objects use it, by name
+    pub materials: BTreeMap<String, (&'static str, usize)>,
+    // kind of each texture and how many materials use it
+    pub textures: BTreeMap<String, (&'static str, usize)>,
+    // the box around everything, None for an empty scene
+    pub bounds: Option<Aabb>,
+    // the box of each object drawn, a whole mesh being one, and whether it gives off light
+    pub boxes: Vec<ObjectBox>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ObjectBox {
+    pub bounds: Aabb,
+    pub light: bool,
+}
+
+// What a bounding volume hierarchy over the scene would come to, see SceneInfo::bvh_estimate
+#[derive(Debug)]
+pub struct BvhEstimate {
+    pub depth: usize,
+    pub nodes: usize,
+    pub bytes: usize,
+}
+
+impl SceneInfo {
+    pub fn primitives(&self) -> usize {
+        self.spheres + self.shells + self.triangles + self.fractals + self.volumes + self.meshes.iter().map(|(_, n)| n).sum::<usize>()
+    }
+
+    // The renderer tests every ray against every primitive. A balanced binary BVH with one
+    // primitive per leaf would be this deep and this big, a box and two child indices a node.
+    pub fn bvh_estimate(&self) -> BvhEstimate {
+        let n = self.primitives();
+        let nodes = (2 * n).saturating_sub(1);
+        BvhEstimate {
+            // levels, counting the root
+            depth: if n == 0 { 0 } else { n.next_power_of_two().trailing_zeros() as usize + 1 },
+            nodes,
+            bytes: nodes * (6 * size_of::<Float>() + 2 * size_of::<u32>()),
+        }
+    }
+
+    // the points of one object, `light` when it gives off light
+    fn include(&mut self, points: impl IntoIterator<Item = Point3>, light: bool) {
+        let Some(bounds) = Aabb::around(points) else {
+            return;
+        };
+        self.boxes.push(ObjectBox { bounds, light });
+        self.bounds = Some(self.bounds.map_or(bounds, |all| all.surrounding(bounds)));
+    }
+}
+
+// Loads the meshes and textures to count and measure them, but builds nothing else
+pub fn inspect(file: &SceneFile) -> Result<SceneInfo> {
+    let mut info = SceneInfo::default();
+    for (name, desc) in &file.textures {
+        let kind = match desc {
+            TextureDesc::Solid { .. } => "solid",
+            TextureDesc::Checker { .. } => "checker",
+            TextureDesc::Image { .. } => "image",
+            TextureDesc::UvChecker { .. } => "uvchecker",
+            TextureDesc::Wood { .. } => "wood",
+            TextureDesc::Brick { .. } => "brick",
+            TextureDesc::Tiles { .. } => "tiles",
+        };
+        info.textures.insert(name.clone(), (kind, 0));
+    }
+    for (name, desc) in &file.materials {
+        let kind = match desc {
+            MaterialDesc::Lambertian { texture, .. } => {
+                if let Some((_, users)) = texture.as_ref().and_then(|t| info.textures.get_mut(t)) {
+                    *users += 1;
+                }
+                "lambertian"
+            }
+            MaterialDesc::Metal { .. } => "metal",
+            MaterialDesc::Dielectric { .. } => "dielectric",
+            MaterialDesc::Light { .. } => "light",
+            MaterialDesc::ShadowCatcher { .. } => "shadowcatcher",
+            MaterialDesc::Mix { mask, .. } => {
+                if let MaskDesc::Texture { texture, .. } = mask {
+                    if let Some((_, users)) = info.textures.get_mut(texture) {
+                        *users += 1;
+                    }
+                }
+                "mix"
+            }
+        };
+        info.materials.insert(name.clone(), (kind, 0));
+    }
+
+    let mut walk = Walk::new(file)?;
+    walk.objects(&mut info, &file.objects, &transform::IDENTITY)?;
+    Ok(info)
+}
+
+// The box around the objects of each named group in the group's own space, for the impostors
+// of scene_file::LodDesc
+pub fn group_bounds(file: &SceneFile) -> Result<HashMap<String, Aabb>> {
+    let mut walk = Walk::new(file)?;
+    walk.objects(&mut SceneInfo::default(), &file.objects, &transform::IDENTITY)?;
+    Ok(walk.measured.into_iter().map(|(name, bounds)| (name.to_string(), bounds)).collect())
+}
+
+// Goes through the objects and into the groups, counting instances as often as they're drawn
+struct Walk<'a> {
+    file: &'a SceneFile,
+    materials: HashMap<String, Arc<dyn Scatter>>,
+    // the objects of the named groups so far
+    groups: HashMap<&'a str, &'a [ObjectDesc]>,
+    // and the box around them in the group's space
+    measured: HashMap<&'a str, Aabb>,
+}
+
+impl<'a> Walk<'a> {
+    fn new(file: &'a SceneFile) -> Result<Walk<'a>> {
+        let materials = scene_file::build_materials(&file.textures, &file.materials, &file.base_dir,
+                                                    file.render.working_space.unwrap_or_default())?;
+        Ok(Walk { file, materials, groups: HashMap::new(), measured: HashMap::new() })
+    }
+
+    // An instance or scattered copy of the group `of`, or its stand-in for the level of
+    // detail. `m` places it in the list it's in, `still` takes that list to the scene's space
+    // without keyframes, which is where the level is picked, `placement` with them.
+    fn copy(&mut self, info: &mut SceneInfo, of: &'a str, lod: &'a [LodDesc], m: &Matrix, still: &Matrix, placement: &Matrix) -> Result<()> {
+        let origin = transform::point(&transform::mul(still, m), Point3::new(0.0, 0.0, 0.0));
+        let c = self.file.camera.lookfrom;
+        let distance = (origin - Point3::new(c[0], c[1], c[2])).length();
+        let at = transform::mul(placement, m);
+        info.instances += 1;
+        let name = match LodDesc::pick(lod, distance) {
+            None => of,
+            Some(LodDesc { of: Some(other), .. }) => other.as_str(),
+            Some(LodDesc { impostor: Some(material), .. }) => {
+                let Aabb { min: lo, max: hi } = self.measured.get(of).copied()
+                    .ok_or_else(|| RendererError::Scene(format!("group '{}' is empty, there's nothing for an impostor to stand in for", of)))?;
+                let center = 0.5 * (lo + hi);
+                info.spheres += 1;
+                if let Some((_, users)) = info.materials.get_mut(material) {
+                    *users += 1;
+                }
+                let corners = box_corners(center.to_array(), 0.5 * (hi - lo).length());
+                info.include(corners.into_iter().map(|p| transform::point(&at, p)), self.is_light(material));
+                return Ok(());
+            }
+            Some(_) => return Err(RendererError::Scene(format!("a level of detail of '{}' has neither a group nor an impostor", of))),
+        };
+        let objects = self.groups.get(name)
+            .copied()
+            .ok_or_else(|| RendererError::Scene(format!("no group named '{}' before the instance", name)))?;
+        self.objects(info, objects, &at)
+    }
+
+    // `placement` takes the objects from their group's space to the scene's
+    fn objects(&mut self, info: &mut SceneInfo, descs: &'a [ObjectDesc], placement: &Matrix) -> Result<()> {
+        for desc in descs {
+            if desc.hidden() && !matches!(desc, ObjectDesc::Group { .. }) {
+                continue;
+            }
+            let still = *placement;
+            let keyframes = desc.keyframes();
+            let placement = if keyframes.is_empty() {
+                *placement
+            } else {
+                info.animated += 1;
+                transform::mul(placement, &Track::new(keyframes.to_vec()).matrix(0.0))
+            };
+            // the object's own extent, corners of its box for spheres, shells and fractals
+            let (points, material): (Vec<Point3>, _) = match desc {
+                ObjectDesc::Sphere { center, radius, material, .. } => {
+                    info.spheres += 1;
+                    (box_corners(*center, radius.abs()), material)
+                }
+                ObjectDesc::Shell { center, outer, material, .. } => {
+                    info.shells += 1;
+                    (box_corners(*center, *outer), material)
+                }
+                ObjectDesc::Triangle { vertices, material, .. } => {
+                    info.triangles += 1;
+                    (vertices.iter().map(|v| Point3::new(v[0], v[1], v[2])).collect(), material)
+                }
+                ObjectDesc::Fractal { shape, center, size, material, .. } => {
+                    info.fractals += 1;
+                    (box_corners(*center, shape.extent(size.abs())), material)
+                }
+                // there's no material, the box of the grid's active voxels is taken to the scene
+                ObjectDesc::Volume { file: path, grid, temperature, emission, transform, .. } => {
+                    info.volumes += 1;
+                    let grid = Grid::load(&self.file.base_dir.join(path), grid)?;
+                    if let Some((lo, hi)) = grid.bounds() {
+                        let m = transform::mul(&placement, &transform::mul(&transform.unwrap_or(transform::IDENTITY), grid.transform()));
+                        let corners = (0..8).map(|i| {
+                            let pick = |bit: usize, axis: usize| if i & bit == 0 { lo[axis] } else { hi[axis] };
+                            Point3::new(pick(1, 0), pick(2, 1), pick(4, 2))
+                        });
+                        info.include(corners.map(|p| transform::point(&m, p)), temperature.is_some() && *emission > 0.0);
+                    }
+                    continue;
+                }
+                ObjectDesc::Mesh { file: path, material, transform, up, unit_scale, .. } => {
+                    let m = self.materials.get(material)
+                        .ok_or_else(|| RendererError::Scene(format!("unknown material '{}'", material)))?;
+                    let mut mesh = Mesh::load_obj(&self.file.base_dir.join(path), m.clone())?;
+                    if let Some(t) = scene_file::mesh_transform(transform.as_ref(), *up, *unit_scale) {
+                        mesh = mesh.transformed(&t);
+                    }
+                    info.meshes.push((path.clone(), mesh.triangles().len()));
+                    (mesh.triangles().iter().flat_map(|t| t.vertices()).collect(), material)
+                }
+                ObjectDesc::Group { name, objects, transform, hidden, .. } => {
+                    let inside = transform::mul(&placement, &transform.unwrap_or(transform::IDENTITY));
+                    if *hidden {
+                        // only for the groups named inside it, nothing of it is drawn
+                        self.objects(&mut SceneInfo::default(), objects, &inside)?;
+                    } else {
+                        info.groups += 1;
+                        self.objects(info, objects, &inside)?;
+                    }
+                    if let Some(name) = name {
+                        self.groups.insert(name, objects);
+                        let mut local = SceneInfo::default();
+                        self.objects(&mut local, objects, &transform::IDENTITY)?;
+                        if let Some(bounds) = local.bounds {
+                            self.measured.insert(name, bounds);
+                        }
+                    }
+                    continue;
+                }
+                ObjectDesc::Scatter { of, lod, .. } => {
+                    if !self.groups.contains_key(of.as_str()) {
+                        return Err(RendererError::Scene(format!("no group named '{}' before the scatter", of)));
+                    }
+                    for m in instancer::scatter(self.file, descs, desc)? {
+                        self.copy(info, of, lod, &m, &still, &placement)?;
+                    }
+                    continue;
+                }
+                ObjectDesc::Instance { of, transform, lod, .. } => {
+                    self.copy(info, of, lod, &transform.unwrap_or(transform::IDENTITY), &still, &placement)?;
+                    continue;
+                }
+                ObjectDesc::Spheres { .. } => {
+                    for (center, radius, material) in instancer::sphere_field(desc) {
+                        info.spheres += 1;
+                        if let Some((_, users)) = info.materials.get_mut(material) {
+                            *users += 1;
+                        }
+                        let corners = box_corners(center.to_array(), radius);
+                        info.include(corners.into_iter().map(|p| transform::point(&placement, p)), self.is_light(material));
+                    }
+                    continue;
+                }
+            };
+            if let Some((_, users)) = info.materials.get_mut(material) {
+                *users += 1;
+            }
+            info.include(points.into_iter().map(|p| transform::point(&placement, p)), self.is_light(material));
+        }
+        Ok(())
+    }
+
+    fn is_light(&self, material: &str) -> bool {
+        self.materials.get(material).is_some_and(|m| m.is_light())
+    }
+}
+
+fn box_corners(center: [Float; 3], r: Float) -> Vec<Point3> {
+    (0..8).map(|i| {
+        let pick = |bit: usize, c: Float| if i & bit == 0 { c - r } else { c + r };
+        Point3::new(pick(1, center[0]), pick(2, center[1]), pick(4, center[2]))
+    }).collect()
+}
@@ -0,0 +1,270 @@
+use std::sync::Arc;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use crate::colorspace::WorkingSpace;
+use crate::error::{RendererError, Result};
+use crate::material::Lambertian;
+use crate::mesh::Mesh;
+use crate::onb::Onb;
+use crate::scene_file::{self, ObjectDesc, SceneFile};
+use crate::sphere::Sphere;
+use crate::texture::Texture;
+use crate::transform::{self, Matrix};
+use crate::triangle::Triangle;
+use crate::{float::consts, Color, Float, Point3, Vec3};
+
+// Copies of a group spread at random over a surface or through a box, for fields of rocks,
+// clumps of grass or a forest, without listing every one of them. The copies share the
+// group's objects like instances do. Where they go only depends on the seed, so the same
+// scene file always gives the same field.
+#[derive(Copy, Clone, Debug)]
+pub struct Instancer {
+    pub count: u32,
+    pub seed: u64,
+    // smallest and largest size of the copies, picked between them for each
+    pub scale: (Float, Float),
+    // each copy is turned around its up axis by up to this many degrees, 360 for any way
+    pub rotation: Float,
+    // on surfaces, up is along the normal instead of the world's y
+    pub align: bool,
+}
+
+// where the copies go
+#[derive(Debug)]
+pub enum Domain {
+    Surface(Vec<Triangle>),
+    Sphere(Point3, Float),
+    Volume(Point3, Point3),
+}
+
+// a spot picked in the domain: the point, the normal and the texture coordinates there
+struct Spot {
+    p: Point3,
+    normal: Vec3,
+    u: Float,
+    v: Float,
+}
+
+// give up on the density after this many misses per copy, the texture is close to black
+const TRIES: u32 = 100;
+
+impl Instancer {
+    // Where each copy goes, from its own space to the group's it's in. With a density texture
+    // spots are kept in proportion to how bright it is there, the mean of its channels; a box
+    // looks it up at the point, a surface at its texture coordinates.
+    pub fn placements(&self, domain: &Domain, density: Option<&dyn Texture>) -> Vec<Matrix> {
+        let mut rng = StdRng::seed_from_u64(self.seed);
+        let areas = match domain {
+            Domain::Surface(triangles) => triangles.iter()
+                .scan(0.0, |total, tri| {
+                    let [v0, v1, v2] = tri.vertices();
+                    *total += 0.5 * (v1 - v0).cross(v2 - v0).length();
+                    Some(*total)
+                })
+                .collect(),
+            _ => Vec::new(),
+        };
+        let mut placements = Vec::with_capacity(self.count as usize);
+        let mut tries = 0;
+        while placements.len() < self.count as usize && tries < self.count * TRIES {
+            tries += 1;
+            let Some(spot) = pick(domain, &areas, &mut rng) else { break };
+            if let Some(texture) = density {
+                let c = texture.value(spot.u, spot.v, spot.p);
+                if rng.gen::<Float>() >= (c[0] + c[1] + c[2]) / 3.0 {
+                    continue;
+                }
+            }
+            let angle = self.rotation * rng.gen::<Float>();
+            let (lo, hi) = self.scale;
+            let size = lo + (hi - lo) * rng.gen::<Float>();
+            let mut m = transform::translate(spot.p);
+            if self.align && !matches!(domain, Domain::Volume(..)) {
+                m = transform::mul(&m, &standing(spot.normal));
+            }
+            let m = transform::mul(&m, &transform::rotate(angle, Vec3::new(0.0, 1.0, 0.0)));
+            placements.push(transform::mul(&m, &transform::scale(Vec3::new(size, size, size))));
+        }
+        if placements.len() < self.count as usize {
+            log::warn!("only {} of {} copies placed, the density is too low almost everywhere", placements.len(), self.count);
+        }
+        placements
+    }
+}
+
+// Spheres strewn at random through a box, for benchmark scenes of any size. Where they go,
+// how big they are and what they're made of all come from the seed, so the same settings
+// always give the same spheres.
+#[derive(Clone, Debug)]
+pub struct SphereField {
+    pub count: u32,
+    pub seed: u64,
+    // smallest and largest radius, picked evenly between them
+    pub radius: (Float, Float),
+    // how likely each material is, by its place in the list
+    pub weights: Vec<Float>,
+    // Some((n, spread)) gathers the spheres around n random points in the box, normally
+    // distributed around each with a deviation of `spread` along each axis
+    pub clusters: Option<(u32, Float)>,
+    // sitting on the bottom of the box rather than anywhere in it
+    pub resting: bool,
+}
+
+impl SphereField {
+    // The center, radius and material of each sphere, the material by its place in the
+    // weights. Centers are kept inside the box, clusters pile up against its sides.
+    pub fn spheres(&self, lo: Point3, hi: Point3) -> Vec<(Point3, Float, usize)> {
+        let mut rng = StdRng::seed_from_u64(self.seed);
+        let anywhere = |rng: &mut StdRng| lo + Vec3::new(rng.gen(), rng.gen(), rng.gen()) * (hi - lo);
+        let centers: Vec<Point3> = match self.clusters {
+            Some((n, _)) => (0..n).map(|_| anywhere(&mut rng)).collect(),
+            None => Vec::new(),
+        };
+        let total: Float = self.weights.iter().sum();
+        let (small, big) = self.radius;
+        (0..self.count)
+            .map(|_| {
+                let p = match self.clusters {
+                    Some((_, spread)) if !centers.is_empty() => {
+                        let around = centers[rng.gen_range(0..centers.len())];
+                        let p = around + spread * Vec3::new(gaussian(&mut rng), gaussian(&mut rng), gaussian(&mut rng));
+                        Point3::new(p.x().clamp(lo.x(), hi.x()), p.y().clamp(lo.y(), hi.y()), p.z().clamp(lo.z(), hi.z()))
+                    }
+                    _ => anywhere(&mut rng),
+                };
+                let radius = small + (big - small) * rng.gen::<Float>();
+                let p = if self.resting { Point3::new(p.x(), lo.y() + radius, p.z()) } else { p };
+                let mut at = total * rng.gen::<Float>();
+                let material = self.weights.iter()
+                    .position(|w| {
+                        at -= w;
+                        at < 0.0
+                    })
+                    .unwrap_or(self.weights.len().saturating_sub(1));
+                (p, radius, material)
+            })
+            .collect()
+    }
+}
+
+// Where the copies of a scatter in a scene file go, `descs` being the list of objects it's in
+pub fn scatter(file: &SceneFile, descs: &[ObjectDesc], desc: &ObjectDesc) -> Result<Vec<Matrix>> {
+    let ObjectDesc::Scatter { on, inside, count, seed, scale, rotation, align, density, .. } = desc else {
+        return Ok(Vec::new());
+    };
+    let domain = domain(file, descs, on.as_deref(), *inside)?;
+    let density = density.as_ref()
+        .map(|name| {
+            let desc = file.textures.get(name)
+                .ok_or_else(|| RendererError::Scene(format!("unknown texture '{}'", name)))?;
+            // a density rather than a color, the values are taken as they are
+            scene_file::build_texture(desc, &file.base_dir, WorkingSpace::Srgb)
+        })
+        .transpose()?;
+    let instancer = Instancer { count: *count, seed: *seed, scale: (scale[0], scale[1]), rotation: *rotation, align: *align };
+    Ok(instancer.placements(&domain, density.as_deref()))
+}
+
+// The spheres of a spheres node in a scene file, each with the name of its material
+pub fn sphere_field(desc: &ObjectDesc) -> Vec<(Point3, Float, &str)> {
+    let ObjectDesc::Spheres { count, seed, inside: [a, b], radius, materials, clusters, spread, resting, .. } = desc else {
+        return Vec::new();
+    };
+    // in order of their names, for the same spheres whatever order the file has them in
+    let mut materials: Vec<(&String, &Float)> = materials.iter().collect();
+    materials.sort_by_key(|&(name, _)| name);
+    let field = SphereField {
+        count: *count,
+        seed: *seed,
+        radius: (radius[0], radius[1]),
+        weights: materials.iter().map(|&(_, w)| *w).collect(),
+        clusters: clusters.map(|n| (n, *spread)),
+        resting: *resting,
+    };
+    let lo = Point3::new(a[0].min(b[0]), a[1].min(b[1]), a[2].min(b[2]));
+    let hi = Point3::new(a[0].max(b[0]), a[1].max(b[1]), a[2].max(b[2]));
+    field.spheres(lo, hi).into_iter()
+        .filter_map(|(p, r, i)| Some((p, r, materials.get(i)?.0.as_str())))
+        .collect()
+}
+
+// The domain named in a scatter: the object called `on` in `descs`, or the box between the
+// corners of `inside`
+fn domain(file: &SceneFile, descs: &[ObjectDesc], on: Option<&str>, inside: Option<[[Float; 3]; 2]>) -> Result<Domain> {
+    let point = |p: [Float; 3]| Point3::new(p[0], p[1], p[2]);
+    let name = match (on, inside) {
+        (Some(name), None) => name,
+        (None, Some([a, b])) => {
+            let (a, b) = (point(a), point(b));
+            let lo = Point3::new(a.x().min(b.x()), a.y().min(b.y()), a.z().min(b.z()));
+            let hi = Point3::new(a.x().max(b.x()), a.y().max(b.y()), a.z().max(b.z()));
+            return Ok(Domain::Volume(lo, hi));
+        }
+        _ => return Err(RendererError::Scene("a scatter goes either on an object or inside a box".to_string())),
+    };
+    let desc = descs.iter().find(|o| o.name() == Some(name))
+        .ok_or_else(|| RendererError::Scene(format!("no object named '{}' to scatter on", name)))?;
+    // the material doesn't matter, only where the surface is
+    let material = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+    match desc {
+        ObjectDesc::Sphere { center, radius, .. } => Ok(Domain::Sphere(point(*center), radius.abs())),
+        ObjectDesc::Shell { center, outer, .. } => Ok(Domain::Sphere(point(*center), *outer)),
+        ObjectDesc::Triangle { vertices: v, uv, .. } => {
+            let uv = uv.unwrap_or([[0.0, 0.0], [1.0, 0.0], [0.0, 1.0]]).map(|[u, v]| (u, v));
+            Ok(Domain::Surface(vec![Triangle::new(point(v[0]), point(v[1]), point(v[2]), material).with_uv(uv)]))
+        }
+        ObjectDesc::Mesh { file: path, transform, up, unit_scale, .. } => {
+            let mut mesh = Mesh::load_obj(&file.base_dir.join(path), material)?;
+            if let Some(t) = scene_file::mesh_transform(transform.as_ref(), *up, *unit_scale) {
+                mesh = mesh.transformed(&t);
+            }
+            Ok(Domain::Surface(mesh.into_triangles()))
+        }
+        _ => Err(RendererError::Unsupported(format!("can't scatter on '{}', only on spheres, shells, triangles and meshes", name))),
+    }
+}
+
+fn pick(domain: &Domain, areas: &[Float], rng: &mut StdRng) -> Option<Spot> {
+    match domain {
+        Domain::Surface(triangles) => {
+            // by area, so the copies spread evenly however the surface is split into triangles
+            let total = *areas.last()?;
+            let at = rng.gen::<Float>() * total;
+            let i = areas.partition_point(|&a| a < at).min(triangles.len() - 1);
+            let (r1, r2) = (rng.gen::<Float>().sqrt(), rng.gen::<Float>());
+            let rec = triangles[i].surface(r1 * (1.0 - r2), r1 * r2);
+            Some(Spot { p: rec.p, normal: rec.normal, u: rec.u, v: rec.v })
+        }
+        Domain::Sphere(center, radius) => {
+            let y = 2.0 * rng.gen::<Float>() - 1.0;
+            let phi = 2.0 * consts::PI * rng.gen::<Float>();
+            let ring = (1.0 - y * y).max(0.0).sqrt();
+            let normal = Vec3::new(ring * phi.cos(), y, ring * phi.sin());
+            let (u, v) = Sphere::uv(normal);
+            Some(Spot { p: *center + *radius * normal, normal, u, v })
+        }
+        Domain::Volume(lo, hi) => {
+            let t = Vec3::new(rng.gen(), rng.gen(), rng.gen());
+            Some(Spot { p: *lo + t * (*hi - *lo), normal: Vec3::new(0.0, 1.0, 0.0), u: 0.0, v: 0.0 })
+        }
+    }
+}
+
+// turns y to `up`, x and z anywhere around it
+fn standing(up: Vec3) -> Matrix {
+    let frame = Onb::from_w(up);
+    let (x, y, z) = (frame.v(), frame.w(), frame.u());
+    [
+        [x.x(), y.x(), z.x(), 0.0],
+        [x.y(), y.y(), z.y(), 0.0],
+        [x.z(), y.z(), z.z(), 0.0],
+        [0.0, 0.0, 0.0, 1.0],
+    ]
+}
+
+// a normally distributed number, mean 0 and deviation 1, by the Box-Muller transform
+fn gaussian(rng: &mut StdRng) -> Float {
+    // in (0, 1], the log has to be finite
+    let u = 1.0 - rng.gen::<Float>();
+    (-2.0 * u.ln()).sqrt() * (2.0 * consts::PI * rng.gen::<Float>()).cos()
+}
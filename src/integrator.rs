@@ -0,0 +1,102 @@
+use crate::hit::Hit;
+use crate::render::ray_color;
+use crate::scene::Scene;
+use crate::{stats, Color, Float, Ray};
+
+// How the color of a camera ray is worked out. Path is the real thing; the others look at the
+// first surface hit only, one sample through the middle of each pixel, for finding problems
+// with the geometry or imported meshes in a fraction of the time. Rays that miss are black
+// except in the depth map.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum Integrator {
+    #[default]
+    Path,
+    // the shading normal, 0.5 * (n + 1) so every direction has its own color
+    Normal,
+    // the world position as is, write EXR to get at the actual values
+    Position,
+    // texture coordinates in red and green
+    Uv,
+    // green where the ray sees the outside of a surface, red where it's inside
+    Facing,
+    // distance to the surface in gray, for depth of field in compositing or checking focus
+    Depth(DepthRange),
+}
+
+// How distances come out of the depth integrator: from black at `near` to white at `far` and
+// beyond, rays that miss everything counting as infinitely far. With `raw` they are written as
+// they are instead, infinity for misses, to be read back from EXR or PFM files.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct DepthRange {
+    // 0 when not given
+    pub near: Option<Float>,
+    // twice the camera's focus distance when not given, so the focus plane lands half way
+    pub far: Option<Float>,
+    pub raw: bool,
+}
+
+impl DepthRange {
+    // the distance as a gray level for this camera
+    pub fn map(&self, distance: Float, focus_dist: Float) -> Float {
+        if self.raw {
+            return distance;
+        }
+        let near = self.near.unwrap_or(0.0);
+        let far = self.far.unwrap_or(2.0 * focus_dist);
+        ((distance - near) / (far - near)).clamp(0.0, 1.0)
+    }
+}
+
+impl Integrator {
+    pub fn name(self) -> &'static str {
+        match self {
+            Integrator::Path => "path",
+            Integrator::Normal => "normal",
+            Integrator::Position => "position",
+            Integrator::Uv => "uv",
+            Integrator::Facing => "facing",
+            Integrator::Depth(_) => "depth",
+        }
+    }
+
+    pub fn color(self, r: &Ray, scene: &Scene, max_depth: u64) -> Color {
+        if self == Integrator::Path {
+            return ray_color(r, scene, max_depth);
+        }
+        stats::count(&stats::RAYS);
+        let Some(rec) = scene.world.hit(r, 0.0, Float::INFINITY) else {
+            return match self {
+                Integrator::Depth(range) => {
+                    let d = range.map(Float::INFINITY, scene.camera.focus_dist());
+                    Color::new(d, d, d)
+                }
+                _ => Color::new(0.0, 0.0, 0.0),
+            };
+        };
+        match self {
+            Integrator::Normal => 0.5 * (rec.normal + Color::new(1.0, 1.0, 1.0)),
+            Integrator::Position => rec.p,
+            Integrator::Uv => Color::new(rec.u, rec.v, 0.0),
+            Integrator::Depth(range) => {
+                // camera rays aren't normalized
+                let d = range.map(rec.t * r.direction().length(), scene.camera.focus_dist());
+                Color::new(d, d, d)
+            }
+            _ if rec.front_face => Color::new(0.0, 1.0, 0.0),
+            _ => Color::new(1.0, 0.0, 0.0),
+        }
+    }
+}
+
+// By name on the command line, the depth range is set separately
+#[cfg(feature = "native")]
+impl clap::ValueEnum for Integrator {
+    fn value_variants<'a>() -> &'a [Integrator] {
+        const DEPTH: Integrator = Integrator::Depth(DepthRange { near: None, far: None, raw: false });
+        &[Integrator::Path, Integrator::Normal, Integrator::Position, Integrator::Uv, Integrator::Facing, DEPTH]
+    }
+
+    fn to_possible_value(&self) -> Option<clap::builder::PossibleValue> {
+        Some(clap::builder::PossibleValue::new(self.name()))
+    }
+}
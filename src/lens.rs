@@ -0,0 +1,316 @@
+use std::path::{Path, PathBuf};
+use crate::error::{RendererError, Result};
+use crate::{float::consts, Float, Point3, Vec3};
+
+// A real camera lens: the spherical surfaces of its glass elements and the aperture stop, as
+// in the lens prescriptions of patents and optics books. Camera rays start on the film and are
+// refracted through every surface out into the scene, so the image gets the lens's own
+// distortion, its vignetting where the barrel cuts rays off, and bokeh shaped by the stop.
+//
+// The prescription is a text file with one surface per line, from the front of the lens to the
+// back, each as four numbers in millimeters:
+//
+//     radius  thickness  ior  aperture
+//
+// the radius of curvature (positive when the surface bulges toward the scene, 0 for the
+// aperture stop), the distance to the next surface or the film, the index of refraction of
+// what's behind the surface (0 or 1 for air) and the diameter of the surface. Everything after
+// a # is a comment.
+#[derive(Clone, Debug)]
+pub struct Prescription {
+    path: PathBuf,
+    surfaces: Vec<Surface>,
+}
+
+#[derive(Copy, Clone, Debug)]
+struct Surface {
+    radius: Float,
+    thickness: Float,
+    ior: Float,
+    // half the diameter
+    aperture: Float,
+}
+
+impl Prescription {
+    pub fn load(path: &Path) -> Result<Prescription> {
+        let text = std::fs::read_to_string(path).map_err(|e| RendererError::io(path, e))?;
+        let mut surfaces = Vec::new();
+        for (n, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let values: Vec<Float> = line.split_whitespace()
+                .map(|v| v.parse::<Float>())
+                .collect::<std::result::Result<_, _>>()
+                .map_err(|e| RendererError::parse(path, format!("line {}: {}", n + 1, e)))?;
+            let [radius, thickness, ior, diameter] = values[..] else {
+                return Err(RendererError::parse(path, format!("line {}: a surface has 4 numbers, radius, thickness, ior and aperture", n + 1)));
+            };
+            if values.iter().any(|v| !v.is_finite()) || thickness < 0.0 || ior < 0.0 || diameter <= 0.0 {
+                return Err(RendererError::parse(path, format!("line {}: {} can't be a lens surface", n + 1, line)));
+            }
+            surfaces.push(Surface { radius, thickness, ior: if ior == 0.0 { 1.0 } else { ior }, aperture: diameter / 2.0 });
+        }
+        if surfaces.is_empty() {
+            return Err(RendererError::parse(path, "the lens has no surfaces"));
+        }
+        Ok(Prescription { path: path.to_path_buf(), surfaces })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    // the same lens with the aperture stop opened or closed to `diameter` millimeters
+    pub fn with_stop(mut self, diameter: Float) -> Result<Prescription> {
+        let stop = self.surfaces.iter_mut().find(|s| s.radius == 0.0)
+            .ok_or_else(|| RendererError::Scene(format!("{} has no aperture stop to set", self.path.display())))?;
+        stop.aperture = diameter / 2.0;
+        Ok(self)
+    }
+
+    pub fn stop(&self) -> Option<Float> {
+        self.surfaces.iter().find(|s| s.radius == 0.0).map(|s| 2.0 * s.aperture)
+    }
+}
+
+// A prescription put in front of the film: moved so what's `focus` away from the film is
+// sharp, with a film big enough for the field of view
+#[derive(Clone, Debug)]
+pub struct Lens {
+    prescription: Prescription,
+    // millimeters per scene unit
+    scale: Float,
+    // distance of each surface's vertex from the film, front first
+    z: Vec<Float>,
+    // film size in millimeters
+    film: (Float, Float),
+    // The exit pupil: where on the rear surface rays from the film get through the lens, as
+    // bounds (x0, x1, y0, y1) for film points on the x axis from the center out to the corner,
+    // None where nothing does. Rays are only sent into them, which matters for small stops.
+    pupils: Vec<Option<(Float, Float, Float, Float)>>,
+    // what a film point gathers through the rear surface, measured at the center of the film,
+    // so the image is as bright there as with a thin lens
+    center: Float,
+}
+
+// paths from the film to the rear surface tried for the brightness at the center
+const CENTER_SAMPLES: usize = 64;
+// the distances from the center of the film the exit pupil is found for, and the points tried
+// across the rear surface for each
+const PUPILS: usize = 64;
+const PUPIL_GRID: usize = 32;
+
+impl Lens {
+    // `focus` in scene units, `scale` millimeters to one of them; `vfov` in degrees across the
+    // film's height, for objects far away
+    pub fn new(prescription: Prescription, scale: Float, focus: Float, vfov: Float, aspect_ratio: Float) -> Result<Lens> {
+        let mut lens = Lens { z: Vec::new(), prescription, scale, film: (0.0, 0.0), pupils: Vec::new(), center: 1.0 };
+        lens.place(lens.prescription.surfaces.last().map_or(0.0, |s| s.thickness));
+        let path = lens.prescription.path.display().to_string();
+        let no_focus = || RendererError::Scene(format!("{} doesn't focus light, it can't be a camera lens", path));
+
+        // the image side principal plane and focal length from a ray coming in parallel to
+        // the axis, the object side principal plane from one going out parallel from the film
+        let height = 0.1 * lens.prescription.surfaces[0].aperture;
+        let front = lens.z[0] + 1.0;
+        let inside = lens.trace_in(Point3::new(height, 0.0, front), Vec3::new(0.0, 0.0, -1.0)).ok_or_else(no_focus)?;
+        let (image_principal, image_focal) = cardinal(height, inside).ok_or_else(no_focus)?;
+        let rear = lens.z[lens.z.len() - 1] - 1.0;
+        let outside = lens.trace_out(Point3::new(height, 0.0, rear), Vec3::new(0.0, 0.0, 1.0)).ok_or_else(no_focus)?;
+        let (object_principal, _) = cardinal(height, outside).ok_or_else(no_focus)?;
+        let focal_length = image_principal - image_focal;
+        if focal_length <= 0.0 {
+            return Err(no_focus());
+        }
+
+        // thin lens equation between the principal planes, then the film moved to where the
+        // image is; that moves the object by as much, which is nothing next to `focus`
+        let object = focus * scale - object_principal;
+        if object <= focal_length {
+            return Err(RendererError::Scene(format!("the lens can't focus as close as {} mm, its focal length is {:.1} mm", focus * scale, focal_length)));
+        }
+        let image = 1.0 / (1.0 / focal_length - 1.0 / object);
+        let film = image_principal - image;
+        let back = lens.z[lens.z.len() - 1] - film;
+        lens.place(back);
+
+        let height = 2.0 * focal_length * (vfov.to_radians() / 2.0).tan();
+        lens.film = (aspect_ratio * height, height);
+        lens.pupils = (0..PUPILS).map(|i| lens.pupil(i)).collect();
+        lens.center = lens.gathered(Point3::new(0.0, 0.0, 0.0)).max(Float::EPSILON);
+        Ok(lens)
+    }
+
+    pub fn prescription(&self) -> &Prescription {
+        &self.prescription
+    }
+
+    pub fn scale(&self) -> Float {
+        self.scale
+    }
+
+    // A ray for the film point at (u, v) of the image, (0, 0) bottom left, going through
+    // `rear`, a point in the unit disk standing for the exit pupil. The ray is in the lens's
+    // space, z toward the scene, and comes with the share of light it brings to the film; None
+    // when the lens blocks it.
+    pub fn ray(&self, u: Float, v: Float, rear: Vec3) -> Option<(Point3, Vec3, Float)> {
+        // the lens turns the image upside down
+        let film = Point3::new(-(u - 0.5) * self.film.0, -(v - 0.5) * self.film.1, 0.0);
+        let (origin, direction, weight) = self.through(film, rear)?;
+        Some((origin, direction, weight / self.center))
+    }
+
+    // the film sits at z = 0 and the rear surface `back` in front of it
+    fn place(&mut self, back: Float) {
+        let surfaces = &self.prescription.surfaces;
+        let mut z = vec![0.0; surfaces.len()];
+        let mut at = back;
+        for i in (0..surfaces.len()).rev() {
+            z[i] = at;
+            if i > 0 {
+                at += surfaces[i - 1].thickness;
+            }
+        }
+        self.z = z;
+    }
+
+    // half the film's diagonal
+    fn corner(&self) -> Float {
+        0.5 * self.film.0.hypot(self.film.1)
+    }
+
+    // The bounds of the exit pupil for film points between the i-th and the next of PUPILS
+    // distances from the center, from which of a grid of points on the rear surface rays get
+    // through. They're grown by a cell of the grid for what falls between its points.
+    fn pupil(&self, i: usize) -> Option<(Float, Float, Float, Float)> {
+        let last = self.prescription.surfaces.len() - 1;
+        let radius = self.prescription.surfaces[last].aperture;
+        let cell = 2.0 * radius / PUPIL_GRID as Float;
+        let mut bounds: Option<(Float, Float, Float, Float)> = None;
+        for end in [i, i + 1] {
+            let film = Point3::new(self.corner() * end as Float / PUPILS as Float, 0.0, 0.0);
+            for (gx, gy) in (0..=PUPIL_GRID).flat_map(|gx| (0..=PUPIL_GRID).map(move |gy| (gx, gy))) {
+                let (x, y) = (-radius + gx as Float * cell, -radius + gy as Float * cell);
+                if self.trace_out(film, Point3::new(x, y, self.z[last]) - film).is_none() {
+                    continue;
+                }
+                bounds = Some(match bounds {
+                    Some((x0, x1, y0, y1)) => (x0.min(x), x1.max(x), y0.min(y), y1.max(y)),
+                    None => (x, x, y, y),
+                });
+            }
+        }
+        bounds.map(|(x0, x1, y0, y1)| (x0 - cell, x1 + cell, y0 - cell, y1 + cell))
+    }
+
+    fn through(&self, film: Point3, rear: Vec3) -> Option<(Point3, Vec3, Float)> {
+        // the pupil is found on the x axis and turned to where the film point is
+        let r = film.x().hypot(film.y());
+        let bin = ((r / self.corner() * PUPILS as Float) as usize).min(PUPILS - 1);
+        let (x0, x1, y0, y1) = self.pupils.get(bin).copied().flatten()?;
+        // the square of the distance from the middle of the unit disk and the angle are each
+        // even between 0 and 1, which spreads `rear` evenly over the bounds
+        let (sx, sy) = (rear.x() * rear.x() + rear.y() * rear.y(), 0.5 + rear.y().atan2(rear.x()) / (2.0 * consts::PI));
+        let (x, y) = (x0 + (x1 - x0) * sx, y0 + (y1 - y0) * sy);
+        let (sin, cos) = if r > 0.0 { (film.y() / r, film.x() / r) } else { (0.0, 1.0) };
+        let last = self.z.len() - 1;
+        let target = Point3::new(x * cos - y * sin, x * sin + y * cos, self.z[last]);
+        let d = target - film;
+        let (origin, direction) = self.trace_out(film, d)?;
+        // irradiance on the film from the pupil: its area, cos^4 and the distance
+        let cos = d.z() / d.length();
+        let area = (x1 - x0) * (y1 - y0);
+        Some((origin, direction, area * cos.powi(4) / (self.z[last] * self.z[last])))
+    }
+
+    // the average of `through` over the rear surface, without randomness
+    fn gathered(&self, film: Point3) -> Float {
+        let n = (CENTER_SAMPLES as Float).sqrt() as usize;
+        let mut sum = 0.0;
+        for i in 0..n {
+            for j in 0..n {
+                // rings of equal area, so every point stands for as much of the disk
+                let r = ((i as Float + 0.5) / n as Float).sqrt();
+                let phi = 2.0 * consts::PI * (j as Float + 0.5) / n as Float;
+                let rear = Vec3::new(r * phi.cos(), r * phi.sin(), 0.0);
+                sum += self.through(film, rear).map_or(0.0, |(_, _, w)| w);
+            }
+        }
+        sum / (n * n) as Float
+    }
+
+    // from the film out into the scene
+    fn trace_out(&self, origin: Point3, direction: Vec3) -> Option<(Point3, Vec3)> {
+        let surfaces = &self.prescription.surfaces;
+        let (mut o, mut d) = (origin, direction.normalized());
+        for i in (0..surfaces.len()).rev() {
+            let outside = if i > 0 { surfaces[i - 1].ior } else { 1.0 };
+            (o, d) = self.cross(i, o, d, surfaces[i].ior, outside)?;
+        }
+        Some((o, d))
+    }
+
+    // from the scene in to the film
+    fn trace_in(&self, origin: Point3, direction: Vec3) -> Option<(Point3, Vec3)> {
+        let surfaces = &self.prescription.surfaces;
+        let (mut o, mut d) = (origin, direction.normalized());
+        for i in 0..surfaces.len() {
+            let outside = if i > 0 { surfaces[i - 1].ior } else { 1.0 };
+            (o, d) = self.cross(i, o, d, outside, surfaces[i].ior)?;
+        }
+        Some((o, d))
+    }
+
+    // through surface i from a medium of index `from` into one of index `to`, None when the
+    // ray misses the glass or the stop, or reflects off the surface
+    fn cross(&self, i: usize, o: Point3, d: Vec3, from: Float, to: Float) -> Option<(Point3, Vec3)> {
+        let surface = self.prescription.surfaces[i];
+        let z = self.z[i];
+        if surface.radius == 0.0 {
+            let t = (z - o.z()) / d.z();
+            let p = o + t * d;
+            return (t > 0.0 && p.x() * p.x() + p.y() * p.y() <= surface.aperture * surface.aperture).then_some((p, d));
+        }
+        let center = Point3::new(0.0, 0.0, z - surface.radius);
+        let oc = o - center;
+        let half_b = oc.dot(d);
+        let c = oc.dot(oc) - surface.radius * surface.radius;
+        let discriminant = half_b * half_b - c;
+        if discriminant < 0.0 {
+            return None;
+        }
+        // the side of the sphere the surface is on, the far one when it bulges the way the
+        // ray goes
+        let sqrtd = discriminant.sqrt();
+        let t = if (d.z() > 0.0) == (surface.radius > 0.0) { -half_b + sqrtd } else { -half_b - sqrtd };
+        let p = o + t * d;
+        if t <= 0.0 || p.x() * p.x() + p.y() * p.y() > surface.aperture * surface.aperture {
+            return None;
+        }
+        let mut n = (p - center) / surface.radius.abs();
+        if n.dot(d) > 0.0 {
+            n = -1.0 * n;
+        }
+        let eta = from / to;
+        let cos_i = -d.dot(n);
+        let sin2_t = eta * eta * (1.0 - cos_i * cos_i);
+        if sin2_t > 1.0 {
+            return None;
+        }
+        let cos_t = (1.0 - sin2_t).sqrt();
+        Some((p, eta * d + (eta * cos_i - cos_t) * n))
+    }
+}
+
+// Where a ray that came in parallel to the axis at `height` crosses the axis (the focal point)
+// and where it meets the line it came in on (the principal plane), as z
+fn cardinal(height: Float, (o, d): (Point3, Vec3)) -> Option<(Float, Float)> {
+    if d.x().abs() < 1.0e-12 {
+        return None;
+    }
+    let focal = o.z() - o.x() * d.z() / d.x();
+    let principal = o.z() + (height - o.x()) * d.z() / d.x();
+    Some((principal, focal))
+}
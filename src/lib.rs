@@ -0,0 +1,86 @@
+// The ray tracer as a library: build a Scene, pick the Settings and hand both to a Renderer.
+// The command line tool in main.rs is a thin layer over this.
+
+// In single precision the casts to f32 for the output formats do nothing and some constants
+// have more digits than an f32 holds, both are there for the f64 build
+#![cfg_attr(feature = "f32", allow(clippy::unnecessary_cast, clippy::excessive_precision))]
+
+pub mod float;
+pub mod vec3;
+pub mod ray;
+pub mod hit;
+pub mod aabb;
+pub mod sphere;
+pub mod camera;
+pub mod lens;
+pub mod cancel;
+pub mod material;
+pub mod blackbody;
+pub mod framebuffer;
+pub mod film;
+pub mod output;
+pub mod colorspace;
+pub mod ocio;
+pub mod sink;
+pub mod review;
+pub mod metadata;
+pub mod stats;
+pub mod random;
+pub mod render;
+pub mod workers;
+pub mod integrator;
+pub mod occlusion;
+pub mod dirt;
+pub mod fog;
+pub mod lpe;
+pub mod trace;
+pub mod convergence;
+pub mod bake;
+pub mod scene;
+pub mod scene_file;
+pub mod scenes;
+pub mod furnace;
+pub mod studio;
+pub mod texture;
+pub mod noise;
+pub mod triangle;
+pub mod mesh;
+pub mod group;
+pub mod fractal;
+pub mod vdb;
+pub mod volume;
+pub mod instancer;
+pub mod pbrt;
+pub mod mitsuba;
+pub mod transform;
+pub mod onb;
+pub mod animation;
+pub mod clip;
+pub mod visibility;
+pub mod inspect;
+pub mod overlay;
+pub mod validate;
+pub mod overrides;
+pub mod input;
+pub mod diff;
+pub mod accumulate;
+pub mod error;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+// the references are double precision renders, in f32 the noise comes out different
+#[cfg(all(test, not(feature = "f32")))]
+mod golden;
+#[cfg(test)]
+mod properties;
+
+pub use crate::float::Float;
+pub use crate::vec3::{Color, Point3, Vec3};
+pub use crate::ray::Ray;
+pub use crate::hit::{Hit, HittableList};
+pub use crate::camera::Camera;
+pub use crate::cancel::Cancel;
+pub use crate::error::{RendererError, Result};
+pub use crate::framebuffer::Framebuffer;
+pub use crate::render::{Renderer, Settings};
+pub use crate::sink::ImageSink;
+pub use crate::scene::{Background, Environment, Scene};
@@ -0,0 +1,145 @@
+use std::io::{self, BufRead};
+use std::path::Path;
+use std::slice;
+use std::str::FromStr;
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use raytracer_test::film::Film;
+use raytracer_test::framebuffer::Framebuffer;
+use raytracer_test::output::Format;
+use raytracer_test::overrides::{self, Override};
+use raytracer_test::render::Settings;
+use raytracer_test::scene_file::SceneFile;
+use raytracer_test::{Float, Result, Scene};
+use crate::cli::Args;
+
+// Renders the scene a sixteenth of its samples at a time, adding each round to the image
+// written over the output, so an image viewer that reloads on change shows it clearing up.
+// Meanwhile changes come in on stdin, a line each:
+//   exposure EV       the image written brighter or darker, the samples are kept
+//   fov DEGREES       the camera's vfov
+//   sun DEGREES       the environment turned about the up axis, and the sun in it
+//   TARGET=VALUE      any override, like --override takes
+//   quit
+// Changes to the scene start the samples over from the next round. With all the samples in
+// it waits for the next change, and ends when stdin does.
+pub fn live(mut file: SceneFile, output: &Path, format: Format, args: &Args) -> Result<()> {
+    let renderer = crate::renderer(args)?;
+    let options = crate::write_options(args, format)?;
+    let lines = read_stdin();
+    let (mut settings, mut scene) = build(&file, args)?;
+    let mut film = Film::new(settings.width, settings.height);
+    let mut samples = 0;
+    let mut exposure = 0.0;
+    let mut last: Option<Framebuffer> = None;
+    log::info!("Rendering {} live, changes go on stdin", output.display());
+
+    loop {
+        // with stdin closed the render still gets all its samples
+        let mut waiting: Vec<String> = lines.try_iter().collect();
+        if samples >= settings.samples_per_pixel && waiting.is_empty() {
+            match lines.recv() {
+                Ok(line) => waiting.push(line),
+                Err(_) => return Ok(()),
+            }
+        }
+
+        let mut rewrite = false;
+        for line in waiting {
+            match line.parse::<Change>() {
+                Ok(Change::Quit) => return Ok(()),
+                Ok(Change::Exposure(ev)) => {
+                    exposure = ev;
+                    rewrite = true;
+                }
+                Ok(Change::Scene(o)) => {
+                    let changed = overrides::apply(file.clone(), slice::from_ref(&o))
+                        .and_then(|changed| Ok((build(&changed, args)?, changed)));
+                    match changed {
+                        Ok(((s, built), changed)) => {
+                            (file, settings, scene) = (changed, s, built);
+                            film = Film::new(settings.width, settings.height);
+                            samples = 0;
+                        }
+                        Err(e) => log::error!("{}", e),
+                    }
+                }
+                Err(e) => log::error!("{}", e),
+            }
+        }
+
+        if samples < settings.samples_per_pixel {
+            let round = (settings.samples_per_pixel / 16).max(1).min(settings.samples_per_pixel - samples);
+            // a seed of its own for every round, or they'd all take the same samples
+            let seed = settings.seed.wrapping_add(samples as u64);
+            let mut data = renderer.render(&scene, &Settings { samples_per_pixel: round, seed, ..settings });
+            film.splat_image(&data, round as Float);
+            samples += round;
+            film.resolve_into(&mut data);
+            for (key, value) in &mut data.metadata {
+                if key == "Samples" {
+                    *value = samples.to_string();
+                }
+            }
+            log::debug!("{} of {} samples", samples, settings.samples_per_pixel);
+            last = Some(data);
+            rewrite = true;
+        }
+        if let (true, Some(data)) = (rewrite, &last) {
+            crate::write(output, &data.exposed(exposure), format, args, &options)?;
+        }
+    }
+}
+
+// a change that came in on stdin
+enum Change {
+    Exposure(Float),
+    Scene(Override),
+    Quit,
+}
+
+impl FromStr for Change {
+    type Err = String;
+
+    fn from_str(line: &str) -> std::result::Result<Change, String> {
+        // overrides can have spaces in their values
+        if line.contains('=') {
+            return line.trim().parse().map(Change::Scene);
+        }
+        let number = |s: &str| s.parse::<Float>().map_err(|_| format!("'{}' is not a number", s));
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words[..] {
+            ["quit"] => Ok(Change::Quit),
+            ["exposure", ev] => Ok(Change::Exposure(number(ev)?)),
+            ["fov", degrees] => format!("camera:vfov={}", number(degrees)?).parse().map(Change::Scene),
+            ["sun", degrees] => format!("environment:rotation={}", number(degrees)?).parse().map(Change::Scene),
+            _ => Err(format!("'{}' isn't a change, try exposure EV, fov DEGREES, sun DEGREES, TARGET=VALUE or quit", line.trim())),
+        }
+    }
+}
+
+// The lines of stdin as they come, read on a thread of their own so the render goes on
+// between them. The channel closes with stdin.
+fn read_stdin() -> Receiver<String> {
+    let (send, receive) = mpsc::channel();
+    thread::spawn(move || {
+        for line in io::stdin().lock().lines() {
+            let Ok(line) = line else { break };
+            if !line.trim().is_empty() && send.send(line).is_err() {
+                break;
+            }
+        }
+    });
+    receive
+}
+
+// the scene file's settings, with --seed, and its scene
+fn build(file: &SceneFile, args: &Args) -> Result<(Settings, Scene)> {
+    let mut settings = file.settings();
+    if let Some(seed) = args.seed {
+        settings.seed = seed;
+    }
+    let scene = file.build_with_shutter(0.0, (args.shutter / args.fps) as Float, (args.rolling_shutter / args.fps) as Float,
+                                       settings.aspect_ratio())?;
+    Ok((settings, scene))
+}
@@ -0,0 +1,185 @@
+use std::str::FromStr;
+use crate::visibility::TraceGroup;
+
+// Light path expressions: regular expressions over what happened along a path, for sorting
+// the light into passes of its own, caustics or reflections say. A path is a string of
+// events read from the camera outward:
+//
+//   C  the camera, where every path starts
+//   D  a diffuse bounce
+//   S  a specular reflection, off metal or glass
+//   T  a transmission, through glass
+//   V  a scattering in the fog, see fog::Fog
+//   L  a light, where the path picks up what the light gives off
+//   B  the background, where the path leaves the scene
+//
+// and an expression matches it as a whole. `.` is any event, `[ST]` any of those, `[^D]`
+// anything but, `*`, `+` and `?` repeat what's before them, `(` `)` group and `|` picks one
+// side. Caustics on diffuse surfaces are CD[ST]+L, everything seen in mirrors C S.*, direct
+// light on diffuse surfaces CDL, light shafts in fog CVL.
+#[derive(Clone, Debug)]
+pub struct LightPath {
+    name: String,
+    expression: String,
+    node: Node,
+}
+
+#[derive(Clone, Debug)]
+enum Node {
+    // one event out of the set, or one that isn't in it
+    Event { events: Vec<u8>, negated: bool },
+    Sequence(Vec<Node>),
+    Either(Vec<Node>),
+    Repeat { node: Box<Node>, min: usize, max: Option<usize> },
+}
+
+const EVENTS: &[u8] = b"CDSTVLB";
+
+// the event for a bounce, see Ray::lobe
+pub fn event(lobe: TraceGroup) -> u8 {
+    match lobe {
+        TraceGroup::DIFFUSE => b'D',
+        TraceGroup::TRANSMISSION => b'T',
+        TraceGroup::CAMERA => b'C',
+        _ => b'S',
+    }
+}
+
+impl FromStr for LightPath {
+    type Err = String;
+
+    // name=expression, the name being what the pass is called in the output
+    fn from_str(s: &str) -> Result<LightPath, String> {
+        let (name, expression) = s.split_once('=').ok_or_else(|| format!("'{}' should be name=expression, e.g. caustics=CD[ST]+L", s))?;
+        if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            return Err(format!("'{}' can't name a pass, use letters, digits and _", name));
+        }
+        let events: Vec<u8> = expression.bytes().filter(|b| !b.is_ascii_whitespace()).collect();
+        let mut parser = Parser { text: &events, at: 0 };
+        let node = parser.either()?;
+        if parser.at < events.len() {
+            return Err(format!("'{}': unexpected '{}'", expression, events[parser.at] as char));
+        }
+        Ok(LightPath { name: name.to_string(), expression: expression.to_string(), node })
+    }
+}
+
+impl LightPath {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn expression(&self) -> &str {
+        &self.expression
+    }
+
+    pub fn matches(&self, path: &[u8]) -> bool {
+        matches(&self.node, path, 0, &mut |end| end == path.len())
+    }
+}
+
+// Whether `node` matches path[at..end] for an end that `rest` accepts, backtracking through
+// the ends the node can stop at
+fn matches(node: &Node, path: &[u8], at: usize, rest: &mut dyn FnMut(usize) -> bool) -> bool {
+    match node {
+        Node::Event { events, negated } => {
+            at < path.len() && events.contains(&path[at]) != *negated && rest(at + 1)
+        }
+        Node::Sequence(nodes) => sequence(nodes, path, at, rest),
+        Node::Either(nodes) => nodes.iter().any(|n| matches(n, path, at, rest)),
+        Node::Repeat { node, min, max } => repeat(node, *min, *max, path, at, rest),
+    }
+}
+
+fn sequence(nodes: &[Node], path: &[u8], at: usize, rest: &mut dyn FnMut(usize) -> bool) -> bool {
+    match nodes.split_first() {
+        None => rest(at),
+        Some((first, others)) => matches(first, path, at, &mut |next| sequence(others, path, next, rest)),
+    }
+}
+
+// greedy, and every round past the minimum has to take up an event so x** can't loop forever
+fn repeat(node: &Node, min: usize, max: Option<usize>, path: &[u8], at: usize, rest: &mut dyn FnMut(usize) -> bool) -> bool {
+    if max == Some(0) {
+        return rest(at);
+    }
+    let again = |next: usize, rest: &mut dyn FnMut(usize) -> bool| {
+        (min > 0 || next > at) && repeat(node, min.saturating_sub(1), max.map(|m| m - 1), path, next, rest)
+    };
+    matches(node, path, at, &mut |next| again(next, rest)) || (min == 0 && rest(at))
+}
+
+// Recursive descent over the expression: either = sequence ('|' sequence)*, sequence = atom*,
+// atom = (event | '.' | '[' set ']' | '(' either ')') repeat?
+struct Parser<'a> {
+    text: &'a [u8],
+    at: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<u8> {
+        self.text.get(self.at).copied()
+    }
+
+    fn either(&mut self) -> Result<Node, String> {
+        let mut sides = vec![self.sequence()?];
+        while self.peek() == Some(b'|') {
+            self.at += 1;
+            sides.push(self.sequence()?);
+        }
+        Ok(if sides.len() == 1 { sides.pop().unwrap() } else { Node::Either(sides) })
+    }
+
+    fn sequence(&mut self) -> Result<Node, String> {
+        let mut nodes = Vec::new();
+        while let Some(c) = self.peek().filter(|c| !b"|)".contains(c)) {
+            self.at += 1;
+            let atom = match c {
+                b'.' => Node::Event { events: EVENTS.to_vec(), negated: false },
+                b'[' => self.set()?,
+                b'(' => {
+                    let inner = self.either()?;
+                    if self.peek() != Some(b')') {
+                        return Err("a '(' is never closed".to_string());
+                    }
+                    self.at += 1;
+                    inner
+                }
+                c if EVENTS.contains(&c) => Node::Event { events: vec![c], negated: false },
+                c => return Err(format!("'{}' is no event, the events are C, D, S, T, V, L and B", c as char)),
+            };
+            nodes.push(self.repeated(atom));
+        }
+        Ok(Node::Sequence(nodes))
+    }
+
+    fn set(&mut self) -> Result<Node, String> {
+        let negated = self.peek() == Some(b'^');
+        if negated {
+            self.at += 1;
+        }
+        let mut events = Vec::new();
+        loop {
+            match self.peek() {
+                Some(b']') => break,
+                Some(c) if EVENTS.contains(&c) => events.push(c),
+                Some(c) => return Err(format!("'{}' is no event, the events are C, D, S, T, V, L and B", c as char)),
+                None => return Err("a '[' is never closed".to_string()),
+            }
+            self.at += 1;
+        }
+        self.at += 1;
+        Ok(Node::Event { events, negated })
+    }
+
+    fn repeated(&mut self, atom: Node) -> Node {
+        let (min, max) = match self.peek() {
+            Some(b'*') => (0, None),
+            Some(b'+') => (1, None),
+            Some(b'?') => (0, Some(1)),
+            _ => return atom,
+        };
+        self.at += 1;
+        Node::Repeat { node: Box::new(atom), min, max }
+    }
+}
@@ -1,160 +1,596 @@
-mod vec3;
-mod ray;
-mod hit;
-mod sphere;
-mod camera;
-mod material;
-
-use std::fs::File;
-use std::io::{BufWriter, stderr, Write};
+mod cli;
+mod sequence;
+mod video;
+mod report;
+mod watch;
+mod live;
+mod turntable;
+mod server;
+mod matpreview;
+mod sweep;
+mod logger;
+
+use std::collections::HashSet;
+use std::panic::{self, AssertUnwindSafe};
 use std::path::Path;
-use std::rc::Rc;
-use std::sync::{Arc, Mutex};
-use png;
-use rand::Rng;
-use rand::rngs::StdRng;
-use threadpool::ThreadPool;
-use rayon::prelude::*;
-use crate::camera::Camera;
-use crate::hit::{Hit, World};
-use crate::material::{Dielectric, Lambertian, Metal};
-use crate::vec3::{Color, Point3, Vec3};
-use crate::ray::Ray;
-use crate::sphere::Sphere;
-
-
-// gets the color of the ray at intersection
-fn ray_color(r: &Ray, world: &World, depth: u64) -> Color {
-    if depth <= 0 {
-        // Exceeding the ray bounce limit, no more light is gathered
-        return Color::new(0.0, 0.0, 0.0);
-    }
-
-    if let Some(rec) = world.hit(r, 0.001, f64::INFINITY) {
-        // material (description of ray behaviour)
-        if let Some((attenuation, scattered)) = rec.mat.scatter(r, &rec) {
-            attenuation * ray_color(&scattered, world, depth-1)
-        } else {
-            Color::new(0.0, 0.0, 0.0)
+use std::process;
+use std::sync::Arc;
+use std::time::Instant;
+use clap::Parser;
+use raytracer_test::{bake, diff, furnace, input, inspect, metadata, ocio, output, overrides, review, studio, trace};
+use raytracer_test::bake::Bake;
+use raytracer_test::colorspace::Display;
+use raytracer_test::accumulate::Accumulator;
+use raytracer_test::convergence::NoiseThreshold;
+use raytracer_test::framebuffer::Framebuffer;
+use raytracer_test::integrator::{DepthRange, Integrator};
+use raytracer_test::material::{Dielectric, Lambertian, Metal, Scatter};
+use raytracer_test::occlusion::Occlusion;
+use raytracer_test::output::{BitDepth, Collision, ExrLayout, ExrPrecision, Format, WriteOptions};
+use raytracer_test::overrides::Override;
+use raytracer_test::render::{Renderer, Settings};
+use raytracer_test::scenes::Builtin;
+use raytracer_test::scene_file::{MaterialLibrary, SceneFile};
+use raytracer_test::stats::Stats;
+use raytracer_test::transform::Up;
+use raytracer_test::{Cancel, Color, Float, RendererError, Result, Scene};
+use crate::cli::{AccumulateArgs, Args, BakeArgs, Command, DiffArgs, FurnaceArgs, InspectArgs, SceneArg};
+use crate::sequence::frame_path;
+use crate::video::VideoEncoder;
+use crate::report::{FrameReport, Report, Timings};
+
+
+// The OCIO view is loaded and the display checked against the format here, before the render,
+// so options that can't be used stop it from starting
+fn write_options(args: &Args, format: Format) -> Result<WriteOptions> {
+    output::check_display(format, args.display)?;
+    let view = match &args.ocio {
+        Some(config) => {
+            let view = ocio::Config::load(config)?.view(args.view.as_deref())?;
+            log::info!("8 and 16-bit images are shown through OCIO view {}", view.name());
+            Some(Arc::new(view))
         }
-    } else {
-        // background
-        let unit_direction = r.direction().normalized();
-        let t = 0.5 * (unit_direction.y() + 1.0);
-        (1.0 - t) * Color::new(1.0, 1.0, 1.0) + t*Color::new(0.5, 0.7, 1.0)
+        None => None,
+    };
+    Ok(WriteOptions {
+        // PQ needs 10 bits at least not to band
+        bit_depth: if args.sixteen_bit || args.display == Display::Hdr10 { BitDepth::Sixteen } else { BitDepth::Eight },
+        exr_precision: if args.half { ExrPrecision::Half } else { ExrPrecision::Full },
+        exr_layout: if args.exr_parts { ExrLayout::Parts } else { ExrLayout::Channels },
+        quality: args.quality,
+        view,
+        display: args.display,
+    })
+}
+
+// the render, plus the thumbnail, exposure brackets, contact sheet, occlusion and light path
+// passes when asked for
+fn write(path: &Path, data: &Framebuffer, format: Format, args: &Args, options: &WriteOptions) -> Result<()> {
+    output::write(path, data, format, options)?;
+    if let Some(size) = args.thumbnail {
+        output::write_thumbnail(&review::thumbnail_path(path), &review::thumbnail(data, size))?;
     }
+
+    for &ev in &args.brackets {
+        output::write(&review::bracket_path(path, ev), &data.exposed(ev), format, options)?;
+    }
+    if args.contact_sheet {
+        output::write(&review::sheet_path(path), &review::contact_sheet(data), format, options)?;
+    }
+    // EXR has the pass as channels of its own
+    if let Some(pass) = review::occlusion_pass(data).filter(|_| format != Format::Exr) {
+        output::write(&review::occlusion_path(path), &pass, format, options)?;
+    }
+    for (name, pass) in review::light_path_passes(data).filter(|_| format != Format::Exr) {
+        output::write(&review::pass_path(path, name), &pass, format, options)?;
+    }
+    Ok(())
 }
 
-fn main() {
-    // Image
-    const ASPECT_RATIO: f64 = 3.0 / 2.0;
-    const IMAGE_WIDTH: u32 = 1200;
-    const IMAGE_HEIGHT: u32 = ((IMAGE_WIDTH as f64) / ASPECT_RATIO) as u32;
-    const SAMPLES_PER_PIXEL: u32 = 100;
-    const MAX_DEPTH: u64 = 10;
-
-    // World
-    let mut world = World::new();
-
-    let mat_ground = Arc::new(Lambertian::new(Color::new(0.8, 0.8, 0.0)));
-    let mat_center = Arc::new(Dielectric::new(1.1));
-    // let mat_left = Rc::new(Dielectric::new(1.5));
-    let mat_right = Arc::new(Metal::new(Color::new(0.8, 0.6, 0.2), 0.0));
-    let mat_left_inner = Arc::new(Dielectric::new(1.5));
-    let mat_matte = Arc::new(Lambertian::new(Color::new(0.4, 0.2, 0.4)));
-
-    world.push(Box::new(Sphere::new(Point3::new(0.0, -100.5, -1.0), 100.0, mat_ground)));
-    world.push(Box::new(Sphere::new(Point3::new(0.0, 0.0, -1.0), 0.5, mat_center)));
-    // world.push(Box::new(Sphere::new(Point3::new(-1.0, 0.0, -1.0), 0.5, mat_left)));
-    world.push(Box::new(Sphere::new(Point3::new(1.0, 0.0, -1.0), 0.5, mat_right)));
-    world.push(Box::new(Sphere::new(Point3::new(-1.0, 0.0, -1.0), -0.4, mat_left_inner)));
-    world.push(Box::new(Sphere::new(Point3::new(0.0, 0.0, 2.5), 1.2, mat_matte)));
-
-    let arc_world = Arc::new(world);
-
-    // Camera
-    let lookfrom = Point3::new(12.0, 3.0, 3.0);
-    let lookat = Point3::new(0.0, 0.0, -1.0);
-    let cam = Camera::new(
-        lookfrom,
-        lookat,
-        Vec3::new(0.0, 1.0, 0.0),
-        20.0,
-        ASPECT_RATIO,
-        0.1,
-        (lookfrom - lookat).length()
-    );
-
-    // Rendering
-    // let mut vec_data = vec![0; (IMAGE_HEIGHT*IMAGE_WIDTH*3) as usize];
-    // let mut data_slice = vec_data.as_mut_slice();
-    let mut data = Arc::new(Mutex::new(vec![0; (IMAGE_HEIGHT*IMAGE_WIDTH*3) as usize]));
-
-    let pool = threadpool::Builder::new()
-        .num_threads(8)
-        .thread_stack_size(2_000_000)
-        .build();
-
-    for x in 0..IMAGE_WIDTH {
-            let data_clone = data.clone();
-            let arc_world = arc_world.clone();
-            pool.execute(move || {
-                // eprintln!("Thread: x:{} -- STARTED", x);
-                // stderr().flush().unwrap();
-
-                let mut rng = rand::thread_rng();
-                for y in 0..IMAGE_HEIGHT {
-                    // let mut pixel_color = Color::new(0.0, 0.0, 0.0);
-                    let pixel_color: Color = (0..SAMPLES_PER_PIXEL)
-                        .into_iter()
-                        .map(|_| {
-                            let rand_u: f64 = rng.gen();
-                            let rand_v: f64 = rng.gen();
-
-                            let u = ((x as f64) + rand_u) / ((IMAGE_WIDTH - 1) as f64);
-                            let v = ((y as f64) + rand_v) / ((IMAGE_HEIGHT - 1) as f64);
-
-                            // ray generation from the camera (0,0,0) to corresponding coordinates of each pixel of the output image
-                            let r = cam.get_ray(u, v);
-                            ray_color(&r, &arc_world, MAX_DEPTH)
-                        })
-                        .sum();
-                    // for _ in 0..SAMPLES_PER_PIXEL {
-                    //     let mut rng = rand::thread_rng();
-                    //
-                    //     let rand_u: f64 = rng.gen();
-                    //     let rand_v: f64 = rng.gen();
-                    //
-                    //     let u = ((x as f64) + rand_u) / ((IMAGE_WIDTH - 1) as f64);
-                    //     let v = ((y as f64) + rand_v) / ((IMAGE_HEIGHT - 1) as f64);
-                    //
-                    //     // ray generation from the camera (0,0,0) to corresponding coordinates of each pixel of the output image
-                    //     let r = cam.get_ray(u, v);
-                    //     pixel_color += ray_color(&r, &arc_world, MAX_DEPTH);
-                    // }
-                    let (r, g, b) = pixel_color.color_rgb(SAMPLES_PER_PIXEL);
-                    let mut data = data_clone.lock().unwrap();
-                    data[((IMAGE_HEIGHT-y-1)*IMAGE_WIDTH*3 + x*3 + 0) as usize] = r;
-                    data[((IMAGE_HEIGHT-y-1)*IMAGE_WIDTH*3 + x*3 + 1) as usize] = g;
-                    data[((IMAGE_HEIGHT-y-1)*IMAGE_WIDTH*3 + x*3 + 2) as usize] = b;
+fn renderer(args: &Args) -> Result<Renderer> {
+    let integrator = match args.integrator {
+        Integrator::Depth(_) => {
+            if let (Some(near), Some(far)) = (args.near, args.far) {
+                if near == far {
+                    return Err(RendererError::Unsupported("--near and --far can't be the same distance".to_string()));
+                }
+            }
+            Integrator::Depth(DepthRange { near: args.near, far: args.far, raw: args.raw_depth })
+        }
+        integrator => integrator,
+    };
+    let mut renderer = Renderer::new().with_integrator(integrator).with_tile_order(args.tile_order);
+    if let Some(distance) = args.occlusion {
+        renderer = renderer.with_occlusion(Occlusion::new(distance, args.occlusion_samples));
+    }
+    // the background's light pass is called background too
+    let mut names: HashSet<&str> = args.light_passes.then_some("background").into_iter().collect();
+    if let Some(path) = args.light_paths.iter().find(|p| !names.insert(p.name())) {
+        return Err(RendererError::Unsupported(format!("two passes are called {}", path.name())));
+    }
+    if !args.light_paths.is_empty() {
+        renderer = renderer.with_light_paths(args.light_paths.clone());
+    }
+    renderer = renderer.with_light_passes(args.light_passes).with_radiance_check(args.check_radiance);
+    if let Some(threshold) = args.noise_threshold {
+        renderer = renderer.with_noise_threshold(NoiseThreshold::new(threshold, args.max_samples));
+    }
+    Ok(match args.threads {
+        Some(threads) => renderer.with_threads(threads as usize),
+        None => renderer,
+    })
+}
+
+// every bounce of the pixel's samples, or just the one asked for
+fn trace_pixel(scene: &Scene, settings: &Settings, x: u32, y: u32, sample: Option<u32>) -> Result<()> {
+    if x >= settings.width || y >= settings.height {
+        return Err(RendererError::Unsupported(format!("pixel ({}, {}) is outside the {}x{} image", x, y, settings.width, settings.height)));
+    }
+    if let Some(sample) = sample.filter(|&s| s >= settings.samples_per_pixel) {
+        return Err(RendererError::Unsupported(format!("sample {} is past the {} samples per pixel", sample, settings.samples_per_pixel)));
+    }
+    let traces = trace::trace_pixel(scene, settings, x, y);
+    for t in traces.iter().filter(|t| sample.is_none_or(|s| s == t.sample)) {
+        println!("{}", t);
+    }
+    let bad = traces.iter().filter(|t| trace::is_bad(t.radiance)).count();
+    let sum: Color = traces.iter().map(|t| t.radiance).sum();
+    println!("pixel ({}, {}): {} samples, average {}, {} of them bad", x, y, traces.len(), sum / traces.len() as Float, bad);
+    Ok(())
+}
+
+// The first Ctrl-C stops the render and writes out what is done, the second quits right away
+fn cancel_on_ctrl_c() -> Cancel {
+    let cancel = Cancel::new();
+    let handler = cancel.clone();
+    let installed = ctrlc::set_handler(move || {
+        if handler.is_cancelled() {
+            process::exit(130);
+        }
+        log::warn!("stopping, the image so far gets written (Ctrl-C again to quit right away)");
+        handler.cancel();
+    });
+    if let Err(e) = installed {
+        log::warn!("Ctrl-C won't save the render: {}", e);
+    }
+    cancel
+}
+
+// a scene file, or one of the built-in scenes
+enum Source {
+    File(Box<SceneFile>),
+    Builtin(Builtin),
+}
+
+impl Source {
+    // Scene files are turned into this renderer's up axis and units by `conversion` (see
+    // SceneFile::convert), built-in scenes are turned into scene files for the overrides and
+    // the studio, see studio::apply
+    fn load(arg: Option<&SceneArg>, conversion: Option<(Up, Float)>, overrides: &[Override], studio: bool) -> Result<Source> {
+        let source = match arg {
+            Some(SceneArg::File(path)) => {
+                let mut file = SceneFile::load(path)?;
+                if let Some((up, unit_scale)) = conversion {
+                    file.convert(up, unit_scale);
                 }
-                eprintln!("T:X:{} ## C", x);
-                stderr().flush().unwrap();
-            });
+                Source::File(Box::new(file))
+            }
+            Some(SceneArg::Builtin(b)) => Source::Builtin(*b),
+            None => Source::Builtin(Builtin::Default),
+        };
+        if overrides.is_empty() && !studio {
+            return Ok(source);
+        }
+        let mut file = source.into_file()?;
+        if studio {
+            file = studio::apply(file)?;
+        }
+        Ok(Source::File(Box::new(overrides::apply(file, overrides)?)))
+    }
+
+    fn into_file(self) -> Result<SceneFile> {
+        match self {
+            Source::File(file) => Ok(*file),
+            Source::Builtin(b) => {
+                let settings = b.settings();
+                SceneFile::from_scene(&b.build(0.0, settings.aspect_ratio()), &settings)
+            }
+        }
+    }
+
+    fn settings(&self) -> Settings {
+        match self {
+            Source::File(file) => file.settings(),
+            Source::Builtin(b) => b.settings(),
+        }
     }
-    pool.join();
 
-    let path = Path::new("./src/output.png");
-    let file = File::create(path).unwrap();
-    let ref mut w = BufWriter::new(file);
+    // the scene at `time` with the shutter open for `shutter` seconds, see
+    // SceneFile::build_with_shutter
+    fn build(&self, time: Float, shutter: Float, readout: Float, aspect_ratio: Float) -> Result<Scene> {
+        match self {
+            Source::File(file) => file.build_with_shutter(time, shutter, readout, aspect_ratio),
+            Source::Builtin(b) => {
+                let mut scene = b.build(time, aspect_ratio);
+                scene.camera = scene.camera.with_shutter(shutter).with_rolling_shutter(readout);
+                Ok(scene)
+            }
+        }
+    }
+}
 
-    let mut encoder = png::Encoder::new(w, IMAGE_WIDTH, IMAGE_HEIGHT);
-    encoder.set_color(png::ColorType::Rgb);
-    encoder.set_depth(png::BitDepth::Eight);
+fn run(args: &Args, report: &mut Report) -> Result<()> {
+    let format = args.format
+        .or_else(|| args.output.as_deref().and_then(Format::from_path))
+        .unwrap_or(Format::Png);
+    let output = args.output.clone().unwrap_or_else(|| output::default_path(format));
+    if args.watch {
+        return match &args.scene {
+            Some(SceneArg::File(path)) if args.frames.is_none() && !args.stream => watch::watch(path, &output, format, args),
+            Some(SceneArg::File(_)) => Err(RendererError::Unsupported("--watch renders single images, not --frames or --stream".to_string())),
+            _ => Err(RendererError::Unsupported("--watch needs a scene file".to_string())),
+        };
+    }
+
+    let source = Source::load(args.scene.as_ref(), args.conversion(), &args.overrides, args.studio)?;
+    if args.live {
+        return live::live(source.into_file()?, &output, format, args);
+    }
+    let options = write_options(args, format)?;
+    let mut settings = source.settings();
+    if let Some(seed) = args.seed {
+        settings.seed = seed;
+    }
+    if let Some(preview) = args.preview {
+        settings = preview.settings(&settings);
+        log::info!("Previewing at {}x{}, {} spp, max depth {}", settings.width, settings.height, settings.samples_per_pixel, settings.max_depth);
+    }
+    // the camera frames the scene's image, the overscan goes around it
+    let frame = settings;
+    let margin = args.overscan.map(|percent| {
+        let margin = |size: u32| (size as Float * percent / 100.0).round() as u32;
+        (margin(frame.width), margin(frame.height))
+    });
+    if let Some((x, y)) = margin {
+        settings.width += 2 * x;
+        settings.height += 2 * y;
+        log::info!("Rendering {}x{}, {} and {} pixels past the sides and the top and bottom of the frame", settings.width,
+                   settings.height, x, y);
+    }
+    let scene = |time: f64| {
+        let mut scene = source.build(time as Float, (args.shutter / args.fps) as Float, (args.rolling_shutter / args.fps) as Float,
+                                     frame.aspect_ratio())?;
+        if args.preview.is_some() {
+            scene.camera = scene.camera.pinhole();
+        }
+        if let Some(margin) = margin {
+            scene.camera = scene.camera.with_overscan(margin, (frame.width, frame.height));
+        }
+        Ok::<_, RendererError>(scene)
+    };
+    if let Some(path) = &args.save_scene {
+        return SceneFile::from_scene(&scene(0.0)?, &settings)?.save(path);
+    }
+    if let Some((x, y)) = args.trace_pixel {
+        return trace_pixel(&scene(0.0)?, &settings, x, y, args.trace_sample);
+    }
+    let renderer = renderer(args)?.with_cancel(cancel_on_ctrl_c());
+    report.settings = report::Settings {
+        width: settings.width,
+        height: settings.height,
+        samples_per_pixel: settings.samples_per_pixel,
+        max_depth: settings.max_depth,
+        format: format!("{:?}", format),
+        output: output.display().to_string(),
+    };
 
-    let mut writer = encoder.write_header().unwrap();
-    {
-        let mut rdt = data.lock().expect("Failed to retrieve image data");
-        writer.write_image_data(rdt.as_mut_slice()).expect("Fail to save the image");
+    match args.frames {
+        None if args.stream => {
+            if format != Format::Exr {
+                return Err(RendererError::Unsupported("--stream only supports EXR output".to_string()));
+            }
+            if renderer.noise_threshold().is_some() {
+                return Err(RendererError::Unsupported("--stream writes each tile once, it can't go on until --noise-threshold".to_string()));
+            }
+            if !renderer.light_paths().is_empty() || renderer.light_passes() {
+                return Err(RendererError::Unsupported("--stream can't write light path or light passes, render without them".to_string()));
+            }
+            let mut frame = FrameReport::default();
+            let scene = Timings::measure(&mut frame.timings.scene, || scene(0.0))?;
+            let metadata = metadata::render_metadata(&scene, &settings, None);
+            let path = output::resolve_path(output, args.on_exists)?;
+            let precision = if args.half { ExrPrecision::Half } else { ExrPrecision::Full };
+            let layout = if args.exr_parts { ExrLayout::Parts } else { ExrLayout::Channels };
+            // rendering and writing overlap, all of it counts as render time
+            Timings::measure(&mut frame.timings.render, || {
+                let tiles = renderer.render_tiles(scene, settings, args.tile_size);
+                output::write_exr_streamed(&path, settings.width, settings.height, args.tile_size, precision, layout,
+                                           renderer.occlusion().is_some(), &metadata, tiles.into_iter())
+            })?;
+            frame.output = path.display().to_string();
+            frame.stats = Stats::take();
+            report.frames.push(frame);
+            if renderer.is_cancelled() {
+                return Err(RendererError::Cancelled);
+            }
+        }
+        None => {
+            let mut frame = FrameReport::default();
+            let scene = Timings::measure(&mut frame.timings.scene, || scene(0.0))?;
+            let data = Timings::measure(&mut frame.timings.render, || renderer.render(&scene, &settings));
+            let path = output::resolve_path(output, args.on_exists)?;
+            Timings::measure(&mut frame.timings.write, || write(&path, &data, format, args, &options))?;
+            frame.output = path.display().to_string();
+            frame.stats = Stats::take();
+            report.frames.push(frame);
+            if renderer.is_cancelled() {
+                return Err(RendererError::Cancelled);
+            }
+        }
+        Some(frames) if VideoEncoder::is_video(&output) => {
+            if options.display != Display::Srgb {
+                return Err(RendererError::Unsupported(format!("videos can't be for display {}, only PNG says which display it's for",
+                                                              options.display.name())));
+            }
+            let path = output::resolve_path(output, args.on_exists)?;
+            let mut encoder = VideoEncoder::new(&path, settings.width, settings.height, args.fps, args.bitrate.as_deref())?;
+            for n in frames.start..=frames.end {
+                log::info!("Frame {}", n);
+                let time = n as f64 / args.fps;
+                let mut frame = FrameReport { frame: Some(n), time, ..FrameReport::default() };
+                let scene = Timings::measure(&mut frame.timings.scene, || scene(time))?;
+                let data = Timings::measure(&mut frame.timings.render, || renderer.render(&scene, &settings));
+                Timings::measure(&mut frame.timings.write, || encoder.push_frame(&data, options.encoding()))?;
+                frame.output = path.display().to_string();
+                frame.stats = Stats::take();
+                report.frames.push(frame);
+                // the video ends with the partial frame
+                if renderer.is_cancelled() {
+                    encoder.finish()?;
+                    return Err(RendererError::Cancelled);
+                }
+            }
+            encoder.finish()?;
+        }
+        Some(frames) => {
+            for n in frames.start..=frames.end {
+                log::info!("Frame {}", n);
+                let time = n as f64 / args.fps;
+                let mut frame = FrameReport { frame: Some(n), time, ..FrameReport::default() };
+                let scene = Timings::measure(&mut frame.timings.scene, || scene(time))?;
+                let data = Timings::measure(&mut frame.timings.render, || renderer.render(&scene, &settings));
+                let path = output::resolve_path(frame_path(&output, n), args.on_exists)?;
+                Timings::measure(&mut frame.timings.write, || write(&path, &data, format, args, &options))?;
+                frame.output = path.display().to_string();
+                frame.stats = Stats::take();
+                report.frames.push(frame);
+                if renderer.is_cancelled() {
+                    return Err(RendererError::Cancelled);
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn run_diff(args: &DiffArgs) -> Result<()> {
+    let reference = input::read_image(&args.reference)?;
+    let test = input::read_image(&args.test)?;
+    let result = diff::compare(&reference, &test)?;
+
+    println!("RMSE: {:.6}", result.rmse);
+    println!("PSNR: {:.2} dB", result.psnr);
+    println!("FLIP: {:.6}", result.flip);
+
+    if let Some(path) = &args.heatmap {
+        let map = diff::heatmap(&result, reference.width(), reference.height());
+        let format = Format::from_path(path).unwrap_or(Format::Png);
+        output::write(path, &map, format, &WriteOptions::default())?;
+    }
+    Ok(())
+}
+
+fn run_accumulate(args: &AccumulateArgs) -> Result<()> {
+    let resumed = (args.resume && args.output.exists()).then_some(&args.output);
+    let mut accumulator = Accumulator::new();
+    for path in resumed.into_iter().chain(&args.inputs) {
+        accumulator.add(path, input::read_image(path)?)?;
+    }
+    let samples = accumulator.samples();
+    let Some(image) = accumulator.finish() else { return Ok(()) };
+
+    let format = Format::from_path(&args.output).unwrap_or(Format::Png);
+    output::write(&args.output, &image, format, &WriteOptions::default())?;
+    log::info!("{} renders merged into {}, {} samples per pixel", args.inputs.len() + resumed.iter().count(),
+               args.output.display(), samples);
+    Ok(())
+}
+
+// Every material in both white furnaces, see furnace::Furnace, failing when one of them comes
+// out further from 1 than the tolerance
+fn run_furnace(args: &FurnaceArgs, renderer: Renderer) -> Result<()> {
+    let white = Color::new(1.0, 1.0, 1.0);
+    let materials: Vec<(String, Arc<dyn Scatter>)> = match &args.file {
+        None => vec![
+            ("lambertian".to_string(), Arc::new(Lambertian::new(white))),
+            ("metal".to_string(), Arc::new(Metal::new(white, 0.0))),
+            ("metal fuzz 0.5".to_string(), Arc::new(Metal::new(white, 0.5))),
+            ("metal fuzz 1".to_string(), Arc::new(Metal::new(white, 1.0))),
+            ("dielectric".to_string(), Arc::new(Dielectric::new(1.5))),
+        ],
+        Some(file) => {
+            let mut materials = MaterialLibrary::load(file)?.build()?;
+            match &args.material {
+                Some(name) => vec![(name.clone(), materials.remove(name).ok_or_else(|| {
+                    RendererError::Scene(format!("{} has no material '{}'", file.display(), name))
+                })?)],
+                None if materials.is_empty() => {
+                    return Err(RendererError::Scene(format!("{} has no materials", file.display())));
+                }
+                None => {
+                    let mut all: Vec<_> = materials.into_iter().collect();
+                    all.sort_by(|a, b| a.0.cmp(&b.0));
+                    all
+                }
+            }
+        }
+    };
+
+    let settings = furnace::settings(args.size, args.samples, args.seed.unwrap_or(0));
+    let renderer = renderer.with_cancel(cancel_on_ctrl_c());
+    let width = materials.iter().map(|(name, _)| name.len()).max().unwrap_or(0).max(8);
+    println!("{:<width$}  {:<8} {:>8} {:>8}", "material", "furnace", "mean", "error");
+    let mut failed = Vec::new();
+    for (name, material) in materials {
+        for f in furnace::Furnace::ALL {
+            let mean = furnace::measure(&renderer, f, material.clone(), &settings);
+            if renderer.is_cancelled() {
+                return Err(RendererError::Cancelled);
+            }
+            let error = mean - 1.0;
+            let over = error.abs() > args.tolerance;
+            println!("{:<width$}  {:<8} {:>8.4} {:>+7.2}%{}", name, f.name(), mean, 100.0 * error, if over { "  !" } else { "" });
+            if over {
+                failed.push(format!("{} in the {}", name, f.name()));
+            }
+        }
+    }
+    if failed.is_empty() {
+        Ok(())
+    } else {
+        Err(RendererError::Unsupported(format!("energy off by more than {}% for {}", 100.0 * args.tolerance, failed.join(", "))))
+    }
+}
+
+fn run_bake(args: &BakeArgs, overrides: &[Override], renderer: Renderer) -> Result<()> {
+    let file = overrides::apply(SceneFile::load(&args.scene)?, overrides)?;
+    let settings = file.settings();
+    let scene = file.build(0.0, settings.aspect_ratio())?;
+    let triangles = bake::mesh_triangles(&file, &args.object)?;
+    let options = Bake {
+        mode: args.mode,
+        width: args.size,
+        height: args.size,
+        samples: args.samples,
+        max_depth: settings.max_depth,
+        seed: args.seed.unwrap_or(settings.seed),
+        padding: args.padding,
+        distance: args.distance,
+    };
+    let renderer = renderer.with_cancel(cancel_on_ctrl_c());
+    let start = Instant::now();
+    let data = bake::bake(&renderer, &scene, &triangles, &options);
+    if renderer.is_cancelled() {
+        return Err(RendererError::Cancelled);
+    }
+
+    let format = args.output.as_deref().and_then(Format::from_path).unwrap_or(Format::Png);
+    let output = args.output.clone().unwrap_or_else(|| output::default_path(format));
+    let path = output::resolve_path(output, Collision::Overwrite)?;
+    output::write(&path, &data, format, &WriteOptions::default())?;
+    log::info!("{} of {} ({} triangles) baked into {} in {:.2?}", args.mode.name(), args.object, triangles.len(),
+               path.display(), start.elapsed());
+    Ok(())
+}
+
+// built-in scenes are looked at as they'd be saved to a scene file
+fn run_inspect(args: &InspectArgs, overrides: &[Override]) -> Result<()> {
+    let file = match Source::load(Some(&args.scene), None, overrides, false)? {
+        Source::File(file) => *file,
+        Source::Builtin(b) => {
+            let settings = b.settings();
+            SceneFile::from_scene(&b.build(0.0, settings.aspect_ratio()), &settings)?
+        }
+    };
+    let info = inspect::inspect(&file)?;
+    let settings = file.settings();
+
+    println!("Render: {}x{}, {} spp, max depth {}", settings.width, settings.height, settings.samples_per_pixel, settings.max_depth);
+    println!("Primitives: {}", info.primitives());
+    println!("  spheres: {}", info.spheres);
+    println!("  shells: {}", info.shells);
+    println!("  triangles: {}", info.triangles);
+    println!("  fractals: {}", info.fractals);
+    println!("  volumes: {}", info.volumes);
+    println!("  meshes: {} ({} triangles)", info.meshes.len(), info.meshes.iter().map(|(_, n)| n).sum::<usize>());
+    for (path, triangles) in &info.meshes {
+        println!("    {}: {} triangles", path.display(), triangles);
+    }
+    println!("  animated: {}", info.animated);
+    println!("  groups: {} ({} instances)", info.groups, info.instances);
+
+    let bvh = info.bvh_estimate();
+    println!("Acceleration: none, every ray is tested against all {} primitives", info.primitives());
+    println!("  a BVH would be {} levels deep with {} nodes, about {:.1} KiB", bvh.depth, bvh.nodes, bvh.bytes as f64 / 1024.0);
+
+    match info.bounds {
+        Some(b) => println!("Bounds: {} to {} (size {})", b.min, b.max, b.max - b.min),
+        None => println!("Bounds: the scene is empty"),
+    }
+
+    let used = |users: usize, what: &str| match users {
+        0 => "unused".to_string(),
+        1 => format!("1 {}", what),
+        n => format!("{} {}s", n, what),
+    };
+    println!("Materials: {}", info.materials.len());
+    for (name, (kind, users)) in &info.materials {
+        println!("  {} ({}): {}", name, kind, used(*users, "object"));
+    }
+    println!("Textures: {}", info.textures.len());
+    for (name, (kind, users)) in &info.textures {
+        println!("  {} ({}): {}", name, kind, used(*users, "material"));
+    }
+    Ok(())
+}
+
+fn main() {
+    let args = Args::parse();
+    logger::init(args.verbose, args.quiet);
+    if let Some(command) = &args.command {
+        let result = match command {
+            Command::Diff(diff_args) => run_diff(diff_args),
+            Command::Turntable(turntable_args) => renderer(&args).and_then(|r| turntable::run(turntable_args, &args.overrides, r)),
+            Command::Serve(serve_args) => renderer(&args).and_then(|r| server::serve(serve_args, r)),
+            Command::MatPreview(preview_args) => renderer(&args).and_then(|r| matpreview::run(preview_args, r)),
+            Command::Inspect(inspect_args) => run_inspect(inspect_args, &args.overrides),
+            Command::Accumulate(accumulate_args) => run_accumulate(accumulate_args),
+            Command::Sweep(sweep_args) => renderer(&args).and_then(|r| sweep::run(sweep_args, &args.overrides, r)),
+            Command::Bake(bake_args) => renderer(&args).and_then(|r| run_bake(bake_args, &args.overrides, r)),
+            Command::Furnace(furnace_args) => renderer(&args).and_then(|r| run_furnace(furnace_args, r)),
+        };
+        if let Err(e) = result {
+            log::error!("{}", e);
+            process::exit(1);
+        }
+        return;
+    }
+    let start = Instant::now();
+    let mut report = Report::new();
+
+    // a failed render still gets its report written, panics included
+    let result = panic::catch_unwind(AssertUnwindSafe(|| run(&args, &mut report)));
+    let error = match result {
+        Ok(Ok(())) => None,
+        Ok(Err(e)) => {
+            log::error!("{}", e);
+            Some(e.to_string())
+        }
+        // the panic hook already printed it
+        Err(e) => Some(e.downcast_ref::<String>().cloned()
+            .or_else(|| e.downcast_ref::<&str>().map(|s| s.to_string()))
+            .unwrap_or_else(|| "unknown error".to_string())),
+    };
+    let mut failed = error.is_some();
+    report.finish(start.elapsed(), error);
+
+    if let Some(path) = &args.report {
+        if let Err(e) = report.write(path) {
+            log::error!("{}", e);
+            failed = true;
+        }
+    }
+    if failed {
+        process::exit(1);
     }
 }
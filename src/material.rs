@@ -1,21 +1,71 @@
-use rand::Rng;
-use crate::{Color, Ray, Vec3};
-use crate::hit::HitRecord;
+use std::fmt::Debug;
+use std::sync::Arc;
+use crate::{random, Color, Point3, Ray, Vec3};
+use crate::error::{RendererError, Result};
+use crate::dirt::{Dirt, DirtKind};
+use crate::hit::{HitRecord, HittableList};
+use crate::occlusion::Occlusion;
+use crate::scene_file::{Exporter, MaskDesc, MaterialDesc, TextureDesc};
+use crate::texture::{SolidColor, Texture, UvTransform};
+use crate::visibility::TraceGroup;
+use crate::Float;
 
-pub trait Scatter : Send + Sync {
+pub trait Scatter : Send + Sync + Debug {
     fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Color, Ray)>;
+
+    // light given off by the surface, nothing for everything but lights
+    fn emitted(&self, _u: Float, _v: Float, _p: Point3) -> Color {
+        Color::new(0.0, 0.0, 0.0)
+    }
+
+    // whether emitted gives anything off at all
+    fn is_light(&self) -> bool {
+        false
+    }
+
+    // the material as written in a scene file, textures get registered with `out`
+    fn export(&self, _out: &mut Exporter) -> Result<MaterialDesc> {
+        Err(RendererError::Unsupported(format!("{} can't be saved to a scene file", std::any::type_name::<Self>())))
+    }
+
+    // the trace group the material puts its scattered rays in, when it's not the usual one
+    fn trace_group(&self) -> Option<&str> {
+        None
+    }
+
+    // For materials made of others (see Mix), the one that shades `rec`, picked knowing the
+    // scene around it. None for the rest, which shade everything themselves.
+    fn pick(&self, _rec: &HitRecord, _world: &HittableList, _time: Float) -> Option<Arc<dyn Scatter>> {
+        None
+    }
 }
 
+#[derive(Debug)]
 pub struct Lambertian {
-    albedo: Color
+    albedo: Arc<dyn Texture>,
+    uv: Option<UvTransform>,
 }
 
 impl Lambertian {
     pub fn new(a: Color) -> Lambertian {
         Lambertian {
-            albedo: a
+            albedo: Arc::new(SolidColor::new(a)),
+            uv: None,
+        }
+    }
+
+    pub fn textured(a: Arc<dyn Texture>) -> Lambertian {
+        Lambertian {
+            albedo: a,
+            uv: None,
         }
     }
+
+    // the texture moved, turned or repeated over the object's UVs
+    pub fn with_uv(mut self, transform: UvTransform) -> Lambertian {
+        self.uv = Some(transform);
+        self
+    }
 }
 
 impl Scatter for Lambertian {
@@ -33,19 +83,30 @@ impl Scatter for Lambertian {
             // Catches degenerate scatter direction
             scatter_dir = rec.normal;
         }
-        let scattered = Ray::new(rec.p, scatter_dir);
+        let scattered = rec.spawn_ray(scatter_dir)
+            .with_time(r_in.time())
+            .with_group(TraceGroup::DIFFUSE);
 
-        Some((self.albedo, scattered))
+        let (u, v) = self.uv.map_or((rec.u, rec.v), |t| t.apply(rec.u, rec.v));
+        Some((self.albedo.value(u, v, rec.p), scattered))
+    }
+
+    fn export(&self, out: &mut Exporter) -> Result<MaterialDesc> {
+        Ok(match self.albedo.export()? {
+            TextureDesc::Solid { color } => MaterialDesc::Lambertian { albedo: Some(color), texture: None, uv: None },
+            _ => MaterialDesc::Lambertian { albedo: None, texture: Some(out.texture(&self.albedo)?), uv: self.uv },
+        })
     }
 }
 
+#[derive(Debug)]
 pub struct Metal {
     albedo: Color,
-    fuzz: f64,
+    fuzz: Float,
 }
 
 impl Metal {
-    pub fn new(a: Color, f: f64) -> Metal {
+    pub fn new(a: Color, f: Float) -> Metal {
         Metal {
             albedo: a,
             fuzz: f,
@@ -55,8 +116,12 @@ impl Metal {
 
 impl Scatter for Metal {
     fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Color, Ray)> {
-        let reflected = r_in.direction().reflect(rec.normal).normalized();
-        let scattered = Ray::new(rec.p, reflected + self.fuzz*Vec3::rand_in_unit_sphere());
+        let fuzz = self.fuzz*Vec3::rand_in_unit_sphere();
+        let mirror = |d: Vec3| d.reflect(rec.normal).normalized() + fuzz;
+        let scattered = rec.spawn_ray(mirror(r_in.direction()))
+            .with_time(r_in.time())
+            .with_differentials(rec.scatter_differentials(r_in, mirror))
+            .with_group(TraceGroup::SPECULAR);
 
         if scattered.direction().dot(rec.normal) > 0.0 {
             Some((self.albedo, scattered))
@@ -64,20 +129,25 @@ impl Scatter for Metal {
             None
         }
     }
+
+    fn export(&self, _out: &mut Exporter) -> Result<MaterialDesc> {
+        Ok(MaterialDesc::Metal { albedo: self.albedo.to_array(), fuzz: self.fuzz })
+    }
 }
 
+#[derive(Debug)]
 pub struct Dielectric {
-    ir: f64,
+    ir: Float,
 }
 
 impl Dielectric {
-    pub fn new(index_of_refraction: f64) -> Dielectric {
+    pub fn new(index_of_refraction: Float) -> Dielectric {
         Dielectric {
             ir: index_of_refraction,
         }
     }
 
-    fn reflectance(cosine: f64, ref_idx: f64) -> f64 {
+    fn reflectance(cosine: Float, ref_idx: Float) -> Float {
         // Using Schlick's approximation for reflectance
         let r0 = ((1.0-ref_idx) / (1.0+ref_idx)).powi(2);
         r0 + (1.0-r0)*(1.0-cosine).powi(5)
@@ -96,18 +166,188 @@ impl Scatter for Dielectric {
         let cos_theta = ((-1.0)*unit_dir).dot(rec.normal).min(1.0);
         let sin_theta = (1.0-cos_theta.powi(2)).sqrt();
 
-        let mut rng = rand::thread_rng();
         let cannot_refr = refr_rat*sin_theta > 1.0;
-        let will_refl = rng.gen::<f64>() < Self::reflectance(cos_theta, refr_rat);
+        let will_refl = random::gen::<Float>() < Self::reflectance(cos_theta, refr_rat);
 
-        let dir = if cannot_refr || will_refl {
-            unit_dir.reflect(rec.normal)
+        // the neighbouring rays go the same way, even where they'd be reflected by themselves
+        let bend = |d: Vec3| if cannot_refr || will_refl {
+            d.reflect(rec.normal)
         } else {
-            unit_dir.refract(rec.normal, refr_rat)
+            d.refract(rec.normal, refr_rat)
         };
 
-        let scattered = Ray::new(rec.p, dir);
+        let group = if cannot_refr || will_refl { TraceGroup::SPECULAR } else { TraceGroup::TRANSMISSION };
+        let scattered = rec.spawn_ray(bend(unit_dir))
+            .with_time(r_in.time())
+            .with_differentials(rec.scatter_differentials(r_in, bend))
+            .with_group(group);
 
         Some((Color::new(1.0, 1.0, 1.0), scattered))
     }
-}
\ No newline at end of file
+
+    fn export(&self, _out: &mut Exporter) -> Result<MaterialDesc> {
+        Ok(MaterialDesc::Dielectric { ir: self.ir })
+    }
+}
+
+// Emissive surface, doesn't scatter anything
+#[derive(Debug)]
+pub struct DiffuseLight {
+    emit: Arc<dyn Texture>,
+}
+
+impl DiffuseLight {
+    pub fn new(c: Color) -> DiffuseLight {
+        DiffuseLight {
+            emit: Arc::new(SolidColor::new(c)),
+        }
+    }
+}
+
+impl Scatter for DiffuseLight {
+    fn scatter(&self, _r_in: &Ray, _rec: &HitRecord) -> Option<(Color, Ray)> {
+        None
+    }
+
+    fn emitted(&self, u: Float, v: Float, p: Point3) -> Color {
+        self.emit.value(u, v, p)
+    }
+
+    fn is_light(&self) -> bool {
+        true
+    }
+
+    fn export(&self, _out: &mut Exporter) -> Result<MaterialDesc> {
+        match self.emit.export()? {
+            TextureDesc::Solid { color } => Ok(MaterialDesc::Light { color, intensity: 1.0, temperature: None }),
+            _ => Err(RendererError::Unsupported("textured lights can't be saved to a scene file".to_string())),
+        }
+    }
+}
+
+// Two materials in one: `a` where the mask is 0, `b` where it's 1, and in between each hit
+// picks one at random with those odds, which averages out to the blend. For dirt in the
+// crevices or paint worn off the edges of what's underneath, see dirt::Dirt.
+#[derive(Debug)]
+pub struct Mix {
+    a: Arc<dyn Scatter>,
+    b: Arc<dyn Scatter>,
+    mask: Mask,
+}
+
+#[derive(Debug)]
+pub enum Mask {
+    // how bright the texture is, laid out by the transform when there is one
+    Texture(Arc<dyn Texture>, Option<UvTransform>),
+    Dirt(Dirt),
+}
+
+impl Mix {
+    pub fn new(a: Arc<dyn Scatter>, b: Arc<dyn Scatter>, mask: Mask) -> Mix {
+        Mix {
+            a,
+            b,
+            mask,
+        }
+    }
+
+    // without the scene around the hit dirt can't be told, it's taken as clean
+    fn choose(&self, rec: &HitRecord, world: Option<(&HittableList, Float)>) -> &Arc<dyn Scatter> {
+        let amount = match (&self.mask, world) {
+            (Mask::Texture(t, uv), _) => {
+                let (u, v) = uv.map_or((rec.u, rec.v), |uv| uv.apply(rec.u, rec.v));
+                let c = t.value(u, v, rec.p);
+                (c[0] + c[1] + c[2]) / 3.0
+            }
+            (Mask::Dirt(dirt), Some((world, time))) => dirt.at(rec, world, time),
+            (Mask::Dirt(_), None) => 0.0,
+        };
+        if random::gen::<Float>() < amount { &self.b } else { &self.a }
+    }
+}
+
+impl Scatter for Mix {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Color, Ray)> {
+        self.choose(rec, None).scatter(r_in, rec)
+    }
+
+    fn is_light(&self) -> bool {
+        self.a.is_light() || self.b.is_light()
+    }
+
+    fn pick(&self, rec: &HitRecord, world: &HittableList, time: Float) -> Option<Arc<dyn Scatter>> {
+        Some(self.choose(rec, Some((world, time))).clone())
+    }
+
+    fn export(&self, out: &mut Exporter) -> Result<MaterialDesc> {
+        let mask = match &self.mask {
+            Mask::Texture(t, uv) => MaskDesc::Texture { texture: out.texture(t)?, uv: *uv },
+            Mask::Dirt(Dirt { kind: DirtKind::Crevices, distance, samples }) => MaskDesc::Crevices { distance: *distance, samples: *samples },
+            Mask::Dirt(Dirt { kind: DirtKind::Edges, distance, samples }) => MaskDesc::Edges { distance: *distance, samples: *samples },
+        };
+        Ok(MaterialDesc::Mix { a: out.material(&self.a)?, b: out.material(&self.b)?, mask })
+    }
+}
+
+// The ground of a studio setup (see studio::apply), there only to take the shadows of what
+// stands on it. Rays go straight through to whatever is behind, the backdrop mostly, but as
+// often as the hemisphere above the hit is blocked within `distance` they're stopped there, so
+// the ground darkens under and around the objects like ambient occlusion and is invisible
+// everywhere else.
+#[derive(Debug)]
+pub struct ShadowCatcher {
+    occlusion: Occlusion,
+    through: Arc<dyn Scatter>,
+    stopped: Arc<dyn Scatter>,
+}
+
+impl ShadowCatcher {
+    pub fn new(distance: Float, samples: u32) -> ShadowCatcher {
+        ShadowCatcher {
+            occlusion: Occlusion::new(distance, samples),
+            through: Arc::new(Through),
+            stopped: Arc::new(Stopped),
+        }
+    }
+}
+
+impl Scatter for ShadowCatcher {
+    // without the scene around the hit there's no telling, it's taken as open
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Color, Ray)> {
+        self.through.scatter(r_in, rec)
+    }
+
+    fn pick(&self, rec: &HitRecord, world: &HittableList, time: Float) -> Option<Arc<dyn Scatter>> {
+        let open = self.occlusion.at(rec, world, time);
+        Some(if random::gen::<Float>() < open { self.through.clone() } else { self.stopped.clone() })
+    }
+
+    fn export(&self, _out: &mut Exporter) -> Result<MaterialDesc> {
+        Ok(MaterialDesc::ShadowCatcher { distance: self.occlusion.distance, samples: self.occlusion.samples })
+    }
+}
+
+// the two ways a ray leaves a shadow catcher, as if it wasn't there and in its shadow
+#[derive(Debug)]
+struct Through;
+
+impl Scatter for Through {
+    // still the ray it was, in its group and lobe, so a camera ray goes on seeing the backdrop
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Color, Ray)> {
+        let ray = rec.spawn_ray(r_in.direction())
+            .with_time(r_in.time())
+            .with_differentials(r_in.differentials())
+            .with_group(r_in.lobe())
+            .with_group(r_in.group());
+        Some((Color::new(1.0, 1.0, 1.0), ray))
+    }
+}
+
+#[derive(Debug)]
+struct Stopped;
+
+impl Scatter for Stopped {
+    fn scatter(&self, _r_in: &Ray, _rec: &HitRecord) -> Option<(Color, Ray)> {
+        None
+    }
+}
@@ -0,0 +1,47 @@
+use raytracer_test::output::{self, Collision, Format, WriteOptions};
+use raytracer_test::render::{Renderer, Settings};
+use raytracer_test::scene_file::MaterialLibrary;
+use raytracer_test::scenes;
+use raytracer_test::{RendererError, Result};
+use crate::cli::MatPreviewArgs;
+
+// Renders one material of the file on the shader ball scene, see scenes::shader_ball
+pub fn run(args: &MatPreviewArgs, renderer: Renderer) -> Result<()> {
+    let mut materials = MaterialLibrary::load(&args.file)?.build()?;
+    let material = match &args.material {
+        Some(name) => materials.remove(name).ok_or_else(|| {
+            RendererError::Scene(format!("{} has no material '{}'", args.file.display(), name))
+        })?,
+        None if materials.len() == 1 => materials.into_values().next().unwrap(),
+        None if materials.is_empty() => {
+            return Err(RendererError::Scene(format!("{} has no materials", args.file.display())));
+        }
+        None => {
+            let mut names: Vec<&str> = materials.keys().map(String::as_str).collect();
+            names.sort();
+            return Err(RendererError::Scene(format!("{} has several materials, pick one with --material: {}",
+                                                    args.file.display(), names.join(", "))));
+        }
+    };
+
+    let settings = Settings {
+        width: args.size,
+        height: args.size,
+        samples_per_pixel: args.samples,
+        max_depth: 50,
+        seed: args.seed.unwrap_or(0),
+        ..Settings::default()
+    };
+    let scene = scenes::shader_ball(material, settings.aspect_ratio());
+    let renderer = renderer.with_cancel(crate::cancel_on_ctrl_c());
+    let data = renderer.render(&scene, &settings);
+
+    let format = args.output.as_deref().and_then(Format::from_path).unwrap_or(Format::Png);
+    let output = args.output.clone().unwrap_or_else(|| output::default_path(format));
+    let path = output::resolve_path(output, Collision::Overwrite)?;
+    output::write(&path, &data, format, &WriteOptions::default())?;
+    if renderer.is_cancelled() {
+        return Err(RendererError::Cancelled);
+    }
+    Ok(())
+}
@@ -0,0 +1,132 @@
+use std::path::Path;
+use std::sync::Arc;
+use crate::aabb::Aabb;
+use crate::error::{RendererError, Result};
+use crate::hit::HitRecord;
+use crate::material::Scatter;
+use crate::scene_file::Exporter;
+use crate::transform::Matrix;
+use crate::triangle::Triangle;
+use crate::{Float, Hit, Point3, Ray};
+
+// Triangle mesh, intersected triangle by triangle
+#[derive(Debug)]
+pub struct Mesh {
+    triangles: Vec<Triangle>,
+}
+
+impl Mesh {
+    pub fn new(triangles: Vec<Triangle>) -> Mesh {
+        Mesh {
+            triangles
+        }
+    }
+
+    // Wavefront OBJ, only positions, texture coordinates and faces are read. Polygons are
+    // split into triangle fans.
+    pub fn load_obj(path: &Path, m: Arc<dyn Scatter>) -> Result<Mesh> {
+        let text = std::fs::read_to_string(path).map_err(|e| RendererError::io(path, e))?;
+        let mut positions = Vec::new();
+        let mut uvs = Vec::new();
+        let mut triangles = Vec::new();
+
+        for (n, line) in text.lines().enumerate() {
+            let err = |what: &str| RendererError::parse(path, format!("line {}: {}", n + 1, what));
+            let mut fields = line.split_whitespace();
+            let nums = |fields: std::str::SplitWhitespace| -> Result<Vec<Float>> {
+                fields.map(|f| f.parse::<Float>().map_err(|_| err("bad number"))).collect()
+            };
+
+            match fields.next() {
+                Some("v") => {
+                    let v = nums(fields)?;
+                    if v.len() < 3 {
+                        return Err(err("vertex needs 3 coordinates"));
+                    }
+                    positions.push(Point3::new(v[0], v[1], v[2]));
+                }
+                Some("vt") => {
+                    let v = nums(fields)?;
+                    uvs.push((v.first().copied().unwrap_or(0.0), v.get(1).copied().unwrap_or(0.0)));
+                }
+                Some("f") => {
+                    // "v", "v/vt", "v//vn" or "v/vt/vn", indices are 1-based and negative ones count from the end
+                    let corners = fields
+                        .map(|f| {
+                            let mut idx = f.split('/');
+                            let resolve = |i: Option<&str>, len: usize| -> Result<Option<usize>> {
+                                match i.filter(|s| !s.is_empty()) {
+                                    None => Ok(None),
+                                    Some(s) => {
+                                        let i: i64 = s.parse().map_err(|_| err("bad index"))?;
+                                        let i = if i < 0 { len as i64 + i } else { i - 1 };
+                                        if i < 0 || i as usize >= len {
+                                            return Err(err("index out of range"));
+                                        }
+                                        Ok(Some(i as usize))
+                                    }
+                                }
+                            };
+                            let v = resolve(idx.next(), positions.len())?.ok_or_else(|| err("missing vertex index"))?;
+                            let vt = resolve(idx.next(), uvs.len())?;
+                            Ok((v, vt))
+                        })
+                        .collect::<Result<Vec<_>>>()?;
+                    if corners.len() < 3 {
+                        return Err(err("face needs at least 3 vertices"));
+                    }
+
+                    for i in 1..corners.len() - 1 {
+                        let c = [corners[0], corners[i], corners[i + 1]];
+                        let mut tri = Triangle::new(positions[c[0].0], positions[c[1].0], positions[c[2].0], m.clone());
+                        if let (Some(a), Some(b), Some(d)) = (c[0].1, c[1].1, c[2].1) {
+                            tri = tri.with_uv([uvs[a], uvs[b], uvs[d]]);
+                        }
+                        triangles.push(tri);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        log::debug!("{}: {} vertices, {} triangles", path.display(), positions.len(), triangles.len());
+        Ok(Mesh::new(triangles))
+    }
+
+    pub fn triangles(&self) -> &[Triangle] {
+        &self.triangles
+    }
+
+    pub fn into_triangles(self) -> Vec<Triangle> {
+        self.triangles
+    }
+
+    pub fn transformed(self, m: &Matrix) -> Mesh {
+        Mesh::new(self.triangles.into_iter().map(|t| t.transformed(m)).collect())
+    }
+}
+
+impl Hit for Mesh {
+    fn hit(&self, r: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord> {
+        let mut tmp_rec = None;
+        let mut closest_so_far = t_max;
+
+        for tri in &self.triangles {
+            if let Some(rec) = tri.hit(r, t_min, closest_so_far) {
+                closest_so_far = rec.t;
+                tmp_rec = Some(rec);
+            }
+        }
+
+        tmp_rec
+    }
+
+    fn bounding_box(&self, _time0: Float, _time1: Float) -> Option<Aabb> {
+        Aabb::around(self.triangles.iter().flat_map(Triangle::vertices))
+    }
+
+    // written out triangle by triangle, the OBJ file it came from isn't kept
+    fn export(&self, out: &mut Exporter) -> Result<()> {
+        self.triangles.iter().try_for_each(|tri| tri.export(out))
+    }
+}
@@ -0,0 +1,32 @@
+use std::time::Duration;
+use crate::scene::Scene;
+use crate::render::Settings;
+
+// Settings a render was made with, written into the image so it can be reproduced from the file alone
+pub fn render_metadata(scene: &Scene, settings: &Settings,
+                       duration: Option<Duration>) -> Vec<(String, String)> {
+    let mut metadata = vec![
+        ("Software".to_string(), format!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"))),
+        ("SceneHash".to_string(), format!("{:016x}", fnv1a(format!("{:?} {:?}", scene.world, scene.environment).as_bytes()))),
+        ("Resolution".to_string(), format!("{}x{}", settings.width, settings.height)),
+        ("Samples".to_string(), settings.samples_per_pixel.to_string()),
+        ("MaxDepth".to_string(), settings.max_depth.to_string()),
+        ("Seed".to_string(), settings.seed.to_string()),
+        ("Time".to_string(), scene.time.to_string()),
+        ("Camera".to_string(), scene.camera.describe()),
+    ];
+    // left out when there are none, like in renders from before they were there
+    metadata.extend(settings.limits.describe().map(|limits| ("PathLimits".to_string(), limits)));
+    metadata.extend(scene.working_space.metadata());
+    metadata.extend(scene.scale.map(|scale| ("SceneScale".to_string(), scale.to_string())));
+    // not known yet when the image is streamed out during the render
+    if let Some(d) = duration {
+        metadata.push(("RenderDuration".to_string(), format!("{:.3}s", d.as_secs_f64())));
+    }
+    metadata
+}
+
+// FNV-1a, stable across builds unlike std's hasher
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |h, &b| (h ^ b as u64).wrapping_mul(0x100000001b3))
+}
@@ -0,0 +1,466 @@
+// Importer for Mitsuba 0.6/2/3 XML scenes, translated into a SceneFile like the PBRT importer.
+// Supported: the perspective sensor with its film and sampler, the path integrator's max depth,
+// <default> parameters, to_world transforms, diffuse/conductor/dielectric/plastic BSDFs
+// (twosided is unwrapped) with RGB or bitmap reflectance, area emitters with RGB or blackbody
+// radiance, constant emitters, and sphere, rectangle, cube and OBJ shapes. Anything else is
+// skipped with a warning.
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use roxmltree::{Document, Node};
+use crate::error::{RendererError, Result};
+use crate::scene_file::{BackgroundDesc, CameraDesc, EnvironmentDesc, MaterialDesc, ObjectDesc, RenderDesc, SceneFile, TextureDesc};
+use crate::texture::Wrap;
+use crate::transform::{self, mirror, mul, rotate, scale, translate, Matrix, Up, IDENTITY};
+use crate::{Float, Point3, Vec3};
+
+// named indices of refraction Mitsuba accepts in place of a number
+const IOR: [(&str, Float); 8] = [
+    ("vacuum", 1.0),
+    ("air", 1.000277),
+    ("water", 1.333),
+    ("acrylic glass", 1.49),
+    ("polypropylene", 1.49),
+    ("bk7", 1.5046),
+    ("sapphire", 1.77),
+    ("diamond", 2.419),
+];
+
+struct Importer<'a> {
+    path: &'a Path,
+    doc: &'a Document<'a>,
+    defaults: HashMap<String, String>,
+    materials: HashMap<String, MaterialDesc>,
+    textures: HashMap<String, TextureDesc>,
+    objects: Vec<ObjectDesc>,
+    background: BackgroundDesc,
+    render: RenderDesc,
+    camera: Option<(Matrix, CameraDesc)>,
+}
+
+impl<'a> Importer<'a> {
+    fn line(&self, node: Node) -> u32 {
+        self.doc.text_pos_at(node.range().start).row
+    }
+
+    fn error(&self, node: Node, message: impl std::fmt::Display) -> RendererError {
+        RendererError::parse(self.path, format!("line {}: {}", self.line(node), message))
+    }
+
+    fn warn(&self, node: Node, message: impl std::fmt::Display) {
+        log::warn!("{}: line {}: {}", self.path.display(), self.line(node), message);
+    }
+
+    // attribute with $name references to <default> parameters filled in
+    fn attr(&self, node: Node, name: &str) -> Option<String> {
+        let value = node.attribute(name)?;
+        match value.strip_prefix('$') {
+            Some(key) => self.defaults.get(key).cloned().or_else(|| Some(value.to_string())),
+            None => Some(value.to_string()),
+        }
+    }
+
+    fn number(&self, node: Node, name: &str) -> Result<Float> {
+        let s = self.attr(node, name).ok_or_else(|| self.error(node, format!("missing '{}'", name)))?;
+        s.trim().parse().map_err(|_| self.error(node, format!("'{}' is not a number", s)))
+    }
+
+    // "x, y, z" or "x y z", a single value is repeated
+    fn numbers(&self, node: Node, name: &str) -> Result<Vec<Float>> {
+        let s = self.attr(node, name).ok_or_else(|| self.error(node, format!("missing '{}'", name)))?;
+        let v = s.split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|p| !p.is_empty())
+            .map(|p| p.parse::<Float>().map_err(|_| self.error(node, format!("'{}' is not a number", p))))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(v)
+    }
+
+    fn vec3(&self, node: Node, name: &str) -> Result<Vec3> {
+        let v = self.numbers(node, name)?;
+        match v.len() {
+            1 => Ok(Vec3::new(v[0], v[0], v[0])),
+            3 => Ok(Vec3::new(v[0], v[1], v[2])),
+            _ => Err(self.error(node, format!("'{}' needs 1 or 3 values", name))),
+        }
+    }
+
+    // <point name="center" x=".." y=".." z=".."/> or value="x, y, z"
+    fn xyz(&self, node: Node, default: Float) -> Result<Vec3> {
+        if node.has_attribute("value") {
+            return self.vec3(node, "value");
+        }
+        let get = |a: &str| if node.has_attribute(a) { self.number(node, a) } else { Ok(default) };
+        Ok(Vec3::new(get("x")?, get("y")?, get("z")?))
+    }
+
+    // child property element (<float>, <rgb>, ...) with the given name, either spelling
+    fn property(&self, node: Node<'a, 'a>, names: &[&str]) -> Option<Node<'a, 'a>> {
+        node.children().find(|c| c.is_element() && c.attribute("name").is_some_and(|n| names.contains(&n)))
+    }
+
+    fn float(&self, node: Node<'a, 'a>, names: &[&str], default: Float) -> Result<Float> {
+        match self.property(node, names) {
+            Some(p) => self.number(p, "value"),
+            None => Ok(default),
+        }
+    }
+
+    fn string(&self, node: Node<'a, 'a>, names: &[&str]) -> Option<String> {
+        self.property(node, names).and_then(|p| self.attr(p, "value"))
+    }
+
+    // <transform name="to_world">, each operation is applied after the ones before it
+    fn transform(&self, node: Node<'a, 'a>) -> Result<Matrix> {
+        let t = match self.property(node, &["to_world", "toWorld"]) {
+            Some(t) => t,
+            None => return Ok(IDENTITY),
+        };
+        let mut m = IDENTITY;
+        for op in t.children().filter(|c| c.is_element()) {
+            let step = match op.tag_name().name() {
+                "translate" => translate(self.xyz(op, 0.0)?),
+                "scale" => scale(self.xyz(op, 1.0)?),
+                "rotate" => rotate(self.number(op, "angle")?, self.xyz(op, 0.0)?),
+                "matrix" => {
+                    let v = self.numbers(op, "value")?;
+                    if v.len() != 16 {
+                        return Err(self.error(op, "a matrix needs 16 values"));
+                    }
+                    let mut m = [[0.0; 4]; 4];
+                    for (i, row) in m.iter_mut().enumerate() {
+                        row.copy_from_slice(&v[i*4..i*4 + 4]);
+                    }
+                    m
+                }
+                "lookat" => {
+                    let origin = self.vec3(op, "origin")?;
+                    let target = self.vec3(op, "target")?;
+                    let up = if op.has_attribute("up") { self.vec3(op, "up")? } else { Vec3::new(0.0, 1.0, 0.0) };
+                    let dir = (target - origin).normalized();
+                    let left = up.normalized().cross(dir);
+                    if left.near_zero() {
+                        return Err(self.error(op, "the up vector is parallel to the view direction"));
+                    }
+                    let left = left.normalized();
+                    let new_up = dir.cross(left);
+                    [
+                        [left.x(), new_up.x(), dir.x(), origin.x()],
+                        [left.y(), new_up.y(), dir.y(), origin.y()],
+                        [left.z(), new_up.z(), dir.z(), origin.z()],
+                        [0.0, 0.0, 0.0, 1.0],
+                    ]
+                }
+                other => {
+                    self.warn(op, format!("<{}> transforms are not supported, skipped", other));
+                    IDENTITY
+                }
+            };
+            m = mul(&step, &m);
+        }
+        Ok(m)
+    }
+
+    fn run(&mut self, root: Node<'a, 'a>) -> Result<()> {
+        for node in root.children().filter(|c| c.is_element()) {
+            match node.tag_name().name() {
+                "default" => {
+                    let name = self.attr(node, "name").ok_or_else(|| self.error(node, "<default> needs a name"))?;
+                    let value = self.attr(node, "value").ok_or_else(|| self.error(node, "<default> needs a value"))?;
+                    self.defaults.insert(name, value);
+                }
+                "integrator" => {
+                    let depth = self.float(node, &["max_depth", "maxDepth"], -1.0)?;
+                    // -1 means no limit, which we don't have
+                    self.render.max_depth = Some(if depth < 0.0 { 50 } else { depth as u64 });
+                }
+                "sensor" => self.sensor(node)?,
+                "bsdf" => {
+                    let id = self.attr(node, "id").ok_or_else(|| self.error(node, "top-level <bsdf> needs an id"))?;
+                    let material = self.bsdf(node)?;
+                    self.materials.insert(id, material);
+                }
+                "shape" => self.shape(node)?,
+                "emitter" => self.emitter(node)?,
+                other => self.warn(node, format!("<{}> is not supported, skipped", other)),
+            }
+        }
+        Ok(())
+    }
+
+    fn sensor(&mut self, node: Node<'a, 'a>) -> Result<()> {
+        if node.attribute("type") != Some("perspective") {
+            self.warn(node, "only perspective sensors are supported, rendering it as one");
+        }
+        for child in node.children().filter(|c| c.is_element()) {
+            match child.tag_name().name() {
+                "film" => {
+                    self.render.width = Some(self.float(child, &["width"], 768.0)? as u32);
+                    self.render.height = Some(self.float(child, &["height"], 576.0)? as u32);
+                }
+                "sampler" => {
+                    self.render.samples_per_pixel = Some(self.float(child, &["sample_count", "sampleCount"], 4.0)? as u32);
+                }
+                _ => {}
+            }
+        }
+
+        let (width, height) = (self.render.width.unwrap_or(768) as Float, self.render.height.unwrap_or(576) as Float);
+        let tan_vertical = match (self.property(node, &["fov"]), self.property(node, &["focal_length", "focalLength"])) {
+            (Some(f), _) => {
+                let tan = (self.number(f, "value")? / 2.0).to_radians().tan();
+                match self.string(node, &["fov_axis", "fovAxis"]).as_deref().unwrap_or("x") {
+                    "y" => tan,
+                    "diagonal" => tan * height / width.hypot(height),
+                    "smaller" if height <= width => tan,
+                    "larger" if height >= width => tan,
+                    _ => tan * height / width,
+                }
+            }
+            // 35mm film equivalent, measured along the diagonal
+            (None, f) => {
+                let mm = match f.and_then(|f| self.attr(f, "value")) {
+                    Some(s) => s.trim_end_matches("mm").parse().map_err(|_| self.error(node, "bad focal length"))?,
+                    None => 50.0,
+                };
+                Float::hypot(36.0, 24.0) / (2.0 * mm) * height / width.hypot(height)
+            }
+        };
+
+        let to_world = self.transform(node)?;
+        let camera = CameraDesc {
+            lookfrom: [0.0; 3],
+            lookat: [0.0; 3],
+            vup: [0.0, 1.0, 0.0],
+            vfov: 2.0 * tan_vertical.atan().to_degrees(),
+            aperture: 2.0 * self.float(node, &["aperture_radius", "apertureRadius"], 0.0)?,
+            focus_dist: self.property(node, &["focus_distance", "focusDistance"])
+                .map(|f| self.number(f, "value")).transpose()?,
+            lens: None,
+            projection: None,
+        };
+        self.camera = Some((to_world, camera));
+        Ok(())
+    }
+
+    fn rgb(&self, node: Node<'a, 'a>, names: &[&str], default: [Float; 3]) -> Result<[Float; 3]> {
+        match self.property(node, names) {
+            Some(p) if matches!(p.tag_name().name(), "rgb" | "spectrum" | "color" | "srgb") => {
+                let v = self.vec3(p, "value")?;
+                Ok([v.x(), v.y(), v.z()])
+            }
+            Some(p) => {
+                self.warn(p, format!("<{}> values are not supported, using the default", p.tag_name().name()));
+                Ok(default)
+            }
+            None => Ok(default),
+        }
+    }
+
+    // The temperature of a black body radiance: <blackbody temperature="2700K"/> in 0.6, a
+    // <spectrum type="blackbody"> with a temperature float in 2 and 3
+    fn blackbody(&self, node: Node<'a, 'a>) -> Result<Option<Float>> {
+        let Some(p) = self.property(node, &["radiance"]) else { return Ok(None) };
+        if p.has_tag_name("blackbody") {
+            let t = self.attr(p, "temperature").ok_or_else(|| self.error(p, "missing 'temperature'"))?;
+            let kelvin = t.trim().trim_end_matches(['K', 'k']).trim();
+            return kelvin.parse().map(Some).map_err(|_| self.error(p, format!("'{}' is not a temperature", t)));
+        }
+        if p.has_tag_name("spectrum") && p.attribute("type") == Some("blackbody") {
+            return self.float(p, &["temperature"], 6504.0).map(Some);
+        }
+        Ok(None)
+    }
+
+    fn ior(&self, node: Node<'a, 'a>) -> Result<Float> {
+        let p = match self.property(node, &["int_ior", "intIOR"]) {
+            Some(p) => p,
+            None => return Ok(1.5046),
+        };
+        let value = self.attr(p, "value").unwrap_or_default();
+        match IOR.iter().find(|(name, _)| *name == value) {
+            Some((_, ior)) => Ok(*ior),
+            None => self.number(p, "value"),
+        }
+    }
+
+    fn bsdf(&mut self, node: Node<'a, 'a>) -> Result<MaterialDesc> {
+        let ty = node.attribute("type").unwrap_or("diffuse");
+        Ok(match ty {
+            "twosided" => match node.children().find(|c| c.has_tag_name("bsdf")) {
+                Some(inner) => return self.bsdf(inner),
+                None => return Err(self.error(node, "twosided needs a nested <bsdf>")),
+            },
+            "diffuse" | "roughdiffuse" => {
+                // a bitmap texture or a constant
+                let bitmap = node.children()
+                    .find(|c| c.has_tag_name("texture") && c.attribute("type") == Some("bitmap"));
+                match bitmap.and_then(|b| self.string(b, &["filename"])) {
+                    Some(file) => {
+                        let name = format!("texture_{}", self.textures.len());
+                        self.textures.insert(name.clone(), TextureDesc::Image { file: PathBuf::from(file), wrap: Wrap::Clamp });
+                        MaterialDesc::Lambertian { albedo: None, texture: Some(name), uv: None }
+                    }
+                    None => MaterialDesc::Lambertian {
+                        albedo: Some(self.rgb(node, &["reflectance"], [0.5, 0.5, 0.5])?),
+                        texture: None,
+                        uv: None,
+                    },
+                }
+            }
+            "plastic" | "roughplastic" | "principled" => MaterialDesc::Lambertian {
+                albedo: Some(self.rgb(node, &["diffuse_reflectance", "diffuseReflectance", "base_color"], [0.5, 0.5, 0.5])?),
+                texture: None,
+                uv: None,
+            },
+            "conductor" | "roughconductor" => MaterialDesc::Metal {
+                albedo: self.rgb(node, &["specular_reflectance", "specularReflectance"], [0.9, 0.9, 0.9])?,
+                fuzz: if ty == "roughconductor" { self.float(node, &["alpha"], 0.1)?.clamp(0.0, 1.0) } else { 0.0 },
+            },
+            "dielectric" | "roughdielectric" | "thindielectric" => MaterialDesc::Dielectric { ir: self.ior(node)? },
+            _ => {
+                self.warn(node, format!("{} BSDFs are not supported, using a grey diffuse one", ty));
+                MaterialDesc::Lambertian { albedo: Some([0.5, 0.5, 0.5]), texture: None, uv: None }
+            }
+        })
+    }
+
+    fn emitter(&mut self, node: Node<'a, 'a>) -> Result<()> {
+        match node.attribute("type") {
+            Some("constant") => {
+                let color = self.rgb(node, &["radiance"], [1.0, 1.0, 1.0])?;
+                self.background = BackgroundDesc::Color { color };
+            }
+            ty => self.warn(node, format!("{} emitters are not supported, skipped", ty.unwrap_or("untyped"))),
+        }
+        Ok(())
+    }
+
+    fn shape(&mut self, node: Node<'a, 'a>) -> Result<()> {
+        // an area emitter wins over the BSDF, otherwise a reference or a nested BSDF
+        let material = if let Some(e) = node.children().find(|c| c.has_tag_name("emitter")) {
+            if e.attribute("type") != Some("area") {
+                self.warn(e, "only area emitters can be attached to shapes");
+            }
+            let (color, temperature) = match self.blackbody(e)? {
+                Some(t) => ([1.0, 1.0, 1.0], Some(t)),
+                None => (self.rgb(e, &["radiance"], [1.0, 1.0, 1.0])?, None),
+            };
+            let name = format!("light_{}", self.materials.len());
+            self.materials.insert(name.clone(), MaterialDesc::Light { color, intensity: 1.0, temperature });
+            name
+        } else if let Some(r) = node.children().find(|c| c.has_tag_name("ref")) {
+            let id = self.attr(r, "id").ok_or_else(|| self.error(r, "<ref> needs an id"))?;
+            if !self.materials.contains_key(&id) {
+                return Err(self.error(r, format!("unknown BSDF '{}'", id)));
+            }
+            id
+        } else if let Some(b) = node.children().find(|c| c.has_tag_name("bsdf")) {
+            let material = self.bsdf(b)?;
+            let name = format!("material_{}", self.materials.len());
+            self.materials.insert(name.clone(), material);
+            name
+        } else {
+            self.materials.entry("default".to_string())
+                .or_insert(MaterialDesc::Lambertian { albedo: Some([0.5, 0.5, 0.5]), texture: None, uv: None });
+            "default".to_string()
+        };
+
+        let m = self.transform(node)?;
+        let p = |x: Float, y: Float, z: Float| {
+            let p = transform::point(&m, Point3::new(x, y, z));
+            [p.x(), p.y(), p.z()]
+        };
+        let quad = |q: [[Float; 3]; 4], objects: &mut Vec<ObjectDesc>| {
+            objects.push(ObjectDesc::Triangle { name: None, vertices: [q[0], q[1], q[2]], uv: None, material: material.clone(), hidden: false, keyframes: Vec::new(), visible_to: None });
+            objects.push(ObjectDesc::Triangle { name: None, vertices: [q[0], q[2], q[3]], uv: None, material: material.clone(), hidden: false, keyframes: Vec::new(), visible_to: None });
+        };
+
+        match node.attribute("type") {
+            Some("sphere") => {
+                let center = match self.property(node, &["center"]) {
+                    Some(c) => self.xyz(c, 0.0)?,
+                    None => Vec3::new(0.0, 0.0, 0.0),
+                };
+                // only uniform scales keep a sphere a sphere
+                let radius = self.float(node, &["radius"], 1.0)? * transform::vector(&m, Vec3::new(1.0, 0.0, 0.0)).length();
+                self.objects.push(ObjectDesc::Sphere { name: None, center: p(center.x(), center.y(), center.z()), radius, material, hidden: false, keyframes: Vec::new(), visible_to: None });
+            }
+            Some("rectangle") => {
+                quad([p(-1.0, -1.0, 0.0), p(1.0, -1.0, 0.0), p(1.0, 1.0, 0.0), p(-1.0, 1.0, 0.0)], &mut self.objects);
+            }
+            Some("cube") => {
+                for (a, b) in [(0, 1), (1, 2), (0, 2)] {
+                    for side in [-1.0, 1.0] {
+                        // the face at `side` along the remaining axis
+                        let axis = 3 - a - b;
+                        let corner = |u: Float, v: Float| {
+                            let mut xyz = [0.0; 3];
+                            xyz[axis] = side;
+                            xyz[a] = u;
+                            xyz[b] = v;
+                            p(xyz[0], xyz[1], xyz[2])
+                        };
+                        quad([corner(-1.0, -1.0), corner(1.0, -1.0), corner(1.0, 1.0), corner(-1.0, 1.0)], &mut self.objects);
+                    }
+                }
+            }
+            Some("obj") => {
+                let file = self.string(node, &["filename"]).ok_or_else(|| self.error(node, "obj shapes need a filename"))?;
+                self.objects.push(ObjectDesc::Mesh { name: None, file: PathBuf::from(file), material, transform: Some(m), up: Up::Y, unit_scale: None, hidden: false, keyframes: Vec::new(), visible_to: None });
+            }
+            ty => self.warn(node, format!("{} shapes are not supported, skipped", ty.unwrap_or("untyped"))),
+        }
+        Ok(())
+    }
+}
+
+pub fn load(path: &Path) -> Result<SceneFile> {
+    let text = std::fs::read_to_string(path).map_err(|e| RendererError::io(path, e))?;
+    let doc = Document::parse(&text).map_err(|e| RendererError::parse(path, e))?;
+    let root = doc.root_element();
+    if !root.has_tag_name("scene") {
+        return Err(RendererError::parse(path, "the root element has to be <scene>"));
+    }
+
+    let mut importer = Importer {
+        path,
+        doc: &doc,
+        defaults: HashMap::new(),
+        materials: HashMap::new(),
+        textures: HashMap::new(),
+        objects: Vec::new(),
+        background: BackgroundDesc::Color { color: [0.0, 0.0, 0.0] },
+        render: RenderDesc::default(),
+        camera: None,
+    };
+    importer.run(root)?;
+
+    let (to_world, mut camera) = importer.camera.take()
+        .ok_or_else(|| RendererError::parse(path, "the scene has no <sensor>"))?;
+    let eye = transform::point(&to_world, Point3::new(0.0, 0.0, 0.0));
+    let dir = transform::vector(&to_world, Vec3::new(0.0, 0.0, 1.0)).normalized();
+    let up = transform::vector(&to_world, Vec3::new(0.0, 1.0, 0.0)).normalized();
+    let left = transform::vector(&to_world, Vec3::new(1.0, 0.0, 0.0)).normalized();
+    camera.lookfrom = [eye.x(), eye.y(), eye.z()];
+    camera.lookat = [eye.x() + dir.x(), eye.y() + dir.y(), eye.z() + dir.z()];
+    camera.vup = [up.x(), up.y(), up.z()];
+
+    let mut scene = SceneFile {
+        render: importer.render,
+        camera,
+        background: importer.background,
+        environment: EnvironmentDesc::default(),
+        fog: None,
+        textures: importer.textures,
+        materials: importer.materials,
+        objects: importer.objects,
+        clip: Vec::new(),
+        ray_groups: HashMap::new(),
+        base_dir: path.parent().map(Path::to_path_buf).unwrap_or_default(),
+    };
+    // the sensor's x axis points left in the image; a flipped to_world makes it point right,
+    // which our camera can't do, so the world gets mirrored instead
+    if left.dot(up.cross(dir)) < 0.0 {
+        scene.transform(&mirror(eye, left));
+    }
+    Ok(scene)
+}
@@ -0,0 +1,64 @@
+use crate::{Float, Point3, Vec3};
+
+// Noise for procedural textures (see texture::Wood and texture::Brick): Perlin's gradient
+// noise, smooth and random looking but the same at the same point every time. The lattice is
+// hashed rather than drawn from the random generator, so textures don't change what the
+// renderer's random numbers come out as.
+
+// Gradient noise at `p`, between about -1 and 1 and 0 at whole coordinates
+pub fn perlin(p: Point3) -> Float {
+    let (x, y, z) = (p.x().floor(), p.y().floor(), p.z().floor());
+    let (fx, fy, fz) = (p.x() - x, p.y() - y, p.z() - z);
+    let (ix, iy, iz) = (x as i64, y as i64, z as i64);
+    let fade = |t: Float| t * t * t * (t * (6.0 * t - 15.0) + 10.0);
+    let (u, v, w) = (fade(fx), fade(fy), fade(fz));
+    let lerp = |t: Float, a: Float, b: Float| a + t * (b - a);
+
+    let corner = |dx: i64, dy: i64, dz: i64| {
+        let g = gradient(hash(ix + dx, iy + dy, iz + dz));
+        g.dot(Vec3::new(fx - dx as Float, fy - dy as Float, fz - dz as Float))
+    };
+    lerp(w,
+         lerp(v, lerp(u, corner(0, 0, 0), corner(1, 0, 0)), lerp(u, corner(0, 1, 0), corner(1, 1, 0))),
+         lerp(v, lerp(u, corner(0, 0, 1), corner(1, 0, 1)), lerp(u, corner(0, 1, 1), corner(1, 1, 1))))
+}
+
+// `octaves` of noise each twice as fine and half as strong as the one before, their sizes
+// added up: billowy, between 0 and about 1
+pub fn turbulence(p: Point3, octaves: u32) -> Float {
+    let (mut sum, mut weight, mut p) = (0.0, 0.5, p);
+    for _ in 0..octaves {
+        sum += weight * perlin(p).abs();
+        weight *= 0.5;
+        p = 2.0 * p;
+    }
+    sum
+}
+
+// A number between 0 and 1 for each lattice point, for telling bricks and tiles apart
+pub fn cell(x: i64, y: i64, z: i64) -> Float {
+    (hash(x, y, z) >> 8) as Float / (1u32 << 24) as Float
+}
+
+// the lattice point's bits well mixed, after the finalizer of MurmurHash3
+fn hash(x: i64, y: i64, z: i64) -> u32 {
+    let mut h = (x as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15)
+        ^ (y as u64).wrapping_mul(0xc2b2_ae3d_27d4_eb4f)
+        ^ (z as u64).wrapping_mul(0x1656_67b1_9e37_79f9);
+    h ^= h >> 33;
+    h = h.wrapping_mul(0xff51_afd7_ed55_8ccd);
+    h ^= h >> 33;
+    h = h.wrapping_mul(0xc4ce_b9fe_1a85_ec53);
+    h ^= h >> 33;
+    h as u32
+}
+
+// one of the twelve directions to the middles of a cube's edges, as in Perlin's improved noise
+fn gradient(h: u32) -> Vec3 {
+    let (a, b) = (if h & 1 == 0 { 1.0 } else { -1.0 }, if h & 2 == 0 { 1.0 } else { -1.0 });
+    match (h >> 2) % 3 {
+        0 => Vec3::new(a, b, 0.0),
+        1 => Vec3::new(a, 0.0, b),
+        _ => Vec3::new(0.0, a, b),
+    }
+}
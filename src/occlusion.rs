@@ -0,0 +1,39 @@
+use crate::hit::{Hit, HitRecord, HittableList};
+use crate::visibility::TraceGroup;
+use crate::{stats, Float, Vec3};
+
+// Ambient occlusion, rendered as a pass of its own next to the beauty so contact shadows can
+// be graded separately in compositing. From the surface seen through the pixel center rays go
+// out over the hemisphere, cosine weighted like a diffuse bounce, and whatever they hit within
+// `distance` counts as blocking: 1 is open all around, 0 is buried. Only nearby geometry
+// matters, so a room's far walls don't darken everything in it.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Occlusion {
+    pub distance: Float,
+    pub samples: u32,
+}
+
+impl Occlusion {
+    pub fn new(distance: Float, samples: u32) -> Occlusion {
+        Occlusion {
+            distance,
+            samples: samples.max(1),
+        }
+    }
+
+    // the open share of the hemisphere above `rec`, seen by the rays of diffuse bounces
+    pub fn at(&self, rec: &HitRecord, world: &HittableList, time: Float) -> Float {
+        let open = (0..self.samples)
+            .filter(|_| {
+                let mut direction = rec.normal + Vec3::rand_in_unit_sphere().normalized();
+                if direction.near_zero() {
+                    direction = rec.normal;
+                }
+                let r = rec.spawn_ray(direction.normalized()).with_time(time).with_group(TraceGroup::DIFFUSE);
+                stats::count(&stats::RAYS);
+                world.hit(&r, 0.0, self.distance).is_none()
+            })
+            .count();
+        open as Float / self.samples as Float
+    }
+}
@@ -0,0 +1,715 @@
+use std::path::{Path, PathBuf};
+use crate::error::{RendererError, Result};
+use crate::transform::{self, Matrix};
+use crate::{Color, Float};
+
+// Output transforms out of an OpenColorIO config, so renders come out the way the rest of a
+// studio's pipeline shows them. The beauty is taken to be in the colorspace of the config's
+// scene_linear role, which should be the renderer's working space (see
+// colorspace::WorkingSpace). It goes through that colorspace's transforms to the config's
+// reference space and from there into the colorspace of a view. What comes out is what the
+// 8 and 16-bit formats store; EXR and the other float formats keep the scene linear values.
+//
+// The config is read without the OpenColorIO library, so only the transforms that are plain
+// arithmetic are understood: MatrixTransform, ExponentTransform, ExponentWithLinearTransform,
+// RangeTransform, ColorSpaceTransform, GroupTransform and the ACEScg_to_ACES2065-1
+// BuiltinTransform. Configs that need LUT files, log curves, looks or the view transforms of
+// OCIO 2 are turned down naming what isn't supported.
+#[derive(Debug)]
+pub struct Config {
+    path: PathBuf,
+    roles: Vec<(String, String)>,
+    colorspaces: Vec<ColorSpace>,
+    // the names of the OCIO 2 display colorspaces, only to say they aren't supported
+    display_colorspaces: Vec<String>,
+    // the views of each display, see View
+    displays: Vec<(String, Vec<Node>)>,
+}
+
+#[derive(Debug)]
+struct ColorSpace {
+    name: String,
+    aliases: Vec<String>,
+    to_reference: Option<Node>,
+    from_reference: Option<Node>,
+}
+
+// A way of showing the renders: the colorspace of a display's view, or any colorspace
+#[derive(Debug)]
+pub struct View {
+    name: String,
+    ops: Vec<Op>,
+}
+
+#[derive(Clone, Debug)]
+enum Op {
+    // the RGB part of an OCIO matrix and its offset
+    Matrix(Matrix),
+    Exponent([Float; 3]),
+    // a power curve with a straight bit at the bottom, sRGB's for instance; from the curve to
+    // linear unless inverse
+    ExponentWithLinear { gamma: [Float; 3], offset: [Float; 3], inverse: bool },
+    Range { scale: Float, offset: Float, lo: Option<Float>, hi: Option<Float> },
+}
+
+// ACEScg to ACES2065-1, AP1 to AP0 primaries
+const AP1_TO_AP0: Matrix = [
+    [0.6954522414, 0.1406786965, 0.1638690622, 0.0],
+    [0.0447945634, 0.8596711185, 0.0955343182, 0.0],
+    [-0.0055258826, 0.0040252103, 1.0015006723, 0.0],
+    [0.0, 0.0, 0.0, 1.0],
+];
+
+// how deep groups and colorspace transforms may go, deeper they're taken to refer to each other
+const MAX_DEPTH: usize = 16;
+
+impl Config {
+    pub fn load(path: &Path) -> Result<Config> {
+        let text = std::fs::read_to_string(path).map_err(|e| RendererError::io(path, e))?;
+        let root = Yaml::parse(path, &text)?;
+        let roles = root.get("roles").map_or(&[][..], Node::entries).iter()
+            .filter_map(|(role, node)| Some((role.clone(), node.str()?.to_string())))
+            .collect();
+        let colorspaces = root.get("colorspaces").map_or(&[][..], Node::items).iter()
+            .map(|cs| {
+                let name = cs.get("name").and_then(Node::str)
+                    .ok_or_else(|| RendererError::parse(path, "a colorspace has no name"))?;
+                Ok(ColorSpace {
+                    name: name.to_string(),
+                    aliases: cs.get("aliases").map_or(&[][..], Node::items).iter()
+                        .filter_map(|a| a.str().map(str::to_string))
+                        .collect(),
+                    to_reference: cs.get("to_scene_reference").or_else(|| cs.get("to_reference")).cloned(),
+                    from_reference: cs.get("from_scene_reference").or_else(|| cs.get("from_reference")).cloned(),
+                })
+            })
+            .collect::<Result<Vec<ColorSpace>>>()?;
+        let display_colorspaces = root.get("display_colorspaces").map_or(&[][..], Node::items).iter()
+            .filter_map(|cs| cs.get("name").and_then(Node::str).map(str::to_string))
+            .collect();
+        let displays = root.get("displays").map_or(&[][..], Node::entries).iter()
+            .map(|(display, views)| (display.clone(), views.items().to_vec()))
+            .collect();
+        Ok(Config { path: path.to_path_buf(), roles, colorspaces, display_colorspaces, displays })
+    }
+
+    // The view called `name`, "display/view" or just a colorspace. Without a name it's the
+    // first view of the first display, which is what OCIO apps start with.
+    pub fn view(&self, name: Option<&str>) -> Result<View> {
+        let (name, colorspace) = match name {
+            None => {
+                let (display, views) = self.displays.first()
+                    .ok_or_else(|| RendererError::parse(&self.path, "the config has no displays, name a colorspace to show the renders in"))?;
+                let view = views.first()
+                    .ok_or_else(|| RendererError::parse(&self.path, format!("display '{}' has no views", display)))?;
+                (format!("{}/{}", display, view.get("name").and_then(Node::str).unwrap_or_default()), self.view_colorspace(display, view)?)
+            }
+            Some(name) => match self.display_view(name) {
+                Some((display, view)) => (name.to_string(), self.view_colorspace(display, view)?),
+                None => (name.to_string(), name),
+            },
+        };
+        let scene_linear = self.role("scene_linear")
+            .ok_or_else(|| RendererError::parse(&self.path, "the config has no scene_linear role, the colorspace renders are in"))?;
+        let mut ops = self.to_reference(scene_linear, 0)?;
+        ops.extend(self.out_of_reference(colorspace, 0)?);
+        Ok(View { name, ops })
+    }
+
+    fn role(&self, role: &str) -> Option<&str> {
+        self.roles.iter().find(|(r, _)| r.eq_ignore_ascii_case(role)).map(|(_, cs)| cs.as_str())
+    }
+
+    fn display_view(&self, name: &str) -> Option<(&str, &Node)> {
+        let (display, view) = name.split_once('/')?;
+        let (display, views) = self.displays.iter().find(|(d, _)| d == display)?;
+        let view = views.iter().find(|v| v.get("name").and_then(Node::str) == Some(view))?;
+        Some((display, view))
+    }
+
+    fn view_colorspace<'a>(&self, display: &str, view: &'a Node) -> Result<&'a str> {
+        let name = view.get("name").and_then(Node::str).unwrap_or_default();
+        if view.get("view_transform").is_some() {
+            return Err(RendererError::Unsupported(format!("{}: view '{}/{}' goes through a view transform, which isn't supported",
+                                                          self.path.display(), display, name)));
+        }
+        if view.get("looks").and_then(Node::str).is_some_and(|looks| !looks.is_empty()) {
+            return Err(RendererError::Unsupported(format!("{}: view '{}/{}' applies looks, which aren't supported",
+                                                          self.path.display(), display, name)));
+        }
+        view.get("colorspace").and_then(Node::str)
+            .ok_or_else(|| RendererError::parse(&self.path, format!("view '{}/{}' has no colorspace", display, name)))
+    }
+
+    // by name or alias, or the colorspace of a role; OCIO doesn't mind the case
+    fn colorspace(&self, name: &str) -> Result<&ColorSpace> {
+        let name = self.role(name).unwrap_or(name);
+        if let Some(cs) = self.colorspaces.iter()
+            .find(|cs| cs.name.eq_ignore_ascii_case(name) || cs.aliases.iter().any(|a| a.eq_ignore_ascii_case(name))) {
+            return Ok(cs);
+        }
+        if self.display_colorspaces.iter().any(|cs| cs.eq_ignore_ascii_case(name)) {
+            return Err(RendererError::Unsupported(format!("{}: '{}' is a display colorspace, which isn't supported",
+                                                          self.path.display(), name)));
+        }
+        let mut views: Vec<String> = self.displays.iter()
+            .flat_map(|(display, views)| views.iter()
+                .filter_map(move |v| v.get("name").and_then(Node::str).map(|view| format!("{}/{}", display, view))))
+            .collect();
+        views.extend(self.colorspaces.iter().map(|cs| cs.name.clone()));
+        Err(RendererError::parse(&self.path, format!("no colorspace or view '{}', there are: {}", name, views.join(", "))))
+    }
+
+    fn to_reference(&self, name: &str, depth: usize) -> Result<Vec<Op>> {
+        let cs = self.colorspace(name)?;
+        match (&cs.to_reference, &cs.from_reference) {
+            (Some(to), _) => self.ops(to, false, depth),
+            (None, Some(from)) => self.ops(from, true, depth),
+            (None, None) => Ok(Vec::new()),
+        }
+    }
+
+    fn out_of_reference(&self, name: &str, depth: usize) -> Result<Vec<Op>> {
+        let cs = self.colorspace(name)?;
+        match (&cs.from_reference, &cs.to_reference) {
+            (Some(from), _) => self.ops(from, false, depth),
+            (None, Some(to)) => self.ops(to, true, depth),
+            (None, None) => Ok(Vec::new()),
+        }
+    }
+
+    // the steps of a transform, backwards when it's inverted here or in the file
+    fn ops(&self, node: &Node, inverse: bool, depth: usize) -> Result<Vec<Op>> {
+        if depth > MAX_DEPTH {
+            return Err(RendererError::parse(&self.path, "transforms go too deep, colorspaces may refer to each other"));
+        }
+        let inverse = inverse != (node.get("direction").and_then(Node::str) == Some("inverse"));
+        let tag = node.tag.as_deref().unwrap_or("a transform without a type");
+        let op = match tag {
+            "GroupTransform" => {
+                let children = node.get("children").map_or(&[][..], Node::items);
+                let mut ops = Vec::new();
+                if inverse {
+                    for child in children.iter().rev() {
+                        ops.extend(self.ops(child, true, depth + 1)?);
+                    }
+                } else {
+                    for child in children {
+                        ops.extend(self.ops(child, false, depth + 1)?);
+                    }
+                }
+                return Ok(ops);
+            }
+            "ColorSpaceTransform" => {
+                let src = node.get("src").and_then(Node::str)
+                    .ok_or_else(|| RendererError::parse(&self.path, "a ColorSpaceTransform has no src"))?;
+                let dst = node.get("dst").and_then(Node::str)
+                    .ok_or_else(|| RendererError::parse(&self.path, "a ColorSpaceTransform has no dst"))?;
+                let (src, dst) = if inverse { (dst, src) } else { (src, dst) };
+                let mut ops = self.to_reference(src, depth + 1)?;
+                ops.extend(self.out_of_reference(dst, depth + 1)?);
+                return Ok(ops);
+            }
+            "MatrixTransform" => {
+                let m = match node.get("matrix") {
+                    Some(m) => self.floats(m, 16)?,
+                    None => vec![1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0],
+                };
+                let offset = match node.get("offset") {
+                    Some(o) => self.floats(o, 4)?,
+                    None => vec![0.0; 4],
+                };
+                let matrix = [
+                    [m[0], m[1], m[2], offset[0]],
+                    [m[4], m[5], m[6], offset[1]],
+                    [m[8], m[9], m[10], offset[2]],
+                    [0.0, 0.0, 0.0, 1.0],
+                ];
+                Op::Matrix(self.inverted(matrix, inverse)?)
+            }
+            "BuiltinTransform" => match node.get("style").and_then(Node::str) {
+                Some("ACEScg_to_ACES2065-1") => Op::Matrix(self.inverted(AP1_TO_AP0, inverse)?),
+                style => return Err(RendererError::Unsupported(format!("{}: BuiltinTransform {} isn't supported",
+                                                                       self.path.display(), style.unwrap_or("without a style")))),
+            },
+            "ExponentTransform" => {
+                let value = self.rgb(node.get("value"), 1.0)?;
+                if value.iter().any(|&v| v <= 0.0) {
+                    return Err(RendererError::parse(&self.path, "ExponentTransform values have to be positive"));
+                }
+                Op::Exponent(if inverse { value.map(|v| 1.0 / v) } else { value })
+            }
+            "ExponentWithLinearTransform" => {
+                let gamma = self.rgb(node.get("gamma"), 1.0)?;
+                let offset = self.rgb(node.get("offset"), 0.0)?;
+                if gamma.iter().any(|&g| g <= 1.0) || offset.iter().any(|&o| o <= 0.0) {
+                    return Err(RendererError::parse(&self.path, "ExponentWithLinearTransform needs a gamma over 1 and a positive offset"));
+                }
+                Op::ExponentWithLinear { gamma, offset, inverse }
+            }
+            "RangeTransform" => {
+                let value = |key: &str| -> Result<Option<Float>> {
+                    node.get(key).map(|v| self.floats(v, 1).map(|v| v[0])).transpose()
+                };
+                let (mut min_in, mut max_in) = (value("min_in_value")?, value("max_in_value")?);
+                let (mut min_out, mut max_out) = (value("min_out_value")?, value("max_out_value")?);
+                if inverse {
+                    (min_in, max_in, min_out, max_out) = (min_out, max_out, min_in, max_in);
+                }
+                let (scale, offset) = match (min_in, max_in, min_out, max_out) {
+                    (Some(a), Some(b), Some(c), Some(d)) if a != b => ((d - c) / (b - a), c - a * (d - c) / (b - a)),
+                    (Some(a), _, Some(c), _) => (1.0, c - a),
+                    (_, Some(b), _, Some(d)) => (1.0, d - b),
+                    _ => (1.0, 0.0),
+                };
+                let clamp = node.get("style").and_then(Node::str) != Some("noClamp");
+                Op::Range { scale, offset, lo: min_out.filter(|_| clamp), hi: max_out.filter(|_| clamp) }
+            }
+            other => return Err(RendererError::Unsupported(format!("{}: {} isn't supported", self.path.display(), other))),
+        };
+        Ok(vec![op])
+    }
+
+    fn inverted(&self, m: Matrix, inverse: bool) -> Result<Matrix> {
+        if !inverse {
+            return Ok(m);
+        }
+        transform::inverse(&m).ok_or_else(|| RendererError::parse(&self.path, "a MatrixTransform can't be inverted"))
+    }
+
+    // `count` numbers, or one for all of them
+    fn floats(&self, node: &Node, count: usize) -> Result<Vec<Float>> {
+        let values = match &node.value {
+            Value::Scalar(s) => vec![s.as_str()],
+            Value::List(items) => items.iter().filter_map(Node::str).collect(),
+            Value::Map(_) => Vec::new(),
+        };
+        let values: Vec<Float> = values.iter()
+            .map(|v| v.parse::<Float>())
+            .collect::<std::result::Result<_, _>>()
+            .map_err(|e| RendererError::parse(&self.path, format!("{:?}: {}", values, e)))?;
+        match values.len() {
+            1 => Ok(vec![values[0]; count]),
+            n if n == count => Ok(values),
+            n => Err(RendererError::parse(&self.path, format!("{} numbers where {} go", n, count))),
+        }
+    }
+
+    // the red, green and blue of a value given for RGBA or for all of them
+    fn rgb(&self, node: Option<&Node>, default: Float) -> Result<[Float; 3]> {
+        let Some(node) = node else { return Ok([default; 3]) };
+        let values = match &node.value {
+            Value::List(items) if items.len() == 4 => self.floats(node, 4)?,
+            _ => self.floats(node, 1)?,
+        };
+        Ok([values[0], values[1 % values.len()], values[2 % values.len()]])
+    }
+}
+
+impl View {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    // a scene linear color as the view shows it, from 0 to 1 where the display can show it
+    pub fn apply(&self, c: Color) -> Color {
+        self.ops.iter().fold(c, |c, op| op.apply(c))
+    }
+}
+
+impl Op {
+    fn apply(&self, c: Color) -> Color {
+        match self {
+            Op::Matrix(m) => transform::point(m, c),
+            Op::Exponent(e) => Color::new(c[0].max(0.0).powf(e[0]), c[1].max(0.0).powf(e[1]), c[2].max(0.0).powf(e[2])),
+            Op::ExponentWithLinear { gamma, offset, inverse } => {
+                let curve = |i: usize| {
+                    let (g, o) = (gamma[i], offset[i]);
+                    // where the straight bit meets the curve, on the curve's side, and its slope
+                    let knee = o / (g - 1.0);
+                    let slope = knee / ((knee + o) / (1.0 + o)).powf(g);
+                    let x = c[i];
+                    if *inverse {
+                        if x <= knee / slope { x * slope } else { (1.0 + o) * x.powf(1.0 / g) - o }
+                    } else if x <= knee {
+                        x / slope
+                    } else {
+                        ((x + o) / (1.0 + o)).powf(g)
+                    }
+                };
+                Color::new(curve(0), curve(1), curve(2))
+            }
+            Op::Range { scale, offset, lo, hi } => {
+                let range = |v: Float| {
+                    let v = v * scale + offset;
+                    let v = lo.map_or(v, |lo| v.max(lo));
+                    hi.map_or(v, |hi| v.min(hi))
+                };
+                Color::new(range(c[0]), range(c[1]), range(c[2]))
+            }
+        }
+    }
+}
+
+// The part of YAML OCIO configs are written in: block maps and lists by indentation, flow
+// maps and lists in braces and brackets, plain and quoted scalars and the !<Type> tags that
+// say what a transform is. Block scalars (the | and > descriptions) are read as empty.
+#[derive(Clone, Debug)]
+struct Node {
+    tag: Option<String>,
+    value: Value,
+}
+
+#[derive(Clone, Debug)]
+enum Value {
+    Scalar(String),
+    List(Vec<Node>),
+    Map(Vec<(String, Node)>),
+}
+
+impl Node {
+    fn new(value: Value) -> Node {
+        Node { tag: None, value }
+    }
+
+    fn get(&self, key: &str) -> Option<&Node> {
+        self.entries().iter().find(|(k, _)| k == key).map(|(_, v)| v)
+    }
+
+    fn str(&self) -> Option<&str> {
+        match &self.value {
+            Value::Scalar(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    fn items(&self) -> &[Node] {
+        match &self.value {
+            Value::List(items) => items,
+            _ => &[],
+        }
+    }
+
+    fn entries(&self) -> &[(String, Node)] {
+        match &self.value {
+            Value::Map(entries) => entries,
+            _ => &[],
+        }
+    }
+}
+
+struct Yaml<'a> {
+    path: &'a Path,
+    // line number, indentation and text without comments, flow values that go on over
+    // several lines joined into one
+    lines: Vec<(usize, usize, String)>,
+    at: usize,
+}
+
+impl Yaml<'_> {
+    fn parse(path: &Path, text: &str) -> Result<Node> {
+        let mut lines: Vec<(usize, usize, String)> = Vec::new();
+        // the indentation of the key a block scalar belongs to, while skipping its lines
+        let mut block: Option<usize> = None;
+        let mut open = 0;
+        for (n, raw) in text.lines().enumerate() {
+            let indent = raw.len() - raw.trim_start().len();
+            if let Some(key) = block {
+                if raw.trim().is_empty() || indent > key {
+                    continue;
+                }
+                block = None;
+            }
+            let line = uncommented(raw).trim();
+            if line.is_empty() || line.starts_with('%') || line == "---" || line == "..." {
+                continue;
+            }
+            if open > 0 {
+                let (_, _, joined) = lines.last_mut().expect("an open flow value has a line");
+                joined.push(' ');
+                joined.push_str(line);
+            } else {
+                let mut line = line.to_string();
+                if let Some(at) = line.rfind(' ').filter(|&at| is_block_scalar(&line[at + 1..]) && line[..at].ends_with(':')) {
+                    line.replace_range(at + 1.., "\"\"");
+                    block = Some(indent);
+                }
+                lines.push((n + 1, indent, line));
+            }
+            open = (open + depth_change(line)).max(0);
+        }
+        let mut yaml = Yaml { path, lines, at: 0 };
+        let indent = yaml.lines.first().map(|(_, indent, _)| *indent)
+            .ok_or_else(|| RendererError::parse(path, "the config is empty"))?;
+        yaml.block(indent)
+    }
+
+    fn error(&self, n: usize, message: impl std::fmt::Display) -> RendererError {
+        RendererError::parse(self.path, format!("line {}: {}", n, message))
+    }
+
+    fn block(&mut self, indent: usize) -> Result<Node> {
+        if is_item(&self.lines[self.at].2) { self.list(indent) } else { self.map(indent) }
+    }
+
+    // what's below a key or item with nothing after it, nothing when the next line isn't deeper
+    fn child(&mut self, indent: usize) -> Result<Node> {
+        match self.lines.get(self.at) {
+            Some(&(_, deeper, _)) if deeper > indent => self.block(deeper),
+            _ => Ok(Node::new(Value::Scalar(String::new()))),
+        }
+    }
+
+    fn list(&mut self, indent: usize) -> Result<Node> {
+        let mut items = Vec::new();
+        while let Some((n, i, text)) = self.lines.get(self.at).cloned() {
+            if i != indent || !is_item(&text) {
+                break;
+            }
+            let rest = text[1..].trim_start();
+            if rest.is_empty() {
+                self.at += 1;
+                items.push(self.child(indent)?);
+            } else if let Some(tag) = only_tag(rest) {
+                self.at += 1;
+                let mut node = self.child(indent)?;
+                node.tag = Some(tag);
+                items.push(node);
+            } else if is_entry(rest) {
+                // a map that starts on the item's line, its keys line up with the first
+                let inner = indent + text.len() - rest.len();
+                self.lines[self.at] = (n, inner, rest.to_string());
+                items.push(self.map(inner)?);
+            } else {
+                self.at += 1;
+                items.push(inline(rest).map_err(|e| self.error(n, e))?);
+            }
+        }
+        Ok(Node::new(Value::List(items)))
+    }
+
+    fn map(&mut self, indent: usize) -> Result<Node> {
+        let mut entries = Vec::new();
+        while let Some((n, i, text)) = self.lines.get(self.at).cloned() {
+            if i < indent || (i == indent && is_item(&text)) {
+                break;
+            }
+            self.at += 1;
+            if i > indent {
+                // a plain scalar going on over more lines, only the first is kept
+                continue;
+            }
+            let (key, rest) = split_entry(&text).ok_or_else(|| self.error(n, format!("'{}' isn't a key: value", text)))?;
+            let (tag, rest) = match rest.strip_prefix("!<").and_then(|r| r.split_once('>')) {
+                Some((tag, rest)) => (Some(tag.to_string()), rest.trim()),
+                None => (None, rest),
+            };
+            let mut node = if rest.is_empty() {
+                match self.lines.get(self.at) {
+                    // a list under a key may start at the key's own indentation
+                    Some((_, i, next)) if *i == indent && is_item(next) => self.list(indent)?,
+                    _ => self.child(indent)?,
+                }
+            } else {
+                inline(rest).map_err(|e| self.error(n, e))?
+            };
+            if tag.is_some() {
+                node.tag = tag;
+            }
+            entries.push((unquote(key), node));
+        }
+        Ok(Node::new(Value::Map(entries)))
+    }
+}
+
+fn is_item(text: &str) -> bool {
+    text == "-" || text.starts_with("- ")
+}
+
+fn is_block_scalar(s: &str) -> bool {
+    (s.starts_with('|') || s.starts_with('>')) && s[1..].chars().all(|c| c == '-' || c == '+' || c.is_ascii_digit())
+}
+
+fn is_entry(text: &str) -> bool {
+    !text.starts_with(['{', '[', '"', '\'', '!']) && split_entry(text).is_some()
+}
+
+// "!<Type>" alone
+fn only_tag(text: &str) -> Option<String> {
+    let tag = text.strip_prefix("!<")?.strip_suffix('>')?;
+    (!tag.contains('>')).then(|| tag.to_string())
+}
+
+// the key and the rest, at the first colon followed by a space or the end
+fn split_entry(text: &str) -> Option<(&str, &str)> {
+    let at = text.char_indices()
+        .find(|&(i, c)| c == ':' && text[i + 1..].chars().next().is_none_or(|next| next == ' '))?.0;
+    Some((text[..at].trim(), text[at + 1..].trim()))
+}
+
+// the line up to a # starting a comment, outside of quotes
+fn uncommented(line: &str) -> &str {
+    let mut quote = None;
+    let mut last = ' ';
+    for (i, c) in line.char_indices() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => {}
+            None if c == '"' || c == '\'' => quote = Some(c),
+            None if c == '#' && last.is_whitespace() => return &line[..i],
+            None => {}
+        }
+        last = c;
+    }
+    line
+}
+
+// how many more braces and brackets the line opens than it closes, outside of quotes
+fn depth_change(line: &str) -> i32 {
+    let mut quote = None;
+    let mut depth = 0;
+    for c in line.chars() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => {}
+            None => match c {
+                '"' | '\'' => quote = Some(c),
+                '{' | '[' => depth += 1,
+                '}' | ']' => depth -= 1,
+                _ => {}
+            },
+        }
+    }
+    depth
+}
+
+fn unquote(s: &str) -> String {
+    let s = s.trim();
+    if s.len() >= 2 && s.starts_with('"') && s.ends_with('"') {
+        s[1..s.len() - 1].replace("\\\"", "\"").replace("\\\\", "\\")
+    } else if s.len() >= 2 && s.starts_with('\'') && s.ends_with('\'') {
+        s[1..s.len() - 1].replace("''", "'")
+    } else {
+        s.to_string()
+    }
+}
+
+// the value after a key or dash, flow collections or a scalar that takes the rest of the line
+fn inline(text: &str) -> std::result::Result<Node, String> {
+    if text.starts_with(['{', '[', '!']) {
+        let mut flow = Flow { chars: text.chars().collect(), at: 0 };
+        let node = flow.value(false)?;
+        flow.skip_spaces();
+        if flow.at < flow.chars.len() {
+            return Err(format!("'{}' after the value", flow.chars[flow.at..].iter().collect::<String>()));
+        }
+        Ok(node)
+    } else {
+        Ok(Node::new(Value::Scalar(unquote(text))))
+    }
+}
+
+struct Flow {
+    chars: Vec<char>,
+    at: usize,
+}
+
+impl Flow {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.at).copied()
+    }
+
+    fn skip_spaces(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.at += 1;
+        }
+    }
+
+    // `nested` inside braces or brackets, where commas end plain scalars
+    fn value(&mut self, nested: bool) -> std::result::Result<Node, String> {
+        self.skip_spaces();
+        let mut tag = None;
+        if self.chars[self.at..].starts_with(&['!', '<']) {
+            let end = self.chars[self.at..].iter().position(|&c| c == '>').ok_or("a tag without its >")?;
+            tag = Some(self.chars[self.at + 2..self.at + end].iter().collect());
+            self.at += end + 1;
+            self.skip_spaces();
+        }
+        let value = match self.peek() {
+            Some('{') => {
+                self.at += 1;
+                let mut entries = Vec::new();
+                loop {
+                    self.skip_spaces();
+                    if self.peek() == Some('}') {
+                        self.at += 1;
+                        break;
+                    }
+                    let key = self.scalar(true);
+                    self.skip_spaces();
+                    if self.peek() != Some(':') {
+                        return Err(format!("'{}' in braces isn't a key: value", key));
+                    }
+                    self.at += 1;
+                    entries.push((key, self.value(true)?));
+                    self.next_item('}')?;
+                }
+                Value::Map(entries)
+            }
+            Some('[') => {
+                self.at += 1;
+                let mut items = Vec::new();
+                loop {
+                    self.skip_spaces();
+                    if self.peek() == Some(']') {
+                        self.at += 1;
+                        break;
+                    }
+                    items.push(self.value(true)?);
+                    self.next_item(']')?;
+                }
+                Value::List(items)
+            }
+            _ if nested => Value::Scalar(self.scalar(false)),
+            _ => {
+                let rest: String = self.chars[self.at..].iter().collect();
+                self.at = self.chars.len();
+                Value::Scalar(unquote(&rest))
+            }
+        };
+        Ok(Node { tag, value })
+    }
+
+    // past the comma after an item, or up to the closing brace or bracket
+    fn next_item(&mut self, close: char) -> std::result::Result<(), String> {
+        self.skip_spaces();
+        match self.peek() {
+            Some(',') => {
+                self.at += 1;
+                Ok(())
+            }
+            Some(c) if c == close => Ok(()),
+            Some(c) => Err(format!("'{}' where a , or {} goes", c, close)),
+            None => Err(format!("a {} is missing", close)),
+        }
+    }
+
+    // a key ends at a colon, any scalar at a comma or closing brace or bracket
+    fn scalar(&mut self, key: bool) -> String {
+        self.skip_spaces();
+        if let Some(q @ ('"' | '\'')) = self.peek() {
+            let start = self.at;
+            self.at += 1;
+            while self.peek().is_some_and(|c| c != q) {
+                self.at += 1 + (q == '"' && self.peek() == Some('\\')) as usize;
+            }
+            self.at = (self.at + 1).min(self.chars.len());
+            return unquote(&self.chars[start..self.at].iter().collect::<String>());
+        }
+        let start = self.at;
+        while self.peek().is_some_and(|c| !(matches!(c, ',' | '}' | ']') || key && c == ':')) {
+            self.at += 1;
+        }
+        self.chars[start..self.at].iter().collect::<String>().trim().to_string()
+    }
+}
@@ -0,0 +1,55 @@
+// Orthonormal bases: a right-handed frame of three unit vectors, for working in a surface's or
+// the camera's own coordinates, with w along the normal or the viewing axis
+use crate::{Float, Vec3};
+
+#[derive(Copy, Clone, Debug)]
+pub struct Onb {
+    u: Vec3,
+    v: Vec3,
+    w: Vec3,
+}
+
+impl Onb {
+    // w along `w`, u perpendicular to `up`, so a camera's v ends up pointing up on the image
+    pub fn new(w: Vec3, up: Vec3) -> Onb {
+        let w = w.normalized();
+        let u = up.cross(w).normalized();
+        let v = w.cross(u);
+        Onb { u, v, w }
+    }
+
+    // Any frame around `n` where the orientation of u and v doesn't matter, like around a
+    // normal. Duff et al., "Building an Orthonormal Basis, Revisited", without the branch on
+    // the axis closest to n or the precision loss near it.
+    pub fn from_w(n: Vec3) -> Onb {
+        let w = n.normalized();
+        let sign = Float::copysign(1.0, w.z());
+        let a = -1.0 / (sign + w.z());
+        let b = w.x() * w.y() * a;
+        let u = Vec3::new(1.0 + sign * w.x() * w.x() * a, sign * b, -sign * w.x());
+        let v = Vec3::new(b, sign + w.y() * w.y() * a, -w.y());
+        Onb { u, v, w }
+    }
+
+    pub fn u(&self) -> Vec3 {
+        self.u
+    }
+
+    pub fn v(&self) -> Vec3 {
+        self.v
+    }
+
+    pub fn w(&self) -> Vec3 {
+        self.w
+    }
+
+    // coordinates in the basis to world space
+    pub fn local(&self, a: Vec3) -> Vec3 {
+        a.x() * self.u + a.y() * self.v + a.z() * self.w
+    }
+
+    // world space to coordinates in the basis
+    pub fn to_local(&self, a: Vec3) -> Vec3 {
+        Vec3::new(a.dot(self.u), a.dot(self.v), a.dot(self.w))
+    }
+}
@@ -0,0 +1,487 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use exr::prelude::*;
+use exr::meta::attribute::Chromaticities;
+use crate::colorspace::{Display, Encoding, WorkingSpace};
+use crate::error::{RendererError, Result};
+use crate::framebuffer::{Framebuffer, Tile};
+use crate::ocio::View;
+use crate::Float;
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+#[cfg_attr(feature = "native", derive(clap::ValueEnum))]
+pub enum Format {
+    Png,
+    Tiff,
+    Exr,
+    Hdr,
+    Ppm,
+    Pfm,
+    Jpeg,
+    Webp,
+}
+
+impl Format {
+    pub fn from_path(path: &Path) -> Option<Format> {
+        match path.extension()?.to_str()?.to_lowercase().as_str() {
+            "png" => Some(Format::Png),
+            "tif" | "tiff" => Some(Format::Tiff),
+            "exr" => Some(Format::Exr),
+            "hdr" => Some(Format::Hdr),
+            "ppm" => Some(Format::Ppm),
+            "pfm" => Some(Format::Pfm),
+            "jpg" | "jpeg" => Some(Format::Jpeg),
+            "webp" => Some(Format::Webp),
+            _ => None,
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            Format::Png => "png",
+            Format::Tiff => "tiff",
+            Format::Exr => "exr",
+            Format::Hdr => "hdr",
+            Format::Ppm => "ppm",
+            Format::Pfm => "pfm",
+            Format::Jpeg => "jpg",
+            Format::Webp => "webp",
+        }
+    }
+}
+
+// What to do when the output file already exists
+#[derive(Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "native", derive(clap::ValueEnum))]
+pub enum Collision {
+    Overwrite,
+    Increment,
+}
+
+// Timestamped file under ./renders, used when no output path was asked for
+#[cfg(feature = "native")]
+pub fn default_path(format: Format) -> PathBuf {
+    let stamp = chrono::Local::now().format("%Y%m%d-%H%M%S");
+    PathBuf::from(format!("./renders/render-{}.{}", stamp, format.extension()))
+}
+
+// Picks the final output path, going "name-1.ext", "name-2.ext", ... when incrementing past
+// existing files. Missing parent directories are created.
+pub fn resolve_path(path: PathBuf, collision: Collision) -> Result<PathBuf> {
+    let path = match collision {
+        Collision::Overwrite => path,
+        Collision::Increment => {
+            let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or("render").to_string();
+            let ext = path.extension().and_then(|e| e.to_str()).map(|e| format!(".{}", e)).unwrap_or_default();
+            let mut candidate = path.clone();
+            let mut n = 1;
+            while candidate.exists() {
+                candidate = path.with_file_name(format!("{}-{}{}", stem, n, ext));
+                n += 1;
+            }
+            candidate
+        }
+    };
+
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).map_err(|e| RendererError::io(dir, e))?;
+    }
+    Ok(path)
+}
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum BitDepth {
+    Eight,
+    Sixteen,
+}
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum ExrPrecision {
+    Full,
+    Half,
+}
+
+// Where the AOVs and light path passes go in an EXR
+#[derive(Copy, Clone, PartialEq)]
+pub enum ExrLayout {
+    // channels of the one part the beauty is in, "normal.X", "caustics.R", ...
+    Channels,
+    // a part of their own each, named after them, the multi-part files compositing apps
+    // expect. The channels keep their full names, so they don't clash when the parts are
+    // merged again.
+    Parts,
+}
+
+#[derive(Clone)]
+pub struct WriteOptions {
+    pub bit_depth: BitDepth,
+    pub exr_precision: ExrPrecision,
+    pub exr_layout: ExrLayout,
+    // 0 to 100, for the lossy formats
+    pub quality: u8,
+    // the OCIO view the 8 and 16-bit formats show the beauty in, the display below without one
+    pub view: Option<Arc<View>>,
+    // the display the 8 and 16-bit formats are made for, anything but sRGB is for PNG only
+    // (see check_display)
+    pub display: Display,
+}
+
+impl Default for WriteOptions {
+    fn default() -> WriteOptions {
+        WriteOptions {
+            bit_depth: BitDepth::Eight,
+            exr_precision: ExrPrecision::Full,
+            exr_layout: ExrLayout::Channels,
+            quality: 90,
+            view: None,
+            display: Display::Srgb,
+        }
+    }
+}
+
+impl WriteOptions {
+    pub fn encoding(&self) -> Encoding<'_> {
+        match &self.view {
+            Some(view) => Encoding::View(view),
+            None => Encoding::Display(self.display),
+        }
+    }
+}
+
+// Only PNG can say which display it's for, images in the other 8 and 16-bit formats would be
+// taken for sRGB. The linear formats aren't for a display and don't mind.
+pub fn check_display(format: Format, display: Display) -> Result<()> {
+    match format {
+        Format::Tiff | Format::Ppm | Format::Jpeg | Format::Webp if display != Display::Srgb =>
+            Err(RendererError::Unsupported(format!("{:?} images can't be for display {}, only PNG says which display it's for",
+                                                   format, display.name()))),
+        _ => Ok(()),
+    }
+}
+
+pub fn write(path: &Path, data: &Framebuffer, format: Format, options: &WriteOptions) -> Result<()> {
+    let encoding = options.encoding();
+    match format {
+        Format::Png => write_png(path, data, options.bit_depth, encoding),
+        Format::Tiff => write_tiff(path, data, options.bit_depth, encoding),
+        Format::Exr => write_exr(path, data, options.exr_precision, options.exr_layout),
+        Format::Hdr => write_hdr(path, data),
+        Format::Ppm => write_ppm(path, data, encoding),
+        Format::Pfm => write_pfm(path, data),
+        Format::Jpeg => write_jpeg(path, data, options.quality, encoding),
+        Format::Webp => write_webp(path, data, options.quality, encoding),
+    }
+}
+
+pub fn write_png(path: &Path, fb: &Framebuffer, depth: BitDepth, encoding: Encoding) -> Result<()> {
+    let file = File::create(path).map_err(|e| RendererError::io(path, e))?;
+    encode_png(BufWriter::new(file), fb, depth, encoding).map_err(|e| RendererError::encode(path, e))
+}
+
+// PNG into anything writable, a file or a buffer to send over the network
+pub fn encode_png<W: Write>(w: W, fb: &Framebuffer, depth: BitDepth, encoding: Encoding) -> std::result::Result<(), png::EncodingError> {
+    let mut encoder = png::Encoder::new(w, fb.width(), fb.height());
+    encoder.set_color(png::ColorType::Rgb);
+    let display = encoding.display();
+    for (key, value) in fb.metadata.iter().chain(&display.metadata()) {
+        encoder.add_text_chunk(key.clone(), value.clone())?;
+    }
+
+    let data = match depth {
+        BitDepth::Eight => {
+            encoder.set_depth(png::BitDepth::Eight);
+            fb.to_rgb8(encoding)
+        }
+        BitDepth::Sixteen => {
+            // PNG stores 16-bit samples big-endian
+            encoder.set_depth(png::BitDepth::Sixteen);
+            fb.to_rgb16(encoding).iter().flat_map(|s| s.to_be_bytes()).collect()
+        }
+    };
+    let mut writer = encoder.write_header()?;
+    // the encoder has no setter for it, it goes anywhere before the pixels
+    if let Some(cicp) = display.cicp() {
+        writer.write_chunk(png::chunk::ChunkType(*b"cICP"), &cicp)?;
+    }
+    writer.write_image_data(&data)
+}
+
+pub fn write_tiff(path: &Path, fb: &Framebuffer, depth: BitDepth, encoding: Encoding) -> Result<()> {
+    use tiff::encoder::{colortype, TiffEncoder};
+
+    let file = File::create(path).map_err(|e| RendererError::io(path, e))?;
+    let mut encoder = TiffEncoder::new(BufWriter::new(file)).map_err(|e| RendererError::encode(path, e))?;
+    match depth {
+        BitDepth::Eight => encoder.write_image::<colortype::RGB8>(fb.width(), fb.height(), &fb.to_rgb8(encoding)),
+        BitDepth::Sixteen => encoder.write_image::<colortype::RGB16>(fb.width(), fb.height(), &fb.to_rgb16(encoding)),
+    }.map_err(|e| RendererError::encode(path, e))
+}
+
+// Writes the linear radiance as RGB plus the AOVs and light path passes as extra channels
+// ("normal.X", "depth.Z", "caustics.R", ...) so compositing apps show them as separate layers,
+// in the beauty's part or in parts of their own (see ExrLayout)
+pub fn write_exr(path: &Path, fb: &Framebuffer, precision: ExrPrecision, layout: ExrLayout) -> Result<()> {
+    let samples = |values: Vec<f32>| match precision {
+        ExrPrecision::Full => FlatSamples::F32(values),
+        ExrPrecision::Half => FlatSamples::F16(values.into_iter().map(f16::from_f32).collect()),
+    };
+    let component = |data: &[crate::Vec3], i: usize| -> Vec<f32> {
+        data.iter().map(|v| v[i] as f32).collect()
+    };
+
+    // each with the part it goes in with ExrLayout::Parts
+    let mut channels = vec![
+        ("beauty", AnyChannel::new("R", samples(component(&fb.beauty, 0)))),
+        ("beauty", AnyChannel::new("G", samples(component(&fb.beauty, 1)))),
+        ("beauty", AnyChannel::new("B", samples(component(&fb.beauty, 2)))),
+        ("normal", AnyChannel::new("normal.X", samples(component(&fb.normal, 0)))),
+        ("normal", AnyChannel::new("normal.Y", samples(component(&fb.normal, 1)))),
+        ("normal", AnyChannel::new("normal.Z", samples(component(&fb.normal, 2)))),
+        // depth stays 32-bit, half floats lose too much precision at distance
+        ("depth", AnyChannel::new("depth.Z", FlatSamples::F32(fb.depth.iter().map(|&d| d as f32).collect()))),
+    ];
+    if let Some(occlusion) = &fb.occlusion {
+        channels.push(("occlusion", AnyChannel::new("occlusion.Y", samples(occlusion.iter().map(|&o| o as f32).collect()))));
+    }
+    for pass in &fb.passes {
+        for (i, c) in ["R", "G", "B"].iter().enumerate() {
+            channels.push((pass.name.as_str(), AnyChannel::new(format!("{}.{}", pass.name, c).as_str(), samples(component(&pass.values, i)))));
+        }
+    }
+
+    // the metadata goes with the beauty
+    let layer = |name: &str, channels: Vec<AnyChannel<FlatSamples>>| {
+        let mut attributes = LayerAttributes::named(name);
+        if name == "beauty" {
+            attributes.other = exr_attributes(&fb.metadata);
+        }
+        Layer::new((fb.width() as usize, fb.height() as usize), attributes, exr::prelude::Encoding::FAST_LOSSLESS, AnyChannels::sort(channels.into()))
+    };
+    let mut attributes = ImageAttributes::with_size((fb.width() as usize, fb.height() as usize));
+    attributes.chromaticities = Some(chromaticities(WorkingSpace::of(&fb.metadata)));
+    match layout {
+        ExrLayout::Channels => {
+            let image = Image::from_layers(attributes, vec![layer("beauty", channels.into_iter().map(|(_, c)| c).collect())]);
+            image.write().to_file(path)
+        }
+        ExrLayout::Parts => {
+            let mut parts: Vec<(&str, Vec<AnyChannel<FlatSamples>>)> = Vec::new();
+            for (part, channel) in channels {
+                match parts.iter_mut().find(|(name, _)| *name == part) {
+                    Some((_, list)) => list.push(channel),
+                    None => parts.push((part, vec![channel])),
+                }
+            }
+            let layers: Vec<_> = parts.into_iter().map(|(name, channels)| layer(name, channels)).collect();
+            let image = Image::from_layers(attributes, layers);
+            image.write().to_file(path)
+        }
+    }.map_err(|e| RendererError::encode(path, e))
+}
+
+// the primaries and white of the working space, so other apps know which RGB it is
+fn chromaticities(space: WorkingSpace) -> Chromaticities {
+    let [red, green, blue, white] = space.chromaticities().map(|(x, y)| Vec2(x, y));
+    Chromaticities { red, green, blue, white }
+}
+
+fn exr_attributes(metadata: &[(String, String)]) -> std::collections::HashMap<Text, AttributeValue> {
+    metadata.iter()
+        .map(|(key, value)| (Text::from(key.as_str()), AttributeValue::Text(Text::from(value.as_str()))))
+        .collect()
+}
+
+// Tiled EXR written chunk by chunk in whatever order the tiles arrive, so only the tiles
+// in flight are ever in memory. Channels and parts are the same as write_exr, the occlusion
+// pass has to be asked for up front as the header comes before the tiles.
+#[allow(clippy::too_many_arguments)]
+pub fn write_exr_streamed(path: &Path, width: u32, height: u32, tile_size: u32, precision: ExrPrecision, layout: ExrLayout,
+                          occlusion: bool, metadata: &[(String, String)], tiles: impl Iterator<Item = Tile>) -> Result<()> {
+    use exr::block::{BlockIndex, UncompressedBlock};
+    use exr::block::writer::ChunksWriter;
+    use exr::math::RoundingMode;
+    use exr::meta::attribute::{ChannelDescription, LevelMode, LineOrder, SampleType, TileDescription};
+    use exr::meta::header::Header;
+    use exr::meta::BlockDescription;
+
+    let color_type = match precision {
+        ExrPrecision::Full => SampleType::F32,
+        ExrPrecision::Half => SampleType::F16,
+    };
+    // the part each channel goes in with ExrLayout::Parts, channels have to be sorted by name
+    type Channel = (&'static str, &'static str, SampleType, fn(&Framebuffer, usize) -> f32);
+    let mut channels: Vec<Channel> = vec![
+        ("beauty", "B", color_type, |fb, i| fb.beauty[i][2] as f32),
+        ("beauty", "G", color_type, |fb, i| fb.beauty[i][1] as f32),
+        ("beauty", "R", color_type, |fb, i| fb.beauty[i][0] as f32),
+        ("depth", "depth.Z", SampleType::F32, |fb, i| fb.depth[i] as f32),
+        ("normal", "normal.X", color_type, |fb, i| fb.normal[i][0] as f32),
+        ("normal", "normal.Y", color_type, |fb, i| fb.normal[i][1] as f32),
+        ("normal", "normal.Z", color_type, |fb, i| fb.normal[i][2] as f32),
+    ];
+    if occlusion {
+        // blank tiles of a cancelled render have none, they come out open
+        channels.push(("occlusion", "occlusion.Y", color_type, |fb, i| fb.occlusion.as_ref().map_or(1.0, |o| o[i] as f32)));
+    }
+    let mut parts: Vec<(&str, Vec<Channel>)> = Vec::new();
+    for channel in channels {
+        let part = if layout == ExrLayout::Parts { channel.0 } else { "beauty" };
+        match parts.iter_mut().find(|(name, _)| *name == part) {
+            Some((_, list)) => list.push(channel),
+            None => parts.push((part, vec![channel])),
+        }
+    }
+
+    let headers = parts.iter().map(|(name, channels)| {
+        let list = SmallVec::from_iter(channels.iter()
+            .map(|(_, name, ty, _)| ChannelDescription::new(*name, *ty, true)));
+        let mut header = Header::new((*name).into(), (width as usize, height as usize), list)
+            .with_encoding(
+                Compression::RLE,
+                BlockDescription::Tiles(TileDescription {
+                    tile_size: Vec2(tile_size as usize, tile_size as usize),
+                    level_mode: LevelMode::Singular,
+                    rounding_mode: RoundingMode::Down,
+                }),
+                LineOrder::Unspecified,
+            );
+        header.shared_attributes.chromaticities = Some(chromaticities(WorkingSpace::of(metadata)));
+        if *name == "beauty" {
+            header.own_attributes.other = exr_attributes(metadata);
+        }
+        header
+    }).collect();
+
+    let file = BufWriter::new(File::create(path).map_err(|e| RendererError::io(path, e))?);
+    let tiles_x = width.div_ceil(tile_size);
+    exr::block::write(file, headers, true, |meta, writer| {
+        for tile in tiles {
+            for (layer, (_, channels)) in parts.iter().enumerate() {
+                let index = BlockIndex {
+                    layer,
+                    level: Vec2(0, 0),
+                    pixel_position: Vec2(tile.x as usize, tile.y as usize),
+                    pixel_size: Vec2(tile.data.width() as usize, tile.data.height() as usize),
+                };
+                let block = UncompressedBlock::from_lines(&meta.headers[layer].channels, index, |line| {
+                    let row = (line.location.position.y() - tile.y as usize) * tile.data.width() as usize;
+                    let (_, _, ty, value) = channels[line.location.channel];
+                    match ty {
+                        SampleType::F16 => line.write_samples(|i| f16::from_f32(value(&tile.data, row + i))),
+                        _ => line.write_samples(|i| value(&tile.data, row + i)),
+                    }.expect("Tile lines match the header");
+                });
+
+                let chunk_index = ((tile.y / tile_size) * tiles_x + tile.x / tile_size) as usize;
+                writer.write_chunk(chunk_index, block.compress_to_chunk(&meta.headers)?)?;
+            }
+        }
+        Ok(())
+    }).map_err(|e| RendererError::encode(path, e))
+}
+
+// Creates the file and hands it to the body, IO errors get reported against the path
+fn write_file(path: &Path, body: impl FnOnce(&mut BufWriter<File>) -> std::io::Result<()>) -> Result<()> {
+    let file = File::create(path).map_err(|e| RendererError::io(path, e))?;
+    let mut w = BufWriter::new(file);
+    body(&mut w).and_then(|_| w.flush()).map_err(|e| RendererError::io(path, e))
+}
+
+// Radiance RGBE: a shared 8-bit exponent for the three mantissas
+fn rgbe(c: crate::Color) -> [u8; 4] {
+    // anything brighter, infinity included, comes out as the brightest the exponent can hold
+    let largest = 255.0 / 256.0 * Float::powi(2.0, 127);
+    let c = [c[0], c[1], c[2]].map(|x| if x.is_nan() { 0.0 } else { x.clamp(0.0, largest) });
+    let v = c[0].max(c[1]).max(c[2]);
+    if v < 1.0e-32 {
+        return [0, 0, 0, 0];
+    }
+
+    // frexp: v = m * 2^e with m in [0.5, 1)
+    let e = v.log2().floor() as i32 + 1;
+    let scale = 256.0 / Float::powi(2.0, e);
+    [
+        (c[0] * scale) as u8,
+        (c[1] * scale) as u8,
+        (c[2] * scale) as u8,
+        (e + 128) as u8,
+    ]
+}
+
+// Flat (non run-length encoded) scanlines, which every .hdr reader accepts
+pub fn write_hdr(path: &Path, fb: &Framebuffer) -> Result<()> {
+    write_file(path, |w| {
+        write!(w, "#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y {} +X {}\n", fb.height(), fb.width())?;
+        for c in &fb.beauty {
+            w.write_all(&rgbe(*c))?;
+        }
+        Ok(())
+    })
+}
+
+// Binary (P6) PPM, 8-bit like PNG
+pub fn write_ppm(path: &Path, fb: &Framebuffer, encoding: Encoding) -> Result<()> {
+    write_file(path, |w| {
+        write!(w, "P6\n{} {}\n255\n", fb.width(), fb.height())?;
+        w.write_all(&fb.to_rgb8(encoding))?;
+        Ok(())
+    })
+}
+
+// Linear radiance as 32-bit floats. PFM stores rows bottom to top and a negative scale means little-endian
+pub fn write_pfm(path: &Path, fb: &Framebuffer) -> Result<()> {
+    write_file(path, |w| {
+        write!(w, "PF\n{} {}\n-1.0\n", fb.width(), fb.height())?;
+        for row in fb.beauty.chunks(fb.width() as usize).rev() {
+            for c in row {
+                for i in 0..3 {
+                    w.write_all(&(c[i] as f32).to_le_bytes())?;
+                }
+            }
+        }
+        Ok(())
+    })
+}
+
+// Lossy formats for quick previews, quality goes from 0 to 100
+pub fn write_jpeg(path: &Path, fb: &Framebuffer, quality: u8, encoding: Encoding) -> Result<()> {
+    check_size(fb, "JPEG", u16::MAX as u32)?;
+    let encoder = jpeg_encoder::Encoder::new_file(path, quality).map_err(|e| RendererError::encode(path, e))?;
+    encoder.encode(&fb.to_rgb8(encoding), fb.width() as u16, fb.height() as u16, jpeg_encoder::ColorType::Rgb)
+        .map_err(|e| RendererError::encode(path, e))
+}
+
+// Progressive, so a directory listing or dashboard showing many of them gets a blurry whole
+// picture early on
+pub fn write_thumbnail(path: &Path, fb: &Framebuffer) -> Result<()> {
+    check_size(fb, "JPEG", u16::MAX as u32)?;
+    let mut encoder = jpeg_encoder::Encoder::new_file(path, 80).map_err(|e| RendererError::encode(path, e))?;
+    encoder.set_progressive(true);
+    encoder.encode(&fb.to_rgb8(Encoding::default()), fb.width() as u16, fb.height() as u16, jpeg_encoder::ColorType::Rgb)
+        .map_err(|e| RendererError::encode(path, e))
+}
+
+// JPEG keeps the sizes in 16 bits and WebP in 14, checked before the file is made
+fn check_size(fb: &Framebuffer, format: &str, limit: u32) -> Result<()> {
+    if fb.width() > limit || fb.height() > limit {
+        return Err(RendererError::Unsupported(format!("the image is {}x{}, {} is limited to {} pixels per side",
+                                                      fb.width(), fb.height(), format, limit)));
+    }
+    Ok(())
+}
+
+#[cfg(feature = "native")]
+pub fn write_webp(path: &Path, fb: &Framebuffer, quality: u8, encoding: Encoding) -> Result<()> {
+    check_size(fb, "WebP", 16383)?;
+    let data = fb.to_rgb8(encoding);
+    let encoded = webp::Encoder::from_rgb(&data, fb.width(), fb.height()).encode(quality as f32);
+    std::fs::write(path, &*encoded).map_err(|e| RendererError::io(path, e))
+}
+
+// the encoder is C code, left out of builds without the native feature
+#[cfg(not(feature = "native"))]
+pub fn write_webp(_path: &Path, _fb: &Framebuffer, _quality: u8, _encoding: Encoding) -> Result<()> {
+    Err(RendererError::Unsupported("WebP output needs the native feature".to_string()))
+}
@@ -0,0 +1,214 @@
+use crate::aabb::Aabb;
+use crate::camera::{Camera, Projection};
+use crate::framebuffer::Framebuffer;
+use crate::inspect::SceneInfo;
+use crate::{Color, Float, Point3, Vec3};
+
+// Wireframes drawn over a render, for finding out why a scene comes out black or empty: are the
+// lights where they should be, is anything in front of the camera at all. The renderer has no
+// BVH to show, every object is tested by every ray, so the bounds are the boxes of the objects
+// themselves (see inspect::SceneInfo::boxes).
+#[derive(Copy, Clone, PartialEq, Debug)]
+#[cfg_attr(feature = "native", derive(clap::ValueEnum))]
+pub enum Overlay {
+    // the boxes of the objects that give off light, in yellow, with a cross at their middle
+    Lights,
+    // the boxes of the other objects in green, and the box around everything in cyan
+    Bounds,
+    // where the camera looks at in magenta, and the horizon
+    Camera,
+    // what comes out sharp through the thin lens tinted green, and the focal plane in magenta
+    // where it cuts through the scene, see `focus`
+    Focus,
+}
+
+// scene units in front of the camera lines are cut off at
+const NEAR: Float = 1.0e-3;
+
+// half the width of the crosses, in pixels
+const CROSS: Float = 6.0;
+
+// how much further one pixel's point can be than its neighbour's and still be taken to be on
+// the same surface, as a share of the distance
+const SAME_SURFACE: Float = 0.05;
+
+// Draws `overlays` over the beauty of `data`, as `camera` sees the objects of `info`. Points
+// count as in focus while their circle of confusion is at most `coc` pixels across.
+pub fn draw(data: &mut Framebuffer, camera: &Camera, info: &SceneInfo, overlays: &[Overlay], coc: Float) {
+    // shading goes under the lines
+    if overlays.contains(&Overlay::Focus) {
+        focus(data, camera, coc, Color::new(0.0, 1.0, 0.0), Color::new(1.0, 0.0, 1.0));
+    }
+    let mut canvas = Canvas { data, camera, forward: (camera.lookat() - camera.lookfrom()).normalized() };
+    let (yellow, green, cyan, magenta) = (Color::new(1.0, 1.0, 0.0), Color::new(0.0, 1.0, 0.0),
+                                          Color::new(0.0, 1.0, 1.0), Color::new(1.0, 0.0, 1.0));
+    if overlays.contains(&Overlay::Bounds) {
+        for b in info.boxes.iter().filter(|b| !b.light) {
+            canvas.bounding_box(&b.bounds, green);
+        }
+        if let Some(bounds) = &info.bounds {
+            canvas.bounding_box(bounds, cyan);
+        }
+    }
+    if overlays.contains(&Overlay::Lights) {
+        for b in info.boxes.iter().filter(|b| b.light) {
+            canvas.bounding_box(&b.bounds, yellow);
+            canvas.cross(0.5 * (b.bounds.min + b.bounds.max), yellow);
+        }
+    }
+    if overlays.contains(&Overlay::Camera) {
+        canvas.cross(camera.lookat(), magenta);
+        canvas.horizon(magenta);
+    }
+}
+
+// The depth of field of the thin lens, from the depth pass. A point z along the view from the
+// camera spreads over a circle on the focal plane as wide as the aperture times how far it is
+// off the plane over z; in pixels that says whether it comes out sharp, and those that do are
+// tinted `sharp`. Pixels next to one on the other side of the plane are painted `plane`, as
+// long as they're on the same surface, not just far apart at the outline of something.
+// Panoramas and real lenses aren't thin lenses, nothing is drawn for them.
+fn focus(data: &mut Framebuffer, camera: &Camera, coc: Float, sharp: Color, plane: Color) {
+    if camera.projection() != Projection::Perspective || camera.lens().is_some() {
+        return;
+    }
+    let (w, h) = (data.width() as usize, data.height() as usize);
+    let forward = (camera.lookat() - camera.lookfrom()).normalized();
+    let pinhole = camera.clone().pinhole();
+    let f = camera.focus_dist();
+    // pixels per scene unit on the focal plane
+    let scale = h as Float / (2.0 * f * (camera.vert_fov().to_radians() / 2.0).tan());
+    // the depth pass goes along the ray through the middle of the pixel, z along the view;
+    // None where the sky is
+    let z: Vec<Option<Float>> = (0..w * h)
+        .map(|k| {
+            let depth = data.depth[k];
+            depth.is_finite().then(|| {
+                let (i, j) = (k % w, h - 1 - k / w);
+                let (u, v) = ((i as Float + 0.5) / (w - 1).max(1) as Float, (j as Float + 0.5) / (h - 1).max(1) as Float);
+                depth * pinhole.get_ray(u, v).direction().normalized().dot(forward)
+            })
+        })
+        .collect();
+    let side = |z: Float| z < f;
+    for k in 0..w * h {
+        let Some(zk) = z[k] else { continue };
+        let across = [(k % w + 1 < w).then_some(k + 1), (k + w < w * h).then_some(k + w)]
+            .into_iter()
+            .flatten()
+            .any(|n| z[n].is_some_and(|zn| side(zn) != side(zk) && (zn - zk).abs() < SAME_SURFACE * zn.min(zk)));
+        if across {
+            data.beauty[k] = plane;
+        } else if camera.aperture() * (zk - f).abs() / zk * scale <= coc {
+            data.beauty[k] = 0.6 * data.beauty[k] + 0.4 * sharp;
+        }
+    }
+}
+
+struct Canvas<'a> {
+    data: &'a mut Framebuffer,
+    camera: &'a Camera,
+    forward: Vec3,
+}
+
+impl Canvas<'_> {
+    fn bounding_box(&mut self, bounds: &Aabb, color: Color) {
+        let corners = bounds.corners();
+        // the edges join corners one bit apart
+        for i in 0..8 {
+            for bit in [1, 2, 4] {
+                if i & bit == 0 {
+                    self.line(corners[i], corners[i | bit], color);
+                }
+            }
+        }
+    }
+
+    fn cross(&mut self, p: Point3, color: Color) {
+        if let Some((x, y)) = self.project(p) {
+            self.pixels((x - CROSS, y), (x + CROSS, y), color);
+            self.pixels((x, y - CROSS), (x, y + CROSS), color);
+        }
+    }
+
+    // where level ground would meet the sky, far points straight ahead to either side
+    fn horizon(&mut self, color: Color) {
+        let up = self.camera.vup().normalized();
+        let ahead = self.forward - self.forward.dot(up) * up;
+        // looking straight up or down there's none to see
+        if ahead.near_zero() {
+            return;
+        }
+        let ahead = ahead.normalized();
+        let side = ahead.cross(up);
+        let far = 1.0e6 * (self.camera.focus_dist() + 1.0);
+        let o = self.camera.lookfrom();
+        self.line(o + far * (ahead - 4.0 * side), o + far * (ahead + 4.0 * side), color);
+    }
+
+    // the segment from `a` to `b`, cut off where it goes behind the camera
+    fn line(&mut self, a: Point3, b: Point3, color: Color) {
+        let o = self.camera.lookfrom();
+        let (da, db) = ((a - o).dot(self.forward), (b - o).dot(self.forward));
+        if da < NEAR && db < NEAR {
+            return;
+        }
+        let cut = |p: Point3, q: Point3, dp: Float, dq: Float| if dp < NEAR { p + (NEAR - dp) / (dq - dp) * (q - p) } else { p };
+        let (a, b) = (cut(a, b, da, db), cut(b, a, db, da));
+        if let (Some(a), Some(b)) = (self.project(a), self.project(b)) {
+            self.pixels(a, b, color);
+        }
+    }
+
+    // pixel coordinates from the top left of a point in front of the camera
+    fn project(&self, p: Point3) -> Option<(Float, Float)> {
+        let o = self.camera.lookfrom();
+        if (p - o).dot(self.forward) < NEAR {
+            return None;
+        }
+        let (u, v) = self.camera.screen(&crate::Ray::new(o, p - o));
+        Some((u * self.data.width() as Float, (1.0 - v) * self.data.height() as Float))
+    }
+
+    fn pixels(&mut self, a: (Float, Float), b: (Float, Float), color: Color) {
+        line(self.data, a, b, color);
+    }
+}
+
+// A line of pixels over the beauty, from and to pixel coordinates counted from the top left,
+// one per step along the longer side and those off the image left out
+pub(crate) fn line(data: &mut Framebuffer, a: (Float, Float), b: (Float, Float), color: Color) {
+    let (w, h) = (data.width() as Float, data.height() as Float);
+    // lines far off the image would take forever, they're cut to the image first
+    let Some((a, b)) = clip(a, b, (w, h)) else {
+        return;
+    };
+    let steps = (b.0 - a.0).abs().max((b.1 - a.1).abs()).ceil().max(1.0) as u32;
+    for i in 0..=steps {
+        let t = i as Float / steps as Float;
+        let (x, y) = (a.0 + t * (b.0 - a.0), a.1 + t * (b.1 - a.1));
+        if x >= 0.0 && y >= 0.0 && x < w && y < h {
+            let i = y as usize * data.width() as usize + x as usize;
+            data.beauty[i] = color;
+        }
+    }
+}
+
+// The part of the line from `a` to `b` on an image of `size`, by Liang and Barsky's clipping:
+// where it crosses each edge as a share of the way from a to b
+fn clip(a: (Float, Float), b: (Float, Float), size: (Float, Float)) -> Option<((Float, Float), (Float, Float))> {
+    let (dx, dy) = (b.0 - a.0, b.1 - a.1);
+    let (mut t0, mut t1): (Float, Float) = (0.0, 1.0);
+    for (p, q) in [(-dx, a.0), (dx, size.0 - a.0), (-dy, a.1), (dy, size.1 - a.1)] {
+        if p == 0.0 {
+            if q < 0.0 {
+                return None;
+            }
+        } else if p < 0.0 {
+            t0 = t0.max(q / p);
+        } else {
+            t1 = t1.min(q / p);
+        }
+    }
+    (t0 <= t1).then_some(((a.0 + t0 * dx, a.1 + t0 * dy), (a.0 + t1 * dx, a.1 + t1 * dy)))
+}
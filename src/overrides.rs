@@ -0,0 +1,220 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+use serde_json::Value;
+use crate::error::{RendererError, Result};
+use crate::scene_file::SceneFile;
+use crate::validate;
+
+// One change to a scene after it's loaded, so a value can be tried out without editing the
+// file: `material:glass.ir=1.33`, `texture:floor.scale=4`, `object:ball.radius=0.8`,
+// `camera:vfov=30` or `environment:rotation=90`. Objects are found by name, in groups too, or
+// by their place in the objects list, and take `visible` as the opposite of `hidden`. The
+// value is read as JSON, and taken as a string when it isn't any.
+#[derive(Clone, Debug)]
+pub struct Override {
+    target: Target,
+    field: String,
+    value: Value,
+}
+
+#[derive(Clone, Debug)]
+enum Target {
+    Material(String),
+    Texture(String),
+    Object(String),
+    // render, camera, background, environment or fog
+    Section(&'static str),
+}
+
+const SECTIONS: [&str; 5] = ["render", "camera", "background", "environment", "fog"];
+
+impl FromStr for Override {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Override, String> {
+        let (path, value) = s.split_once('=').ok_or_else(|| format!("'{}' has no value, e.g. material:glass.ir=1.33", s))?;
+        let (target, field) = target(path)?;
+        Ok(Override { target, field, value: json(value) })
+    }
+}
+
+// what `kind:name.field` points to
+fn target(path: &str) -> std::result::Result<(Target, String), String> {
+    let (kind, rest) = path.split_once(':').ok_or_else(|| format!("'{}' says what to change with kind:name.field", path))?;
+    let (target, field) = match SECTIONS.iter().find(|&&section| section == kind) {
+        Some(section) => (Target::Section(section), rest),
+        None => {
+            let (name, field) = rest.split_once('.').ok_or_else(|| format!("'{}' needs a name and a field, {}:name.field", path, kind))?;
+            let target = match kind {
+                "material" => Target::Material(name.to_string()),
+                "texture" => Target::Texture(name.to_string()),
+                "object" => Target::Object(name.to_string()),
+                _ => return Err(format!("can't override a {}, only material, texture, object, render, camera, background, environment and fog", kind)),
+            };
+            (target, field)
+        }
+    };
+    if field.is_empty() {
+        return Err(format!("'{}' has no field", path));
+    }
+    Ok((target, field.to_string()))
+}
+
+fn json(value: &str) -> Value {
+    serde_json::from_str(value).unwrap_or_else(|_| Value::String(value.to_string()))
+}
+
+impl Override {
+    // on the scene as JSON, checked for sense when it's read back
+    fn set(&self, scene: &mut Value) -> Result<()> {
+        let missing = |what: &str| RendererError::Scene(format!("override: there's no {}", what));
+        match &self.target {
+            Target::Section(section) => {
+                // sections left at their defaults aren't saved
+                let table = &mut scene[section];
+                if table.is_null() {
+                    *table = Value::Object(Default::default());
+                }
+                set(table, &self.field, self.value.clone());
+            }
+            Target::Material(name) => {
+                let material = scene["materials"].get_mut(name).ok_or_else(|| missing(&format!("material '{}'", name)))?;
+                set(material, &self.field, self.value.clone());
+            }
+            Target::Texture(name) => {
+                let texture = scene["textures"].get_mut(name).ok_or_else(|| missing(&format!("texture '{}'", name)))?;
+                set(texture, &self.field, self.value.clone());
+            }
+            Target::Object(name) => {
+                let (field, value) = match (self.field.as_str(), &self.value) {
+                    ("visible", Value::Bool(visible)) => ("hidden", Value::Bool(!visible)),
+                    ("visible", v) => return Err(RendererError::Scene(format!("override: visible is true or false, not {}", v))),
+                    (field, v) => (field, v.clone()),
+                };
+                if named(&mut scene["objects"], name, &mut |object| set(object, field, value.clone())) == 0 {
+                    let object = name.parse().ok()
+                        .and_then(|i: usize| scene["objects"].get_mut(i))
+                        .ok_or_else(|| missing(&format!("object named '{}'", name)))?;
+                    set(object, field, value.clone());
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn set(table: &mut Value, field: &str, value: Value) {
+    if let Some(table) = table.as_object_mut() {
+        table.insert(field.to_string(), value);
+    }
+}
+
+// Changes every object called `name`, the copies in several groups all together, and says
+// how many there were
+fn named(objects: &mut Value, name: &str, change: &mut impl FnMut(&mut Value)) -> usize {
+    let mut found = 0;
+    for object in objects.as_array_mut().into_iter().flatten() {
+        if object["name"] == name {
+            change(object);
+            found += 1;
+        } else if object.get("objects").is_some() {
+            found += named(&mut object["objects"], name, change);
+        }
+    }
+    found
+}
+
+// The scene with the overrides made, in order, and checked again like a freshly loaded one
+pub fn apply(file: SceneFile, overrides: &[Override]) -> Result<SceneFile> {
+    if overrides.is_empty() {
+        return Ok(file);
+    }
+    let mut scene = serde_json::to_value(&file).map_err(|e| RendererError::Scene(e.to_string()))?;
+    for o in overrides {
+        o.set(&mut scene)?;
+    }
+    let mut changed: SceneFile = serde_json::from_value(scene)
+        .map_err(|e| RendererError::Scene(format!("after the overrides: {}", e)))?;
+    changed.base_dir = file.base_dir;
+
+    let problems = validate::check(&changed);
+    if !problems.is_empty() {
+        let problems: Vec<String> = problems.iter().map(|p| p.describe(&HashMap::new())).collect();
+        return Err(RendererError::Scene(format!("after the overrides: {}", problems.join(", "))));
+    }
+    Ok(changed)
+}
+
+// One value tried after another, for comparing them side by side. Written like an override
+// with either a list of values, `render:samples_per_pixel=1,4,16,64`, or a range and how many
+// evenly spaced steps it's cut into, ends included: `material:metal.fuzz=0..1:5`. A list of
+// arrays or strings with commas in them has to be JSON, `[[1,0,0],[0,1,0]]`.
+#[derive(Clone, Debug)]
+pub struct Sweep {
+    target: Target,
+    field: String,
+    values: Vec<Value>,
+}
+
+impl FromStr for Sweep {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Sweep, String> {
+        let (path, values) = s.split_once('=').ok_or_else(|| format!("'{}' has no values, e.g. material:metal.fuzz=0..1:5", s))?;
+        let (target, field) = target(path)?;
+        let range = values.split_once("..").and_then(|(from, rest)| rest.split_once(':').map(|(to, steps)| (from, to, steps)));
+        let values = if let Some((from, to, steps)) = range {
+            range_values(from, to, steps).ok_or_else(|| format!("'{}' isn't a range, FROM..TO:STEPS with at least 2 steps", values))?
+        } else if let Ok(Value::Array(values)) = serde_json::from_str(values) {
+            values
+        } else {
+            values.split(',').map(|v| json(v.trim())).collect()
+        };
+        if values.is_empty() {
+            return Err(format!("'{}' has no values", s));
+        }
+        Ok(Sweep { target, field, values })
+    }
+}
+
+// whole numbers stay whole when both ends are, so counts can be swept too
+fn range_values(from: &str, to: &str, steps: &str) -> Option<Vec<Value>> {
+    let steps: usize = steps.trim().parse().ok().filter(|&n| n >= 2)?;
+    let (a, b): (f64, f64) = (from.trim().parse().ok()?, to.trim().parse().ok()?);
+    let whole = from.trim().parse::<i64>().is_ok() && to.trim().parse::<i64>().is_ok();
+    Some((0..steps).map(|i| {
+        let v = a + (b - a) * i as f64 / (steps - 1) as f64;
+        if whole && v.fract() == 0.0 { Value::from(v as i64) } else { Value::from(v) }
+    }).collect())
+}
+
+impl Sweep {
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    pub fn field(&self) -> &str {
+        &self.field
+    }
+
+    // the override for the i-th value
+    pub fn step(&self, i: usize) -> Override {
+        Override {
+            target: self.target.clone(),
+            field: self.field.clone(),
+            value: self.values[i].clone(),
+        }
+    }
+
+    // the i-th value as it's written, strings without their quotes
+    pub fn value(&self, i: usize) -> String {
+        match &self.values[i] {
+            Value::String(s) => s.clone(),
+            v => v.to_string(),
+        }
+    }
+}
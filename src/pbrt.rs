@@ -0,0 +1,631 @@
+// Importer for a subset of the PBRT v3/v4 scene format, translated into a SceneFile so it
+// renders like any other scene file. Supported: perspective cameras, Film resolution, Sampler
+// pixel samples, Integrator max depth, the transform directives, attribute blocks, named and
+// anonymous materials (diffuse/matte, conductor/metal, mirror, dielectric/glass), diffuse area
+// lights with RGB or blackbody L, infinite lights (as a constant background), spheres,
+// triangle meshes and Include. Anything else is skipped with a warning.
+use std::collections::{HashMap, VecDeque};
+use std::path::{Path, PathBuf};
+use crate::error::{RendererError, Result};
+use crate::scene_file::{BackgroundDesc, CameraDesc, EnvironmentDesc, MaterialDesc, ObjectDesc, RenderDesc, SceneFile};
+use crate::transform::{inverse, mirror, mul, point, rotate, scale, translate, transpose, vector, Matrix, IDENTITY};
+use crate::{Float, Point3, Vec3};
+
+// world to camera, as pbrt builds it
+fn look_at(eye: Point3, look: Point3, up: Vec3) -> Option<Matrix> {
+    let dir = (look - eye).normalized();
+    let right = up.normalized().cross(dir);
+    if right.near_zero() {
+        return None;
+    }
+    let right = right.normalized();
+    let new_up = dir.cross(right);
+    let camera_to_world = [
+        [right.x(), new_up.x(), dir.x(), eye.x()],
+        [right.y(), new_up.y(), dir.y(), eye.y()],
+        [right.z(), new_up.z(), dir.z(), eye.z()],
+        [0.0, 0.0, 0.0, 1.0],
+    ];
+    inverse(&camera_to_world)
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Word(String),
+    Str(String),
+    Num(Float),
+    Open,
+    Close,
+}
+
+// token plus the line it came from, for error messages
+struct Located {
+    token: Token,
+    file: usize,
+    line: usize,
+}
+
+fn tokenize(text: &str, file: usize) -> std::result::Result<Vec<Located>, (usize, String)> {
+    let mut tokens = Vec::new();
+    for (n, line) in text.lines().enumerate() {
+        let mut chars = line.char_indices().peekable();
+        while let Some(&(start, c)) = chars.peek() {
+            let token = match c {
+                '#' => break,
+                c if c.is_whitespace() => {
+                    chars.next();
+                    continue;
+                }
+                '[' => {
+                    chars.next();
+                    Token::Open
+                }
+                ']' => {
+                    chars.next();
+                    Token::Close
+                }
+                '"' => {
+                    chars.next();
+                    let mut s = String::new();
+                    loop {
+                        match chars.next() {
+                            Some((_, '"')) => break,
+                            Some((_, c)) => s.push(c),
+                            None => return Err((n + 1, "unterminated string".to_string())),
+                        }
+                    }
+                    Token::Str(s)
+                }
+                _ => {
+                    let mut end = line.len();
+                    while let Some(&(i, c)) = chars.peek() {
+                        if c.is_whitespace() || c == '[' || c == ']' || c == '"' || c == '#' {
+                            end = i;
+                            break;
+                        }
+                        chars.next();
+                    }
+                    let word = &line[start..end];
+                    match word {
+                        // pbrt-v4 writes booleans bare
+                        "true" | "false" => Token::Str(word.to_string()),
+                        _ if word.starts_with(|c: char| c.is_ascii_digit() || c == '-' || c == '+' || c == '.') => {
+                            Token::Num(word.parse().map_err(|_| (n + 1, format!("bad number '{}'", word)))?)
+                        }
+                        _ => Token::Word(word.to_string()),
+                    }
+                }
+            };
+            tokens.push(Located { token, file, line: n + 1 });
+        }
+    }
+    Ok(tokens)
+}
+
+enum Value {
+    Nums(Vec<Float>),
+    Strs(Vec<String>),
+}
+
+struct Param {
+    ty: String,
+    name: String,
+    value: Value,
+}
+
+#[derive(Default)]
+struct Params(Vec<Param>);
+
+impl Params {
+    fn find(&self, name: &str) -> Option<&Param> {
+        self.0.iter().find(|p| p.name == name)
+    }
+
+    fn nums(&self, name: &str) -> Option<&[Float]> {
+        match self.find(name).map(|p| &p.value) {
+            Some(Value::Nums(v)) => Some(v),
+            _ => None,
+        }
+    }
+
+    fn float(&self, name: &str, default: Float) -> Float {
+        self.nums(name).and_then(|v| v.first().copied()).unwrap_or(default)
+    }
+
+    fn string(&self, name: &str) -> Option<&str> {
+        match self.find(name).map(|p| &p.value) {
+            Some(Value::Strs(v)) => v.first().map(String::as_str),
+            _ => None,
+        }
+    }
+
+    // only plain RGB values, spectra and textures aren't supported
+    // "blackbody" values, the temperature and the scale: pbrt-v3 gives both, v4 only the
+    // temperature
+    fn blackbody(&self, name: &str) -> Option<(Float, Float)> {
+        let p = self.find(name)?;
+        match (&p.value, p.ty.as_str()) {
+            (Value::Nums(v), "blackbody") if !v.is_empty() => Some((v[0], v.get(1).copied().unwrap_or(1.0))),
+            _ => None,
+        }
+    }
+
+    fn rgb(&self, name: &str) -> Option<[Float; 3]> {
+        let p = self.find(name)?;
+        match (&p.value, p.ty.as_str()) {
+            (Value::Nums(v), "rgb" | "color") if v.len() >= 3 => Some([v[0], v[1], v[2]]),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone)]
+struct GraphicsState {
+    ctm: Matrix,
+    material: Option<String>,
+    area_light: Option<String>,
+}
+
+struct Importer {
+    files: Vec<PathBuf>,
+    // for each file its canonical path and the file that included it, to catch include cycles
+    included: Vec<(PathBuf, Option<usize>)>,
+    tokens: VecDeque<Located>,
+    state: GraphicsState,
+    stack: Vec<GraphicsState>,
+    transform_stack: Vec<Matrix>,
+    named_coordinate_systems: HashMap<String, Matrix>,
+    // camera to world, set by the Camera directive
+    camera: Option<(Matrix, Params)>,
+    film: Params,
+    sampler: Params,
+    integrator: Params,
+    materials: HashMap<String, MaterialDesc>,
+    objects: Vec<ObjectDesc>,
+    background: BackgroundDesc,
+    // inside ObjectBegin/ObjectEnd, instancing isn't supported so those shapes are dropped
+    in_object: bool,
+}
+
+impl Importer {
+    fn error(&self, file: usize, line: usize, message: impl std::fmt::Display) -> RendererError {
+        RendererError::parse(&self.files[file], format!("line {}: {}", line, message))
+    }
+
+    fn warn(&self, file: usize, line: usize, message: impl std::fmt::Display) {
+        log::warn!("{}: line {}: {}", self.files[file].display(), line, message);
+    }
+
+    // `from` being the file and line of the Include, None for the scene itself
+    fn include(&mut self, path: &Path, from: Option<(usize, usize)>) -> Result<()> {
+        let canonical = path.canonicalize().map_err(|e| RendererError::io(path, e))?;
+        if let Some((file, line)) = from {
+            // the files the Include is in, from the innermost out
+            let mut outer = Some(file);
+            while let Some(f) = outer {
+                if self.included[f].0 == canonical {
+                    return Err(self.error(file, line, format!("Include cycle, {} includes itself", path.display())));
+                }
+                outer = self.included[f].1;
+            }
+        }
+        let text = std::fs::read_to_string(path).map_err(|e| RendererError::io(path, e))?;
+        self.files.push(path.to_path_buf());
+        self.included.push((canonical, from.map(|(file, _)| file)));
+        let file = self.files.len() - 1;
+        let tokens = tokenize(&text, file).map_err(|(line, e)| self.error(file, line, e))?;
+        // included tokens go in front of whatever comes after the Include
+        for t in tokens.into_iter().rev() {
+            self.tokens.push_front(t);
+        }
+        Ok(())
+    }
+
+    // everything up to the next directive
+    fn arguments(&mut self) -> Vec<Token> {
+        let mut args = Vec::new();
+        while let Some(t) = self.tokens.front() {
+            if let Token::Word(_) = t.token {
+                break;
+            }
+            args.push(self.tokens.pop_front().unwrap().token);
+        }
+        args
+    }
+
+    fn run(&mut self) -> Result<()> {
+        while let Some(Located { token, file, line }) = self.tokens.pop_front() {
+            let directive = match token {
+                Token::Word(w) => w,
+                t => return Err(self.error(file, line, format!("expected a directive, found {:?}", t))),
+            };
+            if directive == "ActiveTransform" {
+                // takes a bare word (All, StartTime or EndTime), motion blur isn't supported
+                self.tokens.pop_front();
+                continue;
+            }
+            let args = self.arguments();
+            self.directive(&directive, args, file, line)?;
+        }
+        Ok(())
+    }
+
+    fn directive(&mut self, directive: &str, args: Vec<Token>, file: usize, line: usize) -> Result<()> {
+        let nums = |count: usize| -> Result<Vec<Float>> {
+            let v: Vec<Float> = args.iter().filter_map(|t| match t { Token::Num(n) => Some(*n), _ => None }).collect();
+            if v.len() != count {
+                return Err(self.error(file, line, format!("{} takes {} numbers", directive, count)));
+            }
+            Ok(v)
+        };
+        let v3 = |v: &[Float]| Vec3::new(v[0], v[1], v[2]);
+
+        match directive {
+            "Identity" => self.state.ctm = IDENTITY,
+            "Translate" => {
+                let v = nums(3)?;
+                self.state.ctm = mul(&self.state.ctm, &translate(v3(&v)));
+            }
+            "Scale" => {
+                let v = nums(3)?;
+                self.state.ctm = mul(&self.state.ctm, &scale(v3(&v)));
+            }
+            "Rotate" => {
+                let v = nums(4)?;
+                self.state.ctm = mul(&self.state.ctm, &rotate(v[0], v3(&v[1..])));
+            }
+            "LookAt" => {
+                let v = nums(9)?;
+                let m = look_at(v3(&v[0..3]), v3(&v[3..6]), v3(&v[6..9]))
+                    .ok_or_else(|| self.error(file, line, "the up vector is parallel to the view direction"))?;
+                self.state.ctm = mul(&self.state.ctm, &m);
+            }
+            "Transform" | "ConcatTransform" => {
+                // given column by column
+                let v = nums(16)?;
+                let mut m = [[0.0; 4]; 4];
+                for (i, row) in m.iter_mut().enumerate() {
+                    row.copy_from_slice(&v[i*4..i*4 + 4]);
+                }
+                let m = transpose(&m);
+                self.state.ctm = if directive == "Transform" { m } else { mul(&self.state.ctm, &m) };
+            }
+            "CoordinateSystem" => {
+                if let Some(Token::Str(name)) = args.first() {
+                    self.named_coordinate_systems.insert(name.clone(), self.state.ctm);
+                }
+            }
+            "CoordSysTransform" => {
+                if let Some(m) = args.first().and_then(|t| match t {
+                    Token::Str(name) => self.named_coordinate_systems.get(name),
+                    _ => None,
+                }) {
+                    self.state.ctm = *m;
+                }
+            }
+            "WorldBegin" => {
+                // the camera transform was set up before, the world starts out untransformed
+                self.named_coordinate_systems.insert("camera".to_string(), self.state.ctm);
+                self.state.ctm = IDENTITY;
+            }
+            "WorldEnd" => {}
+            "AttributeBegin" => self.stack.push(self.state.clone()),
+            "AttributeEnd" => {
+                self.state = self.stack.pop().ok_or_else(|| self.error(file, line, "AttributeEnd without AttributeBegin"))?;
+            }
+            "TransformBegin" => self.transform_stack.push(self.state.ctm),
+            "TransformEnd" => {
+                self.state.ctm = self.transform_stack.pop().ok_or_else(|| self.error(file, line, "TransformEnd without TransformBegin"))?;
+            }
+            "Include" | "Import" => {
+                let name = match args.first() {
+                    Some(Token::Str(s)) => s.clone(),
+                    _ => return Err(self.error(file, line, "Include needs a file name")),
+                };
+                let path = self.files[0].parent().unwrap_or(Path::new("")).join(name);
+                self.include(&path, Some((file, line)))?;
+            }
+            "Camera" => {
+                let (ty, params) = self.typed(args, file, line)?;
+                if ty != "perspective" {
+                    self.warn(file, line, format!("{} camera is not supported, rendering it as perspective", ty));
+                }
+                let camera_to_world = inverse(&self.state.ctm)
+                    .ok_or_else(|| self.error(file, line, "the camera transform can't be inverted"))?;
+                self.camera = Some((camera_to_world, params));
+            }
+            "Film" => self.film = self.typed(args, file, line)?.1,
+            "Sampler" => self.sampler = self.typed(args, file, line)?.1,
+            "Integrator" => self.integrator = self.typed(args, file, line)?.1,
+            "Material" => {
+                let (ty, params) = self.typed(args, file, line)?;
+                let name = format!("material_{}", self.materials.len());
+                let material = self.material(&ty, &params, file, line);
+                self.materials.insert(name.clone(), material);
+                self.state.material = Some(name);
+            }
+            "MakeNamedMaterial" => {
+                let (name, params) = self.typed(args, file, line)?;
+                let ty = params.string("type").unwrap_or("diffuse").to_string();
+                let material = self.material(&ty, &params, file, line);
+                self.materials.insert(name, material);
+            }
+            "NamedMaterial" => match args.first() {
+                Some(Token::Str(name)) => self.state.material = Some(name.clone()),
+                _ => return Err(self.error(file, line, "NamedMaterial needs a name")),
+            },
+            "AreaLightSource" => {
+                let (ty, params) = self.typed(args, file, line)?;
+                if ty != "diffuse" {
+                    self.warn(file, line, format!("{} area lights are not supported", ty));
+                    return Ok(());
+                }
+                let (color, temperature, scale) = match params.blackbody("L") {
+                    Some((t, s)) => ([1.0, 1.0, 1.0], Some(t), s),
+                    None => (params.rgb("L").unwrap_or([1.0, 1.0, 1.0]), None, 1.0),
+                };
+                let name = format!("light_{}", self.materials.len());
+                let intensity = scale * params.float("scale", 1.0);
+                self.materials.insert(name.clone(), MaterialDesc::Light { color, intensity, temperature });
+                self.state.area_light = Some(name);
+            }
+            "LightSource" => {
+                let (ty, params) = self.typed(args, file, line)?;
+                match (ty.as_str(), params.rgb("L")) {
+                    ("infinite", Some(l)) => {
+                        let s = params.float("scale", 1.0);
+                        self.background = BackgroundDesc::Color { color: [l[0] * s, l[1] * s, l[2] * s] };
+                    }
+                    ("infinite", None) => {
+                        let s = params.float("scale", 1.0);
+                        self.background = BackgroundDesc::Color { color: [s, s, s] };
+                    }
+                    _ => self.warn(file, line, format!("{} lights are not supported, only area and infinite lights are", ty)),
+                }
+            }
+            "Shape" => {
+                let (ty, params) = self.typed(args, file, line)?;
+                if self.in_object {
+                    return Ok(());
+                }
+                self.shape(&ty, &params, file, line)?;
+            }
+            "ObjectBegin" => {
+                self.warn(file, line, "object instancing is not supported, the object is skipped");
+                self.stack.push(self.state.clone());
+                self.in_object = true;
+            }
+            "ObjectEnd" => {
+                self.state = self.stack.pop().ok_or_else(|| self.error(file, line, "ObjectEnd without ObjectBegin"))?;
+                self.in_object = false;
+            }
+            "ObjectInstance" | "Texture" | "MakeNamedMedium" | "MediumInterface" | "ReverseOrientation"
+            | "PixelFilter" | "Accelerator" | "ColorSpace" | "Option" | "Attribute" | "TransformTimes" => {
+                self.warn(file, line, format!("{} is not supported, skipped", directive));
+            }
+            _ => return Err(self.error(file, line, format!("unknown directive {}", directive))),
+        }
+        Ok(())
+    }
+
+    // the type (or name) string that most directives start with, then the parameter list
+    fn typed(&self, args: Vec<Token>, file: usize, line: usize) -> Result<(String, Params)> {
+        let mut args = args.into_iter().peekable();
+        let ty = match args.next() {
+            Some(Token::Str(s)) => s,
+            _ => return Err(self.error(file, line, "expected a quoted type name")),
+        };
+
+        let mut params = Params::default();
+        while let Some(t) = args.next() {
+            let decl = match t {
+                Token::Str(s) => s,
+                t => return Err(self.error(file, line, format!("expected a parameter, found {:?}", t))),
+            };
+            let mut parts = decl.split_whitespace();
+            let (ty, name) = match (parts.next(), parts.next()) {
+                (Some(ty), Some(name)) => (ty.to_string(), name.to_string()),
+                _ => return Err(self.error(file, line, format!("bad parameter declaration '{}'", decl))),
+            };
+
+            let mut values = Vec::new();
+            match args.next() {
+                Some(Token::Open) => loop {
+                    match args.next() {
+                        Some(Token::Close) => break,
+                        Some(t) => values.push(t),
+                        None => return Err(self.error(file, line, format!("'{}' is missing its ]", name))),
+                    }
+                },
+                Some(t) => values.push(t),
+                None => return Err(self.error(file, line, format!("'{}' has no value", name))),
+            }
+
+            let value = if values.iter().all(|t| matches!(t, Token::Num(_))) {
+                Value::Nums(values.into_iter().map(|t| match t { Token::Num(n) => n, _ => unreachable!() }).collect())
+            } else if values.iter().all(|t| matches!(t, Token::Str(_))) {
+                Value::Strs(values.into_iter().map(|t| match t { Token::Str(s) => s, _ => unreachable!() }).collect())
+            } else {
+                return Err(self.error(file, line, format!("'{}' mixes numbers and strings", name)));
+            };
+            params.0.push(Param { ty, name, value });
+        }
+        Ok((ty, params))
+    }
+
+    fn material(&self, ty: &str, params: &Params, file: usize, line: usize) -> MaterialDesc {
+        let rgb = |names: &[&str], default: [Float; 3]| {
+            if let Some(p) = names.iter().find_map(|n| params.find(n)) {
+                if params.rgb(&p.name).is_none() {
+                    self.warn(file, line, format!("'{}' is not an RGB value, using the default", p.name));
+                }
+            }
+            names.iter().find_map(|n| params.rgb(n)).unwrap_or(default)
+        };
+        match ty {
+            "diffuse" | "matte" | "coateddiffuse" | "plastic" | "substrate" | "uber" => {
+                MaterialDesc::Lambertian { albedo: Some(rgb(&["reflectance", "Kd"], [0.5, 0.5, 0.5])), texture: None, uv: None }
+            }
+            "conductor" | "metal" => MaterialDesc::Metal {
+                albedo: rgb(&["reflectance"], [0.8, 0.8, 0.8]),
+                fuzz: params.float("roughness", params.float("uroughness", 0.0)).clamp(0.0, 1.0),
+            },
+            "mirror" => MaterialDesc::Metal { albedo: rgb(&["Kr"], [0.9, 0.9, 0.9]), fuzz: 0.0 },
+            "dielectric" | "glass" | "thindielectric" => MaterialDesc::Dielectric {
+                ir: params.float("eta", params.float("index", 1.5)),
+            },
+            _ => {
+                self.warn(file, line, format!("{} material is not supported, using a grey diffuse one", ty));
+                MaterialDesc::Lambertian { albedo: Some([0.5, 0.5, 0.5]), texture: None, uv: None }
+            }
+        }
+    }
+
+    // area light if one is active, otherwise the current material (pbrt's default is a grey diffuse)
+    fn current_material(&mut self) -> String {
+        if let Some(light) = &self.state.area_light {
+            return light.clone();
+        }
+        match &self.state.material {
+            Some(m) => m.clone(),
+            None => {
+                self.materials.entry("default".to_string())
+                    .or_insert(MaterialDesc::Lambertian { albedo: Some([0.5, 0.5, 0.5]), texture: None, uv: None });
+                "default".to_string()
+            }
+        }
+    }
+
+    fn shape(&mut self, ty: &str, params: &Params, file: usize, line: usize) -> Result<()> {
+        let ctm = self.state.ctm;
+        let material = self.current_material();
+        if !self.materials.contains_key(&material) {
+            return Err(self.error(file, line, format!("unknown material '{}'", material)));
+        }
+        let arr = |p: Point3| [p.x(), p.y(), p.z()];
+
+        match ty {
+            "sphere" => {
+                let radius = params.float("radius", 1.0);
+                // only uniform scales keep a sphere a sphere
+                let scale = vector(&ctm, Vec3::new(1.0, 0.0, 0.0)).length();
+                self.objects.push(ObjectDesc::Sphere {
+                    name: None,
+                    center: arr(point(&ctm, Point3::new(0.0, 0.0, 0.0))),
+                    radius: radius * scale,
+                    material,
+                    hidden: false,
+                    keyframes: Vec::new(),
+                    visible_to: None,
+                });
+            }
+            "trianglemesh" => {
+                let p = params.nums("P").ok_or_else(|| self.error(file, line, "trianglemesh needs P"))?;
+                let positions: Vec<Point3> = p.chunks_exact(3).map(|c| point(&ctm, Point3::new(c[0], c[1], c[2]))).collect();
+                let indices: Vec<usize> = match params.nums("indices") {
+                    Some(i) => i.iter().map(|&i| i as usize).collect(),
+                    None if positions.len() == 3 => vec![0, 1, 2],
+                    None => return Err(self.error(file, line, "trianglemesh needs indices")),
+                };
+                for tri in indices.chunks_exact(3) {
+                    if tri.iter().any(|&i| i >= positions.len()) {
+                        return Err(self.error(file, line, "triangle index out of range"));
+                    }
+                    self.objects.push(ObjectDesc::Triangle {
+                        name: None,
+                        vertices: [arr(positions[tri[0]]), arr(positions[tri[1]]), arr(positions[tri[2]])],
+                        uv: None,
+                        material: material.clone(),
+                        hidden: false,
+                        keyframes: Vec::new(),
+                        visible_to: None,
+                    });
+                }
+            }
+            _ => self.warn(file, line, format!("{} shapes are not supported, skipped", ty)),
+        }
+        Ok(())
+    }
+
+    fn finish(mut self) -> Result<SceneFile> {
+        let width = self.film.float("xresolution", 1280.0) as u32;
+        let height = self.film.float("yresolution", 720.0) as u32;
+        let aspect = width as Float / height as Float;
+        let render = RenderDesc {
+            width: Some(width),
+            height: Some(height),
+            samples_per_pixel: Some(self.sampler.float("pixelsamples", 16.0) as u32),
+            max_depth: Some(self.integrator.float("maxdepth", 5.0) as u64),
+            ..RenderDesc::default()
+        };
+
+        let (camera_to_world, params) = self.camera.take()
+            .ok_or_else(|| RendererError::parse(&self.files[0], "the scene has no Camera"))?;
+        let eye = point(&camera_to_world, Point3::new(0.0, 0.0, 0.0));
+        let dir = vector(&camera_to_world, Vec3::new(0.0, 0.0, 1.0)).normalized();
+        let up = vector(&camera_to_world, Vec3::new(0.0, 1.0, 0.0)).normalized();
+        let right = vector(&camera_to_world, Vec3::new(1.0, 0.0, 0.0)).normalized();
+
+        // pbrt's fov spans the shorter side of the image
+        let fov = params.float("fov", 90.0);
+        let vfov = if aspect >= 1.0 {
+            fov
+        } else {
+            2.0 * ((fov / 2.0).to_radians().tan() / aspect).atan().to_degrees()
+        };
+        let lens_radius = params.float("lensradius", 0.0);
+        let camera = CameraDesc {
+            lookfrom: [eye.x(), eye.y(), eye.z()],
+            lookat: [eye.x() + dir.x(), eye.y() + dir.y(), eye.z() + dir.z()],
+            vup: [up.x(), up.y(), up.z()],
+            vfov,
+            aperture: 2.0 * lens_radius,
+            focus_dist: if lens_radius > 0.0 { Some(params.float("focaldistance", 1.0e6)) } else { None },
+            lens: None,
+            projection: None,
+        };
+
+        let mut scene = SceneFile {
+            render,
+            camera,
+            background: self.background,
+            environment: EnvironmentDesc::default(),
+            fog: None,
+            textures: HashMap::new(),
+            materials: self.materials,
+            objects: self.objects,
+            clip: Vec::new(),
+            ray_groups: HashMap::new(),
+            base_dir: self.files[0].parent().map(Path::to_path_buf).unwrap_or_default(),
+        };
+        // pbrt's camera space is left-handed, so its "right" is on the left for our camera.
+        // Mirroring the world across the plane through the camera puts it back where it belongs.
+        if right.dot(up.cross(dir)) > 0.0 {
+            scene.transform(&mirror(eye, right));
+        }
+        Ok(scene)
+    }
+}
+
+pub fn load(path: &Path) -> Result<SceneFile> {
+    let mut importer = Importer {
+        files: Vec::new(),
+        included: Vec::new(),
+        tokens: VecDeque::new(),
+        state: GraphicsState { ctm: IDENTITY, material: None, area_light: None },
+        stack: Vec::new(),
+        transform_stack: Vec::new(),
+        named_coordinate_systems: HashMap::new(),
+        camera: None,
+        film: Params::default(),
+        sampler: Params::default(),
+        integrator: Params::default(),
+        materials: HashMap::new(),
+        objects: Vec::new(),
+        background: BackgroundDesc::Sky,
+        in_object: false,
+    };
+    importer.include(path, None)?;
+    importer.run()?;
+    importer.finish()
+}
@@ -0,0 +1,365 @@
+// Property tests of the geometric kernels: random spheres, triangles and rays checked against
+// what has to hold for any of them, like hits lying on the surface or a list's hit being the
+// nearest of its objects'. Every case has its own seed, a failure names it and how to get it
+// back. Set GEOMETRY_CASES to run more than the usual few thousand cases, which makes a fuzzing
+// run of it, and GEOMETRY_SEED to try other ones.
+use std::sync::Arc;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use crate::material::{Lambertian, Scatter};
+use crate::{Color, Float, Point3, Vec3};
+
+const CASES: u64 = 2000;
+
+// Runs `check` on as many cases as asked for, each with a generator of its own
+pub fn cases(name: &str, check: impl Fn(&mut StdRng) -> Result<(), String>) {
+    let count = env_u64("GEOMETRY_CASES").unwrap_or(CASES);
+    let seed = env_u64("GEOMETRY_SEED").unwrap_or(0);
+    for case in 0..count {
+        let mut rng = StdRng::seed_from_u64(seed.wrapping_add(case));
+        if let Err(e) = check(&mut rng) {
+            panic!("{}, case {}: {} (GEOMETRY_SEED={} GEOMETRY_CASES=1 runs it alone)", name, case, e, seed.wrapping_add(case));
+        }
+    }
+}
+
+fn env_u64(key: &str) -> Option<u64> {
+    std::env::var(key).ok().map(|v| v.parse().unwrap_or_else(|_| panic!("{} should be a number, not '{}'", key, v)))
+}
+
+pub fn point(rng: &mut StdRng, extent: Float) -> Point3 {
+    Point3::new(rng.gen_range(-extent..extent), rng.gen_range(-extent..extent), rng.gen_range(-extent..extent))
+}
+
+// a direction that isn't too short, of any length
+pub fn direction(rng: &mut StdRng) -> Vec3 {
+    loop {
+        let d = point(rng, 1.0);
+        if d.length() > 0.1 {
+            return rng.gen_range(0.1..10.0) * d;
+        }
+    }
+}
+
+pub fn unit(rng: &mut StdRng) -> Vec3 {
+    direction(rng).normalized()
+}
+
+pub fn material() -> Arc<dyn Scatter> {
+    Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)))
+}
+
+// how far two values may be apart after the arithmetic of an intersection, relative to the
+// size of the numbers that went in
+pub fn tolerance(magnitude: Float) -> Float {
+    1.0e4 * Float::EPSILON * magnitude.max(1.0)
+}
+
+pub fn close(what: &str, a: Float, b: Float, magnitude: Float) -> Result<(), String> {
+    if (a - b).abs() <= tolerance(magnitude) {
+        Ok(())
+    } else {
+        Err(format!("{} is {} instead of {}", what, a, b))
+    }
+}
+
+pub fn close_vec(what: &str, a: Vec3, b: Vec3, magnitude: Float) -> Result<(), String> {
+    if (a - b).max_abs() <= tolerance(magnitude) {
+        Ok(())
+    } else {
+        Err(format!("{} is {} instead of {}", what, a, b))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::animation::{Animated, Keyframe, Track};
+    use crate::group::Group;
+    use crate::sphere::{Shell, Sphere};
+    use crate::transform::{self, Transform};
+    use crate::triangle::Triangle;
+    use crate::{Hit, HittableList, Ray};
+
+    // the distance from the ray's line to `p`, and the t of the point on it that's closest
+    fn closest_approach(r: &Ray, p: Point3) -> (Float, Float) {
+        let d = r.direction();
+        let t = (p - r.origin()).dot(d) / d.dot(d);
+        ((r.at(t) - p).length(), t)
+    }
+
+    #[test]
+    fn sphere_hits_lie_on_the_sphere() {
+        cases("sphere_hits_lie_on_the_sphere", |rng| {
+            let (center, radius) = (point(rng, 10.0), rng.gen_range(0.1..5.0));
+            let r = Ray::new(point(rng, 20.0), direction(rng));
+            let (distance, t_closest) = closest_approach(&r, center);
+            let sphere = Sphere::new(center, radius, material());
+            let magnitude = 30.0;
+
+            // grazing it or starting on it the answer is too sensitive to tell either way
+            let margin = 1.0e-3 * radius;
+            let from_center = (r.origin() - center).length();
+            if (from_center - radius).abs() < margin {
+                return Ok(());
+            }
+            let inside = from_center < radius;
+            match sphere.hit(&r, 0.0, Float::INFINITY) {
+                None if inside || (distance < radius - margin && t_closest > 0.0) => {
+                    Err(format!("missed a sphere passing {} from its center, radius {}", distance, radius))
+                }
+                None => Ok(()),
+                Some(_) if distance > radius + margin => Err(format!("hit a sphere passing {} from its center, radius {}", distance, radius)),
+                Some(rec) => {
+                    if rec.t < 0.0 {
+                        return Err(format!("hit behind the ray at t = {}", rec.t));
+                    }
+                    close("the distance to the center", (rec.p - center).length(), radius, magnitude)?;
+                    close_vec("the point", rec.p, r.at(rec.t), magnitude * r.direction().length().max(1.0))?;
+                    close("the normal's length", rec.normal.length(), 1.0, 1.0)?;
+                    if rec.normal.dot(r.direction()) > 0.0 {
+                        return Err("the normal faces away from the ray".to_string());
+                    }
+                    if rec.front_face == inside {
+                        return Err(format!("front_face is {} for a ray starting {} the sphere", rec.front_face, if inside { "inside" } else { "outside" }));
+                    }
+                    // nothing nearer
+                    match sphere.hit(&r, 0.0, rec.t - tolerance(magnitude)) {
+                        Some(nearer) => Err(format!("hit at t = {} but there's one at {}", rec.t, nearer.t)),
+                        None => Ok(()),
+                    }
+                }
+            }
+        });
+    }
+
+    #[test]
+    fn sphere_hits_stay_within_t_range() {
+        cases("sphere_hits_stay_within_t_range", |rng| {
+            let sphere = Sphere::new(point(rng, 5.0), rng.gen_range(0.1..5.0), material());
+            let r = Ray::new(point(rng, 10.0), direction(rng));
+            let t_min = rng.gen_range(-5.0..5.0);
+            let t_max = t_min + rng.gen_range(0.0..10.0);
+            match sphere.hit(&r, t_min, t_max) {
+                Some(rec) if rec.t < t_min || rec.t > t_max => Err(format!("t = {} is outside {}..{}", rec.t, t_min, t_max)),
+                _ => Ok(()),
+            }
+        });
+    }
+
+    #[test]
+    fn shells_hit_the_nearer_of_their_spheres() {
+        cases("shells_hit_the_nearer_of_their_spheres", |rng| {
+            let center = point(rng, 5.0);
+            let outer = rng.gen_range(0.5..5.0);
+            let inner = outer * rng.gen_range(0.1..0.95);
+            let shell = Shell::new(center, outer, inner, material());
+            let spheres = [Sphere::new(center, outer, material()), Sphere::new(center, -inner, material())];
+            let r = Ray::new(point(rng, 10.0), direction(rng));
+
+            let nearest = spheres.iter().filter_map(|s| s.hit(&r, 0.0, Float::INFINITY)).map(|rec| rec.t).reduce(Float::min);
+            match (shell.hit(&r, 0.0, Float::INFINITY), nearest) {
+                (None, None) => Ok(()),
+                (Some(rec), Some(t)) => close("t", rec.t, t, 20.0),
+                (rec, t) => Err(format!("the shell's hit is at {:?}, its spheres' at {:?}", rec.map(|rec| rec.t), t)),
+            }
+        });
+    }
+
+    #[test]
+    fn triangles_are_hit_inside_and_missed_outside() {
+        cases("triangles_are_hit_inside_and_missed_outside", |rng| {
+            let v = [point(rng, 5.0), point(rng, 5.0), point(rng, 5.0)];
+            let normal = (v[1] - v[0]).cross(v[2] - v[0]);
+            // slivers are too thin to aim at
+            if normal.length() < 0.5 {
+                return Ok(());
+            }
+            let triangle = Triangle::new(v[0], v[1], v[2], material());
+
+            let (b1, b2) = (rng.gen_range(0.0..1.0), rng.gen_range(0.0..1.0));
+            let target = (1.0 - b1 - b2) * v[0] + b1 * v[1] + b2 * v[2];
+            let origin = point(rng, 20.0);
+            // not along the plane, where the hit isn't well defined
+            if (target - origin).normalized().dot(normal.normalized()).abs() < 0.05 {
+                return Ok(());
+            }
+            let r = Ray::new(origin, rng.gen_range(0.1..2.0) * (target - origin));
+            let hit = triangle.hit(&r, 0.0, Float::INFINITY);
+
+            let margin = 1.0e-3;
+            let inside = b1 > margin && b2 > margin && b1 + b2 < 1.0 - margin;
+            let outside = b1 + b2 > 1.0 + margin;
+            match hit {
+                Some(rec) if outside => Err(format!("hit at {} outside the triangle", rec.p)),
+                None if inside => Err(format!("missed {} inside the triangle", target)),
+                Some(rec) => {
+                    close_vec("the point", rec.p, target, 30.0)?;
+                    if rec.front_face != (r.direction().dot(normal) < 0.0) {
+                        return Err("front_face doesn't go with the winding".to_string());
+                    }
+                    // the other way round it's the back
+                    let flipped = Triangle::new(v[0], v[2], v[1], material()).hit(&r, 0.0, Float::INFINITY);
+                    match flipped {
+                        Some(back) if back.front_face != rec.front_face => Ok(()),
+                        Some(_) => Err("front_face stays the same with the winding reversed".to_string()),
+                        None => Err("missed with the winding reversed".to_string()),
+                    }
+                }
+                None => Ok(()),
+            }
+        });
+    }
+
+    #[test]
+    fn lists_hit_the_nearest_of_their_objects() {
+        cases("lists_hit_the_nearest_of_their_objects", |rng| {
+            let mut list = HittableList::new();
+            let mut objects: Vec<Arc<dyn Hit>> = Vec::new();
+            for _ in 0..rng.gen_range(1..12) {
+                let object: Arc<dyn Hit> = if rng.gen() {
+                    Arc::new(Sphere::new(point(rng, 10.0), rng.gen_range(0.1..3.0), material()))
+                } else {
+                    Arc::new(Triangle::new(point(rng, 10.0), point(rng, 10.0), point(rng, 10.0), material()))
+                };
+                list.push(object.clone());
+                objects.push(object);
+            }
+            let r = Ray::new(point(rng, 20.0), direction(rng));
+
+            // brute force, every object on its own
+            let nearest = objects.iter().enumerate()
+                .filter_map(|(i, o)| o.hit(&r, 0.0, Float::INFINITY).map(|rec| (i, rec.t)))
+                .min_by(|a, b| a.1.total_cmp(&b.1));
+            match (list.hit_object(&r, 0.0, Float::INFINITY), nearest) {
+                (None, None) => Ok(()),
+                (Some((i, rec)), Some((j, t))) => {
+                    close("t", rec.t, t, 30.0)?;
+                    // ties between objects touching there can go either way
+                    if i != j && (rec.t - t).abs() > tolerance(30.0) {
+                        return Err(format!("hit object {} but {} is nearer", i, j));
+                    }
+                    Ok(())
+                }
+                (rec, t) => Err(format!("the list's hit is {:?}, the objects' {:?}", rec.map(|(i, rec)| (i, rec.t)), t)),
+            }
+        });
+    }
+
+    #[test]
+    fn transformed_groups_hit_like_the_objects_moved() {
+        cases("transformed_groups_hit_like_the_objects_moved", |rng| {
+            let (center, radius) = (point(rng, 3.0), rng.gen_range(0.2..2.0));
+            let scale = rng.gen_range(0.2..3.0);
+            let m = transform::mul(
+                &transform::translate(point(rng, 5.0)),
+                &transform::mul(&transform::rotate(rng.gen_range(0.0..360.0), unit(rng)), &transform::scale(Vec3::new(scale, scale, scale))),
+            );
+            let to_world = Transform::new(m).ok_or("singular transform")?;
+
+            let mut objects = HittableList::new();
+            objects.push(Arc::new(Sphere::new(center, radius, material())));
+            let group = Group::new(objects).with_transform(Transform::new(m).ok_or("singular transform")?);
+            let moved = Sphere::new(to_world.point(center), scale * radius, material());
+
+            let r = Ray::new(point(rng, 20.0), direction(rng));
+            let (distance, _) = closest_approach(&r, to_world.point(center));
+            if (distance - scale * radius).abs() < 1.0e-3 * scale * radius {
+                return Ok(());
+            }
+            match (group.hit(&r, 0.0, Float::INFINITY), moved.hit(&r, 0.0, Float::INFINITY)) {
+                (None, None) => Ok(()),
+                (Some(a), Some(b)) => {
+                    close("t", a.t, b.t, 50.0)?;
+                    close_vec("the point", a.p, b.p, 50.0)?;
+                    close_vec("the normal", a.normal, b.normal, 50.0)
+                }
+                (a, b) => Err(format!("the group's hit is at {:?}, the moved sphere's at {:?}", a.map(|a| a.t), b.map(|b| b.t))),
+            }
+        });
+    }
+
+    #[test]
+    fn hits_lie_inside_the_bounding_box() {
+        cases("hits_lie_inside_the_bounding_box", |rng| {
+            let sphere: Arc<dyn Hit> = Arc::new(Sphere::new(point(rng, 5.0), rng.gen_range(0.1..3.0), material()));
+            let object: Arc<dyn Hit> = match rng.gen_range(0..4) {
+                0 => sphere,
+                1 => Arc::new(Triangle::new(point(rng, 10.0), point(rng, 10.0), point(rng, 10.0), material())),
+                2 => {
+                    let scale = rng.gen_range(0.2..3.0);
+                    let m = transform::mul(
+                        &transform::translate(point(rng, 5.0)),
+                        &transform::mul(&transform::rotate(rng.gen_range(0.0..360.0), unit(rng)), &transform::scale(Vec3::new(scale, scale, scale))),
+                    );
+                    let mut objects = HittableList::new();
+                    objects.push(sphere);
+                    Arc::new(Group::new(objects).with_transform(Transform::new(m).ok_or("singular transform")?))
+                }
+                _ => {
+                    let key = |rng: &mut StdRng, time| Keyframe {
+                        time,
+                        translate: point(rng, 5.0).to_array(),
+                        rotate: point(rng, 180.0).to_array(),
+                        scale: rng.gen_range(0.2..3.0),
+                    };
+                    let track = Track::new(vec![key(rng, 0.0), key(rng, 0.5), key(rng, 1.0)]);
+                    Arc::new(Animated::new(sphere, track))
+                }
+            };
+            let (time0, time1) = (rng.gen_range(0.0..0.6), rng.gen_range(0.6..1.2));
+            let b = object.bounding_box(time0, time1).ok_or("no bounding box")?;
+
+            let r = Ray::new(point(rng, 20.0), direction(rng)).with_time(rng.gen_range(time0..time1));
+            let Some(rec) = object.hit(&r, 0.0, Float::INFINITY) else { return Ok(()) };
+            let slack = tolerance(50.0);
+            if (0..3).any(|a| rec.p[a] < b.min[a] - slack || rec.p[a] > b.max[a] + slack) {
+                return Err(format!("hit at {} outside the box from {} to {}", rec.p, b.min, b.max));
+            }
+            if !b.hit(&r, 0.0, Float::INFINITY) {
+                return Err(format!("hit at {} but the ray misses the box", rec.p));
+            }
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn reflection_mirrors_about_the_normal() {
+        cases("reflection_mirrors_about_the_normal", |rng| {
+            let (v, n) = (direction(rng), unit(rng));
+            let reflected = v.reflect(n);
+            close("the length", reflected.length(), v.length(), 10.0)?;
+            close("the angle", reflected.dot(n), -v.dot(n), 10.0)?;
+            close_vec("reflecting twice", reflected.reflect(n), v, 10.0)
+        });
+    }
+
+    #[test]
+    fn refraction_follows_snells_law() {
+        cases("refraction_follows_snells_law", |rng| {
+            let n = unit(rng);
+            // coming in against the normal, like Dielectric makes sure of
+            let mut v = unit(rng);
+            if v.dot(n) > 0.0 {
+                v = -1.0 * v;
+            }
+            let eta = rng.gen_range(0.5..2.0);
+            let cos_in = -v.dot(n);
+            let sin_in = (1.0 - cos_in * cos_in).max(0.0).sqrt();
+            // total internal reflection is the caller's to catch
+            if eta * sin_in > 0.99 {
+                return Ok(());
+            }
+
+            let refracted = v.refract(n, eta);
+            close("the length", refracted.length(), 1.0, 1.0)?;
+            if refracted.dot(n) > 0.0 {
+                return Err("the refracted ray turned back".to_string());
+            }
+            let sin_out = refracted.cross(n).length();
+            close("sin of the refracted angle", sin_out, eta * sin_in, 1.0)?;
+            close_vec("refracting back", (-1.0 * refracted).refract(-1.0 * n, 1.0 / eta), -1.0 * v, 10.0)?;
+            close_vec("refracting without a change of medium", v.refract(n, 1.0), v, 1.0)
+        });
+    }
+}
@@ -0,0 +1,36 @@
+use std::cell::RefCell;
+use std::ops::Range;
+use rand::distributions::{Distribution, Standard};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use crate::Float;
+
+// Every random number of the renderer comes from this per-thread generator. Reseeding it
+// from the pixel coordinates makes renders repeatable no matter which thread renders what,
+// and since nothing is drawn from the operating system it runs anywhere, wasm32 included.
+// Anything that draws from it has to reseed it first, from the pixel or texel it works on
+// and the seed of the render or of its round (see Renderer::render), never from the thread:
+// then the image comes out the same to the bit with any number of threads and any tile order.
+thread_local! {
+    static RNG: RefCell<StdRng> = RefCell::new(StdRng::seed_from_u64(0));
+}
+
+pub fn reseed(seed: u64) {
+    RNG.with(|rng| *rng.borrow_mut() = StdRng::seed_from_u64(seed));
+}
+
+// seed for a pixel, mixed with splitmix64 so neighbouring pixels get unrelated streams
+pub fn pixel_seed(seed: u64, x: u32, y: u32) -> u64 {
+    let mut z = seed ^ ((x as u64) << 32 | y as u64).wrapping_mul(0x9e3779b97f4a7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
+
+pub fn gen<T>() -> T where Standard: Distribution<T> {
+    RNG.with(|rng| rng.borrow_mut().gen())
+}
+
+pub fn gen_range(r: Range<Float>) -> Float {
+    RNG.with(|rng| rng.borrow_mut().gen_range(r))
+}
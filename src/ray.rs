@@ -1,18 +1,70 @@
+use crate::transform::Transform;
 use crate::vec3::{Point3, Vec3};
+use crate::visibility::TraceGroup;
+use crate::Float;
 
+#[derive(Copy, Clone)]
 pub struct Ray {
     orig: Point3,
-    dir: Vec3
+    dir: Vec3,
+    // when the ray was sent out, for animated objects
+    time: Float,
+    differentials: Option<Differentials>,
+    // which objects it can see, see visibility::TraceGroup
+    group: TraceGroup,
+    // how it left the last surface, the built-in group it was put in before any material's
+    // own group (see visibility::Grouped), for lpe::LightPath
+    lobe: TraceGroup,
+}
+
+// The rays through the next pixel over to the right (x) and up (y), traced alongside without
+// being intersected. How far apart they land from the ray itself is how much of the surface
+// a pixel covers, which is what texture filtering needs.
+#[derive(Copy, Clone, Debug)]
+pub struct Differentials {
+    pub rx_origin: Point3,
+    pub rx_direction: Vec3,
+    pub ry_origin: Point3,
+    pub ry_direction: Vec3,
 }
 
 impl Ray {
     pub fn new(origin: Point3, direction: Vec3) -> Ray {
         Ray {
             orig: origin,
-            dir: direction
+            dir: direction,
+            time: 0.0,
+            differentials: None,
+            group: TraceGroup::CAMERA,
+            lobe: TraceGroup::CAMERA,
         }
     }
 
+    pub fn with_time(mut self, time: Float) -> Ray {
+        self.time = time;
+        self
+    }
+
+    pub fn with_differentials(mut self, differentials: Option<Differentials>) -> Ray {
+        self.differentials = differentials;
+        self
+    }
+
+    // the same ray sent another way, without differentials as they'd no longer fit
+    pub fn with_direction(mut self, direction: Vec3) -> Ray {
+        self.dir = direction;
+        self.differentials = None;
+        self
+    }
+
+    pub fn with_group(mut self, group: TraceGroup) -> Ray {
+        self.group = group;
+        if group.is_built_in() {
+            self.lobe = group;
+        }
+        self
+    }
+
     pub fn origin(&self) -> Point3 {
         self.orig
     }
@@ -21,7 +73,65 @@ impl Ray {
         self.dir
     }
 
-    pub fn at(&self, t: f64) -> Point3 {
+    pub fn time(&self) -> Float {
+        self.time
+    }
+
+    pub fn group(&self) -> TraceGroup {
+        self.group
+    }
+
+    pub fn lobe(&self) -> TraceGroup {
+        self.lobe
+    }
+
+    // None for rays that don't stand for a pixel, like diffuse bounces
+    pub fn differentials(&self) -> Option<Differentials> {
+        self.differentials
+    }
+
+    pub fn at(&self, t: Float) -> Point3 {
         self.orig + t * self.dir
     }
-}
\ No newline at end of file
+
+    // Brings the neighbouring rays closer, by 1 / sqrt(samples per pixel) when every pixel is
+    // sampled many times and each sample only stands for part of it
+    pub fn scale_differentials(mut self, s: Float) -> Ray {
+        if let Some(d) = &mut self.differentials {
+            d.rx_origin = self.orig + s * (d.rx_origin - self.orig);
+            d.rx_direction = self.dir + s * (d.rx_direction - self.dir);
+            d.ry_origin = self.orig + s * (d.ry_origin - self.orig);
+            d.ry_direction = self.dir + s * (d.ry_direction - self.dir);
+        }
+        self
+    }
+
+    // The same ray, differentials and all, in the space `t` leads to
+    pub fn transformed(&self, t: &Transform) -> Ray {
+        Ray {
+            orig: t.point(self.orig),
+            dir: t.vector(self.dir),
+            time: self.time,
+            differentials: self.differentials.map(|d| Differentials {
+                rx_origin: t.point(d.rx_origin),
+                rx_direction: t.vector(d.rx_direction),
+                ry_origin: t.point(d.ry_origin),
+                ry_direction: t.vector(d.ry_direction),
+            }),
+            group: self.group,
+            lobe: self.lobe,
+        }
+    }
+
+    // How far the points seen by the neighbouring rays are from `p` on the plane through p
+    // with normal `n`, the surface the ray hit as far as one pixel is concerned. None without
+    // differentials or when a neighbour runs parallel to the plane.
+    pub fn footprint(&self, p: Point3, n: Vec3) -> Option<(Vec3, Vec3)> {
+        let d = self.differentials?;
+        let on_plane = |o: Point3, dir: Vec3| {
+            let cos = dir.dot(n);
+            (cos != 0.0).then(|| o + ((p - o).dot(n) / cos) * dir)
+        };
+        Some((on_plane(d.rx_origin, d.rx_direction)? - p, on_plane(d.ry_origin, d.ry_direction)? - p))
+    }
+}
@@ -0,0 +1,930 @@
+use std::sync::atomic::Ordering;
+use std::sync::{mpsc, Mutex};
+use std::sync::Arc;
+use std::thread;
+use std::time::Instant;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+use crate::error::{RendererError, Result};
+use crate::cancel::Cancel;
+use crate::convergence::{Convergence, NoiseThreshold, MIN_ROUNDS};
+use crate::framebuffer::{Framebuffer, Tile};
+use crate::hit::{Hit, HitRecord};
+use crate::material::Scatter;
+use crate::integrator::Integrator;
+use crate::lpe::{self, LightPath};
+use crate::occlusion::Occlusion;
+use crate::scene::Scene;
+use crate::sink::ImageSink;
+use crate::visibility::TraceGroup;
+use crate::workers::WorkQueue;
+use crate::{metadata, random, stats, trace, Color, Float, Point3, Ray, Vec3};
+
+#[derive(Copy, Clone)]
+pub struct Settings {
+    pub width: u32,
+    pub height: u32,
+    pub samples_per_pixel: u32,
+    pub max_depth: u64,
+    pub seed: u64,
+    pub limits: PathLimits,
+}
+
+impl Settings {
+    pub fn aspect_ratio(&self) -> Float {
+        self.width as Float / self.height as Float
+    }
+}
+
+impl Default for Settings {
+    fn default() -> Settings {
+        const ASPECT_RATIO: Float = 3.0 / 2.0;
+        const IMAGE_WIDTH: u32 = 1200;
+        Settings {
+            width: IMAGE_WIDTH,
+            height: ((IMAGE_WIDTH as Float) / ASPECT_RATIO) as u32,
+            samples_per_pixel: 100,
+            max_depth: 10,
+            seed: 0,
+            limits: PathLimits::default(),
+        }
+    }
+}
+
+// How many times a path may bounce off surfaces each way, on top of max_depth for all of
+// them together. Glass needs many transmission bounces to get light through a few panes while
+// diffuse light is mostly gathered after a handful, so limiting each keeps long diffuse paths
+// from costing much for little. None is no limit of its own.
+//
+// With `clamp_indirect` the light a sample brings back from past the first bounce is scaled
+// down to be no brighter than that, keeping its hue: a little of the light is lost for far
+// fewer fireflies from caustics and small bright lights.
+//
+// `regularize` makes mirrors and glass rougher once the path has bounced off something
+// diffuse, by that much as the fuzz of a metal (see material::Metal). Caustics seen on diffuse
+// surfaces are then found by the many paths that hit a light through blurred glass rather than
+// the few that hit it through sharp glass, so they come out smooth and a little wider, while
+// mirrors and glass seen straight from the camera stay as sharp as they are.
+#[derive(Copy, Clone, Default, PartialEq, Debug)]
+pub struct PathLimits {
+    pub diffuse: Option<u64>,
+    pub specular: Option<u64>,
+    pub transmission: Option<u64>,
+    pub clamp_indirect: Option<Float>,
+    pub regularize: Option<Float>,
+}
+
+impl PathLimits {
+    // counts a bounce the `lobe` way into `bounces`, false when it's one too many
+    fn bounce(&self, bounces: &mut [u64; 3], lobe: TraceGroup) -> bool {
+        let (i, limit) = match lobe {
+            TraceGroup::DIFFUSE => (0, self.diffuse),
+            TraceGroup::SPECULAR => (1, self.specular),
+            TraceGroup::TRANSMISSION => (2, self.transmission),
+            _ => return true,
+        };
+        bounces[i] += 1;
+        limit.is_none_or(|limit| bounces[i] <= limit)
+    }
+
+    fn clamped(&self, light: Color) -> Color {
+        let max = light[0].max(light[1]).max(light[2]);
+        match self.clamp_indirect {
+            Some(clamp) if max > clamp => (clamp / max) * light,
+            _ => light,
+        }
+    }
+
+    // `scattered` off `rec` with regularize's roughness added when the path has been diffuse,
+    // as it was when that would send it through the surface the other way
+    fn regularized(&self, bounces: &[u64; 3], rec: &HitRecord, scattered: Ray) -> Ray {
+        let Some(strength) = self.regularize.filter(|_| bounces[0] > 0) else {
+            return scattered;
+        };
+        if scattered.lobe() != TraceGroup::SPECULAR && scattered.lobe() != TraceGroup::TRANSMISSION {
+            return scattered;
+        }
+        let d = scattered.direction().normalized();
+        let blurred = d + strength * Vec3::rand_in_unit_sphere();
+        if (blurred.dot(rec.normal) > 0.0) == (d.dot(rec.normal) > 0.0) {
+            scattered.with_direction(blurred)
+        } else {
+            scattered
+        }
+    }
+
+    // for the image metadata, None when there are none
+    pub fn describe(&self) -> Option<String> {
+        let limits: Vec<String> = [("diffuse", self.diffuse), ("specular", self.specular), ("transmission", self.transmission)]
+            .into_iter()
+            .filter_map(|(name, limit)| Some(format!("{} {}", name, limit?)))
+            .chain(self.clamp_indirect.map(|clamp| format!("clamp {}", clamp)))
+            .chain(self.regularize.map(|strength| format!("regularize {}", strength)))
+            .collect();
+        (!limits.is_empty()).then(|| limits.join(", "))
+    }
+}
+
+// Gets the color of the ray at intersection, see trace_path, limited by the depth alone
+pub fn ray_color(r: &Ray, scene: &Scene, depth: u64) -> Color {
+    trace_path(r, scene, depth, &PathLimits::default(), &mut ())
+}
+
+// Sees a path as trace_path follows it, for sorting its light into passes or for looking at
+// it bounce by bounce. Plain renders use (), which sees nothing.
+pub(crate) trait PathObserver {
+    // the ray got away, `light` being what the background adds to the color
+    fn missed(&mut self, _ray: &Ray, _light: Color) {}
+
+    // the ray hit `rec` on the world's `object`, `light` being what the surface gives off
+    fn hit(&mut self, _ray: &Ray, _object: usize, _rec: &HitRecord, _light: Color) {}
+
+    // the path goes on along `scattered`, `throughput` being what's left of it
+    fn scattered(&mut self, _scattered: &Ray, _attenuation: Color, _throughput: Color) {}
+
+    // `light` from the light of material `mat` scattered once in the fog on the way along `ray`
+    fn fogged(&mut self, _ray: &Ray, _mat: &Arc<dyn Scatter>, _light: Color) {}
+}
+
+impl PathObserver for () {}
+
+// The path is followed one bounce after the other rather than recursively, so deep paths don't
+// need a big stack: `throughput` is the share of light that makes it back to the camera from
+// the current bounce. It ends after `depth` bounces or when one goes past its `limits`.
+pub(crate) fn trace_path(r: &Ray, scene: &Scene, depth: u64, limits: &PathLimits, observer: &mut impl PathObserver) -> Color {
+    let mut color = Color::new(0.0, 0.0, 0.0);
+    let mut throughput = Color::new(1.0, 1.0, 1.0);
+    let mut ray = *r;
+    let mut bounces = [0; 3];
+
+    for _ in 0..depth {
+        // the camera ray's light is direct, only what comes after it is clamped
+        let bounced = ray.lobe() != TraceGroup::CAMERA;
+        let clamped = |light: Color| if bounced { limits.clamped(light) } else { light };
+        stats::count(&stats::RAYS);
+        // bounces start off the surface (see HitRecord::spawn_ray), so anything ahead counts
+        // but what's within the scene's epsilon
+        let t_min = if bounced { scene.epsilon() / ray.direction().length() } else { 0.0 };
+        let hit = scene.world.hit_object(&ray, t_min, Float::INFINITY);
+        if let Some(fog) = &scene.fog {
+            let t_max = hit.as_ref().map_or(Float::INFINITY, |(_, rec)| rec.t);
+            fog.in_scattered(&ray, t_max, &scene.world, |mat, light| {
+                let light = clamped(throughput * light);
+                observer.fogged(&ray, mat, light);
+                color += light;
+            });
+            throughput *= fog.transmittance(&ray, t_max);
+        }
+        let Some((object, mut rec)) = hit else {
+            let light = clamped(throughput * scene.environment.color(&ray, &scene.camera));
+            observer.missed(&ray, light);
+            return color + light;
+        };
+        rec.error = rec.error.max(scene.epsilon());
+        // mixed materials settle on one of theirs, see material::Mix
+        while let Some(picked) = rec.mat.pick(&rec, &scene.world, ray.time()) {
+            rec.mat = picked;
+        }
+        let light = clamped(throughput * rec.mat.emitted(rec.u, rec.v, rec.p));
+        observer.hit(&ray, object, &rec, light);
+        color += light;
+        // material (description of ray behaviour)
+        match rec.mat.scatter(&ray, &rec) {
+            Some((attenuation, scattered)) if limits.bounce(&mut bounces, scattered.lobe()) => {
+                throughput *= attenuation;
+                ray = limits.regularized(&bounces, &rec, scattered);
+                observer.scattered(&ray, attenuation, throughput);
+            }
+            _ => return color,
+        }
+    }
+    // Exceeding the ray bounce limit, no more light is gathered
+    color
+}
+
+// Sorts the light of a path into the passes of `shading`, see Shading::pass_names
+struct PassSorter<'a> {
+    shading: &'a Shading,
+    passes: &'a mut [Color],
+    // what happened along the path so far, see lpe::LightPath
+    events: Vec<u8>,
+    // of the camera ray, see Camera::get_ray_differential
+    weight: Float,
+}
+
+impl<'a> PassSorter<'a> {
+    fn new(shading: &'a Shading, passes: &'a mut [Color], weight: Float) -> PassSorter<'a> {
+        PassSorter { shading, passes, events: vec![b'C'], weight }
+    }
+
+    // into the passes of the expressions the path matches with `event` at its end
+    fn add(&mut self, event: u8, light: Color) {
+        self.events.push(event);
+        for (pass, path) in self.passes.iter_mut().zip(self.shading.light_paths.iter()) {
+            if path.matches(&self.events) {
+                *pass += self.weight * light;
+            }
+        }
+        self.events.pop();
+    }
+
+    // into the pass of the light of material `mat`
+    fn add_light(&mut self, mat: &Arc<dyn Scatter>, light: Color) {
+        if let Some(i) = self.shading.lights.iter().position(|(_, m)| Arc::ptr_eq(m, mat)) {
+            self.passes[self.shading.light_paths.len() + i] += self.weight * light;
+        }
+    }
+}
+
+impl PathObserver for PassSorter<'_> {
+    fn missed(&mut self, _ray: &Ray, light: Color) {
+        self.add(b'B', light);
+        // the background's pass comes last
+        if self.shading.light_passes {
+            if let Some(pass) = self.passes.last_mut() {
+                *pass += self.weight * light;
+            }
+        }
+    }
+
+    fn hit(&mut self, _ray: &Ray, _object: usize, rec: &HitRecord, light: Color) {
+        self.add(b'L', light);
+        if self.shading.light_passes && rec.mat.is_light() {
+            self.add_light(&rec.mat, light);
+        }
+    }
+
+    fn scattered(&mut self, scattered: &Ray, _attenuation: Color, _throughput: Color) {
+        self.events.push(lpe::event(scattered.lobe()));
+    }
+
+    fn fogged(&mut self, _ray: &Ray, mat: &Arc<dyn Scatter>, light: Color) {
+        self.events.push(b'V');
+        self.add(b'L', light);
+        self.events.pop();
+        if self.shading.light_passes {
+            self.add_light(mat, light);
+        }
+    }
+}
+
+// What gets rendered besides the beauty
+#[derive(Clone, Debug)]
+struct Shading {
+    integrator: Integrator,
+    occlusion: Option<Occlusion>,
+    light_paths: Arc<[LightPath]>,
+    light_passes: bool,
+    // leaves out and reports samples that come out NaN, infinite or negative
+    check_radiance: bool,
+    // the scene's named lights when there's a pass for each, see Renderer::with_light_passes
+    lights: Arc<[(String, Arc<dyn Scatter>)]>,
+}
+
+impl Shading {
+    // the expressions' passes, then those of the lights and last the background's
+    fn pass_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.light_paths.iter().map(|p| p.name().to_string()).collect();
+        if self.light_passes {
+            names.extend(self.lights.iter().map(|(name, _)| format!("light.{}", name)));
+            names.push("background".to_string());
+        }
+        names
+    }
+
+    // with the lights of the scene about to be rendered
+    fn for_scene(&self, scene: &Scene) -> Shading {
+        let mut shading = self.clone();
+        if self.light_passes {
+            shading.lights = scene.lights().cloned().collect();
+        }
+        shading
+    }
+}
+
+impl Default for Shading {
+    fn default() -> Shading {
+        Shading { integrator: Integrator::Path, occlusion: None, light_paths: Arc::new([]), light_passes: false, check_radiance: false,
+                  lights: Arc::new([]) }
+    }
+}
+
+// The AOVs of a pixel besides the beauty, see center_sample
+type Aovs = (Vec3, Float, Option<Float>);
+
+// Averaged radiance plus the AOVs and the light path passes of the pixel at (x, y), y going up
+// from the bottom row
+fn shade_pixel(x: u32, y: u32, scene: &Scene, settings: &Settings, shading: &Shading) -> (Color, Aovs, Vec<Color>) {
+    random::reseed(random::pixel_seed(settings.seed, x, y));
+    let occlusion = shading.occlusion;
+    let pass_count = shading.light_paths.len() + if shading.light_passes { shading.lights.len() + 1 } else { 0 };
+    let mut passes = vec![Color::default(); pass_count];
+
+    // the debug views make do with the ray the AOVs come from
+    if shading.integrator != Integrator::Path {
+        let (r, aovs) = center_sample(x, y, scene, settings, occlusion);
+        stats::count(&stats::CAMERA_RAYS);
+        return (shading.integrator.color(&r, scene, settings.max_depth), aovs, passes);
+    }
+
+    let mut bad = Vec::new();
+    let pixel_color: Color = (0..settings.samples_per_pixel)
+        .map(|sample| {
+            let (r, weight) = sample_ray(x, y, scene, settings);
+            // the lens stopped it, the film gets nothing
+            if weight == 0.0 {
+                return Color::default();
+            }
+            stats::count(&stats::CAMERA_RAYS);
+            let color = if passes.is_empty() {
+                weight * trace_path(&r, scene, settings.max_depth, &settings.limits, &mut ())
+            } else {
+                weight * trace_path(&r, scene, settings.max_depth, &settings.limits, &mut PassSorter::new(shading, &mut passes, weight))
+            };
+            // one NaN would take the whole pixel with it
+            if shading.check_radiance && trace::is_bad(color) {
+                bad.push((sample, color));
+                return Color::default();
+            }
+            color
+        })
+        .sum();
+
+    // after the samples, the occlusion rays leave the beauty's random numbers alone
+    let (_, aovs) = center_sample(x, y, scene, settings, occlusion);
+    // and so does tracing the bad samples again to show how they came about
+    for (sample, color) in bad {
+        trace::report(scene, settings, x, settings.height - y - 1, sample, color);
+    }
+    let n = settings.samples_per_pixel as Float;
+    (pixel_color / n, aovs, passes.into_iter().map(|c| c / n).collect())
+}
+
+// A ray through a random point of pixel (x, y), y going up from the bottom row, and how much
+// of its light the film gets. The differentials cover the sample's share of the pixel.
+pub(crate) fn sample_ray(x: u32, y: u32, scene: &Scene, settings: &Settings) -> (Ray, Float) {
+    let du = 1.0 / ((settings.width - 1) as Float);
+    let dv = 1.0 / ((settings.height - 1) as Float);
+    // each sample only stands for its share of the pixel
+    let footprint = 1.0 / (settings.samples_per_pixel as Float).sqrt();
+
+    let rand_u: Float = random::gen();
+    let rand_v: Float = random::gen();
+
+    let u = ((x as Float) + rand_u) / ((settings.width - 1) as Float);
+    let v = ((y as Float) + rand_v) / ((settings.height - 1) as Float);
+
+    // ray generation from the camera (0,0,0) to corresponding coordinates of each pixel of the output image
+    let (r, weight) = scene.camera.get_ray_differential(u, v, du, dv);
+    (r.scale_differentials(footprint), weight)
+}
+
+// Normal, distance and occlusion when asked for, from a single ray through the pixel center,
+// and the ray. Nothing occludes the background.
+fn center_sample(x: u32, y: u32, scene: &Scene, settings: &Settings, occlusion: Option<Occlusion>) -> (Ray, Aovs) {
+    let r = center_ray(x, y, scene, settings);
+    let aovs = match scene.world.hit(&r, 0.0, Float::INFINITY).map(|rec| HitRecord { error: rec.error.max(scene.epsilon()), ..rec }) {
+        // camera rays aren't normalized, scale t back to a distance
+        Some(rec) => (rec.normal, rec.t * r.direction().length(), occlusion.map(|o| o.at(&rec, &scene.world, r.time()))),
+        None => (Vec3::default(), Float::INFINITY, occlusion.map(|_| 1.0)),
+    };
+    (r, aovs)
+}
+
+fn center_ray(x: u32, y: u32, scene: &Scene, settings: &Settings) -> Ray {
+    let u = (x as Float + 0.5) / ((settings.width - 1) as Float);
+    let v = (y as Float + 0.5) / ((settings.height - 1) as Float);
+    scene.camera.get_ray(u, v)
+}
+
+// What's under a pixel, see Renderer::pick
+#[derive(Clone, Debug)]
+pub struct HitInfo {
+    // index of the object in the scene's world, the same as in the scene file's objects
+    pub object: usize,
+    // None when the scene didn't name the material
+    pub material: Option<String>,
+    pub point: Point3,
+    // from the camera, along the ray
+    pub distance: Float,
+}
+
+// Renders scenes on a pool of worker threads, one per core unless told otherwise
+#[derive(Clone, Debug)]
+pub struct Renderer {
+    threads: usize,
+    cancel: Cancel,
+    shading: Shading,
+    tile_order: TileOrder,
+    noise_threshold: Option<NoiseThreshold>,
+}
+
+impl Renderer {
+    pub fn new() -> Renderer {
+        // wasm32 and other platforms that can't tell get a single thread
+        let threads = thread::available_parallelism().map_or(1, |n| n.get());
+        Renderer { threads, cancel: Cancel::new(), shading: Shading::default(), tile_order: TileOrder::Scanline,
+                   noise_threshold: None }
+    }
+
+    pub fn with_threads(mut self, threads: usize) -> Renderer {
+        self.threads = threads.max(1);
+        self
+    }
+
+    // stops the renders once the token is cancelled, see Cancel
+    pub fn with_cancel(mut self, cancel: Cancel) -> Renderer {
+        self.cancel = cancel;
+        self
+    }
+
+    // path tracing unless one of the debug views is wanted, see Integrator
+    pub fn with_integrator(mut self, integrator: Integrator) -> Renderer {
+        self.shading.integrator = integrator;
+        self
+    }
+
+    // which tiles render_to and render_tiles start with, see TileOrder
+    pub fn with_tile_order(mut self, tile_order: TileOrder) -> Renderer {
+        self.tile_order = tile_order;
+        self
+    }
+
+    // renders an ambient occlusion pass along with the rest, see Occlusion
+    pub fn with_occlusion(mut self, occlusion: Occlusion) -> Renderer {
+        self.shading.occlusion = Some(occlusion);
+        self
+    }
+
+    pub fn occlusion(&self) -> Option<Occlusion> {
+        self.shading.occlusion
+    }
+
+    // renders a pass for each expression along with the rest, see lpe::LightPath
+    pub fn with_light_paths(mut self, light_paths: Vec<LightPath>) -> Renderer {
+        self.shading.light_paths = light_paths.into();
+        self
+    }
+
+    pub fn light_paths(&self) -> &[LightPath] {
+        &self.shading.light_paths
+    }
+
+    // Renders the light of each of the scene's named light materials into a pass of its own,
+    // "light.<name>", and what comes from the background into one more, so the lights can be
+    // balanced against each other afterwards. Together they add up to the beauty when every
+    // light has a name.
+    pub fn with_light_passes(mut self, light_passes: bool) -> Renderer {
+        self.shading.light_passes = light_passes;
+        self
+    }
+
+    pub fn light_passes(&self) -> bool {
+        self.shading.light_passes
+    }
+
+    // Checks every sample's radiance for NaN, infinite or negative values. Those are left out
+    // of their pixel, counted in stats::BAD_SAMPLES and the first few logged with the path
+    // that led to them, see trace::report.
+    pub fn with_radiance_check(mut self, check_radiance: bool) -> Renderer {
+        self.shading.check_radiance = check_radiance;
+        self
+    }
+
+    // The surface seen through the center of pixel (x, y), counted from the top left like
+    // the image, or None for the background. For finding out what's what by clicking on it.
+    // Through an open aperture or shutter the ray is the one the pixel's seed gives, whichever
+    // thread asks and whatever it drew before.
+    pub fn pick(&self, scene: &Scene, settings: &Settings, x: u32, y: u32) -> Option<HitInfo> {
+        if x >= settings.width || y >= settings.height {
+            return None;
+        }
+        random::reseed(random::pixel_seed(settings.seed, x, settings.height - y - 1));
+        let r = center_ray(x, settings.height - y - 1, scene, settings);
+        let (object, rec) = scene.world.hit_object(&r, 0.0, Float::INFINITY)?;
+        Some(HitInfo {
+            object,
+            material: scene.material_name(&rec.mat).map(str::to_string),
+            point: rec.p,
+            distance: rec.t * r.direction().length(),
+        })
+    }
+
+    pub fn threads(&self) -> usize {
+        self.threads
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancel.is_cancelled()
+    }
+
+    // Renders until the image is clean enough rather than for the settings' samples per pixel,
+    // which become the size of a round. Only render does, render_to and render_tiles hand the
+    // tiles out as they're done and can't go over them again.
+    pub fn with_noise_threshold(mut self, noise_threshold: NoiseThreshold) -> Renderer {
+        self.noise_threshold = Some(noise_threshold);
+        self
+    }
+
+    pub fn noise_threshold(&self) -> Option<NoiseThreshold> {
+        self.noise_threshold
+    }
+
+    // a single thread renders on the calling one, for platforms without threads like wasm32
+    pub fn render(&self, scene: &Scene, settings: &Settings) -> Framebuffer {
+        match self.noise_threshold {
+            Some(stop) => self.render_until(scene, settings, stop),
+            None => self.render_once(scene, settings),
+        }
+    }
+
+    // rounds with seeds one after the other until the noise is below the threshold, see
+    // convergence::NoiseThreshold
+    fn render_until(&self, scene: &Scene, settings: &Settings, stop: NoiseThreshold) -> Framebuffer {
+        let start = (!cfg!(target_arch = "wasm32")).then(Instant::now);
+        let mut convergence = Convergence::new();
+        let mut seeds = Vec::new();
+        let mut error = Float::INFINITY;
+        loop {
+            let seed = settings.seed.wrapping_add(convergence.rounds() as u64);
+            let round = self.render_once(scene, &Settings { seed, ..*settings });
+            // a cancelled round is only partly there, it's left out unless it's all there is
+            if self.is_cancelled() && convergence.rounds() > 0 {
+                break;
+            }
+            convergence.add(round);
+            seeds.push(seed.to_string());
+            error = convergence.error();
+            let samples = convergence.rounds() * settings.samples_per_pixel;
+            log::info!("round {}: {} samples per pixel, noise {:.4}", convergence.rounds(), samples, error);
+            if self.is_cancelled() || samples + settings.samples_per_pixel > stop.max_samples
+                || (convergence.rounds() >= MIN_ROUNDS && error < stop.threshold) {
+                break;
+            }
+        }
+        if error >= stop.threshold {
+            log::warn!("stopped at noise {:.4}, above the threshold of {}", error, stop.threshold);
+        }
+
+        let samples = convergence.rounds() * settings.samples_per_pixel;
+        let mut data = convergence.finish().expect("there's always a round");
+        data.metadata = self.metadata(scene, &Settings { samples_per_pixel: samples, ..*settings }, start);
+        for (key, value) in &mut data.metadata {
+            if key == "Seed" {
+                *value = seeds.join(",");
+            }
+        }
+        data.metadata.push(("NoiseEstimate".to_string(), error.to_string()));
+        data
+    }
+
+    fn render_once(&self, scene: &Scene, settings: &Settings) -> Framebuffer {
+        // there's no clock on wasm32 either
+        let start = (!cfg!(target_arch = "wasm32")).then(Instant::now);
+        log::debug!("rendering on {} threads", self.threads);
+        let shading = self.shading.for_scene(scene);
+        let data = Mutex::new(Framebuffer::new(settings.width, settings.height).with_passes(shading.pass_names()));
+        let render_column = |x: u32| {
+            for y in 0..settings.height {
+                let (pixel_color, (normal, depth, occlusion), passes) = shade_pixel(x, y, scene, settings, &shading);
+
+                let mut data = data.lock().unwrap();
+                data.set(x, settings.height-y-1, pixel_color, normal, depth);
+                if let Some(occlusion) = occlusion {
+                    data.set_occlusion(x, settings.height-y-1, occlusion);
+                }
+                data.set_passes(x, settings.height-y-1, &passes);
+            }
+            log::debug!("column {} of {} done", x + 1, settings.width);
+        };
+
+        if self.threads == 1 {
+            (0..settings.width).take_while(|_| !self.is_cancelled()).for_each(render_column);
+        } else {
+            // columns are dealt out to the workers, see WorkQueue
+            let queue = WorkQueue::new(0..settings.width, self.threads);
+            thread::scope(|s| {
+                for worker in 0..queue.workers() {
+                    let (queue, render_column) = (&queue, &render_column);
+                    s.spawn(move || {
+                        queue.run(worker, |x| {
+                            if self.is_cancelled() {
+                                return false;
+                            }
+                            render_column(x);
+                            true
+                        })
+                    });
+                }
+            });
+            queue.report();
+        }
+
+        let mut data = data.into_inner().unwrap();
+        log_finished(settings, start);
+        data.metadata = self.metadata(scene, settings, start);
+        data
+    }
+
+    // Renders square tiles into the sink, each one as soon as it is done and the whole image at
+    // the end. The sink is only ever called from this thread.
+    pub fn render_to(&self, scene: &Scene, settings: &Settings, tile_size: u32, sink: &mut dyn ImageSink) -> Result<()> {
+        let start = (!cfg!(target_arch = "wasm32")).then(Instant::now);
+        let origins = self.tile_order.tiles(settings, tile_size);
+        let shading = self.shading.for_scene(scene);
+        let mut image = Framebuffer::new(settings.width, settings.height).with_passes(shading.pass_names());
+        sink.begin(settings.width, settings.height)?;
+        let mut deliver = |tile: Tile| {
+            sink.write_tile(&tile)?;
+            image.blit(&tile.data, tile.x, tile.y);
+            Ok::<_, RendererError>(())
+        };
+
+        if self.threads == 1 {
+            for &(x, y) in origins.iter().take_while(|_| !self.is_cancelled()) {
+                deliver(shade_tile(scene, settings, &shading, x, y, tile_size))?;
+            }
+        } else {
+            // tiles are dealt out to the workers, and cut up near the end, see split_piece
+            let pieces = origins.iter().map(|&(x, y)| Piece::of_tile(settings, x, y, tile_size));
+            let queue = WorkQueue::new(pieces, self.threads).with_handover();
+            thread::scope(|s| {
+                let (tx, rx) = mpsc::channel();
+                for worker in 0..queue.workers() {
+                    let tx = tx.clone();
+                    let (queue, shading) = (&queue, &shading);
+                    s.spawn(move || {
+                        queue.run(worker, |piece| {
+                            if self.is_cancelled() {
+                                return false;
+                            }
+                            let tile = shade_piece(scene, settings, shading, piece, |piece, done| split_piece(queue, worker, piece, done));
+                            // the receiver is gone when the sink failed
+                            tx.send(tile).is_ok()
+                        })
+                    });
+                }
+                drop(tx);
+                rx.into_iter().try_for_each(&mut deliver)
+            })?;
+            queue.report();
+        }
+
+        log_finished(settings, start);
+        image.metadata = self.metadata(scene, settings, start);
+        sink.write_image(&image)
+    }
+
+    fn metadata(&self, scene: &Scene, settings: &Settings, start: Option<Instant>) -> Vec<(String, String)> {
+        let mut metadata = metadata::render_metadata(scene, settings, start.map(|s| s.elapsed()));
+        if self.shading.integrator != Integrator::Path {
+            metadata.push(("Integrator".to_string(), self.shading.integrator.name().to_string()));
+        }
+        for path in self.shading.light_paths.iter() {
+            metadata.push((format!("LightPath.{}", path.name()), path.expression().to_string()));
+        }
+        if let Some(occlusion) = self.shading.occlusion {
+            metadata.push(("OcclusionDistance".to_string(), occlusion.distance.to_string()));
+            metadata.push(("OcclusionSamples".to_string(), occlusion.samples.to_string()));
+        }
+        if self.is_cancelled() {
+            log::warn!("render cancelled, the image is incomplete");
+            metadata.push(("Cancelled".to_string(), "true".to_string()));
+        }
+        metadata
+    }
+
+    // Renders square tiles and hands each one over as soon as it is done, so the whole image
+    // never has to be held in memory
+    #[cfg(feature = "native")]
+    pub fn render_tiles(&self, scene: Scene, settings: Settings, tile_size: u32) -> mpsc::Receiver<Tile> {
+        let shading = self.shading.for_scene(&scene);
+        let arc_scene = Arc::new(scene);
+        // bounded so the workers wait for the writer instead of piling finished tiles up
+        let (tx, rx) = mpsc::sync_channel(16);
+
+        let queue = Arc::new(WorkQueue::new(self.tile_order.tiles(&settings, tile_size), self.threads));
+        for worker in 0..queue.workers() {
+            let (tx, queue) = (tx.clone(), queue.clone());
+            let (arc_scene, cancel, shading) = (arc_scene.clone(), self.cancel.clone(), shading.clone());
+            thread::spawn(move || {
+                let last = queue.run(worker, |(x, y)| {
+                    // once cancelled the tiles still come, black, so the writer can finish the file
+                    let tile = if cancel.is_cancelled() {
+                        blank_tile(&settings, x, y, tile_size)
+                    } else {
+                        shade_tile(&arc_scene, &settings, &shading, x, y, tile_size)
+                    };
+                    // the receiver is gone when writing failed, nothing left to do then
+                    tx.send(tile).is_ok()
+                });
+                if last {
+                    queue.report();
+                }
+            });
+        }
+
+        rx
+    }
+}
+
+// Top left corners of the tiles covering the image, row by row from the top. Together with
+// render_tile this renders an image piece by piece on the calling thread, handing control
+// back in between, which is how a browser shows the image coming together.
+pub fn tile_origins(settings: &Settings, tile_size: u32) -> impl Iterator<Item = (u32, u32)> {
+    let (width, height) = (settings.width, settings.height);
+    (0..height).step_by(tile_size as usize)
+        .flat_map(move |y| (0..width).step_by(tile_size as usize).map(move |x| (x, y)))
+}
+
+// The order tiles are rendered in. Whatever comes first shows up first in a progressive
+// render, so the middle of the picture, where the subject usually is, can come before the
+// edges.
+#[derive(Copy, Clone, PartialEq, Debug, Default)]
+#[cfg_attr(feature = "native", derive(clap::ValueEnum))]
+pub enum TileOrder {
+    // row by row from the top
+    #[default]
+    Scanline,
+    // round and round, outward from the center
+    Spiral,
+    // along a Hilbert curve, each tile next to the one before
+    Hilbert,
+    // shuffled with the render's seed, the whole image fills in evenly
+    Random,
+}
+
+impl TileOrder {
+    // top left corners of the tiles covering the image, in this order
+    pub fn tiles(self, settings: &Settings, tile_size: u32) -> Vec<(u32, u32)> {
+        let mut origins: Vec<(u32, u32)> = tile_origins(settings, tile_size).collect();
+        // tiles counted in tiles rather than pixels
+        let grid = |&(x, y): &(u32, u32)| (x / tile_size, y / tile_size);
+        let columns = settings.width.div_ceil(tile_size);
+        let rows = settings.height.div_ceil(tile_size);
+        match self {
+            TileOrder::Scanline => {}
+            TileOrder::Spiral => {
+                let (cx, cy) = ((columns - 1) as f64 / 2.0, (rows - 1) as f64 / 2.0);
+                let ring_and_angle = |tile: &(u32, u32)| {
+                    let (i, j) = grid(tile);
+                    let (dx, dy) = (i as f64 - cx, j as f64 - cy);
+                    (dx.abs().max(dy.abs()).round(), dy.atan2(dx))
+                };
+                origins.sort_by(|a, b| ring_and_angle(a).partial_cmp(&ring_and_angle(b)).unwrap());
+            }
+            TileOrder::Hilbert => {
+                let side = columns.max(rows).next_power_of_two();
+                origins.sort_by_key(|tile| {
+                    let (i, j) = grid(tile);
+                    hilbert_index(side, i, j)
+                });
+            }
+            TileOrder::Random => origins.shuffle(&mut StdRng::seed_from_u64(settings.seed)),
+        }
+        origins
+    }
+}
+
+// Quick looks at a scene before rendering it for real: the settings cut down from the full
+// ones rather than set by hand, so the scene file keeps its final settings. Depth of field is
+// left out too, see Camera::pinhole.
+#[derive(Copy, Clone, PartialEq, Debug)]
+#[cfg_attr(feature = "native", derive(clap::ValueEnum))]
+pub enum Preview {
+    // half the width and height, an eighth of the samples, up to 8 bounces
+    Half,
+    // a quarter of the width and height, a 32nd of the samples, up to 4 bounces
+    Quarter,
+}
+
+impl Preview {
+    pub fn settings(self, full: &Settings) -> Settings {
+        let (size, samples, depth) = match self {
+            Preview::Half => (2, 8, 8),
+            Preview::Quarter => (4, 32, 4),
+        };
+        // like any render at least 2 pixels a side, a pixel's u and v divide by one less
+        Settings {
+            width: (full.width / size).max(2),
+            height: (full.height / size).max(2),
+            samples_per_pixel: (full.samples_per_pixel / samples).max(1),
+            max_depth: full.max_depth.min(depth),
+            ..*full
+        }
+    }
+}
+
+// Distance of cell (x, y) along the Hilbert curve through a `side` x `side` grid, side being a
+// power of two
+fn hilbert_index(side: u32, mut x: u32, mut y: u32) -> u64 {
+    let mut d = 0;
+    let mut s = side / 2;
+    while s > 0 {
+        let rx = (x & s > 0) as u32;
+        let ry = (y & s > 0) as u32;
+        d += s as u64 * s as u64 * ((3 * rx) ^ ry) as u64;
+        // rotate the quadrant so the curve inside it starts and ends in the right corners
+        if ry == 0 {
+            if rx == 1 {
+                x = side - 1 - x;
+                y = side - 1 - y;
+            }
+            std::mem::swap(&mut x, &mut y);
+        }
+        s /= 2;
+    }
+    d
+}
+
+#[cfg(feature = "native")]
+fn blank_tile(settings: &Settings, x: u32, y: u32, tile_size: u32) -> Tile {
+    let data = Framebuffer::new(tile_size.min(settings.width - x), tile_size.min(settings.height - y));
+    Tile { x, y, data }
+}
+
+// the tile with its top left corner at (x, y), cut short at the right and bottom edges
+pub fn render_tile(scene: &Scene, settings: &Settings, x: u32, y: u32, tile_size: u32) -> Tile {
+    shade_tile(scene, settings, &Shading::default(), x, y, tile_size)
+}
+
+fn shade_tile(scene: &Scene, settings: &Settings, shading: &Shading, x: u32, y: u32, tile_size: u32) -> Tile {
+    shade_piece(scene, settings, shading, Piece::of_tile(settings, x, y, tile_size), |_, _| {})
+}
+
+// The piece row by row from the top, `split` being given the piece and the rows done after
+// each row so it can cut the rest short, see split_piece
+fn shade_piece(scene: &Scene, settings: &Settings, shading: &Shading, mut piece: Piece, mut split: impl FnMut(&mut Piece, u32)) -> Tile {
+    let (x, y) = (piece.x, piece.y);
+    let mut data = Framebuffer::new(piece.width, piece.height).with_passes(shading.pass_names());
+    let mut j = 0;
+    while j < piece.height {
+        for i in 0..piece.width {
+            // tiles are laid out in image space, top row first
+            let (color, (normal, depth, occlusion), passes) = shade_pixel(x + i, settings.height - (y + j) - 1, scene, settings, shading);
+            data.set(i, j, color, normal, depth);
+            if let Some(occlusion) = occlusion {
+                data.set_occlusion(i, j, occlusion);
+            }
+            data.set_passes(i, j, &passes);
+        }
+        j += 1;
+        split(&mut piece, j);
+    }
+    data.truncate(piece.height);
+    log::debug!("tile at ({}, {}) done", x, y);
+    Tile { x, y, data }
+}
+
+// A rectangle of the image to render, a tile or the rows of one
+#[derive(Copy, Clone, Debug)]
+struct Piece {
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+}
+
+impl Piece {
+    // cut short at the right and bottom edges
+    fn of_tile(settings: &Settings, x: u32, y: u32, tile_size: u32) -> Piece {
+        Piece { x, y, width: tile_size.min(settings.width - x), height: tile_size.min(settings.height - y) }
+    }
+}
+
+// Some tiles of render_to take far longer than the rest, full of glass or dense geometry, and
+// near the end one worker would be left with such a tile while the others sit idle. So as long
+// as a worker is waiting for something to do, the busy ones give away the bottom half of the
+// rows they haven't got to yet, with `done` rows of `piece` rendered, and the pieces are
+// delivered as tiles of their own. Pixels are seeded by where they are, so how the tiles get
+// cut up doesn't change the image.
+fn split_piece(queue: &WorkQueue<Piece>, worker: usize, piece: &mut Piece, done: u32) {
+    let left = piece.height - done;
+    if left < 2 || !queue.wants_work() {
+        return;
+    }
+    let keep = done + left / 2;
+    queue.give(worker, Piece { y: piece.y + keep, height: piece.height - keep, ..*piece });
+    piece.height = keep;
+}
+
+fn log_finished(settings: &Settings, start: Option<Instant>) {
+    // the counters are left for the caller, who resets them once per frame
+    let rays = stats::RAYS.load(Ordering::Relaxed);
+    let bad = stats::BAD_SAMPLES.load(Ordering::Relaxed);
+    if bad > 0 {
+        log::warn!("{} samples came out NaN, infinite or negative and were left out", bad);
+    }
+    match start {
+        Some(start) => log::info!("rendered {}x{} at {} spp in {:.1}s, {} rays traced",
+                                  settings.width, settings.height, settings.samples_per_pixel, start.elapsed().as_secs_f64(), rays),
+        None => log::info!("rendered {}x{} at {} spp, {} rays traced", settings.width, settings.height, settings.samples_per_pixel, rays),
+    }
+}
+
+impl Default for Renderer {
+    fn default() -> Renderer {
+        Renderer::new()
+    }
+}
@@ -0,0 +1,74 @@
+use std::path::Path;
+use std::time::{Duration, Instant};
+use serde::Serialize;
+use raytracer_test::stats::Stats;
+use raytracer_test::{RendererError, Result};
+
+// Machine readable summary of a render run, for render farms and scripts
+#[derive(Serialize, Default)]
+pub struct Report {
+    pub version: &'static str,
+    pub status: &'static str,
+    pub error: Option<String>,
+    pub settings: Settings,
+    pub frames: Vec<FrameReport>,
+    pub total_seconds: f64,
+}
+
+#[derive(Serialize, Default)]
+pub struct Settings {
+    pub width: u32,
+    pub height: u32,
+    pub samples_per_pixel: u32,
+    pub max_depth: u64,
+    pub format: String,
+    pub output: String,
+}
+
+#[derive(Serialize, Default)]
+pub struct FrameReport {
+    pub frame: Option<u32>,
+    pub time: f64,
+    pub output: String,
+    pub timings: Timings,
+    pub stats: Stats,
+}
+
+// seconds spent in each stage
+#[derive(Serialize, Default)]
+pub struct Timings {
+    pub scene: f64,
+    pub render: f64,
+    pub write: f64,
+}
+
+impl Timings {
+    // time `f` and add it to the given stage
+    pub fn measure<T>(stage: &mut f64, f: impl FnOnce() -> T) -> T {
+        let start = Instant::now();
+        let out = f();
+        *stage += start.elapsed().as_secs_f64();
+        out
+    }
+}
+
+impl Report {
+    pub fn new() -> Report {
+        Report {
+            version: env!("CARGO_PKG_VERSION"),
+            status: "running",
+            ..Report::default()
+        }
+    }
+
+    pub fn finish(&mut self, total: Duration, error: Option<String>) {
+        self.total_seconds = total.as_secs_f64();
+        self.status = if error.is_some() { "failed" } else { "completed" };
+        self.error = error;
+    }
+
+    pub fn write(&self, path: &Path) -> Result<()> {
+        let file = std::fs::File::create(path).map_err(|e| RendererError::io(path, e))?;
+        serde_json::to_writer_pretty(file, self).map_err(|e| RendererError::encode(path, e))
+    }
+}
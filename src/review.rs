@@ -0,0 +1,125 @@
+use std::path::{Path, PathBuf};
+use crate::colorspace::WorkingSpace;
+use crate::framebuffer::Framebuffer;
+use crate::{Color, Float, Vec3};
+
+// "render.png" with +2 EV becomes "render_+2EV.png"
+pub fn bracket_path(path: &Path, ev: Float) -> PathBuf {
+    suffixed(path, &format!("_{:+}EV", ev))
+}
+
+pub fn sheet_path(path: &Path) -> PathBuf {
+    suffixed(path, "_sheet")
+}
+
+pub fn occlusion_path(path: &Path) -> PathBuf {
+    suffixed(path, "_occlusion")
+}
+
+// "render.exr" becomes "render_thumb.jpg", thumbnails are JPEG whatever the image is
+pub fn thumbnail_path(path: &Path) -> PathBuf {
+    let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or("render");
+    path.with_file_name(format!("{}_thumb.jpg", stem))
+}
+
+// "render.png" with a pass called caustics becomes "render_caustics.png"
+pub fn pass_path(path: &Path, name: &str) -> PathBuf {
+    suffixed(path, &format!("_{}", name))
+}
+
+fn suffixed(path: &Path, suffix: &str) -> PathBuf {
+    let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or("render");
+    match path.extension().and_then(|e| e.to_str()) {
+        Some(ext) => path.with_file_name(format!("{}{}.{}", stem, suffix, ext)),
+        None => path.with_file_name(format!("{}{}", stem, suffix)),
+    }
+}
+
+// Half resolution thumbnails of the beauty, normal and depth passes side by side
+pub fn contact_sheet(data: &Framebuffer) -> Framebuffer {
+    let thumb = data.downsampled(2);
+    let (w, h) = (thumb.width(), thumb.height());
+
+    // the AOVs are turned into colors in the beauty channel. Values are squared as the
+    // writers apply a gamma of 2 to the beauty
+    let mut normals = Framebuffer::new(w, h);
+    let mut depths = Framebuffer::new(w, h);
+    let max_depth = thumb.depth.iter().cloned().filter(|d| d.is_finite()).fold(0.0, Float::max);
+    for y in 0..h {
+        for x in 0..w {
+            let i = (y * w + x) as usize;
+            let n = 0.5 * (thumb.normal[i] + Vec3::new(1.0, 1.0, 1.0));
+            normals.set(x, y, n * n, thumb.normal[i], thumb.depth[i]);
+
+            // near is bright, far and background are black
+            let d = if thumb.depth[i].is_finite() && max_depth > 0.0 { 1.0 - thumb.depth[i] / max_depth } else { 0.0 };
+            depths.set(x, y, Color::new(d*d, d*d, d*d), thumb.normal[i], thumb.depth[i]);
+        }
+    }
+
+    let mut sheet = Framebuffer::new(3 * w, h);
+    sheet.blit(&thumb, 0, 0);
+    sheet.blit(&normals, w, 0);
+    sheet.blit(&depths, 2 * w, 0);
+    // the beauty is still in the render's working space
+    sheet.metadata.extend(WorkingSpace::of(&data.metadata).metadata());
+    sheet
+}
+
+// The beauty box filtered down to no more than `size` pixels on its longer side, in sRGB and
+// tone mapped to fit in 0 to 1: extended Reinhard on the luminance, with the brightest pixel
+// just reaching white, so highlights roll off rather than clip and the rest stays as it was.
+pub fn thumbnail(data: &Framebuffer, size: u32) -> Framebuffer {
+    let factor = data.width().max(data.height()).div_ceil(size.max(1)).max(1);
+    let mut thumb = data.downsampled(factor);
+    let space = WorkingSpace::of(&data.metadata);
+    let luminance = |c: Color| 0.2126 * c[0] + 0.7152 * c[1] + 0.0722 * c[2];
+    for c in &mut thumb.beauty {
+        *c = space.to_srgb(*c);
+    }
+    let white = thumb.beauty.iter().map(|&c| luminance(c)).filter(|l| l.is_finite()).fold(1.0, Float::max);
+    for c in &mut thumb.beauty {
+        let l = luminance(*c);
+        if l > 0.0 {
+            *c = (1.0 + l / (white * white)) / (1.0 + l) * *c;
+        }
+    }
+    thumb
+}
+
+// The occlusion pass in gray for the formats without extra channels, squared like the
+// contact sheet so the file holds the values themselves. None when it wasn't rendered.
+pub fn occlusion_pass(data: &Framebuffer) -> Option<Framebuffer> {
+    let occlusion = data.occlusion.as_ref()?;
+    let mut pass = data.clone();
+    pass.occlusion = None;
+    for (c, &o) in pass.beauty.iter_mut().zip(occlusion) {
+        *c = Color::new(o * o, o * o, o * o);
+    }
+    Some(pass)
+}
+
+// The light path passes as images of their own for the formats without extra channels,
+// with their names
+pub fn light_path_passes(data: &Framebuffer) -> impl Iterator<Item = (&str, Framebuffer)> {
+    data.passes.iter().map(|pass| {
+        let mut image = Framebuffer::new(data.width(), data.height());
+        image.beauty = pass.values.clone();
+        image.metadata = data.metadata.clone();
+        (pass.name.as_str(), image)
+    })
+}
+
+// Images of the same size next to each other, `columns` to a row
+pub fn grid(images: &[Framebuffer], columns: usize) -> Framebuffer {
+    let (w, h) = images.first().map_or((0, 0), |i| (i.width(), i.height()));
+    let rows = images.len().div_ceil(columns);
+    let mut grid = Framebuffer::new(w * columns as u32, h * rows as u32);
+    for (i, image) in images.iter().enumerate() {
+        grid.blit(image, w * (i % columns) as u32, h * (i / columns) as u32);
+    }
+    if let Some(first) = images.first() {
+        grid.metadata.extend(WorkingSpace::of(&first.metadata).metadata());
+    }
+    grid
+}
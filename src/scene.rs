@@ -0,0 +1,420 @@
+use std::sync::Arc;
+use crate::animation::{Animated, Track};
+use crate::camera::Camera;
+use crate::clip::{ClipPlane, Clipped};
+use crate::colorspace::WorkingSpace;
+use crate::error::{RendererError, Result};
+use crate::fog::{Fog, FogLight};
+use crate::hit::HittableList;
+use crate::ray::Ray;
+use crate::material::Scatter;
+use crate::triangle::Triangle;
+use crate::visibility::{TraceGroup, Visibility, Visible};
+use crate::Hit;
+use crate::sphere::{Shell, Sphere};
+use crate::texture::{ImageTexture, Texture};
+use crate::float::consts::PI;
+use crate::{Color, Float, Point3, Vec3};
+
+// What rays that miss everything see
+#[derive(Clone, Debug)]
+pub enum Background {
+    // white to light blue gradient going up
+    Sky,
+    Color(Color),
+    // from the first color straight down to the second straight up, like the paper backdrop of
+    // a photo studio
+    Gradient(Color, Color),
+    // a latitude-longitude (equirectangular) map, HDR or EXR for lighting with it: up is +y and
+    // the middle of the image looks toward -z
+    Map(Arc<ImageTexture>),
+}
+
+impl Background {
+    pub fn color(&self, direction: Vec3) -> Color {
+        match self {
+            Background::Sky => {
+                let unit_direction = direction.normalized();
+                let t = 0.5 * (unit_direction.y() + 1.0);
+                (1.0 - t) * Color::new(1.0, 1.0, 1.0) + t*Color::new(0.5, 0.7, 1.0)
+            }
+            Background::Color(c) => *c,
+            Background::Gradient(bottom, top) => {
+                let t = 0.5 * (direction.normalized().y() + 1.0);
+                (1.0 - t) * *bottom + t * *top
+            }
+            Background::Map(map) => {
+                let d = direction.normalized();
+                let u = 0.5 + d.x().atan2(-d.z()) / (2.0 * PI);
+                let v = 0.5 + d.y().clamp(-1.0, 1.0).asin() / PI;
+                map.value(u, v, d)
+            }
+        }
+    }
+}
+
+// The background as it lights the scene. `rotation` turns it about the up axis, in degrees, and
+// `intensity` scales its light. It can be left out of what the camera sees (`camera`), which
+// then sees black, or out of what lights the scene (`lighting`), keeping it for the camera.
+//
+// A backdrop is a photo the camera sees behind the scene instead, pinned to the frame and
+// stretched over it, while the lighting still comes from the background. For rendering
+// objects into a shot: only camera rays see it, reflections and refractions don't.
+#[derive(Clone, Debug)]
+pub struct Environment {
+    pub background: Background,
+    pub rotation: Float,
+    pub intensity: Float,
+    pub camera: bool,
+    pub lighting: bool,
+    pub backdrop: Option<Arc<ImageTexture>>,
+}
+
+impl Environment {
+    pub fn new(background: Background) -> Environment {
+        Environment {
+            background,
+            rotation: 0.0,
+            intensity: 1.0,
+            camera: true,
+            lighting: true,
+            backdrop: None,
+        }
+    }
+
+    pub fn with_rotation(mut self, degrees: Float) -> Environment {
+        self.rotation = degrees;
+        self
+    }
+
+    pub fn with_intensity(mut self, intensity: Float) -> Environment {
+        self.intensity = intensity;
+        self
+    }
+
+    pub fn with_visibility(mut self, camera: bool, lighting: bool) -> Environment {
+        self.camera = camera;
+        self.lighting = lighting;
+        self
+    }
+
+    pub fn with_backdrop(mut self, image: Arc<ImageTexture>) -> Environment {
+        self.backdrop = Some(image);
+        self
+    }
+
+    // what a ray that missed everything brings back, camera rays being those that haven't
+    // bounced; `camera` places the backdrop
+    pub fn color(&self, r: &Ray, camera: &Camera) -> Color {
+        let from_camera = r.lobe() == TraceGroup::CAMERA;
+        if let Some(backdrop) = self.backdrop.as_ref().filter(|_| from_camera) {
+            let (u, v) = camera.screen(r);
+            return backdrop.value(u, v, r.origin());
+        }
+        let seen = if from_camera { self.camera } else { self.lighting };
+        if !seen {
+            return Color::new(0.0, 0.0, 0.0);
+        }
+        let (sin, cos) = self.rotation.to_radians().sin_cos();
+        let d = r.direction();
+        let turned = Vec3::new(cos * d.x() - sin * d.z(), d.y(), sin * d.x() + cos * d.z());
+        self.intensity * self.background.color(turned)
+    }
+}
+
+impl From<Background> for Environment {
+    fn from(background: Background) -> Environment {
+        Environment::new(background)
+    }
+}
+
+#[derive(Clone)]
+pub struct Scene {
+    pub world: HittableList,
+    pub camera: Camera,
+    pub environment: Environment,
+    // animation time (in seconds) the scene was set up for
+    pub time: Float,
+    // names of the materials for picking, scenes built in code usually leave them out
+    pub material_names: Vec<(String, Arc<dyn Scatter>)>,
+    pub fog: Option<Fog>,
+    // what the colors are in, and so the render
+    pub working_space: WorkingSpace,
+    // how big the scene is, see SceneBuilder::set_scale
+    pub scale: Option<Float>,
+}
+
+// the share of the scene's scale rays leaving a surface start away from it
+const SCALE_EPSILON: Float = 1.0e-5;
+
+impl Scene {
+    // how far rays leaving a surface start off it at least, 0 when the scene has no scale and
+    // only the rounding error of the hit counts (see HitRecord::spawn_ray)
+    pub fn epsilon(&self) -> Float {
+        self.scale.map_or(0.0, |scale| SCALE_EPSILON * scale)
+    }
+
+    pub fn material_name(&self, material: &Arc<dyn Scatter>) -> Option<&str> {
+        self.material_names.iter().find(|(_, m)| Arc::ptr_eq(m, material)).map(|(name, _)| name.as_str())
+    }
+
+    // the named materials that give off light
+    pub fn lights(&self) -> impl Iterator<Item = &(String, Arc<dyn Scatter>)> {
+        self.material_names.iter().filter(|(_, m)| m.is_light())
+    }
+}
+
+// Builds a Scene step by step, checking the objects as they are added:
+//
+//     SceneBuilder::new()
+//         .add_sphere(Point3::new(0.0, 0.0, -1.0), 0.5).with_material(glass)
+//         .set_camera(camera)
+//         .build()?
+pub struct SceneBuilder {
+    world: HittableList,
+    camera: Option<Camera>,
+    environment: Environment,
+    time: Float,
+    clip_planes: Vec<ClipPlane>,
+    material_names: Vec<(String, Arc<dyn Scatter>)>,
+    fog: Option<Fog>,
+    working_space: WorkingSpace,
+    scale: Option<Float>,
+    // the spheres and triangles giving off light, for the fog
+    lights: Vec<FogLight>,
+    // the first problem found, reported by build()
+    error: Option<String>,
+}
+
+// An object waiting for its material before it goes into the scene
+pub struct ObjectBuilder {
+    scene: SceneBuilder,
+    shape: Shape,
+    animation: Option<Track>,
+    visibility: Option<Visibility>,
+}
+
+enum Shape {
+    Sphere(Point3, Float),
+    // center, outer and inner radius
+    Shell(Point3, Float, Float),
+    Triangle(Point3, Point3, Point3, [(Float, Float); 3]),
+}
+
+impl SceneBuilder {
+    pub fn new() -> SceneBuilder {
+        SceneBuilder {
+            world: HittableList::new(),
+            camera: None,
+            environment: Environment::new(Background::Sky),
+            time: 0.0,
+            clip_planes: Vec::new(),
+            material_names: Vec::new(),
+            fog: None,
+            working_space: WorkingSpace::Srgb,
+            scale: None,
+            lights: Vec::new(),
+            error: None,
+        }
+    }
+
+    // a negative radius turns the normals in, zero is not allowed; see add_shell for hollow spheres
+    pub fn add_sphere(self, center: Point3, radius: Float) -> ObjectBuilder {
+        ObjectBuilder { scene: self, shape: Shape::Sphere(center, radius), animation: None, visibility: None }
+    }
+
+    // a hollow sphere, for glass bubbles and the like
+    pub fn add_shell(self, center: Point3, outer: Float, inner: Float) -> ObjectBuilder {
+        ObjectBuilder { scene: self, shape: Shape::Shell(center, outer, inner), animation: None, visibility: None }
+    }
+
+    pub fn add_triangle(self, v0: Point3, v1: Point3, v2: Point3) -> ObjectBuilder {
+        ObjectBuilder {
+            scene: self,
+            shape: Shape::Triangle(v0, v1, v2, [(0.0, 0.0), (1.0, 0.0), (0.0, 1.0)]),
+            animation: None,
+            visibility: None,
+        }
+    }
+
+    // anything else that can be hit, meshes for instance, as it is
+    pub fn add_object(mut self, object: Arc<dyn Hit>) -> SceneBuilder {
+        self.world.push(object);
+        self
+    }
+
+    pub fn set_camera(mut self, camera: Camera) -> SceneBuilder {
+        self.camera = Some(camera);
+        self
+    }
+
+    pub fn set_background(mut self, background: Background) -> SceneBuilder {
+        self.environment.background = background;
+        self
+    }
+
+    // the background with its rotation, intensity and visibility, see Environment
+    pub fn set_environment(mut self, environment: Environment) -> SceneBuilder {
+        self.environment = environment;
+        self
+    }
+
+    pub fn set_time(mut self, time: Float) -> SceneBuilder {
+        self.time = time;
+        self
+    }
+
+    // the name picking reports for the material
+    pub fn name_material(mut self, name: impl Into<String>, material: &Arc<dyn Scatter>) -> SceneBuilder {
+        self.material_names.push((name.into(), material.clone()));
+        self
+    }
+
+    // light shafts through fog, see fog::Fog
+    pub fn set_fog(mut self, fog: Fog) -> SceneBuilder {
+        self.fog = Some(fog);
+        self
+    }
+
+    // the space the colors given to the scene are in, see colorspace::WorkingSpace
+    pub fn set_working_space(mut self, space: WorkingSpace) -> SceneBuilder {
+        self.working_space = space;
+        self
+    }
+
+    // How big the scene is in its units, about the size of what's in it: a thousandth for a
+    // ring modelled in meters, thousands for a landscape. Surfaces are hit with some rounding
+    // error, which HitRecord::spawn_ray steps over, but smooth normals, surfaces almost
+    // touching and the like can still show shadow acne or let light leak through; rays
+    // leaving a surface, bounces and the shadow rays of occlusion and dirt, then start a
+    // hundred-thousandth of the scale off it and ignore anything closer.
+    pub fn set_scale(mut self, scale: Float) -> SceneBuilder {
+        if !(scale > 0.0 && scale.is_finite()) {
+            self.error.get_or_insert_with(|| format!("the scene's scale {} should be above 0", scale));
+        }
+        self.scale = Some(scale);
+        self
+    }
+
+    // cuts away part of the whole scene, see ClipPlane
+    pub fn add_clip_plane(mut self, plane: ClipPlane) -> SceneBuilder {
+        let n = plane.normal();
+        if !n.x().is_finite() || !n.y().is_finite() || !n.z().is_finite() {
+            self.error.get_or_insert_with(|| format!("clipping plane through {:?} has no direction", plane.point()));
+        }
+        self.clip_planes.push(plane);
+        self
+    }
+
+    pub fn build(self) -> Result<Scene> {
+        if let Some(e) = self.error {
+            return Err(RendererError::Scene(e));
+        }
+        log::debug!("scene built with {} objects at time {}", self.world.len(), self.time);
+        // objects keep their place in the list, which is what picking reports
+        let world = if self.clip_planes.is_empty() {
+            self.world
+        } else {
+            let mut clipped = HittableList::new();
+            for object in self.world.objects() {
+                clipped.push(Arc::new(Clipped::new(object.clone(), self.clip_planes.clone())));
+            }
+            clipped
+        };
+        Ok(Scene {
+            world,
+            camera: self.camera.ok_or_else(|| RendererError::Scene("the scene has no camera".to_string()))?.at_time(self.time),
+            environment: self.environment,
+            time: self.time,
+            material_names: self.material_names,
+            fog: self.fog.map(|fog| fog.with_lights(self.lights)),
+            working_space: self.working_space,
+            scale: self.scale,
+        })
+    }
+
+    // Just the objects, for putting them into a group (see group::Group). The camera and the
+    // rest of the scene don't matter.
+    pub fn build_objects(self) -> Result<HittableList> {
+        match self.error {
+            Some(e) => Err(RendererError::Scene(e)),
+            None => Ok(self.world),
+        }
+    }
+
+    fn fail(&mut self, error: String) {
+        let n = self.world.len();
+        self.error.get_or_insert_with(|| format!("object {}: {}", n, error));
+    }
+}
+
+impl Default for SceneBuilder {
+    fn default() -> SceneBuilder {
+        SceneBuilder::new()
+    }
+}
+
+impl ObjectBuilder {
+    // texture coordinates of a triangle's vertices, ignored for other shapes
+    pub fn with_uv(mut self, uv: [(Float, Float); 3]) -> ObjectBuilder {
+        if let Shape::Triangle(_, _, _, corners) = &mut self.shape {
+            *corners = uv;
+        }
+        self
+    }
+
+    // moves the object over time, see animation::Track
+    pub fn with_animation(mut self, track: Track) -> ObjectBuilder {
+        self.animation = Some(track);
+        self
+    }
+
+    // hides the object from the rays outside the groups, see visibility::TraceGroup
+    pub fn with_visibility(mut self, visibility: Visibility) -> ObjectBuilder {
+        self.visibility = Some(visibility);
+        self
+    }
+
+    pub fn with_material(self, mat: Arc<dyn Scatter>) -> SceneBuilder {
+        let mut scene = self.scene;
+        let finite = |p: Point3| p.x().is_finite() && p.y().is_finite() && p.z().is_finite();
+        let object: Arc<dyn Hit> = match self.shape {
+            Shape::Sphere(center, radius) => {
+                if !finite(center) || !radius.is_finite() || radius == 0.0 {
+                    scene.fail(format!("sphere at {:?} with radius {} is invalid", center, radius));
+                    return scene;
+                }
+                if mat.is_light() && self.animation.is_none() {
+                    scene.lights.push(FogLight::Sphere(center, radius, mat.clone()));
+                }
+                Arc::new(Sphere::new(center, radius, mat))
+            }
+            Shape::Shell(center, outer, inner) => {
+                if !(finite(center) && inner > 0.0 && inner < outer && outer.is_finite()) {
+                    scene.fail(format!("shell at {:?} with radii {} and {} is invalid", center, outer, inner));
+                    return scene;
+                }
+                Arc::new(Shell::new(center, outer, inner, mat))
+            }
+            Shape::Triangle(v0, v1, v2, uv) => {
+                if ![v0, v1, v2].into_iter().all(finite) || (v1 - v0).cross(v2 - v0).near_zero() {
+                    scene.fail(format!("triangle {:?}, {:?}, {:?} is degenerate", v0, v1, v2));
+                    return scene;
+                }
+                if mat.is_light() && self.animation.is_none() {
+                    scene.lights.push(FogLight::Triangle([v0, v1, v2], mat.clone()));
+                }
+                Arc::new(Triangle::new(v0, v1, v2, mat).with_uv(uv))
+            }
+        };
+        let object: Arc<dyn Hit> = match self.animation {
+            Some(track) => Arc::new(Animated::new(object, track)),
+            None => object,
+        };
+        match self.visibility {
+            Some(visibility) => scene.world.push(Arc::new(Visible::new(object, visibility))),
+            None => scene.world.push(object),
+        }
+        scene
+    }
+}
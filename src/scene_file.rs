@@ -0,0 +1,1346 @@
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use serde::{Deserialize, Serialize, Serializer};
+use crate::aabb::Aabb;
+use crate::animation::{Animated, Keyframe, Motion, Track};
+use crate::blackbody;
+use crate::camera::{Camera, Projection};
+use crate::clip::ClipPlane;
+use crate::colorspace::WorkingSpace;
+use crate::error::{RendererError, Result};
+use crate::fog::Fog;
+use crate::fractal::{Fractal, FractalKind};
+use crate::group::Group;
+use crate::inspect;
+use crate::lens::Prescription;
+use crate::instancer;
+use crate::dirt::{Dirt, DirtKind};
+use crate::material::{Dielectric, DiffuseLight, Lambertian, Mask, Metal, Mix, Scatter, ShadowCatcher};
+use crate::mesh::Mesh;
+use crate::render::{PathLimits, Settings};
+use crate::scene::{Background, Environment, ObjectBuilder, Scene, SceneBuilder};
+use crate::sphere::Sphere;
+use crate::texture::{Brick, Checker, ImageTexture, SolidColor, Texture, Tiles, UvChecker, UvTransform, Wood, Wrap};
+use crate::transform::{self, Matrix, Transform, Up};
+use crate::validate;
+use crate::vdb::Grid;
+use crate::visibility::{Grouped, TraceGroups, Visibility, Visible};
+use crate::volume::Volume;
+use crate::{Float, Hit, HittableList, Point3, Vec3};
+
+// Scene description read from (or saved to) a TOML or JSON file. Textures and materials are
+// declared by name and referenced by name from materials and objects.
+#[derive(Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct SceneFile {
+    #[serde(default)]
+    pub render: RenderDesc,
+    pub camera: CameraDesc,
+    #[serde(default)]
+    pub background: BackgroundDesc,
+    #[serde(default, skip_serializing_if = "EnvironmentDesc::is_default")]
+    pub environment: EnvironmentDesc,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fog: Option<FogDesc>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty", serialize_with = "sorted")]
+    pub textures: HashMap<String, TextureDesc>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty", serialize_with = "sorted")]
+    pub materials: HashMap<String, MaterialDesc>,
+    #[serde(default)]
+    pub objects: Vec<ObjectDesc>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub clip: Vec<ClipDesc>,
+    // trace groups by name and the materials whose scattered rays go into them, see
+    // visibility::TraceGroup
+    #[serde(default, skip_serializing_if = "HashMap::is_empty", serialize_with = "sorted")]
+    pub ray_groups: HashMap<String, Vec<String>>,
+
+    // directory of the scene file, relative paths inside the scene start from there
+    #[serde(skip)]
+    pub base_dir: PathBuf,
+}
+
+// Render settings, anything left out keeps its default
+#[derive(Clone, Deserialize, Serialize, Default)]
+#[serde(deny_unknown_fields)]
+pub struct RenderDesc {
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub samples_per_pixel: Option<u32>,
+    pub max_depth: Option<u64>,
+    // bounces each way on top of max_depth, the brightest indirect light of a sample and the
+    // roughness added to mirrors and glass after diffuse bounces, see render::PathLimits
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub diffuse_depth: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub specular_depth: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub transmission_depth: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub clamp_indirect: Option<Float>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub regularize: Option<Float>,
+    pub seed: Option<u64>,
+    // what the colors of the file are in, sRGB when left out
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub working_space: Option<WorkingSpace>,
+    // how big the scene is in its units, for how far rays start off surfaces, see
+    // SceneBuilder::set_scale
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scale: Option<Float>,
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct CameraDesc {
+    pub lookfrom: [Float; 3],
+    pub lookat: [Float; 3],
+    #[serde(default = "default_vup")]
+    pub vup: [Float; 3],
+    pub vfov: Float,
+    #[serde(default)]
+    pub aperture: Float,
+    // distance from lookfrom to lookat when left out
+    pub focus_dist: Option<Float>,
+    // a real lens to trace the rays through instead of the thin lens of aperture
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lens: Option<LensDesc>,
+    // perspective when left out, see camera::Projection
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub projection: Option<Projection>,
+}
+
+fn default_vup() -> [Float; 3] {
+    [0.0, 1.0, 0.0]
+}
+
+// see lens::Prescription for the file
+#[derive(Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct LensDesc {
+    pub file: PathBuf,
+    // diameter of the aperture stop in millimeters, as in the file when left out
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stop: Option<Float>,
+    // millimeters per scene unit, scenes are in meters by default
+    #[serde(default = "default_lens_scale")]
+    pub scale: Float,
+}
+
+fn default_lens_scale() -> Float {
+    1000.0
+}
+
+// Light shafts in fog, see fog::Fog
+#[derive(Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct FogDesc {
+    pub density: Float,
+    #[serde(default = "white")]
+    pub albedo: [Float; 3],
+    #[serde(default)]
+    pub anisotropy: Float,
+    // corners of the box it fills, everywhere when left out
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub inside: Option<[[Float; 3]; 2]>,
+}
+
+fn white() -> [Float; 3] {
+    [1.0, 1.0, 1.0]
+}
+
+#[derive(Clone, Deserialize, Serialize, Default)]
+#[serde(tag = "type", rename_all = "lowercase", deny_unknown_fields)]
+pub enum BackgroundDesc {
+    #[default]
+    Sky,
+    Color { color: [Float; 3] },
+    Gradient { bottom: [Float; 3], top: [Float; 3] },
+    // latitude-longitude image, see scene::Background::Map
+    Map { file: PathBuf },
+}
+
+// How the background lights the scene, see scene::Environment
+#[derive(Deserialize, Serialize, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct EnvironmentDesc {
+    // degrees about the up axis
+    #[serde(default)]
+    pub rotation: Float,
+    #[serde(default = "one")]
+    pub intensity: Float,
+    // seen by the camera, or only lighting the scene
+    #[serde(default = "yes")]
+    pub camera: bool,
+    #[serde(default = "yes")]
+    pub lighting: bool,
+    // photo seen behind the scene by the camera only
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub backdrop: Option<PathBuf>,
+}
+
+impl EnvironmentDesc {
+    fn is_default(&self) -> bool {
+        *self == EnvironmentDesc::default()
+    }
+}
+
+impl Default for EnvironmentDesc {
+    fn default() -> EnvironmentDesc {
+        EnvironmentDesc { rotation: 0.0, intensity: 1.0, camera: true, lighting: true, backdrop: None }
+    }
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "lowercase", deny_unknown_fields)]
+pub enum TextureDesc {
+    Solid { color: [Float; 3] },
+    Checker { even: [Float; 3], odd: [Float; 3], scale: Float },
+    // `wrap` as "sphere" for maps wrapped round spheres, see texture::Wrap
+    Image { file: PathBuf, #[serde(default, skip_serializing_if = "Wrap::is_clamp")] wrap: Wrap },
+    // see texture::UvChecker
+    UvChecker { #[serde(default = "default_uv_cells")] cells: u32 },
+    // ready-made procedural textures, see texture::Wood, texture::Brick and texture::Tiles; with
+    // nothing but their type they look like what they're named after
+    Wood {
+        #[serde(default = "wood_light")] light: [Float; 3],
+        #[serde(default = "wood_dark")] dark: [Float; 3],
+        #[serde(default = "wood_scale")] scale: Float,
+    },
+    Brick {
+        #[serde(default = "brick_color")] brick: [Float; 3],
+        #[serde(default = "mortar_color")] mortar: [Float; 3],
+        #[serde(default = "brick_rows")] rows: u32,
+    },
+    Tiles {
+        #[serde(default = "tile_even")] even: [Float; 3],
+        #[serde(default = "tile_odd")] odd: [Float; 3],
+        #[serde(default = "grout_color")] grout: [Float; 3],
+        #[serde(default = "default_uv_cells")] cells: u32,
+    },
+}
+
+fn default_uv_cells() -> u32 {
+    8
+}
+
+fn wood_light() -> [Float; 3] {
+    [0.72, 0.49, 0.28]
+}
+
+fn wood_dark() -> [Float; 3] {
+    [0.40, 0.22, 0.10]
+}
+
+fn wood_scale() -> Float {
+    0.15
+}
+
+fn brick_color() -> [Float; 3] {
+    [0.52, 0.19, 0.11]
+}
+
+fn mortar_color() -> [Float; 3] {
+    [0.70, 0.68, 0.64]
+}
+
+fn brick_rows() -> u32 {
+    16
+}
+
+fn tile_even() -> [Float; 3] {
+    [0.85, 0.85, 0.82]
+}
+
+fn tile_odd() -> [Float; 3] {
+    [0.08, 0.08, 0.09]
+}
+
+fn grout_color() -> [Float; 3] {
+    [0.45, 0.44, 0.42]
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "lowercase", deny_unknown_fields)]
+pub enum MaterialDesc {
+    // either a constant albedo or the name of a texture, which uv can lay out differently
+    // from the object's UVs, see texture::UvTransform
+    Lambertian {
+        #[serde(skip_serializing_if = "Option::is_none")] albedo: Option<[Float; 3]>,
+        #[serde(skip_serializing_if = "Option::is_none")] texture: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")] uv: Option<UvTransform>,
+    },
+    Metal { albedo: [Float; 3], #[serde(default)] fuzz: Float },
+    Dielectric { ir: Float },
+    // with a temperature the color of a black body at that many kelvin, times the color
+    Light {
+        #[serde(default = "white")] color: [Float; 3],
+        #[serde(default = "one")] intensity: Float,
+        #[serde(skip_serializing_if = "Option::is_none")] temperature: Option<Float>,
+    },
+    // two other materials by name, `a` where the mask is 0 and `b` where it's 1
+    Mix { a: String, b: String, mask: MaskDesc },
+    // only there for shadows, see material::ShadowCatcher
+    ShadowCatcher { distance: Float, #[serde(default = "dirt_samples")] samples: u32 },
+}
+
+// What a mix material blends by, see material::Mask and dirt::Dirt
+#[derive(Clone, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "lowercase", deny_unknown_fields)]
+pub enum MaskDesc {
+    Texture { texture: String, #[serde(default, skip_serializing_if = "Option::is_none")] uv: Option<UvTransform> },
+    Crevices { distance: Float, #[serde(default = "dirt_samples")] samples: u32 },
+    Edges { distance: Float, #[serde(default = "dirt_samples")] samples: u32 },
+}
+
+fn dirt_samples() -> u32 {
+    8
+}
+
+fn one() -> Float {
+    1.0
+}
+
+fn yes() -> bool {
+    true
+}
+
+// Any object can be animated with keyframes, see animation::Keyframe, and hidden from all
+// rays but those in the trace groups listed in visible_to. Names are for picking objects out
+// with overrides (see overrides::Override) and instances, hidden objects aren't rendered.
+#[derive(Clone, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "lowercase", deny_unknown_fields)]
+pub enum ObjectDesc {
+    Sphere {
+        #[serde(skip_serializing_if = "Option::is_none")] name: Option<String>,
+        center: [Float; 3],
+        radius: Float,
+        material: String,
+        #[serde(default, skip_serializing_if = "is_false")] hidden: bool,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")] keyframes: Vec<Keyframe>,
+        #[serde(skip_serializing_if = "Option::is_none")] visible_to: Option<Vec<String>>,
+    },
+    // a hollow sphere, inner below outer
+    Shell {
+        #[serde(skip_serializing_if = "Option::is_none")] name: Option<String>,
+        center: [Float; 3],
+        outer: Float,
+        inner: Float,
+        material: String,
+        #[serde(default, skip_serializing_if = "is_false")] hidden: bool,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")] keyframes: Vec<Keyframe>,
+        #[serde(skip_serializing_if = "Option::is_none")] visible_to: Option<Vec<String>>,
+    },
+    // uv are the texture coordinates of the vertices, (0, 0), (1, 0) and (0, 1) when left out
+    Triangle {
+        #[serde(skip_serializing_if = "Option::is_none")] name: Option<String>,
+        vertices: [[Float; 3]; 3],
+        #[serde(skip_serializing_if = "Option::is_none")] uv: Option<[[Float; 2]; 3]>,
+        material: String,
+        #[serde(default, skip_serializing_if = "is_false")] hidden: bool,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")] keyframes: Vec<Keyframe>,
+        #[serde(skip_serializing_if = "Option::is_none")] visible_to: Option<Vec<String>>,
+    },
+    // The transform is a row-major 4x4 matrix applied to the vertices. A model from a program
+    // with z up or other units is turned and scaled into this scene's before that, by `up` and
+    // `unit_scale` (see transform::Up).
+    Mesh {
+        #[serde(skip_serializing_if = "Option::is_none")] name: Option<String>,
+        file: PathBuf,
+        material: String,
+        #[serde(skip_serializing_if = "Option::is_none")] transform: Option<Matrix>,
+        #[serde(default, skip_serializing_if = "Up::is_y")] up: Up,
+        #[serde(skip_serializing_if = "Option::is_none")] unit_scale: Option<Float>,
+        #[serde(default, skip_serializing_if = "is_false")] hidden: bool,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")] keyframes: Vec<Keyframe>,
+        #[serde(skip_serializing_if = "Option::is_none")] visible_to: Option<Vec<String>>,
+    },
+    // Objects that go together, placed by the transform like a mesh. Named groups can be
+    // copied by instances further down the file, an instance takes the last group of its name
+    // before it. A hidden group is only there for the instances. With an end_transform the
+    // group moves from the transform to it while the shutter is open, see animation::Motion.
+    Group {
+        #[serde(skip_serializing_if = "Option::is_none")] name: Option<String>,
+        objects: Vec<ObjectDesc>,
+        #[serde(skip_serializing_if = "Option::is_none")] transform: Option<Matrix>,
+        #[serde(skip_serializing_if = "Option::is_none")] end_transform: Option<Matrix>,
+        #[serde(default, skip_serializing_if = "is_false")] hidden: bool,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")] keyframes: Vec<Keyframe>,
+        #[serde(skip_serializing_if = "Option::is_none")] visible_to: Option<Vec<String>>,
+    },
+    // A Menger sponge, sphereflake or Mandelbulb `depth` levels deep, see fractal::Fractal
+    // for what the size is for each of them
+    Fractal {
+        #[serde(skip_serializing_if = "Option::is_none")] name: Option<String>,
+        shape: FractalKind,
+        depth: u32,
+        center: [Float; 3],
+        size: Float,
+        material: String,
+        #[serde(default, skip_serializing_if = "is_false")] hidden: bool,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")] keyframes: Vec<Keyframe>,
+        #[serde(skip_serializing_if = "Option::is_none")] visible_to: Option<Vec<String>>,
+    },
+    // Smoke or fire from the float grid called `grid` in an OpenVDB file, see volume::Volume.
+    // The density is how much light is lost per scene unit where the grid is 1 and the albedo
+    // how much of that is scattered. With a temperature grid it glows like a black body at
+    // `kelvin` times the grid, `emission` times as bright. The transform places it like a
+    // mesh, from where the file has it.
+    Volume {
+        #[serde(skip_serializing_if = "Option::is_none")] name: Option<String>,
+        file: PathBuf,
+        #[serde(default = "density_grid")] grid: String,
+        #[serde(skip_serializing_if = "Option::is_none")] temperature: Option<String>,
+        #[serde(default = "one")] density: Float,
+        #[serde(default = "white")] albedo: [Float; 3],
+        #[serde(default = "one")] emission: Float,
+        #[serde(default = "default_kelvin")] kelvin: Float,
+        #[serde(skip_serializing_if = "Option::is_none")] transform: Option<Matrix>,
+        #[serde(default, skip_serializing_if = "is_false")] hidden: bool,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")] keyframes: Vec<Keyframe>,
+        #[serde(skip_serializing_if = "Option::is_none")] visible_to: Option<Vec<String>>,
+    },
+    // the objects of the group named `of` once more, placed by the transform alone, or a
+    // simpler stand-in when it's far from the camera (see LodDesc). Like groups it can move
+    // to an end_transform while the shutter is open.
+    Instance {
+        #[serde(skip_serializing_if = "Option::is_none")] name: Option<String>,
+        of: String,
+        #[serde(skip_serializing_if = "Option::is_none")] transform: Option<Matrix>,
+        #[serde(skip_serializing_if = "Option::is_none")] end_transform: Option<Matrix>,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")] lod: Vec<LodDesc>,
+        #[serde(default, skip_serializing_if = "is_false")] hidden: bool,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")] keyframes: Vec<Keyframe>,
+        #[serde(skip_serializing_if = "Option::is_none")] visible_to: Option<Vec<String>>,
+    },
+    // `count` copies of the group named `of` spread over the object named `on`, a sphere,
+    // shell, triangle or mesh in the same list (it can be hidden), or through the box between
+    // the two corners of `inside`, see instancer::Instancer. Copies are scaled by a random
+    // amount between the two of `scale` and turned by up to `rotation` degrees, and the
+    // density texture makes them gather where it's bright. Far copies can have stand-ins like
+    // instances.
+    Scatter {
+        #[serde(skip_serializing_if = "Option::is_none")] name: Option<String>,
+        of: String,
+        #[serde(skip_serializing_if = "Option::is_none")] on: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")] inside: Option<[[Float; 3]; 2]>,
+        count: u32,
+        #[serde(default)] seed: u64,
+        #[serde(default = "unscaled")] scale: [Float; 2],
+        #[serde(default)] rotation: Float,
+        #[serde(default, skip_serializing_if = "is_false")] align: bool,
+        #[serde(skip_serializing_if = "Option::is_none")] density: Option<String>,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")] lod: Vec<LodDesc>,
+        #[serde(default, skip_serializing_if = "is_false")] hidden: bool,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")] keyframes: Vec<Keyframe>,
+        #[serde(skip_serializing_if = "Option::is_none")] visible_to: Option<Vec<String>>,
+    },
+    // `count` random spheres through the box between the corners of `inside`, a benchmark
+    // scene of any size from a few lines (see instancer::SphereField). Radii are picked evenly
+    // between the two of `radius` and materials by their weights, e.g. { glass = 1, red = 4 }.
+    // With `clusters` the spheres gather around that many points, `spread` apart from them;
+    // `resting` ones sit on the bottom of the box. The seed decides everything, so the same
+    // node always gives the same spheres.
+    Spheres {
+        #[serde(skip_serializing_if = "Option::is_none")] name: Option<String>,
+        count: u32,
+        #[serde(default)] seed: u64,
+        inside: [[Float; 3]; 2],
+        radius: [Float; 2],
+        #[serde(serialize_with = "sorted")] materials: HashMap<String, Float>,
+        #[serde(skip_serializing_if = "Option::is_none")] clusters: Option<u32>,
+        #[serde(default = "one")] spread: Float,
+        #[serde(default, skip_serializing_if = "is_false")] resting: bool,
+        #[serde(default, skip_serializing_if = "is_false")] hidden: bool,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")] keyframes: Vec<Keyframe>,
+        #[serde(skip_serializing_if = "Option::is_none")] visible_to: Option<Vec<String>>,
+    },
+}
+
+// A level of detail of an instance or scattered copy: from `distance` away from the camera on,
+// the group named `of` is drawn in its place, or a sphere of the `impostor` material around
+// it. The farthest level the copy is past counts. The distance is from the camera's lookfrom
+// in the scene file to the copy's origin, without keyframes.
+//
+// The level is picked once per copy when the scene is built, not per ray. Every camera ray
+// starts at the camera, so it would pick the same level anyway, and this way the reflections
+// and shadows of a copy are of the level the camera sees rather than of whatever level the
+// bounce happens to be near. A camera that moves over the shutter or the frames keeps the
+// levels of where the scene file puts it.
+// Copies inside groups get their level where the group is in the scene file.
+#[derive(Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct LodDesc {
+    pub distance: Float,
+    #[serde(skip_serializing_if = "Option::is_none")] pub of: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")] pub impostor: Option<String>,
+}
+
+impl LodDesc {
+    // the level for a copy `distance` away, None for the full one
+    pub fn pick(levels: &[LodDesc], distance: Float) -> Option<&LodDesc> {
+        levels.iter()
+            .filter(|level| level.distance <= distance)
+            .max_by(|a, b| a.distance.total_cmp(&b.distance))
+    }
+}
+
+fn density_grid() -> String {
+    "density".to_string()
+}
+
+fn default_kelvin() -> Float {
+    1000.0
+}
+
+fn unscaled() -> [Float; 2] {
+    [1.0, 1.0]
+}
+
+fn is_false(b: &bool) -> bool {
+    !b
+}
+
+// Clipping planes, see clip::ClipPlane. A camera plane faces the camera `distance` in front of
+// it and cuts away everything closer, wherever the camera is looking.
+#[derive(Clone, Deserialize, Serialize, PartialEq)]
+#[serde(tag = "type", rename_all = "lowercase", deny_unknown_fields)]
+pub enum ClipDesc {
+    // everything on the side `normal` points to goes
+    Plane { point: [Float; 3], normal: [Float; 3], #[serde(default)] cap: bool },
+    Camera { distance: Float, #[serde(default)] cap: bool },
+}
+
+impl ObjectDesc {
+    pub fn keyframes(&self) -> &[Keyframe] {
+        match self {
+            ObjectDesc::Sphere { keyframes, .. }
+            | ObjectDesc::Shell { keyframes, .. }
+            | ObjectDesc::Triangle { keyframes, .. }
+            | ObjectDesc::Mesh { keyframes, .. }
+            | ObjectDesc::Fractal { keyframes, .. }
+            | ObjectDesc::Volume { keyframes, .. }
+            | ObjectDesc::Group { keyframes, .. }
+            | ObjectDesc::Instance { keyframes, .. }
+            | ObjectDesc::Scatter { keyframes, .. }
+            | ObjectDesc::Spheres { keyframes, .. } => keyframes,
+        }
+    }
+
+    pub fn name(&self) -> Option<&str> {
+        match self {
+            ObjectDesc::Sphere { name, .. }
+            | ObjectDesc::Shell { name, .. }
+            | ObjectDesc::Triangle { name, .. }
+            | ObjectDesc::Mesh { name, .. }
+            | ObjectDesc::Fractal { name, .. }
+            | ObjectDesc::Volume { name, .. }
+            | ObjectDesc::Group { name, .. }
+            | ObjectDesc::Instance { name, .. }
+            | ObjectDesc::Scatter { name, .. }
+            | ObjectDesc::Spheres { name, .. } => name.as_deref(),
+        }
+    }
+
+    pub fn hidden(&self) -> bool {
+        match self {
+            ObjectDesc::Sphere { hidden, .. }
+            | ObjectDesc::Shell { hidden, .. }
+            | ObjectDesc::Triangle { hidden, .. }
+            | ObjectDesc::Mesh { hidden, .. }
+            | ObjectDesc::Fractal { hidden, .. }
+            | ObjectDesc::Volume { hidden, .. }
+            | ObjectDesc::Group { hidden, .. }
+            | ObjectDesc::Instance { hidden, .. }
+            | ObjectDesc::Scatter { hidden, .. }
+            | ObjectDesc::Spheres { hidden, .. } => *hidden,
+        }
+    }
+
+    // None when every ray sees the object
+    pub fn visible_to(&self) -> Option<&[String]> {
+        match self {
+            ObjectDesc::Sphere { visible_to, .. }
+            | ObjectDesc::Shell { visible_to, .. }
+            | ObjectDesc::Triangle { visible_to, .. }
+            | ObjectDesc::Mesh { visible_to, .. }
+            | ObjectDesc::Fractal { visible_to, .. }
+            | ObjectDesc::Volume { visible_to, .. }
+            | ObjectDesc::Group { visible_to, .. }
+            | ObjectDesc::Instance { visible_to, .. }
+            | ObjectDesc::Scatter { visible_to, .. }
+            | ObjectDesc::Spheres { visible_to, .. } => visible_to.as_deref(),
+        }
+    }
+}
+
+// Just the textures and materials of a scene file, for previewing materials on their own. A
+// whole scene file reads as one too, everything but the two sections is skipped.
+#[derive(Deserialize)]
+pub struct MaterialLibrary {
+    #[serde(default)]
+    pub textures: HashMap<String, TextureDesc>,
+    #[serde(default)]
+    pub materials: HashMap<String, MaterialDesc>,
+
+    // directory of the file, image textures are found from there
+    #[serde(skip)]
+    pub base_dir: PathBuf,
+}
+
+impl MaterialLibrary {
+    // .json or .toml, picked from the extension, checked like a scene file
+    pub fn load(path: &Path) -> Result<MaterialLibrary> {
+        let text = std::fs::read_to_string(path).map_err(|e| RendererError::io(path, e))?;
+        let (mut library, lines): (MaterialLibrary, _) = if path.extension().is_some_and(|e| e == "json") {
+            (serde_json::from_str(&text).map_err(|e| RendererError::parse(path, e))?, HashMap::new())
+        } else {
+            (toml::from_str(&text).map_err(|e| RendererError::parse(path, e))?, validate::toml_lines(&text))
+        };
+        library.base_dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+
+        let mut problems = validate::check_library(&library);
+        if problems.is_empty() {
+            return Ok(library);
+        }
+        problems.sort_by_key(|p| lines.get(&p.at).copied().unwrap_or(0));
+        Err(RendererError::Invalid {
+            path: path.to_path_buf(),
+            problems: problems.iter().map(|p| p.describe(&lines)).collect(),
+        })
+    }
+
+    pub fn build(&self) -> Result<HashMap<String, Arc<dyn Scatter>>> {
+        build_materials(&self.textures, &self.materials, &self.base_dir, WorkingSpace::Srgb)
+    }
+}
+
+// the scene if nothing is wrong with it, otherwise every problem found, in file order when the
+// lines are known
+fn checked(scene: SceneFile, path: &Path, lines: &HashMap<String, usize>) -> Result<SceneFile> {
+    let mut problems = validate::check(&scene);
+    if problems.is_empty() {
+        return Ok(scene);
+    }
+    problems.sort_by_key(|p| lines.get(&p.at).copied().unwrap_or(0));
+    Err(RendererError::Invalid {
+        path: path.to_path_buf(),
+        problems: problems.iter().map(|p| p.describe(lines)).collect(),
+    })
+}
+
+// The materials by name, with the textures they use loaded. Image paths start from `base_dir`,
+// images and black bodies are turned into the working space.
+pub(crate) fn build_texture(desc: &TextureDesc, base_dir: &Path, space: WorkingSpace) -> Result<Arc<dyn Texture>> {
+    Ok(match desc {
+        TextureDesc::Solid { color } => Arc::new(SolidColor::new(vec3(*color))),
+        TextureDesc::Checker { even, odd, scale } => Arc::new(Checker::new(vec3(*even), vec3(*odd), *scale)),
+        TextureDesc::Image { file, wrap } => Arc::new(ImageTexture::load(&base_dir.join(file), space)?.with_wrap(*wrap)),
+        TextureDesc::UvChecker { cells } => Arc::new(UvChecker::new(*cells)),
+        TextureDesc::Wood { light, dark, scale } => Arc::new(Wood::new(vec3(*light), vec3(*dark), *scale)),
+        TextureDesc::Brick { brick, mortar, rows } => Arc::new(Brick::new(vec3(*brick), vec3(*mortar), *rows)),
+        TextureDesc::Tiles { even, odd, grout, cells } => Arc::new(Tiles::new(vec3(*even), vec3(*odd), vec3(*grout), *cells)),
+    })
+}
+
+pub(crate) fn build_materials(texture_descs: &HashMap<String, TextureDesc>, material_descs: &HashMap<String, MaterialDesc>,
+                              base_dir: &Path, space: WorkingSpace) -> Result<HashMap<String, Arc<dyn Scatter>>> {
+    let mut textures: HashMap<&str, Arc<dyn Texture>> = HashMap::new();
+    for (name, desc) in texture_descs {
+        textures.insert(name.as_str(), build_texture(desc, base_dir, space)?);
+    }
+
+    let mut materials: HashMap<String, Arc<dyn Scatter>> = HashMap::new();
+    for (name, desc) in material_descs {
+        let material: Arc<dyn Scatter> = match desc {
+            MaterialDesc::Lambertian { albedo, texture, uv } => match (albedo, texture) {
+                (Some(a), None) => Arc::new(Lambertian::new(vec3(*a))),
+                (None, Some(t)) => {
+                    let t = textures.get(t.as_str())
+                        .ok_or_else(|| RendererError::Scene(format!("material '{}': unknown texture '{}'", name, t)))?;
+                    match uv {
+                        Some(uv) => Arc::new(Lambertian::textured(t.clone()).with_uv(*uv)),
+                        None => Arc::new(Lambertian::textured(t.clone())),
+                    }
+                }
+                _ => return Err(RendererError::Scene(format!("material '{}': give either an albedo or a texture", name))),
+            },
+            MaterialDesc::Metal { albedo, fuzz } => Arc::new(Metal::new(vec3(*albedo), *fuzz)),
+            MaterialDesc::Dielectric { ir } => Arc::new(Dielectric::new(*ir)),
+            MaterialDesc::Light { color, intensity, temperature } => {
+                let glow = temperature.map_or(Vec3::new(1.0, 1.0, 1.0), |t| space.from_srgb(blackbody::color(t)));
+                Arc::new(DiffuseLight::new(*intensity * vec3(*color) * glow))
+            }
+            MaterialDesc::ShadowCatcher { distance, samples } => Arc::new(ShadowCatcher::new(*distance, *samples)),
+            MaterialDesc::Mix { .. } => continue,
+        };
+        materials.insert(name.clone(), material);
+    }
+
+    // mixes once the materials they're made of are there, which may be mixes themselves
+    let mut mixes: Vec<(&String, &String, &String, &MaskDesc)> = material_descs.iter()
+        .filter_map(|(name, desc)| match desc {
+            MaterialDesc::Mix { a, b, mask } => Some((name, a, b, mask)),
+            _ => None,
+        })
+        .collect();
+    while !mixes.is_empty() {
+        let before = mixes.len();
+        let mut waiting = Vec::new();
+        for (name, a, b, mask) in mixes {
+            let (Some(ma), Some(mb)) = (materials.get(a), materials.get(b)) else {
+                waiting.push((name, a, b, mask));
+                continue;
+            };
+            let mask = match mask {
+                MaskDesc::Texture { texture, uv } => Mask::Texture(textures.get(texture.as_str()).cloned()
+                    .ok_or_else(|| RendererError::Scene(format!("material '{}': unknown texture '{}'", name, texture)))?, *uv),
+                MaskDesc::Crevices { distance, samples } => Mask::Dirt(Dirt::new(DirtKind::Crevices, *distance, *samples)),
+                MaskDesc::Edges { distance, samples } => Mask::Dirt(Dirt::new(DirtKind::Edges, *distance, *samples)),
+            };
+            let mix = Arc::new(Mix::new(ma.clone(), mb.clone(), mask));
+            materials.insert(name.clone(), mix);
+        }
+        if waiting.len() == before {
+            let mut names: Vec<&str> = waiting.iter().map(|(name, ..)| name.as_str()).collect();
+            names.sort();
+            return Err(RendererError::Scene(format!("materials {}: mixed from unknown materials or from each other", names.join(", "))));
+        }
+        mixes = waiting;
+    }
+    Ok(materials)
+}
+
+// What building the objects needs besides the scene file, see SceneFile::build
+struct Build<'a> {
+    file: &'a SceneFile,
+    materials: HashMap<String, Arc<dyn Scatter>>,
+    trace_groups: TraceGroups,
+    // the named groups so far, for the instances
+    groups: HashMap<String, Group>,
+    // spheres standing in for far copies, by group and material
+    impostors: HashMap<(String, String), Group>,
+    // of every named group in its own space, measured once the first impostor needs them
+    bounds: Option<HashMap<String, Aabb>>,
+    // when the camera's shutter first opens and last closes
+    shutter: (Float, Float),
+}
+
+impl Build<'_> {
+    fn material(&self, name: &str) -> Result<Arc<dyn Scatter>> {
+        self.materials.get(name)
+            .cloned()
+            .ok_or_else(|| RendererError::Scene(format!("unknown material '{}'", name)))
+    }
+
+    // A copy of the group named `of` placed by `transform`, or of its stand-in for the
+    // level of detail at its distance from the camera, see LodDesc. `outer` takes the list
+    // it's in to the scene's space.
+    fn copy(&mut self, of: &str, lod: &[LodDesc], transform: Option<&Matrix>, outer: &Matrix) -> Result<Group> {
+        let world = transform::mul(outer, transform.unwrap_or(&transform::IDENTITY));
+        let distance = (transform::point(&world, Point3::new(0.0, 0.0, 0.0)) - vec3(self.file.camera.lookfrom)).length();
+        let group = match LodDesc::pick(lod, distance) {
+            None => self.group(of)?,
+            Some(LodDesc { of: Some(other), .. }) => self.group(other)?,
+            Some(LodDesc { impostor: Some(material), .. }) => self.impostor(of, material)?,
+            Some(_) => return Err(RendererError::Scene(format!("a level of detail of '{}' has neither a group nor an impostor", of))),
+        };
+        Ok(group.instance(transform.map(placement).transpose()?))
+    }
+
+    // from `start` (or where the group is) to `end` over the shutter
+    fn motion(&self, start: Option<&Matrix>, end: &Matrix) -> Result<Motion> {
+        let (open, close) = self.shutter;
+        Motion::new(*start.unwrap_or(&transform::IDENTITY), *end, open, close)
+            .ok_or_else(|| RendererError::Scene(format!("transform {:?} can't be inverted", end)))
+    }
+
+    fn group(&self, name: &str) -> Result<Group> {
+        self.groups.get(name)
+            .cloned()
+            .ok_or_else(|| RendererError::Scene(format!("no group named '{}' before the instance", name)))
+    }
+
+    // the sphere around the group's objects, in the group's space
+    fn impostor(&mut self, of: &str, material: &str) -> Result<Group> {
+        let key = (of.to_string(), material.to_string());
+        if let Some(group) = self.impostors.get(&key) {
+            return Ok(group.clone());
+        }
+        if self.bounds.is_none() {
+            self.bounds = Some(inspect::group_bounds(self.file)?);
+        }
+        let Aabb { min: lo, max: hi } = self.bounds.as_ref().and_then(|bounds| bounds.get(of)).copied()
+            .ok_or_else(|| RendererError::Scene(format!("group '{}' is empty, there's nothing for an impostor to stand in for", of)))?;
+        let mut objects = HittableList::new();
+        objects.push(Arc::new(Sphere::new(0.5 * (lo + hi), 0.5 * (hi - lo).length(), self.material(material)?)));
+        let group = Group::new(objects);
+        self.impostors.insert(key, group.clone());
+        Ok(group)
+    }
+
+    // the objects in file order, into the groups they're in; `outer` takes them to the
+    // scene's space
+    fn add_objects(&mut self, mut builder: SceneBuilder, descs: &[ObjectDesc], outer: &Matrix) -> Result<SceneBuilder> {
+        for desc in descs {
+            // hidden groups are still built, for their instances
+            if desc.hidden() && !matches!(desc, ObjectDesc::Group { .. }) {
+                continue;
+            }
+            let visibility = desc.visible_to().map(|names| self.trace_groups.visibility(names)).transpose()?;
+            builder = match desc {
+                ObjectDesc::Sphere { center, radius, material: m, keyframes, .. } => {
+                    let sphere = animate(builder.add_sphere(vec3(*center), *radius), keyframes);
+                    hide(sphere, visibility).with_material(self.material(m)?)
+                }
+                ObjectDesc::Shell { center, outer, inner, material: m, keyframes, .. } => {
+                    let shell = animate(builder.add_shell(vec3(*center), *outer, *inner), keyframes);
+                    hide(shell, visibility).with_material(self.material(m)?)
+                }
+                ObjectDesc::Triangle { vertices: v, uv, material: m, keyframes, .. } => {
+                    let uv = uv.unwrap_or([[0.0, 0.0], [1.0, 0.0], [0.0, 1.0]]).map(|[u, v]| (u, v));
+                    let triangle = builder.add_triangle(vec3(v[0]), vec3(v[1]), vec3(v[2])).with_uv(uv);
+                    hide(animate(triangle, keyframes), visibility).with_material(self.material(m)?)
+                }
+                ObjectDesc::Mesh { file, material: m, transform, up, unit_scale, keyframes, .. } => {
+                    let mut mesh = Mesh::load_obj(&self.file.base_dir.join(file), self.material(m)?)?;
+                    if let Some(t) = mesh_transform(transform.as_ref(), *up, *unit_scale) {
+                        mesh = mesh.transformed(&t);
+                    }
+                    builder.add_object(wrap(Arc::new(mesh), keyframes, visibility))
+                }
+                ObjectDesc::Fractal { shape, depth, center, size, material: m, keyframes, .. } => {
+                    let fractal = Fractal::new(*shape, *depth, vec3(*center), *size, self.material(m)?);
+                    builder.add_object(wrap(Arc::new(fractal), keyframes, visibility))
+                }
+                ObjectDesc::Volume { file, grid, temperature, density, albedo, emission, kelvin, transform, keyframes, .. } => {
+                    let path = self.file.base_dir.join(file);
+                    let mut volume = Volume::new(&path, Grid::load(&path, grid)?, *density, vec3(*albedo));
+                    if let Some(t) = transform {
+                        volume = volume.with_transform(t)?;
+                    }
+                    if let Some(name) = temperature {
+                        volume = volume.with_fire(Grid::load(&path, name)?, *emission, *kelvin, self.file.render.working_space.unwrap_or_default())?;
+                    }
+                    builder.add_object(wrap(Arc::new(volume), keyframes, visibility))
+                }
+                ObjectDesc::Group { name, objects, transform, end_transform, hidden, keyframes, .. } => {
+                    let inside = transform::mul(outer, &transform.unwrap_or(transform::IDENTITY));
+                    let mut group = Group::new(self.add_objects(SceneBuilder::new(), objects, &inside)?.build_objects()?);
+                    if let Some(t) = transform {
+                        group = group.with_transform(placement(t)?);
+                    }
+                    if let Some(end) = end_transform {
+                        group = group.with_motion(self.motion(transform.as_ref(), end)?);
+                    }
+                    if let Some(name) = name {
+                        group = group.with_name(name);
+                        self.groups.insert(name.clone(), group.clone());
+                    }
+                    if *hidden {
+                        builder
+                    } else {
+                        builder.add_object(wrap(Arc::new(group), keyframes, visibility))
+                    }
+                }
+                ObjectDesc::Instance { of, transform, end_transform, lod, keyframes, .. } => {
+                    let mut instance = self.copy(of, lod, transform.as_ref(), outer)?;
+                    if let Some(end) = end_transform {
+                        instance = instance.with_motion(self.motion(transform.as_ref(), end)?);
+                    }
+                    builder.add_object(wrap(Arc::new(instance), keyframes, visibility))
+                }
+                ObjectDesc::Scatter { of, lod, keyframes, .. } => {
+                    if !self.groups.contains_key(of) {
+                        return Err(RendererError::Scene(format!("no group named '{}' before the scatter", of)));
+                    }
+                    let mut copies = HittableList::new();
+                    for m in instancer::scatter(self.file, descs, desc)? {
+                        copies.push(Arc::new(self.copy(of, lod, Some(&m), outer)?));
+                    }
+                    builder.add_object(wrap(Arc::new(Group::new(copies)), keyframes, visibility))
+                }
+                ObjectDesc::Spheres { keyframes, .. } => {
+                    let mut spheres = HittableList::new();
+                    for (center, radius, m) in instancer::sphere_field(desc) {
+                        spheres.push(Arc::new(Sphere::new(center, radius, self.material(m)?)));
+                    }
+                    builder.add_object(wrap(Arc::new(Group::new(spheres)), keyframes, visibility))
+                }
+            };
+        }
+        Ok(builder)
+    }
+}
+
+// what a mesh's vertices go through, None when they stay where the file has them
+pub(crate) fn mesh_transform(transform: Option<&Matrix>, up: Up, unit_scale: Option<Float>) -> Option<Matrix> {
+    let conversion = (!up.is_y() || unit_scale.is_some()).then(|| up.conversion(unit_scale.unwrap_or(1.0)));
+    match (transform, conversion) {
+        (Some(t), Some(c)) => Some(transform::mul(t, &c)),
+        (t, c) => t.copied().or(c),
+    }
+}
+
+fn placement(m: &Matrix) -> Result<Transform> {
+    Transform::new(*m).ok_or_else(|| RendererError::Scene(format!("transform {:?} can't be inverted", m)))
+}
+
+// objects that aren't built through an ObjectBuilder, animated and hidden all the same
+fn wrap(mut object: Arc<dyn Hit>, keyframes: &[Keyframe], visibility: Option<Visibility>) -> Arc<dyn Hit> {
+    if !keyframes.is_empty() {
+        object = Arc::new(Animated::new(object, Track::new(keyframes.to_vec())));
+    }
+    if let Some(visibility) = visibility {
+        object = Arc::new(Visible::new(object, visibility));
+    }
+    object
+}
+
+// objects without keyframes stay as they are
+fn animate(object: ObjectBuilder, keyframes: &[Keyframe]) -> ObjectBuilder {
+    if keyframes.is_empty() {
+        object
+    } else {
+        object.with_animation(Track::new(keyframes.to_vec()))
+    }
+}
+
+// objects seen by every ray stay as they are
+fn hide(object: ObjectBuilder, visibility: Option<Visibility>) -> ObjectBuilder {
+    match visibility {
+        Some(v) => object.with_visibility(v),
+        None => object,
+    }
+}
+
+fn vec3(v: [Float; 3]) -> Vec3 {
+    Vec3::new(v[0], v[1], v[2])
+}
+
+// so saved scenes list materials and textures in the same order every time
+fn sorted<S: Serializer, T: Serialize>(map: &HashMap<String, T>, s: S) -> std::result::Result<S::Ok, S::Error> {
+    sorted_entries(map).serialize(s)
+}
+
+fn sorted_entries<T>(map: &HashMap<String, T>) -> BTreeMap<&String, &T> {
+    map.iter().collect()
+}
+
+// Collects a built scene back into a SceneFile. Objects, materials and textures describe
+// themselves through their export methods; a material or texture shared by several objects
+// is written once and referenced by name.
+pub struct Exporter {
+    file: SceneFile,
+    material_names: HashMap<*const (), String>,
+    texture_names: HashMap<*const (), String>,
+}
+
+impl Exporter {
+    // name of the material in the scene file, adding it on first use
+    pub fn material(&mut self, m: &Arc<dyn Scatter>) -> Result<String> {
+        let key = Arc::as_ptr(m) as *const ();
+        if let Some(name) = self.material_names.get(&key) {
+            return Ok(name.clone());
+        }
+        let desc = m.export(self)?;
+        let name = format!("material{:03}", self.file.materials.len());
+        self.file.materials.insert(name.clone(), desc);
+        if let Some(group) = m.trace_group() {
+            self.file.ray_groups.entry(group.to_string()).or_default().push(name.clone());
+        }
+        self.material_names.insert(key, name.clone());
+        Ok(name)
+    }
+
+    pub fn texture(&mut self, t: &Arc<dyn Texture>) -> Result<String> {
+        let key = Arc::as_ptr(t) as *const ();
+        if let Some(name) = self.texture_names.get(&key) {
+            return Ok(name.clone());
+        }
+        let desc = t.export()?;
+        let name = format!("texture{:03}", self.file.textures.len());
+        self.file.textures.insert(name.clone(), desc);
+        self.texture_names.insert(key, name.clone());
+        Ok(name)
+    }
+
+    pub fn object(&mut self, desc: ObjectDesc) {
+        self.file.objects.push(desc);
+    }
+
+    // planes shared by several objects are written once
+    pub fn clip(&mut self, desc: ClipDesc) {
+        if !self.file.clip.contains(&desc) {
+            self.file.clip.push(desc);
+        }
+    }
+
+    // the objects added since `first`, taken out again to go into a group
+    pub fn take_since(&mut self, first: usize) -> Vec<ObjectDesc> {
+        self.file.objects.split_off(first)
+    }
+
+    pub fn object_count(&self) -> usize {
+        self.file.objects.len()
+    }
+
+    // gives the objects added since `first` these keyframes
+    pub fn animate_since(&mut self, first: usize, keys: &[Keyframe]) {
+        for object in &mut self.file.objects[first..] {
+            match object {
+                ObjectDesc::Sphere { keyframes, .. }
+                | ObjectDesc::Shell { keyframes, .. }
+                | ObjectDesc::Triangle { keyframes, .. }
+                | ObjectDesc::Mesh { keyframes, .. }
+                | ObjectDesc::Fractal { keyframes, .. }
+                | ObjectDesc::Volume { keyframes, .. }
+                | ObjectDesc::Group { keyframes, .. }
+                | ObjectDesc::Instance { keyframes, .. }
+                | ObjectDesc::Scatter { keyframes, .. }
+                | ObjectDesc::Spheres { keyframes, .. } => *keyframes = keys.to_vec(),
+            }
+        }
+    }
+
+    // makes the objects added since `first` visible to these trace groups only
+    pub fn visible_since(&mut self, first: usize, groups: &[String]) {
+        for object in &mut self.file.objects[first..] {
+            match object {
+                ObjectDesc::Sphere { visible_to, .. }
+                | ObjectDesc::Shell { visible_to, .. }
+                | ObjectDesc::Triangle { visible_to, .. }
+                | ObjectDesc::Mesh { visible_to, .. }
+                | ObjectDesc::Fractal { visible_to, .. }
+                | ObjectDesc::Volume { visible_to, .. }
+                | ObjectDesc::Group { visible_to, .. }
+                | ObjectDesc::Instance { visible_to, .. }
+                | ObjectDesc::Scatter { visible_to, .. }
+                | ObjectDesc::Spheres { visible_to, .. } => *visible_to = Some(groups.to_vec()),
+            }
+        }
+    }
+}
+
+impl SceneFile {
+    // .toml, .json, .pbrt or Mitsuba .xml, picked from the extension. The scene is checked
+    // before it's returned, every problem found ends up in the error.
+    pub fn load(path: &Path) -> Result<SceneFile> {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("pbrt") => checked(crate::pbrt::load(path)?, path, &HashMap::new()),
+            Some("xml") => checked(crate::mitsuba::load(path)?, path, &HashMap::new()),
+            ext => {
+                let text = std::fs::read_to_string(path).map_err(|e| RendererError::io(path, e))?;
+                SceneFile::parse(&text, ext == Some("json"), path)
+            }
+        }
+    }
+
+    // A TOML or JSON scene that's already in memory. `path` names it in errors, relative file
+    // names in the scene start from its directory.
+    pub fn parse(text: &str, json: bool, path: &Path) -> Result<SceneFile> {
+        let (mut scene, lines): (SceneFile, _) = if json {
+            (serde_json::from_str(text).map_err(|e| RendererError::parse(path, e))?, HashMap::new())
+        } else {
+            (toml::from_str(text).map_err(|e| RendererError::parse(path, e))?, validate::toml_lines(text))
+        };
+        scene.base_dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+        checked(scene, path, &lines)
+    }
+
+    // The scene as a scene file, for saving built-in and imported scenes. Everything in the
+    // world has to support export; meshes come out as separate triangles.
+    pub fn from_scene(scene: &Scene, settings: &Settings) -> Result<SceneFile> {
+        let c = &scene.camera;
+        let file = SceneFile {
+            render: RenderDesc {
+                width: Some(settings.width),
+                height: Some(settings.height),
+                samples_per_pixel: Some(settings.samples_per_pixel),
+                max_depth: Some(settings.max_depth),
+                diffuse_depth: settings.limits.diffuse,
+                specular_depth: settings.limits.specular,
+                transmission_depth: settings.limits.transmission,
+                clamp_indirect: settings.limits.clamp_indirect,
+                regularize: settings.limits.regularize,
+                seed: Some(settings.seed),
+                working_space: (scene.working_space != WorkingSpace::Srgb).then_some(scene.working_space),
+                scale: scene.scale,
+            },
+            camera: CameraDesc {
+                lookfrom: c.lookfrom().to_array(),
+                lookat: c.lookat().to_array(),
+                vup: c.vup().to_array(),
+                vfov: c.vert_fov(),
+                aperture: c.aperture(),
+                focus_dist: Some(c.focus_dist()),
+                lens: c.lens().map(|lens| LensDesc {
+                    file: lens.prescription().path().to_path_buf(),
+                    stop: lens.prescription().stop(),
+                    scale: lens.scale(),
+                }),
+                projection: (c.projection() != Projection::Perspective).then_some(c.projection()),
+            },
+            background: match &scene.environment.background {
+                Background::Sky => BackgroundDesc::Sky,
+                Background::Color(color) => BackgroundDesc::Color { color: color.to_array() },
+                Background::Gradient(bottom, top) => BackgroundDesc::Gradient { bottom: bottom.to_array(), top: top.to_array() },
+                Background::Map(map) => BackgroundDesc::Map { file: map.path().to_path_buf() },
+            },
+            environment: EnvironmentDesc {
+                rotation: scene.environment.rotation,
+                intensity: scene.environment.intensity,
+                camera: scene.environment.camera,
+                lighting: scene.environment.lighting,
+                backdrop: scene.environment.backdrop.as_ref().map(|image| image.path().to_path_buf()),
+            },
+            fog: scene.fog.as_ref().map(|fog| FogDesc {
+                density: fog.density,
+                albedo: fog.albedo.to_array(),
+                anisotropy: fog.anisotropy,
+                inside: fog.bounds.map(|(lo, hi)| [lo.to_array(), hi.to_array()]),
+            }),
+            textures: HashMap::new(),
+            materials: HashMap::new(),
+            objects: Vec::new(),
+            clip: Vec::new(),
+            ray_groups: HashMap::new(),
+            base_dir: PathBuf::new(),
+        };
+        let mut out = Exporter { file, material_names: HashMap::new(), texture_names: HashMap::new() };
+        scene.world.export(&mut out)?;
+        Ok(out.file)
+    }
+
+    // .json or .toml, picked from the extension
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let text = match path.extension().and_then(|e| e.to_str()) {
+            Some("json") => serde_json::to_string_pretty(self).map_err(|e| RendererError::encode(path, e))?,
+            Some("toml") => toml::to_string(self).map_err(|e| RendererError::encode(path, e))?,
+            _ => return Err(RendererError::Unsupported(format!("{}: scenes are saved as .toml or .json", path.display()))),
+        };
+        std::fs::write(path, text).map_err(|e| RendererError::io(path, e))
+    }
+
+    pub fn settings(&self) -> Settings {
+        let default = Settings::default();
+        let r = &self.render;
+        Settings {
+            width: r.width.unwrap_or(default.width),
+            height: r.height.unwrap_or(default.height),
+            samples_per_pixel: r.samples_per_pixel.unwrap_or(default.samples_per_pixel),
+            max_depth: r.max_depth.unwrap_or(default.max_depth),
+            seed: r.seed.unwrap_or(default.seed),
+            limits: PathLimits {
+                diffuse: r.diffuse_depth,
+                specular: r.specular_depth,
+                transmission: r.transmission_depth,
+                clamp_indirect: r.clamp_indirect,
+                regularize: r.regularize,
+            },
+        }
+    }
+
+    // Moves every object and clipping plane by `m`. Sphere and shell radii, fractal sizes and
+    // volume densities follow the scale along x, so anything but uniform scales turns them into
+    // the wrong size.
+    // Keyframes are left alone and still move the objects around the world origin.
+    pub fn transform(&mut self, m: &Matrix) {
+        let point = |v: [Float; 3]| {
+            let p = transform::point(m, vec3(v));
+            [p.x(), p.y(), p.z()]
+        };
+        for object in &mut self.objects {
+            match object {
+                ObjectDesc::Sphere { center, radius, .. } => {
+                    *center = point(*center);
+                    *radius *= transform::vector(m, Vec3::new(1.0, 0.0, 0.0)).length();
+                }
+                ObjectDesc::Shell { center, outer, inner, .. } => {
+                    *center = point(*center);
+                    let scale = transform::vector(m, Vec3::new(1.0, 0.0, 0.0)).length();
+                    *outer *= scale;
+                    *inner *= scale;
+                }
+                ObjectDesc::Fractal { center, size, .. } => {
+                    *center = point(*center);
+                    *size *= transform::vector(m, Vec3::new(1.0, 0.0, 0.0)).length();
+                }
+                ObjectDesc::Triangle { vertices, .. } => {
+                    for v in vertices.iter_mut() {
+                        *v = point(*v);
+                    }
+                }
+                ObjectDesc::Spheres { inside, radius, spread, .. } => {
+                    *inside = inside.map(point);
+                    let factor = transform::vector(m, Vec3::new(1.0, 0.0, 0.0)).length();
+                    *radius = radius.map(|r| r * factor);
+                    *spread *= factor;
+                }
+                // the surface moves by itself, copies grow with the scale like spheres do
+                ObjectDesc::Scatter { inside, scale, .. } => {
+                    if let Some(corners) = inside {
+                        *corners = corners.map(point);
+                    }
+                    let factor = transform::vector(m, Vec3::new(1.0, 0.0, 0.0)).length();
+                    *scale = scale.map(|s| s * factor);
+                }
+                // a bigger volume has as much smoke over a longer way
+                ObjectDesc::Volume { density, transform, .. } => {
+                    *density /= transform::vector(m, Vec3::new(1.0, 0.0, 0.0)).length();
+                    *transform = Some(transform::mul(m, &transform.unwrap_or(transform::IDENTITY)));
+                }
+                // the objects inside a group move along with it
+                ObjectDesc::Mesh { transform, .. } => {
+                    *transform = Some(transform::mul(m, &transform.unwrap_or(transform::IDENTITY)));
+                }
+                ObjectDesc::Group { transform, end_transform, .. }
+                | ObjectDesc::Instance { transform, end_transform, .. } => {
+                    *transform = Some(transform::mul(m, &transform.unwrap_or(transform::IDENTITY)));
+                    if let Some(end) = end_transform {
+                        *end = transform::mul(m, end);
+                    }
+                }
+            }
+        }
+        // camera planes go along with the camera
+        for clip in &mut self.clip {
+            if let ClipDesc::Plane { point: p, normal, .. } = clip {
+                *p = point(*p);
+                if let Some(t) = Transform::new(*m) {
+                    *normal = t.normal(vec3(*normal)).to_array();
+                }
+            }
+        }
+        // a bigger scene has as much fog over a longer way
+        if let Some(fog) = &mut self.fog {
+            if let Some(corners) = &mut fog.inside {
+                *corners = corners.map(point);
+            }
+            fog.density /= transform::vector(m, Vec3::new(1.0, 0.0, 0.0)).length();
+        }
+    }
+
+    // A whole scene made with `up` as its up axis and `unit_scale` as the length of its unit,
+    // e.g. a Blender export, turned and scaled into this renderer's convention (see
+    // transform::Up) with the camera going along, so it still sees the same picture. The
+    // same caveats as with `transform` apply.
+    pub fn convert(&mut self, up: Up, unit_scale: Float) {
+        let m = up.conversion(unit_scale);
+        self.transform(&m);
+        let c = &mut self.camera;
+        c.lookfrom = transform::point(&m, vec3(c.lookfrom)).to_array();
+        c.lookat = transform::point(&m, vec3(c.lookat)).to_array();
+        c.vup = transform::vector(&up.conversion(1.0), vec3(c.vup)).to_array();
+        c.aperture *= unit_scale;
+        c.focus_dist = c.focus_dist.map(|d| d * unit_scale);
+    }
+
+    // the scene at `time`, which places the camera's shutter and so the animated objects
+    pub fn build(&self, time: Float, aspect_ratio: Float) -> Result<Scene> {
+        self.build_with_shutter(time, 0.0, 0.0, aspect_ratio)
+    }
+
+    // the same with the shutter open for `shutter` seconds from `time`, rolling down the image
+    // over `readout` seconds (see Camera::with_rolling_shutter). Groups and instances with an
+    // end_transform move from the top row opening to the bottom one closing.
+    pub fn build_with_shutter(&self, time: Float, shutter: Float, readout: Float, aspect_ratio: Float) -> Result<Scene> {
+        let space = self.render.working_space.unwrap_or_default();
+        let mut materials = build_materials(&self.textures, &self.materials, &self.base_dir, space)?;
+        let mut groups = TraceGroups::new();
+        for (group, names) in sorted_entries(&self.ray_groups) {
+            let group = groups.group(group)?;
+            for name in names {
+                let m = materials.get_mut(name)
+                    .ok_or_else(|| RendererError::Scene(format!("ray_groups: unknown material '{}'", name)))?;
+                *m = Arc::new(Grouped::new(m.clone(), group, &groups));
+            }
+        }
+        let mut builder = SceneBuilder::new();
+        for (name, m) in sorted_entries(&materials) {
+            builder = builder.name_material(name.as_str(), m);
+        }
+        let mut build = Build {
+            file: self,
+            materials,
+            trace_groups: groups,
+            groups: HashMap::new(),
+            impostors: HashMap::new(),
+            bounds: None,
+            shutter: (time, time + readout + shutter),
+        };
+        let mut builder = build.add_objects(builder, &self.objects, &transform::IDENTITY)?;
+
+        let c = &self.camera;
+        let (lookfrom, lookat) = (vec3(c.lookfrom), vec3(c.lookat));
+        let camera = Camera::new(
+            lookfrom,
+            lookat,
+            vec3(c.vup),
+            c.vfov,
+            aspect_ratio,
+            c.aperture,
+            c.focus_dist.unwrap_or((lookfrom - lookat).length()),
+        ).with_shutter(shutter).with_rolling_shutter(readout);
+        let camera = match &c.lens {
+            Some(_) if c.projection.is_some_and(|p| p != Projection::Perspective) => {
+                return Err(RendererError::Scene("camera: a real lens only works with the perspective projection".to_string()));
+            }
+            Some(lens) => {
+                let mut prescription = Prescription::load(&self.base_dir.join(&lens.file))?;
+                if let Some(diameter) = lens.stop {
+                    prescription = prescription.with_stop(diameter)?;
+                }
+                camera.with_lens(prescription, lens.scale)?
+            }
+            None => camera,
+        }.with_projection(c.projection.unwrap_or_default());
+
+        let background = match &self.background {
+            BackgroundDesc::Sky => Background::Sky,
+            BackgroundDesc::Color { color } => Background::Color(vec3(*color)),
+            BackgroundDesc::Gradient { bottom, top } => Background::Gradient(vec3(*bottom), vec3(*top)),
+            BackgroundDesc::Map { file } => Background::Map(Arc::new(ImageTexture::load(&self.base_dir.join(file), space)?)),
+        };
+        let e = &self.environment;
+        let mut environment = Environment::new(background)
+            .with_rotation(e.rotation)
+            .with_intensity(e.intensity)
+            .with_visibility(e.camera, e.lighting);
+        if let Some(file) = &e.backdrop {
+            let image = ImageTexture::load(&self.base_dir.join(file), space)?;
+            let image_aspect = image.width() as Float / image.height() as Float;
+            if (image_aspect / aspect_ratio - 1.0).abs() > 0.01 {
+                log::warn!("backdrop {} is {:.3}:1 and gets stretched to the image's {:.3}:1", file.display(), image_aspect, aspect_ratio);
+            }
+            environment = environment.with_backdrop(Arc::new(image));
+        }
+
+        let view = (lookat - lookfrom).normalized();
+        for desc in &self.clip {
+            builder = builder.add_clip_plane(match *desc {
+                ClipDesc::Plane { point, normal, cap } => ClipPlane::new(vec3(point), vec3(normal)).with_cap(cap),
+                ClipDesc::Camera { distance, cap } => ClipPlane::new(lookfrom + distance * view, -1.0 * view).with_cap(cap),
+            });
+        }
+
+        if let Some(f) = &self.fog {
+            let mut fog = Fog::new(f.density, vec3(f.albedo), f.anisotropy);
+            if let Some([a, b]) = f.inside {
+                fog = fog.with_bounds(vec3(a), vec3(b));
+            }
+            builder = builder.set_fog(fog);
+        }
+
+        if let Some(scale) = self.render.scale {
+            builder = builder.set_scale(scale);
+        }
+        builder
+            .set_camera(camera)
+            .set_environment(environment)
+            .set_time(time)
+            .set_working_space(space)
+            .build()
+    }
+}
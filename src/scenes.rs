@@ -0,0 +1,278 @@
+use std::sync::Arc;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use crate::camera::Camera;
+use crate::material::{Dielectric, DiffuseLight, Lambertian, Metal, Scatter};
+use crate::render::Settings;
+use crate::scene::{Background, Scene, SceneBuilder};
+use crate::texture::Checker;
+use crate::{Color, Float, Point3, Vec3};
+
+// the camera orbits around the look-at point as the animation time (in seconds) goes on
+const ORBIT_SPEED: Float = 15.0;  // degrees per second
+
+// Scenes that come with the renderer, picked by name with `--scene builtin:<name>`
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Builtin {
+    // the five spheres the renderer started out with
+    Default,
+    // the random sphere field from the cover of "Ray Tracing in One Weekend"
+    Final,
+    Cornell,
+    // one row each of diffuse, metal and glass spheres with the parameter going up left to right
+    Materials,
+}
+
+impl Builtin {
+    pub const ALL: [Builtin; 4] = [Builtin::Default, Builtin::Final, Builtin::Cornell, Builtin::Materials];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Builtin::Default => "default",
+            Builtin::Final => "final",
+            Builtin::Cornell => "cornell",
+            Builtin::Materials => "materials",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Builtin> {
+        Builtin::ALL.into_iter().find(|b| b.name() == name)
+    }
+
+    // what the scene is meant to be rendered with
+    pub fn settings(self) -> Settings {
+        let default = Settings::default();
+        match self {
+            Builtin::Default => default,
+            Builtin::Final => Settings { width: 600, height: 400, samples_per_pixel: 50, max_depth: 50, ..default },
+            Builtin::Cornell => Settings { width: 600, height: 600, samples_per_pixel: 200, max_depth: 50, ..default },
+            Builtin::Materials => Settings { width: 800, height: 480, samples_per_pixel: 100, max_depth: 50, ..default },
+        }
+    }
+
+    pub fn build(self, time: Float, aspect_ratio: Float) -> Scene {
+        match self {
+            Builtin::Default => default_scene(time, aspect_ratio),
+            Builtin::Final => random_spheres(time, aspect_ratio),
+            Builtin::Cornell => cornell_box(time, aspect_ratio),
+            Builtin::Materials => material_grid(time, aspect_ratio),
+        }
+    }
+}
+
+pub fn default_scene(time: Float, aspect_ratio: Float) -> Scene {
+    let mat_ground = Arc::new(Lambertian::new(Color::new(0.8, 0.8, 0.0)));
+    let mat_center = Arc::new(Dielectric::new(1.1));
+    // let mat_left = Rc::new(Dielectric::new(1.5));
+    let mat_right = Arc::new(Metal::new(Color::new(0.8, 0.6, 0.2), 0.0));
+    let mat_left_inner = Arc::new(Dielectric::new(1.5));
+    let mat_matte = Arc::new(Lambertian::new(Color::new(0.4, 0.2, 0.4)));
+
+    // Camera
+    let lookat = Point3::new(0.0, 0.0, -1.0);
+    let (sin, cos) = (ORBIT_SPEED * time).to_radians().sin_cos();
+    let offset = Point3::new(12.0, 3.0, 3.0) - lookat;
+    let lookfrom = lookat + Vec3::new(offset.x()*cos + offset.z()*sin, offset.y(), offset.z()*cos - offset.x()*sin);
+    let cam = Camera::new(
+        lookfrom,
+        lookat,
+        Vec3::new(0.0, 1.0, 0.0),
+        20.0,
+        aspect_ratio,
+        0.1,
+        (lookfrom - lookat).length()
+    );
+
+    SceneBuilder::new()
+        .add_sphere(Point3::new(0.0, -100.5, -1.0), 100.0).with_material(mat_ground)
+        .add_sphere(Point3::new(0.0, 0.0, -1.0), 0.5).with_material(mat_center)
+        // .add_sphere(Point3::new(-1.0, 0.0, -1.0), 0.5).with_material(mat_left)
+        .add_sphere(Point3::new(1.0, 0.0, -1.0), 0.5).with_material(mat_right)
+        .add_sphere(Point3::new(-1.0, 0.0, -1.0), -0.4).with_material(mat_left_inner)
+        .add_sphere(Point3::new(0.0, 0.0, 2.5), 1.2).with_material(mat_matte)
+        .set_camera(cam)
+        .set_background(Background::Sky)
+        .set_time(time)
+        .build()
+        .expect("The default scene is valid")
+}
+
+pub fn random_spheres(time: Float, aspect_ratio: Float) -> Scene {
+    // fixed seed, the scene has to come out the same on every run
+    let mut rng = StdRng::seed_from_u64(0x5eed);
+    let rand_color = |lo: Float, hi: Float, rng: &mut StdRng| {
+        Color::new(rng.gen_range(lo..hi), rng.gen_range(lo..hi), rng.gen_range(lo..hi))
+    };
+
+    let mut builder = SceneBuilder::new()
+        .add_sphere(Point3::new(0.0, -1000.0, 0.0), 1000.0)
+        .with_material(Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5))));
+
+    for a in -11..11 {
+        for b in -11..11 {
+            let choose_mat: Float = rng.gen();
+            let center = Point3::new(a as Float + 0.9*rng.gen::<Float>(), 0.2, b as Float + 0.9*rng.gen::<Float>());
+            if (center - Point3::new(4.0, 0.2, 0.0)).length() <= 0.9 {
+                continue;
+            }
+
+            let mat: Arc<dyn Scatter> = if choose_mat < 0.8 {
+                let albedo = rand_color(0.0, 1.0, &mut rng) * rand_color(0.0, 1.0, &mut rng);
+                Arc::new(Lambertian::new(albedo))
+            } else if choose_mat < 0.95 {
+                let albedo = rand_color(0.5, 1.0, &mut rng);
+                Arc::new(Metal::new(albedo, rng.gen_range(0.0..0.5)))
+            } else {
+                Arc::new(Dielectric::new(1.5))
+            };
+            builder = builder.add_sphere(center, 0.2).with_material(mat);
+        }
+    }
+
+    let lookfrom = Point3::new(13.0, 2.0, 3.0);
+    let lookat = Point3::new(0.0, 0.0, 0.0);
+    let cam = Camera::new(lookfrom, lookat, Vec3::new(0.0, 1.0, 0.0), 20.0, aspect_ratio, 0.1, 10.0);
+
+    builder
+        .add_sphere(Point3::new(0.0, 1.0, 0.0), 1.0).with_material(Arc::new(Dielectric::new(1.5)))
+        .add_sphere(Point3::new(-4.0, 1.0, 0.0), 1.0).with_material(Arc::new(Lambertian::new(Color::new(0.4, 0.2, 0.1))))
+        .add_sphere(Point3::new(4.0, 1.0, 0.0), 1.0).with_material(Arc::new(Metal::new(Color::new(0.7, 0.6, 0.5), 0.0)))
+        .set_camera(cam)
+        .set_background(Background::Sky)
+        .set_time(time)
+        .build()
+        .expect("The random sphere scene is valid")
+}
+
+// quad as two triangles, corners going around the edge
+fn add_quad(builder: SceneBuilder, q: [Point3; 4], mat: &Arc<dyn Scatter>) -> SceneBuilder {
+    builder
+        .add_triangle(q[0], q[1], q[2]).with_material(mat.clone())
+        .add_triangle(q[0], q[2], q[3]).with_material(mat.clone())
+}
+
+// box with a corner at the origin, turned around the y axis and then moved to `offset`
+fn add_box(mut builder: SceneBuilder, size: Vec3, angle: Float, offset: Vec3, mat: &Arc<dyn Scatter>) -> SceneBuilder {
+    let (sin, cos) = angle.to_radians().sin_cos();
+    let corner = |x: Float, y: Float, z: Float| {
+        let (x, y, z) = (x * size.x(), y * size.y(), z * size.z());
+        Point3::new(cos*x + sin*z, y, cos*z - sin*x) + offset
+    };
+
+    let faces = [
+        [corner(0.0, 0.0, 0.0), corner(1.0, 0.0, 0.0), corner(1.0, 1.0, 0.0), corner(0.0, 1.0, 0.0)],
+        [corner(0.0, 0.0, 1.0), corner(1.0, 0.0, 1.0), corner(1.0, 1.0, 1.0), corner(0.0, 1.0, 1.0)],
+        [corner(0.0, 0.0, 0.0), corner(0.0, 0.0, 1.0), corner(0.0, 1.0, 1.0), corner(0.0, 1.0, 0.0)],
+        [corner(1.0, 0.0, 0.0), corner(1.0, 0.0, 1.0), corner(1.0, 1.0, 1.0), corner(1.0, 1.0, 0.0)],
+        [corner(0.0, 0.0, 0.0), corner(1.0, 0.0, 0.0), corner(1.0, 0.0, 1.0), corner(0.0, 0.0, 1.0)],
+        [corner(0.0, 1.0, 0.0), corner(1.0, 1.0, 0.0), corner(1.0, 1.0, 1.0), corner(0.0, 1.0, 1.0)],
+    ];
+    for face in faces {
+        builder = add_quad(builder, face, mat);
+    }
+    builder
+}
+
+pub fn cornell_box(time: Float, aspect_ratio: Float) -> Scene {
+    let red: Arc<dyn Scatter> = Arc::new(Lambertian::new(Color::new(0.65, 0.05, 0.05)));
+    let white: Arc<dyn Scatter> = Arc::new(Lambertian::new(Color::new(0.73, 0.73, 0.73)));
+    let green: Arc<dyn Scatter> = Arc::new(Lambertian::new(Color::new(0.12, 0.45, 0.15)));
+    let light: Arc<dyn Scatter> = Arc::new(DiffuseLight::new(Color::new(15.0, 15.0, 15.0)));
+
+    // named so it gets a pass of its own, see Renderer::with_light_passes
+    let builder = SceneBuilder::new().name_material("light", &light);
+    cornell_geometry(builder, [&red, &green, &white, &light])
+        .set_camera(cornell_camera(aspect_ratio))
+        .set_background(Background::Color(Color::new(0.0, 0.0, 0.0)))
+        .set_time(time)
+        .build()
+        .expect("The Cornell box is valid")
+}
+
+// the walls, the light and the two blocks of the Cornell box, in the materials of the left
+// wall, the right wall, everything white and the light
+pub(crate) fn cornell_geometry(mut builder: SceneBuilder, [red, green, white, light]: [&Arc<dyn Scatter>; 4]) -> SceneBuilder {
+    let p = Point3::new;
+    builder = add_quad(builder, [p(555.0, 0.0, 0.0), p(555.0, 555.0, 0.0), p(555.0, 555.0, 555.0), p(555.0, 0.0, 555.0)], green);
+    builder = add_quad(builder, [p(0.0, 0.0, 0.0), p(0.0, 555.0, 0.0), p(0.0, 555.0, 555.0), p(0.0, 0.0, 555.0)], red);
+    builder = add_quad(builder, [p(213.0, 554.0, 227.0), p(343.0, 554.0, 227.0), p(343.0, 554.0, 332.0), p(213.0, 554.0, 332.0)], light);
+    builder = add_quad(builder, [p(0.0, 0.0, 0.0), p(555.0, 0.0, 0.0), p(555.0, 0.0, 555.0), p(0.0, 0.0, 555.0)], white);
+    builder = add_quad(builder, [p(0.0, 555.0, 0.0), p(555.0, 555.0, 0.0), p(555.0, 555.0, 555.0), p(0.0, 555.0, 555.0)], white);
+    builder = add_quad(builder, [p(0.0, 0.0, 555.0), p(555.0, 0.0, 555.0), p(555.0, 555.0, 555.0), p(0.0, 555.0, 555.0)], white);
+    builder = add_box(builder, Vec3::new(165.0, 330.0, 165.0), 15.0, Vec3::new(265.0, 0.0, 295.0), white);
+    add_box(builder, Vec3::new(165.0, 165.0, 165.0), -18.0, Vec3::new(130.0, 0.0, 65.0), white)
+}
+
+pub(crate) fn cornell_camera(aspect_ratio: Float) -> Camera {
+    let lookfrom = Point3::new(278.0, 278.0, -800.0);
+    let lookat = Point3::new(278.0, 278.0, 0.0);
+    Camera::new(lookfrom, lookat, Vec3::new(0.0, 1.0, 0.0), 40.0, aspect_ratio, 0.0, 800.0)
+}
+
+pub fn material_grid(time: Float, aspect_ratio: Float) -> Scene {
+    const COLUMNS: usize = 5;
+    let ground = Arc::new(Lambertian::textured(Arc::new(Checker::new(
+        Color::new(0.2, 0.3, 0.1),
+        Color::new(0.9, 0.9, 0.9),
+        0.5,
+    ))));
+
+    let mut builder = SceneBuilder::new()
+        .add_sphere(Point3::new(0.0, -1000.0, 0.0), 1000.0).with_material(ground);
+
+    for i in 0..COLUMNS {
+        // 0 to 1 going left to right
+        let t = i as Float / (COLUMNS - 1) as Float;
+        let x = (i as Float - (COLUMNS - 1) as Float / 2.0) * 1.1;
+
+        let rows: [(Float, Arc<dyn Scatter>); 3] = [
+            (-1.1, Arc::new(Lambertian::new(Color::new(0.1 + 0.8*t, 0.2, 0.9 - 0.8*t)))),
+            (0.0, Arc::new(Metal::new(Color::new(0.8, 0.8, 0.8), t))),
+            (1.1, Arc::new(Dielectric::new(1.1 + 1.3*t))),
+        ];
+        for (z, mat) in rows {
+            builder = builder.add_sphere(Point3::new(x, 0.5, z), 0.5).with_material(mat);
+        }
+    }
+
+    let lookfrom = Point3::new(0.0, 6.0, 9.0);
+    let lookat = Point3::new(0.0, 0.3, 0.0);
+    let cam = Camera::new(lookfrom, lookat, Vec3::new(0.0, 1.0, 0.0), 35.0, aspect_ratio, 0.0, (lookfrom - lookat).length());
+
+    builder
+        .set_camera(cam)
+        .set_background(Background::Sky)
+        .set_time(time)
+        .build()
+        .expect("The material grid is valid")
+}
+
+// A ball of the material on a plinth, under the sky and a soft light from the upper left, the
+// same every time so previews of different materials can be compared. Not one of the Builtin
+// scenes since it needs the material.
+pub fn shader_ball(material: Arc<dyn Scatter>, aspect_ratio: Float) -> Scene {
+    let floor: Arc<dyn Scatter> = Arc::new(Lambertian::textured(Arc::new(Checker::new(
+        Color::new(0.25, 0.25, 0.25),
+        Color::new(0.75, 0.75, 0.75),
+        0.5,
+    ))));
+    let plinth: Arc<dyn Scatter> = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+    let light: Arc<dyn Scatter> = Arc::new(DiffuseLight::new(Color::new(4.0, 4.0, 4.0)));
+
+    let p = Point3::new;
+    let mut builder = SceneBuilder::new()
+        .add_sphere(p(0.0, -1000.0, 0.0), 1000.0).with_material(floor)
+        .add_sphere(p(0.0, 1.3, 0.0), 1.0).with_material(material);
+    builder = add_box(builder, Vec3::new(1.6, 0.3, 1.6), 0.0, Vec3::new(-0.8, 0.0, -0.8), &plinth);
+    builder = add_quad(builder, [p(-4.0, 5.0, -1.0), p(-1.0, 5.0, -1.0), p(-1.0, 5.0, 2.0), p(-4.0, 5.0, 2.0)], &light);
+
+    let lookfrom = Point3::new(0.0, 2.6, 8.0);
+    let lookat = Point3::new(0.0, 0.9, 0.0);
+    let cam = Camera::new(lookfrom, lookat, Vec3::new(0.0, 1.0, 0.0), 30.0, aspect_ratio, 0.0, (lookfrom - lookat).length());
+
+    builder
+        .set_camera(cam)
+        .set_background(Background::Sky)
+        .build()
+        .expect("The shader ball scene is valid")
+}
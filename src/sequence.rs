@@ -0,0 +1,48 @@
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+// Inclusive range of frame numbers, written "1..240" (or "12" for a single frame)
+#[derive(Copy, Clone)]
+pub struct FrameRange {
+    pub start: u32,
+    pub end: u32,
+}
+
+impl FromStr for FrameRange {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<FrameRange, String> {
+        let parse = |n: &str| n.trim().parse::<u32>().map_err(|e| format!("invalid frame number '{}': {}", n, e));
+
+        let (start, end) = match s.split_once("..") {
+            Some((a, b)) => (parse(a)?, parse(b.trim_start_matches('='))?),
+            None => (parse(s)?, parse(s)?),
+        };
+        if end < start {
+            return Err(format!("frame range {} ends before it starts", s));
+        }
+        Ok(FrameRange { start, end })
+    }
+}
+
+// Substitutes a printf-style "%d" / "%04d" pattern in the file name with the frame number.
+// Without a pattern, "-%04d" is inserted before the extension so frames don't overwrite each other.
+pub fn frame_path(pattern: &Path, frame: u32) -> PathBuf {
+    let name = pattern.file_name().and_then(|n| n.to_str()).unwrap_or("render");
+
+    if let Some(start) = name.find('%') {
+        if let Some(len) = name[start+1..].find('d') {
+            let spec = &name[start+1..start+1+len];
+            if let Ok(width) = if spec.is_empty() { Ok(0) } else { spec.parse::<usize>() } {
+                let numbered = format!("{}{:0width$}{}", &name[..start], frame, &name[start+2+len..], width = width);
+                return pattern.with_file_name(numbered);
+            }
+        }
+    }
+
+    let stem = pattern.file_stem().and_then(|s| s.to_str()).unwrap_or("render");
+    match pattern.extension().and_then(|e| e.to_str()) {
+        Some(ext) => pattern.with_file_name(format!("{}-{:04}.{}", stem, frame, ext)),
+        None => pattern.with_file_name(format!("{}-{:04}", stem, frame)),
+    }
+}
@@ -0,0 +1,316 @@
+use std::collections::HashMap;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+use std::sync::{mpsc, Arc, Mutex, PoisonError};
+use std::thread;
+use std::time::Duration;
+use serde::Serialize;
+use serde_json::json;
+use raytracer_test::colorspace::Encoding;
+use raytracer_test::framebuffer::{Framebuffer, Tile};
+use raytracer_test::output::{self, BitDepth};
+use raytracer_test::render::Renderer;
+use raytracer_test::scene_file::SceneFile;
+use raytracer_test::{ImageSink, RendererError, Result};
+use crate::cli::ServeArgs;
+
+// largest scene accepted in a request body
+const MAX_BODY: usize = 64 << 20;
+const POLL: Duration = Duration::from_millis(100);
+
+// A small HTTP/JSON front end for rendering, enough to back a web page or a render queue:
+//
+//     POST /renders                 scene file in the body (TOML, or JSON with
+//                                   Content-Type: application/json), answers {"id": 1}
+//     GET  /renders                 every job and how far along it is
+//     GET  /renders/<id>            one job
+//     GET  /renders/<id>/tiles      finished tiles as JSON lines, kept open until the render
+//                                   is done; ?since=<n> skips the first n tiles
+//     GET  /renders/<id>/image      the finished render as a PNG
+//
+// Jobs render one after the other in the order they came in. Relative paths in the scenes
+// start from the server's working directory.
+pub fn serve(args: &ServeArgs, renderer: Renderer) -> Result<()> {
+    let listener = TcpListener::bind(&args.bind).map_err(|e| RendererError::io(Path::new(&args.bind), e))?;
+    log::info!("Listening on http://{}", args.bind);
+
+    let jobs: Jobs = Arc::new(Mutex::new(Vec::new()));
+    let (queue, pending) = mpsc::channel();
+    let worker_jobs = jobs.clone();
+    let tile_size = args.tile_size;
+    thread::spawn(move || work(&renderer, pending, worker_jobs, tile_size));
+
+    for stream in listener.incoming() {
+        let Ok(stream) = stream else { continue };
+        let jobs = jobs.clone();
+        let queue = queue.clone();
+        thread::spawn(move || {
+            if let Err(e) = handle(stream, &jobs, &queue) {
+                log::warn!("connection error: {}", e);
+            }
+        });
+    }
+    Ok(())
+}
+
+type Jobs = Arc<Mutex<Vec<Job>>>;
+
+#[derive(Serialize, Copy, Clone, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum Status {
+    Queued,
+    Rendering,
+    Done,
+    Failed,
+}
+
+struct Job {
+    status: Status,
+    width: u32,
+    height: u32,
+    tiles_total: usize,
+    tiles: Vec<TileJson>,
+    image: Option<Framebuffer>,
+    error: Option<String>,
+}
+
+// a finished tile as sent to clients, 8-bit RGB rows from the top
+#[derive(Serialize)]
+struct TileJson {
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+    rgb: Vec<u8>,
+}
+
+impl Job {
+    fn summary(&self, id: usize) -> serde_json::Value {
+        json!({
+            "id": id,
+            "status": self.status,
+            "width": self.width,
+            "height": self.height,
+            "tiles_done": self.tiles.len(),
+            "tiles_total": self.tiles_total,
+            "progress": if self.tiles_total == 0 { 0.0 } else { self.tiles.len() as f64 / self.tiles_total as f64 },
+            "error": self.error,
+        })
+    }
+}
+
+// Renders the queued scenes one at a time, publishing tiles as they finish. A job that
+// panics is failed like any other, the jobs after it still get rendered.
+fn work(renderer: &Renderer, pending: mpsc::Receiver<(usize, SceneFile)>, jobs: Jobs, tile_size: u32) {
+    for (id, file) in pending {
+        let settings = file.settings();
+        let result = panic::catch_unwind(AssertUnwindSafe(|| file.build(0.0, settings.aspect_ratio()).and_then(|scene| {
+            {
+                let mut jobs = jobs.lock().unwrap();
+                jobs[id].status = Status::Rendering;
+                jobs[id].tiles_total = (settings.width.div_ceil(tile_size) * settings.height.div_ceil(tile_size)) as usize;
+            }
+            renderer.render_to(&scene, &settings, tile_size, &mut JobSink { jobs: &jobs, id })
+        })));
+
+        // a panic in a sink can leave the lock poisoned, the jobs in it are still fine
+        let mut jobs = jobs.lock().unwrap_or_else(PoisonError::into_inner);
+        let error = match result {
+            Ok(Ok(())) => None,
+            Ok(Err(e)) => Some(e.to_string()),
+            Err(e) => Some(e.downcast_ref::<String>().cloned()
+                .or_else(|| e.downcast_ref::<&str>().map(|s| s.to_string()))
+                .unwrap_or_else(|| "the render panicked".to_string())),
+        };
+        match error {
+            None => jobs[id].status = Status::Done,
+            Some(e) => {
+                jobs[id].status = Status::Failed;
+                jobs[id].error = Some(e);
+            }
+        }
+    }
+}
+
+// publishes a job's tiles to the clients following along, then its image
+struct JobSink<'a> {
+    jobs: &'a Jobs,
+    id: usize,
+}
+
+impl ImageSink for JobSink<'_> {
+    fn write_tile(&mut self, tile: &Tile) -> Result<()> {
+        let json = TileJson {
+            x: tile.x,
+            y: tile.y,
+            width: tile.data.width(),
+            height: tile.data.height(),
+            rgb: tile.data.to_rgb8(Encoding::default()),
+        };
+        self.jobs.lock().unwrap()[self.id].tiles.push(json);
+        Ok(())
+    }
+
+    fn write_image(&mut self, image: &Framebuffer) -> Result<()> {
+        self.jobs.lock().unwrap()[self.id].image = Some(image.clone());
+        Ok(())
+    }
+}
+
+struct Request {
+    method: String,
+    path: String,
+    query: HashMap<String, String>,
+    headers: HashMap<String, String>,
+    body: Vec<u8>,
+}
+
+fn read_request(stream: &TcpStream) -> io::Result<Request> {
+    let bad = |what: &str| io::Error::new(io::ErrorKind::InvalidData, what.to_string());
+    let mut reader = BufReader::new(stream);
+
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    let mut parts = line.split_whitespace();
+    let method = parts.next().ok_or_else(|| bad("empty request"))?.to_string();
+    let target = parts.next().ok_or_else(|| bad("request without a path"))?;
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let query = query.split('&')
+        .filter_map(|kv| kv.split_once('='))
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect();
+    let path = path.to_string();
+
+    let mut headers = HashMap::new();
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 || line.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            headers.insert(name.trim().to_ascii_lowercase(), value.trim().to_string());
+        }
+    }
+
+    let length: usize = headers.get("content-length").and_then(|l| l.parse().ok()).unwrap_or(0);
+    if length > MAX_BODY {
+        return Err(bad("request body too large"));
+    }
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body)?;
+    Ok(Request { method, path, query, headers, body })
+}
+
+fn respond(mut stream: &TcpStream, status: u16, content_type: &str, body: &[u8]) -> io::Result<()> {
+    let reason = match status {
+        200 => "OK",
+        201 => "Created",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        409 => "Conflict",
+        _ => "Internal Server Error",
+    };
+    write!(stream, "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+           status, reason, content_type, body.len())?;
+    stream.write_all(body)
+}
+
+fn respond_json(stream: &TcpStream, status: u16, value: &serde_json::Value) -> io::Result<()> {
+    respond(stream, status, "application/json", value.to_string().as_bytes())
+}
+
+fn handle(stream: TcpStream, jobs: &Jobs, queue: &mpsc::Sender<(usize, SceneFile)>) -> io::Result<()> {
+    let request = read_request(&stream)?;
+    let segments: Vec<&str> = request.path.split('/').filter(|s| !s.is_empty()).collect();
+    let not_found = || json!({ "error": format!("no such resource {}", request.path) });
+
+    let id = match segments.get(1) {
+        Some(id) => match id.parse::<usize>() {
+            Ok(id) if id < jobs.lock().unwrap().len() => Some(id),
+            _ => return respond_json(&stream, 404, &not_found()),
+        },
+        None => None,
+    };
+
+    match (request.method.as_str(), &segments[..], id) {
+        ("POST", ["renders"], _) => {
+            let json = request.headers.get("content-type").is_some_and(|t| t.starts_with("application/json"));
+            let text = String::from_utf8_lossy(&request.body);
+            let name = if json { "request.json" } else { "request.toml" };
+            match SceneFile::parse(&text, json, Path::new(name)) {
+                Ok(file) => {
+                    let id = {
+                        let mut jobs = jobs.lock().unwrap();
+                        let settings = file.settings();
+                        jobs.push(Job {
+                            status: Status::Queued,
+                            width: settings.width,
+                            height: settings.height,
+                            tiles_total: 0,
+                            tiles: Vec::new(),
+                            image: None,
+                            error: None,
+                        });
+                        jobs.len() - 1
+                    };
+                    let _ = queue.send((id, file));
+                    respond_json(&stream, 201, &json!({ "id": id }))
+                }
+                Err(e) => respond_json(&stream, 400, &json!({ "error": e.to_string() })),
+            }
+        }
+        ("GET", ["renders"], _) => {
+            let jobs = jobs.lock().unwrap();
+            let list: Vec<_> = jobs.iter().enumerate().map(|(id, job)| job.summary(id)).collect();
+            respond_json(&stream, 200, &json!(list))
+        }
+        ("GET", ["renders", _], Some(id)) => {
+            let summary = jobs.lock().unwrap()[id].summary(id);
+            respond_json(&stream, 200, &summary)
+        }
+        ("GET", ["renders", _, "tiles"], Some(id)) => {
+            let since = request.query.get("since").and_then(|s| s.parse().ok()).unwrap_or(0);
+            stream_tiles(&stream, jobs, id, since)
+        }
+        ("GET", ["renders", _, "image"], Some(id)) => {
+            let jobs = jobs.lock().unwrap();
+            match &jobs[id].image {
+                Some(image) => {
+                    let mut png = Vec::new();
+                    match output::encode_png(&mut png, image, BitDepth::Eight, Encoding::default()) {
+                        Ok(()) => respond(&stream, 200, "image/png", &png),
+                        Err(e) => respond_json(&stream, 500, &json!({ "error": e.to_string() })),
+                    }
+                }
+                None => respond_json(&stream, 409, &json!({ "error": "the render isn't finished" })),
+            }
+        }
+        (_, ["renders", ..], _) => respond_json(&stream, 405, &json!({ "error": "method not allowed" })),
+        _ => respond_json(&stream, 404, &not_found()),
+    }
+}
+
+// One JSON object per line and tile, sent as the tiles finish. The connection closes once the
+// render is done or failed.
+fn stream_tiles(mut stream: &TcpStream, jobs: &Jobs, id: usize, mut next: usize) -> io::Result<()> {
+    write!(stream, "HTTP/1.1 200 OK\r\nContent-Type: application/x-ndjson\r\nConnection: close\r\n\r\n")?;
+    loop {
+        let (lines, finished) = {
+            let jobs = jobs.lock().unwrap();
+            let job = &jobs[id];
+            let lines: Vec<String> = job.tiles.iter().skip(next).map(|t| serde_json::to_string(t).unwrap()).collect();
+            (lines, matches!(job.status, Status::Done | Status::Failed))
+        };
+        next += lines.len();
+        for line in lines {
+            writeln!(stream, "{}", line)?;
+        }
+        if finished {
+            return stream.flush();
+        }
+        thread::sleep(POLL);
+    }
+}
@@ -0,0 +1,181 @@
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use crate::colorspace::Encoding;
+use crate::error::{RendererError, Result};
+use crate::framebuffer::{Framebuffer, Tile};
+use crate::output::{self, BitDepth, Format, WriteOptions};
+
+// Where Renderer::render_to delivers pixels: every tile as soon as it's done, in no particular
+// order, then the finished image with its metadata. Sinks that only care about the end result
+// leave write_tile alone, a preview or a progress display picks the tiles up. An error stops
+// the render.
+pub trait ImageSink {
+    // called once before the first tile
+    fn begin(&mut self, _width: u32, _height: u32) -> Result<()> {
+        Ok(())
+    }
+
+    fn write_tile(&mut self, _tile: &Tile) -> Result<()> {
+        Ok(())
+    }
+
+    fn write_image(&mut self, image: &Framebuffer) -> Result<()>;
+}
+
+// An image file in any of the output formats
+pub struct FileSink {
+    path: PathBuf,
+    format: Format,
+    options: WriteOptions,
+}
+
+impl FileSink {
+    // the format follows the extension, PNG when there is none
+    pub fn new(path: impl Into<PathBuf>) -> FileSink {
+        let path = path.into();
+        FileSink {
+            format: Format::from_path(&path).unwrap_or(Format::Png),
+            path,
+            options: WriteOptions::default(),
+        }
+    }
+
+    pub fn with_format(mut self, format: Format) -> FileSink {
+        self.format = format;
+        self
+    }
+
+    pub fn with_options(mut self, options: WriteOptions) -> FileSink {
+        self.options = options;
+        self
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl ImageSink for FileSink {
+    fn write_image(&mut self, image: &Framebuffer) -> Result<()> {
+        output::write(&self.path, image, self.format, &self.options)
+    }
+}
+
+// Keeps the image in memory for library users that want the pixels themselves. The image is
+// filled in tile by tile, so it can be looked at while the render is still going.
+#[derive(Default)]
+pub struct MemorySink {
+    image: Option<Framebuffer>,
+}
+
+impl MemorySink {
+    pub fn new() -> MemorySink {
+        MemorySink::default()
+    }
+
+    // None before the render started
+    pub fn image(&self) -> Option<&Framebuffer> {
+        self.image.as_ref()
+    }
+
+    pub fn into_image(self) -> Option<Framebuffer> {
+        self.image
+    }
+}
+
+impl ImageSink for MemorySink {
+    fn begin(&mut self, width: u32, height: u32) -> Result<()> {
+        self.image = Some(Framebuffer::new(width, height));
+        Ok(())
+    }
+
+    fn write_tile(&mut self, tile: &Tile) -> Result<()> {
+        if let Some(image) = &mut self.image {
+            image.blit(&tile.data, tile.x, tile.y);
+        }
+        Ok(())
+    }
+
+    fn write_image(&mut self, image: &Framebuffer) -> Result<()> {
+        self.image = Some(image.clone());
+        Ok(())
+    }
+}
+
+// Hands each tile to `on_tile` as it's finished, for a window showing the render come in or
+// a connection sending it on, and keeps the finished image like MemorySink. The callback runs
+// on the thread that called render_to, one tile at a time; an error from it stops the render.
+// Tiles are (x, y) from the top left of the image, the pixels with their size in `data`.
+pub struct CallbackSink<F: FnMut(&Tile) -> Result<()>> {
+    on_tile: F,
+    image: Option<Framebuffer>,
+}
+
+impl<F: FnMut(&Tile) -> Result<()>> CallbackSink<F> {
+    pub fn new(on_tile: F) -> CallbackSink<F> {
+        CallbackSink { on_tile, image: None }
+    }
+
+    // None before the render finished
+    pub fn into_image(self) -> Option<Framebuffer> {
+        self.image
+    }
+}
+
+impl<F: FnMut(&Tile) -> Result<()>> ImageSink for CallbackSink<F> {
+    fn write_tile(&mut self, tile: &Tile) -> Result<()> {
+        (self.on_tile)(tile)
+    }
+
+    fn write_image(&mut self, image: &Framebuffer) -> Result<()> {
+        self.image = Some(image.clone());
+        Ok(())
+    }
+}
+
+// A sink sending a copy of every tile down a channel, for rendering on one thread and taking
+// the tiles in on another, a GUI's say: run render_to with the sink on a worker thread and
+// read the receiver until it closes, which it does once the render is over and the sink
+// dropped. Dropping the receiver stops the render as cancelled.
+pub fn tile_channel() -> (CallbackSink<impl FnMut(&Tile) -> Result<()>>, mpsc::Receiver<Tile>) {
+    let (tx, rx) = mpsc::channel();
+    let sink = CallbackSink::new(move |tile: &Tile| {
+        tx.send(Tile { x: tile.x, y: tile.y, data: tile.data.clone() }).map_err(|_| RendererError::Cancelled)
+    });
+    (sink, rx)
+}
+
+// The finished image as a PNG into anything writable: a socket, a pipe, stdout. `name` is what
+// errors are reported against.
+pub struct StreamSink<W: Write> {
+    name: PathBuf,
+    writer: W,
+    bit_depth: BitDepth,
+}
+
+impl<W: Write> StreamSink<W> {
+    pub fn new(name: impl Into<PathBuf>, writer: W) -> StreamSink<W> {
+        StreamSink {
+            name: name.into(),
+            writer,
+            bit_depth: BitDepth::Eight,
+        }
+    }
+
+    pub fn with_bit_depth(mut self, bit_depth: BitDepth) -> StreamSink<W> {
+        self.bit_depth = bit_depth;
+        self
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl<W: Write> ImageSink for StreamSink<W> {
+    fn write_image(&mut self, image: &Framebuffer) -> Result<()> {
+        output::encode_png(&mut self.writer, image, self.bit_depth, Encoding::default()).map_err(|e| RendererError::encode(&self.name, e))?;
+        self.writer.flush().map_err(|e| RendererError::io(&self.name, e))
+    }
+}
@@ -1,17 +1,21 @@
-use std::rc::Rc;
 use std::sync::Arc;
-use crate::hit::HitRecord;
+use crate::aabb::Aabb;
+use crate::error::Result;
+use crate::hit::{rounding_error, HitRecord};
 use crate::{Hit, Point3, Ray, Vec3};
 use crate::material::Scatter;
+use crate::scene_file::{Exporter, ObjectDesc};
+use crate::{float::consts, Float};
 
+#[derive(Debug)]
 pub struct Sphere {
     center: Point3,
-    radius: f64,
+    radius: Float,
     mat: Arc<dyn Scatter>,
 }
 
 impl Sphere {
-    pub fn new(c: Point3, r: f64, m: Arc<dyn Scatter>) -> Sphere {
+    pub fn new(c: Point3, r: Float, m: Arc<dyn Scatter>) -> Sphere {
         Sphere {
             center: c,
             radius: r,
@@ -20,8 +24,17 @@ impl Sphere {
     }
 }
 
+impl Sphere {
+    // u goes around the Y axis starting from -X, v from the bottom pole to the top one
+    pub(crate) fn uv(p: Point3) -> (Float, Float) {
+        let theta = (-p.y()).acos();
+        let phi = (-p.z()).atan2(p.x()) + consts::PI;
+        (phi / (2.0 * consts::PI), theta / consts::PI)
+    }
+}
+
 impl Hit for Sphere {
-    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+    fn hit(&self, r: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord> {
         let oc = r.origin() - self.center;  // difference between ray origin and center of circle
 
         // **simplified** quadratic formula
@@ -44,18 +57,90 @@ impl Hit for Sphere {
             }
         }
 
-        let p = r.at(root);
+        // the root is only roughly right, put the point back onto the surface
+        let d = r.at(root) - self.center;
+        let p = self.center + (self.radius.abs() / d.length()) * d;
         let mut rec = HitRecord {
             p,
             normal: Vec3::new(0.0, 0.0, 0.0),
             mat: self.mat.clone(),
             t: root,
+            u: 0.0,
+            v: 0.0,
             front_face: false,
+            error: rounding_error(self.center.max_abs() + self.radius.abs()),
         };
 
         let outward_normal = (rec.p - self.center) / self.radius;
         rec.set_face_normal(r, outward_normal);
+        (rec.u, rec.v) = Sphere::uv(outward_normal);
 
         Some(rec)
     }
-}
\ No newline at end of file
+    fn bounding_box(&self, _time0: Float, _time1: Float) -> Option<Aabb> {
+        // turned inside out the radius is negative
+        let r = self.radius.abs();
+        Some(Aabb::new(self.center - Vec3::new(r, r, r), self.center + Vec3::new(r, r, r)))
+    }
+
+    fn export(&self, out: &mut Exporter) -> Result<()> {
+        let material = out.material(&self.mat)?;
+        out.object(ObjectDesc::Sphere {
+            name: None,
+            center: self.center.to_array(),
+            radius: self.radius,
+            material,
+            hidden: false,
+            keyframes: Vec::new(),
+            visible_to: None,
+        });
+        Ok(())
+    }
+}
+
+// A sphere with a spherical hole in the middle, like a glass bubble or a fish bowl. Both
+// surfaces face out of the material, the inner one toward the center, so light refracts
+// into the wall and back out into the hollow.
+#[derive(Debug)]
+pub struct Shell {
+    outer: Sphere,
+    inner: Sphere,
+}
+
+impl Shell {
+    // the inner radius is below the outer one, both above zero
+    pub fn new(center: Point3, outer: Float, inner: Float, m: Arc<dyn Scatter>) -> Shell {
+        Shell {
+            outer: Sphere::new(center, outer, m.clone()),
+            // a negative radius turns the normals toward the center
+            inner: Sphere::new(center, -inner, m),
+        }
+    }
+}
+
+impl Hit for Shell {
+    fn hit(&self, r: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord> {
+        let outer = self.outer.hit(r, t_min, t_max);
+        let closest = outer.as_ref().map_or(t_max, |rec| rec.t);
+        self.inner.hit(r, t_min, closest).or(outer)
+    }
+
+    fn bounding_box(&self, time0: Float, time1: Float) -> Option<Aabb> {
+        self.outer.bounding_box(time0, time1)
+    }
+
+    fn export(&self, out: &mut Exporter) -> Result<()> {
+        let material = out.material(&self.outer.mat)?;
+        out.object(ObjectDesc::Shell {
+            name: None,
+            center: self.outer.center.to_array(),
+            outer: self.outer.radius,
+            inner: -self.inner.radius,
+            material,
+            hidden: false,
+            keyframes: Vec::new(),
+            visible_to: None,
+        });
+        Ok(())
+    }
+}
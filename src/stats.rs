@@ -0,0 +1,29 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use serde::Serialize;
+
+// Counters bumped by the render threads, read (and reset) once per frame
+pub static CAMERA_RAYS: AtomicU64 = AtomicU64::new(0);
+pub static RAYS: AtomicU64 = AtomicU64::new(0);
+// samples with NaN, infinite or negative radiance, see Renderer::with_radiance_check
+pub static BAD_SAMPLES: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Serialize, Default, Clone, Copy)]
+pub struct Stats {
+    pub camera_rays: u64,
+    pub rays: u64,
+    pub bad_samples: u64,
+}
+
+impl Stats {
+    pub fn take() -> Stats {
+        Stats {
+            camera_rays: CAMERA_RAYS.swap(0, Ordering::Relaxed),
+            rays: RAYS.swap(0, Ordering::Relaxed),
+            bad_samples: BAD_SAMPLES.swap(0, Ordering::Relaxed),
+        }
+    }
+}
+
+pub fn count(counter: &AtomicU64) {
+    counter.fetch_add(1, Ordering::Relaxed);
+}
@@ -0,0 +1,68 @@
+use crate::aabb::Aabb;
+use crate::error::{RendererError, Result};
+use crate::inspect;
+use crate::scene_file::{BackgroundDesc, MaterialDesc, ObjectDesc, SceneFile};
+use crate::{Float, Point3, Vec3};
+
+// A photo studio put around whatever the scene has in it, for quick product shots of a model
+// without setting up lights: an invisible ground under it that only takes its shadows (see
+// material::ShadowCatcher), a backdrop going from grey below to white above, and the three
+// lights of a portrait around the scene's bounds as its camera sees them. The key light is in
+// front to the left and above, the fill is weaker and lower on the right, and the rim light
+// from behind picks out the outline. They're softboxes the camera doesn't see directly, only
+// in reflections. The materials are called studio_ground, studio_key, studio_fill and
+// studio_rim, for overrides.
+pub fn apply(mut file: SceneFile) -> Result<SceneFile> {
+    let Aabb { min: lo, max: hi } = inspect::inspect(&file)?.bounds
+        .ok_or_else(|| RendererError::Scene("the studio needs something to light, the scene is empty".to_string()))?;
+    let center = 0.5 * (lo + hi);
+    let radius = (0.5 * (hi - lo).length()).max(1.0e-3);
+
+    // around the up axis the way the camera looks, straight down the z axis if it looks
+    // straight up or down
+    let c = &file.camera;
+    let look = Vec3::new(c.lookat[0] - c.lookfrom[0], 0.0, c.lookat[2] - c.lookfrom[2]);
+    let forward = if look.near_zero() { Vec3::new(0.0, 0.0, -1.0) } else { look.normalized() };
+    let up = Vec3::new(0.0, 1.0, 0.0);
+    let right = forward.cross(up);
+
+    // the lights are as big and as far away as the model is big, so they light any size of
+    // model the same
+    let lights = [
+        ("studio_key", -1.0 * forward - right + up, 8.0),
+        ("studio_fill", -1.0 * forward + right + 0.3 * up, 3.0),
+        ("studio_rim", forward + 0.5 * right + 1.2 * up, 8.0),
+    ];
+    for (name, direction, intensity) in lights {
+        let p = center + 4.0 * radius * direction.normalized();
+        file.materials.insert(name.to_string(), MaterialDesc::Light { color: [1.0, 1.0, 1.0], intensity, temperature: None });
+        file.objects.push(ObjectDesc::Sphere {
+            name: Some(name.to_string()),
+            center: p.to_array(),
+            radius: 0.8 * radius,
+            material: name.to_string(),
+            hidden: false,
+            keyframes: Vec::new(),
+            visible_to: Some(["diffuse", "specular", "transmission"].map(String::from).to_vec()),
+        });
+    }
+
+    // a square far out past the model, shadows only reaching as far as it's big
+    file.materials.insert("studio_ground".to_string(), MaterialDesc::ShadowCatcher { distance: radius, samples: 1 });
+    let size = 50.0 * radius;
+    let corner = |x: Float, z: Float| Point3::new(center.x() + x * size, lo.y(), center.z() + z * size).to_array();
+    for vertices in [[corner(-1.0, -1.0), corner(1.0, 1.0), corner(1.0, -1.0)], [corner(-1.0, -1.0), corner(-1.0, 1.0), corner(1.0, 1.0)]] {
+        file.objects.push(ObjectDesc::Triangle {
+            name: Some("studio_ground".to_string()),
+            vertices,
+            uv: None,
+            material: "studio_ground".to_string(),
+            hidden: false,
+            keyframes: Vec::new(),
+            visible_to: None,
+        });
+    }
+
+    file.background = BackgroundDesc::Gradient { bottom: [0.35, 0.35, 0.35], top: [0.85, 0.85, 0.85] };
+    Ok(file)
+}
@@ -0,0 +1,82 @@
+use std::path::{Path, PathBuf};
+use raytracer_test::framebuffer::Framebuffer;
+use raytracer_test::output::{self, Collision, Format, WriteOptions};
+use raytracer_test::overrides::{Override, Sweep};
+use raytracer_test::render::Renderer;
+use raytracer_test::review;
+use raytracer_test::{RendererError, Result};
+use crate::cli::SweepArgs;
+use crate::Source;
+
+// Renders every combination of the swept values, each on top of the --override ones, to its
+// own file named after the values. The last sweep changes fastest, so in the grid each row
+// goes through its values with everything else the same.
+pub fn run(args: &SweepArgs, overrides: &[Override], renderer: Renderer) -> Result<()> {
+    let count: usize = args.vary.iter().map(Sweep::len).product();
+    let format = args.output.as_deref().and_then(Format::from_path).unwrap_or(Format::Png);
+    let output = args.output.clone().unwrap_or_else(|| output::default_path(format));
+    let renderer = renderer.with_cancel(crate::cancel_on_ctrl_c());
+
+    let mut images = Vec::new();
+    for n in 0..count {
+        let mut steps = Vec::new();
+        let mut rest = n;
+        for sweep in args.vary.iter().rev() {
+            steps.push(rest % sweep.len());
+            rest /= sweep.len();
+        }
+        steps.reverse();
+
+        let mut variant = overrides.to_vec();
+        variant.extend(args.vary.iter().zip(&steps).map(|(sweep, &i)| sweep.step(i)));
+        let labels: Vec<String> = args.vary.iter().zip(&steps)
+            .map(|(sweep, &i)| format!("{}-{}", sweep.field(), sweep.value(i)))
+            .collect();
+        log::info!("Variant {} of {}: {}", n + 1, count, labels.join(", "));
+
+        let source = Source::load(args.scene.as_ref(), None, &variant, false)?;
+        let mut settings = source.settings();
+        if let Some(seed) = args.seed {
+            settings.seed = seed;
+        }
+        let scene = source.build(0.0, 0.0, 0.0, settings.aspect_ratio())?;
+        let data = renderer.render(&scene, &settings);
+        let path = output::resolve_path(variant_path(&output, &labels), Collision::Overwrite)?;
+        output::write(&path, &data, format, &WriteOptions::default())?;
+        if renderer.is_cancelled() {
+            return Err(RendererError::Cancelled);
+        }
+        if args.grid.is_some() {
+            images.push(data);
+        }
+    }
+
+    if let Some(path) = &args.grid {
+        write_grid(path, &images, args.vary.last().map_or(1, Sweep::len))?;
+    }
+    Ok(())
+}
+
+fn write_grid(path: &Path, images: &[Framebuffer], columns: usize) -> Result<()> {
+    // sweeping the render size leaves nothing to line up
+    let first = &images[0];
+    if let Some(other) = images.iter().find(|i| (i.width(), i.height()) != (first.width(), first.height())) {
+        return Err(RendererError::SizeMismatch((first.width(), first.height()), (other.width(), other.height())));
+    }
+    let format = Format::from_path(path).unwrap_or(Format::Png);
+    output::write(path, &review::grid(images, columns), format, &WriteOptions::default())
+}
+
+// "sweep.png" with fuzz at 0.5 becomes "sweep_fuzz-0.5.png", anything that doesn't belong in
+// a file name turned into underscores
+fn variant_path(path: &Path, labels: &[String]) -> PathBuf {
+    let suffix: String = labels.iter()
+        .flat_map(|label| std::iter::once('_').chain(label.chars()))
+        .map(|c| if c.is_ascii_alphanumeric() || "-.".contains(c) { c } else { '_' })
+        .collect();
+    let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or("render");
+    match path.extension().and_then(|e| e.to_str()) {
+        Some(ext) => path.with_file_name(format!("{}{}.{}", stem, suffix, ext)),
+        None => path.with_file_name(format!("{}{}", stem, suffix)),
+    }
+}
@@ -0,0 +1,372 @@
+use std::fmt::Debug;
+use std::path::{Path, PathBuf};
+use serde::{Deserialize, Serialize};
+use crate::colorspace::WorkingSpace;
+use crate::error::{RendererError, Result};
+use crate::framebuffer::Framebuffer;
+use crate::scene_file::TextureDesc;
+use crate::{input, noise, Color, Float, Point3};
+
+pub trait Texture : Send + Sync + Debug {
+    fn value(&self, u: Float, v: Float, p: Point3) -> Color;
+
+    // the texture as written in a scene file
+    fn export(&self) -> Result<TextureDesc> {
+        Err(RendererError::Unsupported(format!("{} can't be saved to a scene file", std::any::type_name::<Self>())))
+    }
+}
+
+// How a material lays a texture out over an object's UVs, so the same image can be smaller on
+// one object than on another: turned `rotation` degrees counterclockwise about the middle of
+// the texture, repeated `tiling` times across u and v, then slid along by `offset`. The UVs
+// wrap round past the edges, an image repeating rather than smearing its edge pixels.
+#[derive(Copy, Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct UvTransform {
+    #[serde(default)] pub offset: [Float; 2],
+    #[serde(default)] pub rotation: Float,
+    #[serde(default = "untiled")] pub tiling: [Float; 2],
+}
+
+fn untiled() -> [Float; 2] {
+    [1.0, 1.0]
+}
+
+impl UvTransform {
+    // where the texture is looked up for the surface's (u, v)
+    pub fn apply(&self, u: Float, v: Float) -> (Float, Float) {
+        let (sin, cos) = self.rotation.to_radians().sin_cos();
+        let (x, y) = (u - 0.5, v - 0.5);
+        let (x, y) = (cos * x - sin * y + 0.5, sin * x + cos * y + 0.5);
+        ((x * self.tiling[0] + self.offset[0]).rem_euclid(1.0), (y * self.tiling[1] + self.offset[1]).rem_euclid(1.0))
+    }
+}
+
+#[derive(Debug)]
+pub struct SolidColor {
+    color: Color,
+}
+
+impl SolidColor {
+    pub fn new(c: Color) -> SolidColor {
+        SolidColor {
+            color: c
+        }
+    }
+}
+
+impl Texture for SolidColor {
+    fn value(&self, _u: Float, _v: Float, _p: Point3) -> Color {
+        self.color
+    }
+
+    fn export(&self) -> Result<TextureDesc> {
+        Ok(TextureDesc::Solid { color: self.color.to_array() })
+    }
+}
+
+// 3D checker pattern, `scale` being the size of a cell in world units
+#[derive(Debug)]
+pub struct Checker {
+    even: Color,
+    odd: Color,
+    scale: Float,
+}
+
+impl Checker {
+    pub fn new(even: Color, odd: Color, scale: Float) -> Checker {
+        Checker {
+            even,
+            odd,
+            scale,
+        }
+    }
+}
+
+impl Texture for Checker {
+    fn value(&self, _u: Float, _v: Float, p: Point3) -> Color {
+        let cell = (p.x() / self.scale).floor() + (p.y() / self.scale).floor() + (p.z() / self.scale).floor();
+        if cell as i64 % 2 == 0 {
+            self.even
+        } else {
+            self.odd
+        }
+    }
+
+    fn export(&self) -> Result<TextureDesc> {
+        Ok(TextureDesc::Checker { even: self.even.to_array(), odd: self.odd.to_array(), scale: self.scale })
+    }
+}
+
+// A test pattern laid out by (u, v) rather than in space, for seeing how a mesh is unwrapped:
+// `cells` by `cells` squares, their hue going round the color wheel along u and every other
+// one dark, so stretched and squashed UVs show as stretched squares and seams as jumps in the
+// colors. A white mark in the corner of each square where u is low and v high turns and flips
+// with it, and UVs outside 0 to 1 come out magenta.
+#[derive(Debug)]
+pub struct UvChecker {
+    cells: u32,
+}
+
+impl UvChecker {
+    pub fn new(cells: u32) -> UvChecker {
+        UvChecker { cells: cells.max(1) }
+    }
+}
+
+impl Texture for UvChecker {
+    fn value(&self, u: Float, v: Float, _p: Point3) -> Color {
+        if !(0.0..=1.0).contains(&u) || !(0.0..=1.0).contains(&v) {
+            return Color::new(1.0, 0.0, 1.0);
+        }
+        let n = self.cells as Float;
+        let (x, y) = (u * n, v * n);
+        let (i, j) = (x.floor().min(n - 1.0), y.floor().min(n - 1.0));
+        let (fx, fy) = (x - i, y - j);
+        // a thin dark line between the squares
+        if fx.min(1.0 - fx).min(fy).min(1.0 - fy) < 0.03 {
+            return Color::new(0.02, 0.02, 0.02);
+        }
+        if (0.1..0.3).contains(&fx) && (0.7..0.9).contains(&fy) {
+            return Color::new(1.0, 1.0, 1.0);
+        }
+        let brightness = if (i + j) as i64 % 2 == 0 { 0.8 } else { 0.25 };
+        brightness * hue(i / n)
+    }
+
+    fn export(&self) -> Result<TextureDesc> {
+        Ok(TextureDesc::UvChecker { cells: self.cells })
+    }
+}
+
+// a saturated color `h` of the way round the color wheel, from red through green and blue
+fn hue(h: Float) -> Color {
+    let channel = |n: Float| {
+        let k = (n + 6.0 * h).rem_euclid(6.0);
+        0.3 + 0.7 * (1.0 - k.min(4.0 - k).clamp(0.0, 1.0))
+    };
+    Color::new(channel(5.0), channel(3.0), channel(1.0))
+}
+
+// Solid wood, as if carved out of a log standing up the y axis: growth rings `scale` scene
+// units apart going from `light` to `dark` and then sharply back, wobbled by noise so they
+// aren't perfect circles, with fine streaks along the grain.
+#[derive(Debug)]
+pub struct Wood {
+    light: Color,
+    dark: Color,
+    scale: Float,
+}
+
+impl Wood {
+    pub fn new(light: Color, dark: Color, scale: Float) -> Wood {
+        Wood { light, dark, scale }
+    }
+}
+
+impl Texture for Wood {
+    fn value(&self, _u: Float, _v: Float, p: Point3) -> Color {
+        let q = p / self.scale;
+        let wobble = noise::turbulence(Point3::new(0.3 * q.x(), 0.05 * q.y(), 0.3 * q.z()), 4);
+        let ring = ((q.x() * q.x() + q.z() * q.z()).sqrt() + 2.0 * wobble).fract();
+        let streaks = noise::perlin(Point3::new(12.0 * q.x(), 0.3 * q.y(), 12.0 * q.z()));
+        let t = (ring * ring * ring + 0.2 * streaks).clamp(0.0, 1.0);
+        (1.0 - t) * self.light + t * self.dark
+    }
+
+    fn export(&self) -> Result<TextureDesc> {
+        Ok(TextureDesc::Wood { light: self.light.to_array(), dark: self.dark.to_array(), scale: self.scale })
+    }
+}
+
+// Bricks laid by (u, v) in a running bond, `rows` courses from v 0 to 1, each brick twice as
+// long as it is high and every other course shifted by half a brick. No two bricks are quite
+// the same shade, and the mortar between them is a little uneven.
+#[derive(Debug)]
+pub struct Brick {
+    brick: Color,
+    mortar: Color,
+    rows: u32,
+}
+
+// the mortar's share of a course's height
+const MORTAR: Float = 0.08;
+
+impl Brick {
+    pub fn new(brick: Color, mortar: Color, rows: u32) -> Brick {
+        Brick { brick, mortar, rows: rows.max(1) }
+    }
+}
+
+impl Texture for Brick {
+    fn value(&self, u: Float, v: Float, _p: Point3) -> Color {
+        let n = self.rows as Float;
+        let y = v * n;
+        let j = y.floor();
+        let x = 0.5 * u * n + if j as i64 % 2 == 0 { 0.0 } else { 0.5 };
+        let i = x.floor();
+        let (fx, fy) = (x - i, y - j);
+        let grain = noise::turbulence(Point3::new(4.0 * u * n, 4.0 * y, 0.0), 3);
+        // bricks are twice as wide, the joints between them half as much of their width
+        if fy.min(1.0 - fy) < 0.5 * MORTAR || fx.min(1.0 - fx) < 0.25 * MORTAR {
+            return (0.9 + 0.2 * grain) * self.mortar;
+        }
+        let shade = 0.75 + 0.5 * noise::cell(i as i64, j as i64, 0);
+        (shade * (1.0 - 0.3 * grain)) * self.brick
+    }
+
+    fn export(&self) -> Result<TextureDesc> {
+        Ok(TextureDesc::Brick { brick: self.brick.to_array(), mortar: self.mortar.to_array(), rows: self.rows })
+    }
+}
+
+// Square tiles laid by (u, v), `cells` by `cells` of them from 0 to 1 in a checker of `even`
+// and `odd`, with lines of `grout` between them
+#[derive(Debug)]
+pub struct Tiles {
+    even: Color,
+    odd: Color,
+    grout: Color,
+    cells: u32,
+}
+
+// the grout's share of a tile's width, on each side
+const GROUT: Float = 0.03;
+
+impl Tiles {
+    pub fn new(even: Color, odd: Color, grout: Color, cells: u32) -> Tiles {
+        Tiles { even, odd, grout, cells: cells.max(1) }
+    }
+}
+
+impl Texture for Tiles {
+    fn value(&self, u: Float, v: Float, _p: Point3) -> Color {
+        let n = self.cells as Float;
+        let (x, y) = (u * n, v * n);
+        let (i, j) = (x.floor(), y.floor());
+        let (fx, fy) = (x - i, y - j);
+        if fx.min(1.0 - fx).min(fy).min(1.0 - fy) < GROUT {
+            return self.grout;
+        }
+        let shade = 0.95 + 0.1 * noise::cell(i as i64, j as i64, 1);
+        if (i + j) as i64 % 2 == 0 { shade * self.even } else { shade * self.odd }
+    }
+
+    fn export(&self) -> Result<TextureDesc> {
+        Ok(TextureDesc::Tiles { even: self.even.to_array(), odd: self.odd.to_array(), grout: self.grout.to_array(), cells: self.cells })
+    }
+}
+
+// What an image does past its edges, and how it's looked up
+#[derive(Copy, Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Wrap {
+    // the nearest pixel, the edge pixels going on past the edges
+    #[default]
+    Clamp,
+    // An equirectangular map wrapped round a sphere, longitude along u: filtered between the
+    // four nearest pixels, round from the right edge to the left so there's no seam where they
+    // meet, and going over to the mean of the top or bottom row towards the poles, which the
+    // whole row shares, so they aren't pinched into a star
+    Sphere,
+}
+
+impl Wrap {
+    pub fn is_clamp(&self) -> bool {
+        *self == Wrap::Clamp
+    }
+}
+
+// Image looked up by (u, v), v going up from the bottom of the image
+pub struct ImageTexture {
+    data: Framebuffer,
+    // where it was loaded from, made absolute so saved scenes find it from anywhere
+    path: PathBuf,
+    wrap: Wrap,
+    // the mean of the top and of the bottom row, for Wrap::Sphere
+    poles: [Color; 2],
+}
+
+impl ImageTexture {
+    // with the colors turned from the image's space (see WorkingSpace::of) into `space`
+    pub fn load(path: &Path, space: WorkingSpace) -> Result<ImageTexture> {
+        let mut data = input::read_image(path)?;
+        let from = WorkingSpace::of(&data.metadata);
+        for c in &mut data.beauty {
+            *c = space.convert(from, *c);
+        }
+        Ok(ImageTexture {
+            data,
+            path: std::path::absolute(path).map_err(|e| RendererError::io(path, e))?,
+            wrap: Wrap::Clamp,
+            poles: [Color::default(); 2],
+        })
+    }
+
+    pub fn with_wrap(mut self, wrap: Wrap) -> ImageTexture {
+        let (w, h) = (self.data.width(), self.data.height());
+        let mean = |y: u32| self.data.beauty[(y * w) as usize..((y + 1) * w) as usize].iter().copied().sum::<Color>() / w as Float;
+        self.poles = [mean(0), mean(h - 1)];
+        self.wrap = wrap;
+        self
+    }
+
+    // between the four pixels around (u, v), see Wrap::Sphere
+    fn spherical(&self, u: Float, v: Float) -> Color {
+        let (w, h) = (self.data.width() as i64, self.data.height() as i64);
+        // in pixels from the middle of the top left one
+        let x = u * w as Float - 0.5;
+        let y = ((1.0 - v) * h as Float - 0.5).clamp(-0.5, h as Float - 0.5);
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = (x - x0, y - y0);
+        let row = |j: i64| {
+            let j = j.clamp(0, h - 1) * w;
+            let (i0, i1) = ((x0 as i64).rem_euclid(w), (x0 as i64 + 1).rem_euclid(w));
+            (1.0 - fx) * self.data.beauty[(j + i0) as usize] + fx * self.data.beauty[(j + i1) as usize]
+        };
+        // past the middle of the top or bottom row, half a pixel from the pole
+        if y < 0.0 {
+            let t = -2.0 * y;
+            return (1.0 - t) * row(0) + t * self.poles[0];
+        }
+        if y > (h - 1) as Float {
+            let t = 2.0 * (y - (h - 1) as Float);
+            return (1.0 - t) * row(h - 1) + t * self.poles[1];
+        }
+        (1.0 - fy) * row(y0 as i64) + fy * row(y0 as i64 + 1)
+    }
+
+    pub fn width(&self) -> u32 {
+        self.data.width()
+    }
+
+    pub fn height(&self) -> u32 {
+        self.data.height()
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Debug for ImageTexture {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "ImageTexture({}x{})", self.data.width(), self.data.height())
+    }
+}
+
+impl Texture for ImageTexture {
+    fn value(&self, u: Float, v: Float, _p: Point3) -> Color {
+        if self.wrap == Wrap::Sphere {
+            return self.spherical(u, v);
+        }
+        let (w, h) = (self.data.width(), self.data.height());
+        let x = ((u.clamp(0.0, 1.0) * w as Float) as u32).min(w - 1);
+        let y = (((1.0 - v.clamp(0.0, 1.0)) * h as Float) as u32).min(h - 1);
+        self.data.beauty[(y * w + x) as usize]
+    }
+
+    fn export(&self) -> Result<TextureDesc> {
+        Ok(TextureDesc::Image { file: self.path.clone(), wrap: self.wrap })
+    }
+}
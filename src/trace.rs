@@ -0,0 +1,161 @@
+use std::fmt::{self, Display, Formatter};
+use std::sync::atomic::Ordering;
+use crate::hit::HitRecord;
+use crate::render::{sample_ray, trace_path, PathObserver, Settings};
+use crate::scene::Scene;
+use crate::{random, stats, Color, Float, Point3, Ray, Vec3};
+
+// Following single pixels bounce by bounce, for finding out where fireflies, black pixels and
+// NaNs come from. The pixel's samples are traced again the way the renderer traces them, from
+// the same seed, so they take the same paths.
+
+// bad samples logged with their paths, the rest are only counted
+const REPORTED: u64 = 10;
+
+// One sample of a pixel, from the camera until the path ended
+#[derive(Clone, Debug)]
+pub struct SampleTrace {
+    pub sample: u32,
+    pub bounces: Vec<Bounce>,
+    // what the sample adds to the pixel, before averaging
+    pub radiance: Color,
+}
+
+#[derive(Clone, Debug)]
+pub struct Bounce {
+    pub origin: Point3,
+    pub direction: Vec3,
+    // None when the ray left the scene
+    pub surface: Option<Surface>,
+    // light picked up here, from the surface or the background, as it reaches the camera
+    pub light: Color,
+    // where the path went on and how much of the light it kept, None when it ended here
+    pub scattered: Option<(Vec3, Color)>,
+    // what's left of the path's light after this bounce
+    pub throughput: Color,
+}
+
+#[derive(Clone, Debug)]
+pub struct Surface {
+    // index of the object in the scene's world
+    pub object: usize,
+    // None when the scene didn't name the material
+    pub material: Option<String>,
+    pub point: Point3,
+    pub normal: Vec3,
+    pub front_face: bool,
+    // from where the ray started
+    pub distance: Float,
+}
+
+// NaN, infinite or negative radiance, which no light can give
+pub fn is_bad(c: Color) -> bool {
+    (0..3).any(|i| !c[i].is_finite() || c[i] < 0.0)
+}
+
+// Every sample of pixel (x, y), counted from the top left like the image
+pub fn trace_pixel(scene: &Scene, settings: &Settings, x: u32, y: u32) -> Vec<SampleTrace> {
+    // the renderer counts rows from the bottom
+    let y = settings.height - y - 1;
+    random::reseed(random::pixel_seed(settings.seed, x, y));
+    (0..settings.samples_per_pixel)
+        .map(|sample| {
+            let (r, weight) = sample_ray(x, y, scene, settings);
+            let mut tracer = Tracer { scene, bounces: Vec::new(), throughput: Color::new(1.0, 1.0, 1.0) };
+            let radiance = if weight == 0.0 {
+                Color::default()
+            } else {
+                weight * trace_path(&r, scene, settings.max_depth, &settings.limits, &mut tracer)
+            };
+            SampleTrace { sample, bounces: tracer.bounces, radiance }
+        })
+        .collect()
+}
+
+// Logs a sample that came out bad with the path it took, the first few of them anyway
+pub fn report(scene: &Scene, settings: &Settings, x: u32, y: u32, sample: u32, radiance: Color) {
+    let reported = stats::BAD_SAMPLES.fetch_add(1, Ordering::Relaxed);
+    if reported >= REPORTED {
+        return;
+    }
+    match trace_pixel(scene, settings, x, y).get(sample as usize) {
+        Some(trace) => log::warn!("pixel ({}, {}) {}", x, y, trace),
+        None => log::warn!("pixel ({}, {}) sample {} came out {}", x, y, sample, radiance),
+    }
+    if reported + 1 == REPORTED {
+        log::warn!("only counting the bad samples from here on");
+    }
+}
+
+struct Tracer<'a> {
+    scene: &'a Scene,
+    bounces: Vec<Bounce>,
+    throughput: Color,
+}
+
+impl Tracer<'_> {
+    fn bounce(&mut self, ray: &Ray, surface: Option<Surface>, light: Color) {
+        self.bounces.push(Bounce {
+            origin: ray.origin(),
+            direction: ray.direction(),
+            surface,
+            light,
+            scattered: None,
+            throughput: self.throughput,
+        });
+    }
+}
+
+impl PathObserver for Tracer<'_> {
+    fn missed(&mut self, ray: &Ray, light: Color) {
+        self.bounce(ray, None, light);
+    }
+
+    fn hit(&mut self, ray: &Ray, object: usize, rec: &HitRecord, light: Color) {
+        let surface = Surface {
+            object,
+            material: self.scene.material_name(&rec.mat).map(str::to_string),
+            point: rec.p,
+            normal: rec.normal,
+            front_face: rec.front_face,
+            distance: rec.t * ray.direction().length(),
+        };
+        self.bounce(ray, Some(surface), light);
+    }
+
+    fn scattered(&mut self, scattered: &Ray, attenuation: Color, throughput: Color) {
+        self.throughput = throughput;
+        if let Some(last) = self.bounces.last_mut() {
+            last.scattered = Some((scattered.direction(), attenuation));
+            last.throughput = throughput;
+        }
+    }
+}
+
+impl Display for SampleTrace {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        writeln!(f, "sample {}: radiance {}{}", self.sample, self.radiance, if is_bad(self.radiance) { " (bad)" } else { "" })?;
+        for (depth, b) in self.bounces.iter().enumerate() {
+            write!(f, "  {}: from {} along {}", depth, b.origin, b.direction)?;
+            match &b.surface {
+                Some(s) => {
+                    let material = s.material.as_deref().map_or(String::new(), |m| format!(" ({})", m));
+                    writeln!(f, ", hit object {}{} at {} after {:.4}", s.object, material, s.point, s.distance)?;
+                    writeln!(f, "     normal {}{}", s.normal, if s.front_face { "" } else { ", from behind" })?;
+                }
+                None => writeln!(f, ", into the background")?,
+            }
+            if b.light.max_abs() > 0.0 || is_bad(b.light) {
+                writeln!(f, "     light {}", b.light)?;
+            }
+            match b.scattered {
+                Some((direction, attenuation)) => {
+                    writeln!(f, "     scattered along {}, attenuation {}, throughput {}", direction, attenuation, b.throughput)?
+                }
+                None if b.surface.is_some() => writeln!(f, "     the path ends here")?,
+                None => {}
+            }
+        }
+        Ok(())
+    }
+}
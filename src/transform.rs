@@ -0,0 +1,311 @@
+// 4x4 affine transforms for the scene importers and animation, row-major and applied to
+// column vectors
+use std::ops::Mul;
+use serde::{Deserialize, Serialize};
+use crate::{Float, Point3, Vec3};
+
+pub type Matrix = [[Float; 4]; 4];
+
+pub const IDENTITY: Matrix = [
+    [1.0, 0.0, 0.0, 0.0],
+    [0.0, 1.0, 0.0, 0.0],
+    [0.0, 0.0, 1.0, 0.0],
+    [0.0, 0.0, 0.0, 1.0],
+];
+
+pub fn mul(a: &Matrix, b: &Matrix) -> Matrix {
+    let mut m = [[0.0; 4]; 4];
+    for (i, row) in m.iter_mut().enumerate() {
+        for (j, v) in row.iter_mut().enumerate() {
+            *v = (0..4).map(|k| a[i][k] * b[k][j]).sum();
+        }
+    }
+    m
+}
+
+pub fn transpose(a: &Matrix) -> Matrix {
+    let mut m = [[0.0; 4]; 4];
+    for (i, row) in m.iter_mut().enumerate() {
+        for (j, v) in row.iter_mut().enumerate() {
+            *v = a[j][i];
+        }
+    }
+    m
+}
+
+// Gauss-Jordan with partial pivoting, None for singular matrices
+pub fn inverse(a: &Matrix) -> Option<Matrix> {
+    let mut m = *a;
+    let mut inv = IDENTITY;
+    for col in 0..4 {
+        let pivot = (col..4).max_by(|&x, &y| m[x][col].abs().total_cmp(&m[y][col].abs()))?;
+        if m[pivot][col].abs() < 1.0e-12 {
+            return None;
+        }
+        m.swap(col, pivot);
+        inv.swap(col, pivot);
+        let p = m[col][col];
+        for j in 0..4 {
+            m[col][j] /= p;
+            inv[col][j] /= p;
+        }
+        for row in 0..4 {
+            if row != col {
+                let f = m[row][col];
+                for j in 0..4 {
+                    m[row][j] -= f * m[col][j];
+                    inv[row][j] -= f * inv[col][j];
+                }
+            }
+        }
+    }
+    Some(inv)
+}
+
+pub fn point(m: &Matrix, p: Point3) -> Point3 {
+    let w = m[3][0]*p.x() + m[3][1]*p.y() + m[3][2]*p.z() + m[3][3];
+    Point3::new(
+        m[0][0]*p.x() + m[0][1]*p.y() + m[0][2]*p.z() + m[0][3],
+        m[1][0]*p.x() + m[1][1]*p.y() + m[1][2]*p.z() + m[1][3],
+        m[2][0]*p.x() + m[2][1]*p.y() + m[2][2]*p.z() + m[2][3],
+    ) / w
+}
+
+pub fn vector(m: &Matrix, v: Vec3) -> Vec3 {
+    Vec3::new(
+        m[0][0]*v.x() + m[0][1]*v.y() + m[0][2]*v.z(),
+        m[1][0]*v.x() + m[1][1]*v.y() + m[1][2]*v.z(),
+        m[2][0]*v.x() + m[2][1]*v.y() + m[2][2]*v.z(),
+    )
+}
+
+pub fn translate(d: Vec3) -> Matrix {
+    [
+        [1.0, 0.0, 0.0, d.x()],
+        [0.0, 1.0, 0.0, d.y()],
+        [0.0, 0.0, 1.0, d.z()],
+        [0.0, 0.0, 0.0, 1.0],
+    ]
+}
+
+pub fn scale(s: Vec3) -> Matrix {
+    [
+        [s.x(), 0.0, 0.0, 0.0],
+        [0.0, s.y(), 0.0, 0.0],
+        [0.0, 0.0, s.z(), 0.0],
+        [0.0, 0.0, 0.0, 1.0],
+    ]
+}
+
+pub fn rotate(degrees: Float, axis: Vec3) -> Matrix {
+    let a = axis.normalized();
+    let (sin, cos) = degrees.to_radians().sin_cos();
+    let (x, y, z) = (a.x(), a.y(), a.z());
+    [
+        [x*x + (1.0 - x*x)*cos, x*y*(1.0 - cos) - z*sin, x*z*(1.0 - cos) + y*sin, 0.0],
+        [x*y*(1.0 - cos) + z*sin, y*y + (1.0 - y*y)*cos, y*z*(1.0 - cos) - x*sin, 0.0],
+        [x*z*(1.0 - cos) - y*sin, y*z*(1.0 - cos) + x*sin, z*z + (1.0 - z*z)*cos, 0.0],
+        [0.0, 0.0, 0.0, 1.0],
+    ]
+}
+
+// Which way is up in a model or scene made in another program. Here y is up, as in Maya,
+// Houdini and glTF; Blender, 3ds Max and most CAD programs have z up, with y going away from
+// the viewer.
+#[derive(Copy, Clone, PartialEq, Debug, Default, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+#[cfg_attr(feature = "native", derive(clap::ValueEnum))]
+pub enum Up {
+    #[default]
+    Y,
+    Z,
+}
+
+impl Up {
+    pub fn is_y(&self) -> bool {
+        *self == Up::Y
+    }
+
+    // From the convention's axes into these, `unit_scale` being how long its unit is here, e.g.
+    // 0.001 for a model in millimetres when the scene is in metres. Z up turns a quarter
+    // around x, its y coming out as -z, away from a camera looking down -z as it is there.
+    pub fn conversion(self, unit_scale: Float) -> Matrix {
+        let turn = match self {
+            Up::Y => IDENTITY,
+            Up::Z => [
+                [1.0, 0.0, 0.0, 0.0],
+                [0.0, 0.0, 1.0, 0.0],
+                [0.0, -1.0, 0.0, 0.0],
+                [0.0, 0.0, 0.0, 1.0],
+            ],
+        };
+        mul(&scale(Vec3::new(unit_scale, unit_scale, unit_scale)), &turn)
+    }
+}
+
+// reflection across the plane through `p` with the given normal
+pub fn mirror(p: Point3, normal: Vec3) -> Matrix {
+    let n = normal.normalized();
+    let mut m = IDENTITY;
+    for i in 0..3 {
+        for j in 0..3 {
+            m[i][j] -= 2.0 * n[i] * n[j];
+        }
+    }
+    mul(&translate(p), &mul(&m, &translate(-1.0 * p)))
+}
+
+// `m` as a move, then a turn as a unit quaternion (w, x, y, z), then what's left of it: scales
+// and shears. The turn is the nearest rotation to the linear part, by polar decomposition
+// (Higham's iteration, as in pbrt's AnimatedTransform), and a mirroring goes with the scale.
+// None for singular matrices.
+pub fn decompose(m: &Matrix) -> Option<(Vec3, [Float; 4], Matrix)> {
+    let mut linear = *m;
+    for row in linear.iter_mut().take(3) {
+        row[3] = 0.0;
+    }
+    let mut r = linear;
+    for _ in 0..100 {
+        let it = transpose(&inverse(&r)?);
+        let mut next = r;
+        let mut change: Float = 0.0;
+        for i in 0..3 {
+            for j in 0..3 {
+                next[i][j] = 0.5 * (r[i][j] + it[i][j]);
+                change = change.max((next[i][j] - r[i][j]).abs());
+            }
+        }
+        r = next;
+        if change < 1.0e-6 {
+            break;
+        }
+    }
+    let det = r[0][0] * (r[1][1] * r[2][2] - r[1][2] * r[2][1]) - r[0][1] * (r[1][0] * r[2][2] - r[1][2] * r[2][0])
+        + r[0][2] * (r[1][0] * r[2][1] - r[1][1] * r[2][0]);
+    if det < 0.0 {
+        for row in r.iter_mut().take(3) {
+            for v in row.iter_mut().take(3) {
+                *v = -*v;
+            }
+        }
+    }
+    let rest = mul(&transpose(&r), &linear);
+    Some((Vec3::new(m[0][3], m[1][3], m[2][3]), quaternion(&r), rest))
+}
+
+// the other way round from decompose
+pub fn compose(translation: Vec3, rotation: [Float; 4], rest: &Matrix) -> Matrix {
+    let [w, x, y, z] = rotation;
+    let r = [
+        [1.0 - 2.0 * (y * y + z * z), 2.0 * (x * y - w * z), 2.0 * (x * z + w * y), 0.0],
+        [2.0 * (x * y + w * z), 1.0 - 2.0 * (x * x + z * z), 2.0 * (y * z - w * x), 0.0],
+        [2.0 * (x * z - w * y), 2.0 * (y * z + w * x), 1.0 - 2.0 * (x * x + y * y), 0.0],
+        [0.0, 0.0, 0.0, 1.0],
+    ];
+    mul(&translate(translation), &mul(&r, rest))
+}
+
+// the unit quaternion of a rotation matrix, by the largest of its four possible divisors
+fn quaternion(r: &Matrix) -> [Float; 4] {
+    let trace = r[0][0] + r[1][1] + r[2][2];
+    let q = if trace > 0.0 {
+        let s = 2.0 * (trace + 1.0).sqrt();
+        [0.25 * s, (r[2][1] - r[1][2]) / s, (r[0][2] - r[2][0]) / s, (r[1][0] - r[0][1]) / s]
+    } else if r[0][0] > r[1][1] && r[0][0] > r[2][2] {
+        let s = 2.0 * (1.0 + r[0][0] - r[1][1] - r[2][2]).sqrt();
+        [(r[2][1] - r[1][2]) / s, 0.25 * s, (r[0][1] + r[1][0]) / s, (r[0][2] + r[2][0]) / s]
+    } else if r[1][1] > r[2][2] {
+        let s = 2.0 * (1.0 + r[1][1] - r[0][0] - r[2][2]).sqrt();
+        [(r[0][2] - r[2][0]) / s, (r[0][1] + r[1][0]) / s, 0.25 * s, (r[1][2] + r[2][1]) / s]
+    } else {
+        let s = 2.0 * (1.0 + r[2][2] - r[0][0] - r[1][1]).sqrt();
+        [(r[1][0] - r[0][1]) / s, (r[0][2] + r[2][0]) / s, (r[1][2] + r[2][1]) / s, 0.25 * s]
+    };
+    let length = q.iter().map(|v| v * v).sum::<Float>().sqrt();
+    q.map(|v| v / length)
+}
+
+// `f` of the way from the turn `a` to `b` at an even speed, the short way round
+pub fn slerp(a: [Float; 4], b: [Float; 4], f: Float) -> [Float; 4] {
+    let mut cos: Float = (0..4).map(|i| a[i] * b[i]).sum();
+    let b = if cos < 0.0 {
+        cos = -cos;
+        b.map(|v| -v)
+    } else {
+        b
+    };
+    // nearly the same turn, where the angle can't be divided by
+    let (wa, wb) = if cos > 0.9995 {
+        (1.0 - f, f)
+    } else {
+        let angle = cos.acos();
+        (((1.0 - f) * angle).sin() / angle.sin(), (f * angle).sin() / angle.sin())
+    };
+    let q: [Float; 4] = std::array::from_fn(|i| wa * a[i] + wb * b[i]);
+    let length = q.iter().map(|v| v * v).sum::<Float>().sqrt();
+    q.map(|v| v / length)
+}
+
+// A matrix together with its inverse, for moving points, directions and normals back and forth
+// between an object's own space and the world without inverting on every ray
+#[derive(Copy, Clone, Debug)]
+pub struct Transform {
+    m: Matrix,
+    inv: Matrix,
+}
+
+impl Transform {
+    // None for singular matrices, a zero scale for one
+    pub fn new(m: Matrix) -> Option<Transform> {
+        Some(Transform {
+            m,
+            inv: inverse(&m)?,
+        })
+    }
+
+    pub fn matrix(&self) -> &Matrix {
+        &self.m
+    }
+
+    pub fn inverse(&self) -> Transform {
+        Transform {
+            m: self.inv,
+            inv: self.m,
+        }
+    }
+
+    pub fn point(&self, p: Point3) -> Point3 {
+        point(&self.m, p)
+    }
+
+    pub fn vector(&self, v: Vec3) -> Vec3 {
+        vector(&self.m, v)
+    }
+
+    // normals go through the inverse transpose to stay perpendicular to the surface under
+    // non-uniform scales, they come out unnormalized
+    pub fn normal(&self, n: Vec3) -> Vec3 {
+        vector(&transpose(&self.inv), n)
+    }
+}
+
+impl Default for Transform {
+    fn default() -> Transform {
+        Transform {
+            m: IDENTITY,
+            inv: IDENTITY,
+        }
+    }
+}
+
+// `a * b` applies b first, then a, like the matrices
+impl Mul for Transform {
+    type Output = Transform;
+
+    fn mul(self, other: Transform) -> Transform {
+        Transform {
+            m: mul(&self.m, &other.m),
+            inv: mul(&other.inv, &self.inv),
+        }
+    }
+}
@@ -0,0 +1,121 @@
+use std::sync::Arc;
+use crate::aabb::Aabb;
+use crate::error::Result;
+use crate::hit::{rounding_error, HitRecord};
+use crate::{Hit, Point3, Ray, Vec3};
+use crate::material::Scatter;
+use crate::scene_file::{Exporter, ObjectDesc};
+use crate::transform::{self, Matrix};
+use crate::Float;
+
+const DEFAULT_UV: [(Float, Float); 3] = [(0.0, 0.0), (1.0, 0.0), (0.0, 1.0)];
+
+#[derive(Debug)]
+pub struct Triangle {
+    v: [Point3; 3],
+    // texture coordinates of the vertices
+    uv: [(Float, Float); 3],
+    mat: Arc<dyn Scatter>,
+}
+
+impl Triangle {
+    pub fn new(v0: Point3, v1: Point3, v2: Point3, m: Arc<dyn Scatter>) -> Triangle {
+        Triangle {
+            v: [v0, v1, v2],
+            uv: DEFAULT_UV,
+            mat: m,
+        }
+    }
+
+    pub fn with_uv(mut self, uv: [(Float, Float); 3]) -> Triangle {
+        self.uv = uv;
+        self
+    }
+
+    pub fn vertices(&self) -> [Point3; 3] {
+        self.v
+    }
+
+    pub fn uv(&self) -> [(Float, Float); 3] {
+        self.uv
+    }
+
+    // The point at barycentric coordinates (b1, b2), seen from the side the vertices wind
+    // counterclockwise on. From the vertices rather than along a ray, so the error depends on
+    // the triangle only.
+    pub fn surface(&self, b1: Float, b2: Float) -> HitRecord {
+        let b0 = 1.0 - b1 - b2;
+        HitRecord {
+            p: b0*self.v[0] + b1*self.v[1] + b2*self.v[2],
+            normal: (self.v[1] - self.v[0]).cross(self.v[2] - self.v[0]).normalized(),
+            mat: self.mat.clone(),
+            t: 0.0,
+            u: b0*self.uv[0].0 + b1*self.uv[1].0 + b2*self.uv[2].0,
+            v: b0*self.uv[0].1 + b1*self.uv[1].1 + b2*self.uv[2].1,
+            front_face: true,
+            error: rounding_error(self.v[0].max_abs().max(self.v[1].max_abs()).max(self.v[2].max_abs())),
+        }
+    }
+
+    pub fn transformed(mut self, m: &Matrix) -> Triangle {
+        self.v = self.v.map(|p| transform::point(m, p));
+        self
+    }
+}
+
+impl Hit for Triangle {
+    // Möller–Trumbore
+    fn hit(&self, r: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord> {
+        const EPS: Float = 1.0e-12;
+        let e1 = self.v[1] - self.v[0];
+        let e2 = self.v[2] - self.v[0];
+
+        let pvec = r.direction().cross(e2);
+        let det = e1.dot(pvec);
+        if det.abs() < EPS {
+            // ray parallel to the triangle
+            return None;
+        }
+        let inv_det = 1.0 / det;
+
+        let tvec = r.origin() - self.v[0];
+        let b1 = tvec.dot(pvec) * inv_det;
+        if !(0.0..=1.0).contains(&b1) {
+            return None;
+        }
+        let qvec = tvec.cross(e1);
+        let b2 = r.direction().dot(qvec) * inv_det;
+        if b2 < 0.0 || b1 + b2 > 1.0 {
+            return None;
+        }
+
+        let t = e2.dot(qvec) * inv_det;
+        if t < t_min || t_max < t {
+            return None;
+        }
+
+        let mut rec = self.surface(b1, b2);
+        rec.t = t;
+        let outward = rec.normal;
+        rec.set_face_normal(r, outward);
+
+        Some(rec)
+    }
+    fn bounding_box(&self, _time0: Float, _time1: Float) -> Option<Aabb> {
+        Aabb::around(self.v)
+    }
+
+    fn export(&self, out: &mut Exporter) -> Result<()> {
+        let material = out.material(&self.mat)?;
+        out.object(ObjectDesc::Triangle {
+            name: None,
+            vertices: self.v.map(Vec3::to_array),
+            uv: (self.uv != DEFAULT_UV).then(|| self.uv.map(|(u, v)| [u, v])),
+            material,
+            hidden: false,
+            keyframes: Vec::new(),
+            visible_to: None,
+        });
+        Ok(())
+    }
+}
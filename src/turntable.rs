@@ -0,0 +1,61 @@
+use raytracer_test::colorspace::Encoding;
+use raytracer_test::output::{self, Collision, Format, WriteOptions};
+use raytracer_test::overrides::Override;
+use raytracer_test::render::Renderer;
+use raytracer_test::transform;
+use raytracer_test::{Float, RendererError, Result};
+use crate::cli::TurntableArgs;
+use crate::sequence::frame_path;
+use crate::video::VideoEncoder;
+use crate::Source;
+
+// Renders the scene from all around: the camera keeps its distance and height and circles
+// the target about its up direction, one full turn over the frames. The scene itself stays
+// put at time 0, so the lighting doesn't change from frame to frame.
+pub fn run(args: &TurntableArgs, overrides: &[Override], renderer: Renderer) -> Result<()> {
+    let source = Source::load(args.scene.as_ref(), None, overrides, false)?;
+    let mut settings = source.settings();
+    if let Some(seed) = args.seed {
+        settings.seed = seed;
+    }
+    let mut scene = source.build(0.0, 0.0, 0.0, settings.aspect_ratio())?;
+    let start = scene.camera;
+    let target = args.target.unwrap_or_else(|| start.lookat());
+    let offset = start.lookfrom() - target;
+
+    let format = args.output.as_deref().and_then(Format::from_path).unwrap_or(Format::Png);
+    let output = args.output.clone().unwrap_or_else(|| output::default_path(format));
+    let mut encoder = if VideoEncoder::is_video(&output) {
+        let path = output::resolve_path(output.clone(), Collision::Overwrite)?;
+        Some(VideoEncoder::new(&path, settings.width, settings.height, args.fps, None)?)
+    } else {
+        None
+    };
+    let renderer = renderer.with_cancel(crate::cancel_on_ctrl_c());
+
+    for n in 0..args.frames {
+        log::info!("Frame {}", n);
+        let angle = 360.0 * n as Float / args.frames as Float;
+        let turn = transform::rotate(angle, start.vup());
+        scene.camera = start.moved(target + transform::vector(&turn, offset), target);
+
+        let data = renderer.render(&scene, &settings);
+        match &mut encoder {
+            Some(encoder) => encoder.push_frame(&data, Encoding::default())?,
+            None => {
+                let path = output::resolve_path(frame_path(&output, n), Collision::Overwrite)?;
+                output::write(&path, &data, format, &WriteOptions::default())?
+            }
+        }
+        if renderer.is_cancelled() {
+            break;
+        }
+    }
+    if let Some(encoder) = encoder {
+        encoder.finish()?;
+    }
+    if renderer.is_cancelled() {
+        return Err(RendererError::Cancelled);
+    }
+    Ok(())
+}
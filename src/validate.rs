@@ -0,0 +1,578 @@
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use serde::Deserialize;
+use toml::Spanned;
+use crate::camera::Projection;
+use crate::fractal::FractalKind;
+use crate::framebuffer::MAX_PIXELS;
+use crate::scene_file::{BackgroundDesc, ClipDesc, LodDesc, MaskDesc, MaterialDesc, MaterialLibrary, ObjectDesc, SceneFile, TextureDesc};
+use crate::texture::UvTransform;
+use crate::transform::{self, Matrix};
+use crate::visibility::TraceGroups;
+use crate::Vec3;
+use crate::Float;
+
+// A value in a scene file that would break the render or make it meaningless
+#[derive(Debug)]
+pub struct Problem {
+    // the part of the file it's in, e.g. "objects[3]" or "materials.glass"
+    pub at: String,
+    pub field: &'static str,
+    pub message: String,
+}
+
+impl Problem {
+    // "line 12, objects[3].radius: ...", the line when `lines` knows where the part starts
+    pub fn describe(&self, lines: &HashMap<String, usize>) -> String {
+        let place = if self.field.is_empty() { self.at.clone() } else { format!("{}.{}", self.at, self.field) };
+        match lines.get(&self.at) {
+            Some(line) => format!("line {}, {}: {}", line, place, self.message),
+            None => format!("{}: {}", place, self.message),
+        }
+    }
+}
+
+// Everything wrong with the scene, section by section. Checked after parsing so one bad
+// value doesn't hide the next, and so the render never starts on a scene that can't work.
+pub fn check(scene: &SceneFile) -> Vec<Problem> {
+    let mut c = Checker { problems: Vec::new() };
+
+    let r = &scene.render;
+    // a pixel's u and v divide by one less than the width and height
+    for (field, value) in [("width", r.width), ("height", r.height)] {
+        if matches!(value, Some(0 | 1)) {
+            c.fail("render", field, "must be at least 2");
+        }
+    }
+    let size = scene.settings();
+    if (size.width as usize).checked_mul(size.height as usize).is_none_or(|pixels| pixels > MAX_PIXELS) {
+        c.fail("render", "width", format!("{}x{} is more than the {} pixels an image can have", size.width, size.height, MAX_PIXELS));
+    }
+    if r.samples_per_pixel == Some(0) {
+        c.fail("render", "samples_per_pixel", "must be at least 1");
+    }
+    if let Some(clamp) = r.clamp_indirect.filter(|v| !(*v > 0.0 && v.is_finite())) {
+        c.fail("render", "clamp_indirect", format!("{} is not a brightness above 0", clamp));
+    }
+    if let Some(strength) = r.regularize.filter(|v| !(*v >= 0.0 && v.is_finite())) {
+        c.fail("render", "regularize", format!("{} is not a roughness of 0 or more", strength));
+    }
+    if let Some(scale) = r.scale {
+        c.positive("render", "scale", scale);
+    }
+
+    let cam = &scene.camera;
+    c.finite("camera", "lookfrom", &cam.lookfrom);
+    c.finite("camera", "lookat", &cam.lookat);
+    c.finite("camera", "vup", &cam.vup);
+    let view = vec3(cam.lookat) - vec3(cam.lookfrom);
+    if view.near_zero() {
+        c.fail("camera", "lookat", "is the same point as lookfrom");
+    } else if vec3(cam.vup).cross(view).near_zero() {
+        c.fail("camera", "vup", "points along the view direction");
+    }
+    // panoramic projections see all around
+    let panoramic = cam.projection.unwrap_or_default() != Projection::Perspective;
+    let widest = if panoramic { 360.0 } else { 180.0 };
+    if !(cam.vfov > 0.0 && cam.vfov < widest) {
+        c.fail("camera", "vfov", format!("{} is not between 0 and {} degrees", cam.vfov, widest));
+    }
+    if !(cam.aperture >= 0.0 && cam.aperture.is_finite()) {
+        c.fail("camera", "aperture", format!("{} is not a size", cam.aperture));
+    }
+    if let Some(d) = cam.focus_dist {
+        c.positive("camera", "focus_dist", d);
+    }
+    if let Some(lens) = &cam.lens {
+        if panoramic {
+            c.fail("camera", "lens", "a real lens only works with the perspective projection");
+        }
+        if !scene.base_dir.join(&lens.file).is_file() {
+            c.fail("camera", "lens.file", format!("{} does not exist", scene.base_dir.join(&lens.file).display()));
+        }
+        if let Some(stop) = lens.stop {
+            c.positive("camera", "lens.stop", stop);
+        }
+        c.positive("camera", "lens.scale", lens.scale);
+    }
+
+    match &scene.background {
+        BackgroundDesc::Sky => {}
+        BackgroundDesc::Color { color } => c.finite("background", "color", color),
+        BackgroundDesc::Gradient { bottom, top } => {
+            c.finite("background", "bottom", bottom);
+            c.finite("background", "top", top);
+        }
+        BackgroundDesc::Map { file } => {
+            if !scene.base_dir.join(file).is_file() {
+                c.fail("background", "file", format!("{} does not exist", scene.base_dir.join(file).display()));
+            }
+        }
+    }
+    let env = &scene.environment;
+    c.finite("environment", "rotation", &[env.rotation]);
+    if !(env.intensity >= 0.0 && env.intensity.is_finite()) {
+        c.fail("environment", "intensity", format!("{} is not a brightness", env.intensity));
+    }
+    if let Some(file) = &env.backdrop {
+        if !scene.base_dir.join(file).is_file() {
+            c.fail("environment", "backdrop", format!("{} does not exist", scene.base_dir.join(file).display()));
+        }
+    }
+
+    if let Some(fog) = &scene.fog {
+        if !(fog.density >= 0.0 && fog.density.is_finite()) {
+            c.fail("fog", "density", format!("{} is not a density", fog.density));
+        }
+        c.finite("fog", "albedo", &fog.albedo);
+        if fog.albedo.iter().any(|&a| a < 0.0) {
+            c.fail("fog", "albedo", "can't be negative");
+        }
+        if !(fog.anisotropy > -1.0 && fog.anisotropy < 1.0) {
+            c.fail("fog", "anisotropy", format!("{} is not between -1 and 1", fog.anisotropy));
+        }
+        if let Some([a, b]) = &fog.inside {
+            c.finite("fog", "inside", a);
+            c.finite("fog", "inside", b);
+        }
+    }
+
+    c.materials(&scene.base_dir, &scene.textures, &scene.materials);
+
+    c.objects(scene, &scene.objects, "objects", &mut HashSet::new());
+
+    for (i, desc) in scene.clip.iter().enumerate() {
+        let at = format!("clip[{}]", i);
+        match desc {
+            ClipDesc::Plane { point, normal, .. } => {
+                c.finite(&at, "point", point);
+                c.finite(&at, "normal", normal);
+                if vec3(*normal).near_zero() {
+                    c.fail(&at, "normal", "has no direction");
+                }
+            }
+            ClipDesc::Camera { distance, .. } => c.positive(&at, "distance", *distance),
+        }
+    }
+
+    // a material's rays can only be in one group
+    let mut grouped: HashMap<&str, &str> = HashMap::new();
+    for (group, names) in sorted(&scene.ray_groups) {
+        let at = format!("ray_groups.{}", group);
+        if group.is_empty() {
+            c.fail(&at, "", "the group has no name");
+        }
+        for name in names {
+            if !scene.materials.contains_key(name) {
+                c.fail(&at, "", format!("unknown material '{}'", name));
+            } else if let Some(other) = grouped.insert(name, group) {
+                c.fail(&at, "", format!("material '{}' is in group '{}' already", name, other));
+            }
+        }
+    }
+    let mut groups = TraceGroups::new();
+    let names = scene.ray_groups.keys().chain(scene.objects.iter().flat_map(|o| o.visible_to().unwrap_or_default()));
+    if let Some(name) = names.into_iter().find(|n| groups.group(n).is_err()) {
+        c.fail("ray_groups", "", format!("'{}' is one trace group too many, a scene can have 64", name));
+    }
+
+    c.problems
+}
+
+// The same checks for the textures and materials of a material library
+pub fn check_library(library: &MaterialLibrary) -> Vec<Problem> {
+    let mut c = Checker { problems: Vec::new() };
+    c.materials(&library.base_dir, &library.textures, &library.materials);
+    c.problems
+}
+
+// Line each part of a TOML scene starts on, to point at the problems. Parsed again loosely
+// because the spans don't survive into the SceneFile.
+pub fn toml_lines(text: &str) -> HashMap<String, usize> {
+    #[derive(Deserialize)]
+    struct Spans {
+        render: Option<Spanned<toml::Table>>,
+        camera: Option<Spanned<toml::Table>>,
+        background: Option<Spanned<toml::Table>>,
+        environment: Option<Spanned<toml::Table>>,
+        fog: Option<Spanned<toml::Table>>,
+        #[serde(default)]
+        textures: HashMap<String, Spanned<toml::Table>>,
+        #[serde(default)]
+        materials: HashMap<String, Spanned<toml::Table>>,
+        #[serde(default)]
+        objects: Vec<Spanned<toml::Table>>,
+        #[serde(default)]
+        clip: Vec<Spanned<toml::Table>>,
+        #[serde(default)]
+        ray_groups: HashMap<String, Spanned<toml::Value>>,
+    }
+
+    let mut lines = HashMap::new();
+    let Ok(spans) = toml::from_str::<Spans>(text) else {
+        return lines;
+    };
+    let line = |s: &Spanned<toml::Table>| text[..s.span().start].matches('\n').count() + 1;
+
+    for (name, table) in [("render", &spans.render), ("camera", &spans.camera), ("background", &spans.background),
+                          ("environment", &spans.environment), ("fog", &spans.fog)] {
+        if let Some(t) = table {
+            lines.insert(name.to_string(), line(t));
+        }
+    }
+    for (name, t) in &spans.textures {
+        lines.insert(format!("textures.{}", name), line(t));
+    }
+    for (name, t) in &spans.materials {
+        lines.insert(format!("materials.{}", name), line(t));
+    }
+    for (i, t) in spans.objects.iter().enumerate() {
+        lines.insert(format!("objects[{}]", i), line(t));
+    }
+    for (i, t) in spans.clip.iter().enumerate() {
+        lines.insert(format!("clip[{}]", i), line(t));
+    }
+    for (name, v) in &spans.ray_groups {
+        lines.insert(format!("ray_groups.{}", name), text[..v.span().start].matches('\n').count() + 1);
+    }
+    lines
+}
+
+struct Checker {
+    problems: Vec<Problem>,
+}
+
+impl Checker {
+    // the objects in a list, the scene's or a group's, named `path` in the problems
+    fn objects(&mut self, scene: &SceneFile, descs: &[ObjectDesc], path: &str, groups: &mut HashSet<String>) {
+        for (i, desc) in descs.iter().enumerate() {
+            let at = format!("{}[{}]", path, i);
+            let material = match desc {
+                ObjectDesc::Sphere { center, radius, material, .. } => {
+                    self.finite(&at, "center", center);
+                    if !radius.is_finite() || *radius == 0.0 {
+                        self.fail(&at, "radius", format!("{} is not a radius", radius));
+                    } else if *radius < 0.0 && !matches!(scene.materials.get(material), Some(MaterialDesc::Dielectric { .. }) | None) {
+                        // hollow spheres turn the normals inside out, which only glass makes use of
+                        self.fail(&at, "radius", "is negative, only glass spheres can have a negative radius, use a shell for hollow spheres");
+                    }
+                    Some(material)
+                }
+                ObjectDesc::Shell { center, outer, inner, material, .. } => {
+                    self.finite(&at, "center", center);
+                    self.positive(&at, "inner", *inner);
+                    self.positive(&at, "outer", *outer);
+                    if *inner >= *outer {
+                        self.fail(&at, "inner", format!("{} should be below the outer radius {}", inner, outer));
+                    }
+                    Some(material)
+                }
+                ObjectDesc::Triangle { vertices, material, .. } => {
+                    vertices.iter().for_each(|v| self.finite(&at, "vertices", v));
+                    let [v0, v1, v2] = vertices.map(vec3);
+                    if (v1 - v0).cross(v2 - v0).near_zero() {
+                        self.fail(&at, "vertices", "the triangle has no area");
+                    }
+                    Some(material)
+                }
+                ObjectDesc::Mesh { file, material, transform, unit_scale, .. } => {
+                    if !scene.base_dir.join(file).is_file() {
+                        self.fail(&at, "file", format!("{} does not exist", scene.base_dir.join(file).display()));
+                    }
+                    if let Some(m) = transform {
+                        m.iter().for_each(|row| self.finite(&at, "transform", row));
+                    }
+                    if let Some(s) = unit_scale {
+                        self.positive(&at, "unit_scale", *s);
+                    }
+                    Some(material)
+                }
+                ObjectDesc::Fractal { shape, depth, center, size, material, .. } => {
+                    self.finite(&at, "center", center);
+                    self.positive(&at, "size", *size);
+                    if *shape == FractalKind::Mandelbulb && *depth == 0 {
+                        self.fail(&at, "depth", "the mandelbulb needs at least one iteration");
+                    } else if *depth > shape.max_depth() {
+                        self.fail(&at, "depth", format!("{} is too deep, a {} goes to {} at most", depth, shape.name(), shape.max_depth()));
+                    }
+                    Some(material)
+                }
+                ObjectDesc::Volume { file, density, albedo, emission, kelvin, transform, .. } => {
+                    if !scene.base_dir.join(file).is_file() {
+                        self.fail(&at, "file", format!("{} does not exist", scene.base_dir.join(file).display()));
+                    }
+                    self.positive(&at, "density", *density);
+                    self.finite(&at, "albedo", albedo);
+                    if albedo.iter().any(|&a| a < 0.0) {
+                        self.fail(&at, "albedo", "can't be negative");
+                    }
+                    if !(*emission >= 0.0 && emission.is_finite()) {
+                        self.fail(&at, "emission", format!("{} should be 0 or above", emission));
+                    }
+                    self.positive(&at, "kelvin", *kelvin);
+                    if let Some(m) = transform {
+                        m.iter().for_each(|row| self.finite(&at, "transform", row));
+                    }
+                    None
+                }
+                ObjectDesc::Group { name, objects, transform, end_transform, .. } => {
+                    if let Some(m) = transform {
+                        self.placement(&at, "transform", m);
+                    }
+                    if let Some(m) = end_transform {
+                        self.placement(&at, "end_transform", m);
+                    }
+                    self.objects(scene, objects, &format!("{}.objects", at), groups);
+                    // added after the objects inside, a group can't be an instance of itself
+                    if let Some(name) = name {
+                        groups.insert(name.clone());
+                    }
+                    None
+                }
+                ObjectDesc::Instance { of, transform, end_transform, lod, .. } => {
+                    if let Some(m) = transform {
+                        self.placement(&at, "transform", m);
+                    }
+                    if let Some(m) = end_transform {
+                        self.placement(&at, "end_transform", m);
+                    }
+                    if !groups.contains(of) {
+                        self.fail(&at, "of", format!("no group named '{}' before the instance", of));
+                    }
+                    self.lod(scene, &at, lod, groups);
+                    None
+                }
+                ObjectDesc::Scatter { of, on, inside, scale, rotation, density, lod, .. } => {
+                    self.lod(scene, &at, lod, groups);
+                    if !groups.contains(of) {
+                        self.fail(&at, "of", format!("no group named '{}' before the scatter", of));
+                    }
+                    match (on, inside) {
+                        (Some(name), None) => match descs.iter().find(|o| o.name() == Some(name.as_str())) {
+                            None => self.fail(&at, "on", format!("no object named '{}' in the same list", name)),
+                            Some(ObjectDesc::Sphere { .. } | ObjectDesc::Shell { .. } | ObjectDesc::Triangle { .. } | ObjectDesc::Mesh { .. }) => {}
+                            Some(_) => self.fail(&at, "on", format!("'{}' isn't a sphere, shell, triangle or mesh", name)),
+                        },
+                        (None, Some(corners)) => corners.iter().for_each(|c| self.finite(&at, "inside", c)),
+                        _ => self.fail(&at, "on", "give either an object to scatter on or the corners of a box inside"),
+                    }
+                    self.positive(&at, "scale", scale[0]);
+                    self.positive(&at, "scale", scale[1]);
+                    if scale[0] > scale[1] {
+                        self.fail(&at, "scale", "the smallest scale comes first");
+                    }
+                    self.finite(&at, "rotation", &[*rotation]);
+                    if let Some(texture) = density.as_ref().filter(|t| !scene.textures.contains_key(*t)) {
+                        self.fail(&at, "density", format!("unknown texture '{}'", texture));
+                    }
+                    None
+                }
+                ObjectDesc::Spheres { inside, radius, materials, clusters, spread, .. } => {
+                    inside.iter().for_each(|c| self.finite(&at, "inside", c));
+                    self.positive(&at, "radius", radius[0]);
+                    self.positive(&at, "radius", radius[1]);
+                    if radius[0] > radius[1] {
+                        self.fail(&at, "radius", "the smallest radius comes first");
+                    }
+                    if materials.is_empty() {
+                        self.fail(&at, "materials", "is empty, give at least one material and its weight");
+                    }
+                    for (name, weight) in materials {
+                        if !scene.materials.contains_key(name) {
+                            self.fail(&at, "materials", format!("unknown material '{}'", name));
+                        }
+                        if !weight.is_finite() || *weight < 0.0 {
+                            self.fail(&at, "materials", format!("the weight {} of '{}' should be 0 or above", weight, name));
+                        }
+                    }
+                    if !materials.is_empty() && materials.values().all(|w| *w == 0.0) {
+                        self.fail(&at, "materials", "every weight is 0");
+                    }
+                    if let Some(n) = clusters {
+                        if *n == 0 {
+                            self.fail(&at, "clusters", "should be above 0, leave it out for no clusters");
+                        }
+                        self.positive(&at, "spread", *spread);
+                    }
+                    None
+                }
+            };
+            if desc.name() == Some("") {
+                self.fail(&at, "name", "is empty, leave it out instead");
+            }
+            if let Some(material) = material.filter(|m| !scene.materials.contains_key(*m)) {
+                self.fail(&at, "material", format!("unknown material '{}'", material));
+            }
+            match desc.visible_to() {
+                Some([]) => self.fail(&at, "visible_to", "no ray sees the object, leave it out instead"),
+                Some(names) if names.iter().any(|n| n.is_empty()) => self.fail(&at, "visible_to", "has an empty group name"),
+                _ => {}
+            }
+            for k in desc.keyframes() {
+                self.finite(&at, "keyframes", &[k.time, k.scale]);
+                self.finite(&at, "keyframes", &k.translate);
+                self.finite(&at, "keyframes", &k.rotate);
+                if k.scale <= 0.0 {
+                    self.fail(&at, "keyframes", format!("the scale at time {} should be above 0", k.time));
+                }
+            }
+        }
+    }
+
+    // the levels of detail of an instance or scatter
+    fn lod(&mut self, scene: &SceneFile, at: &str, levels: &[LodDesc], groups: &HashSet<String>) {
+        for level in levels {
+            if !(level.distance >= 0.0 && level.distance.is_finite()) {
+                self.fail(at, "lod", format!("the distance {} should be 0 or above", level.distance));
+            }
+            match (&level.of, &level.impostor) {
+                (Some(of), None) if !groups.contains(of) => self.fail(at, "lod", format!("no group named '{}' before the instance", of)),
+                (None, Some(material)) if !scene.materials.contains_key(material) => {
+                    self.fail(at, "lod", format!("unknown impostor material '{}'", material))
+                }
+                (Some(_), None) | (None, Some(_)) => {}
+                _ => self.fail(at, "lod", format!("the level at {} needs either a group or an impostor material", level.distance)),
+            }
+        }
+    }
+
+    // a group's transform has to be undone for the rays going in
+    fn placement(&mut self, at: &str, field: &'static str, m: &Matrix) {
+        m.iter().for_each(|row| self.finite(at, field, row));
+        if m.iter().flatten().all(|v| v.is_finite()) && transform::inverse(m).is_none() {
+            self.fail(at, field, "can't be inverted, it flattens the group");
+        }
+    }
+
+    // the textures and materials sections, which material libraries have too
+    fn materials(&mut self, base_dir: &Path, textures: &HashMap<String, TextureDesc>, materials: &HashMap<String, MaterialDesc>) {
+        for (name, desc) in sorted(textures) {
+            let at = format!("textures.{}", name);
+            match desc {
+                TextureDesc::Solid { color } => self.finite(&at, "color", color),
+                TextureDesc::Checker { even, odd, scale } => {
+                    self.finite(&at, "even", even);
+                    self.finite(&at, "odd", odd);
+                    self.positive(&at, "scale", *scale);
+                }
+                TextureDesc::Image { file, .. } => {
+                    if !base_dir.join(file).is_file() {
+                        self.fail(&at, "file", format!("{} does not exist", base_dir.join(file).display()));
+                    }
+                }
+                TextureDesc::UvChecker { cells } => {
+                    if *cells == 0 {
+                        self.fail(&at, "cells", "must be at least 1");
+                    }
+                }
+                TextureDesc::Wood { light, dark, scale } => {
+                    self.finite(&at, "light", light);
+                    self.finite(&at, "dark", dark);
+                    self.positive(&at, "scale", *scale);
+                }
+                TextureDesc::Brick { brick, mortar, rows } => {
+                    self.finite(&at, "brick", brick);
+                    self.finite(&at, "mortar", mortar);
+                    if *rows == 0 {
+                        self.fail(&at, "rows", "must be at least 1");
+                    }
+                }
+                TextureDesc::Tiles { even, odd, grout, cells } => {
+                    self.finite(&at, "even", even);
+                    self.finite(&at, "odd", odd);
+                    self.finite(&at, "grout", grout);
+                    if *cells == 0 {
+                        self.fail(&at, "cells", "must be at least 1");
+                    }
+                }
+            }
+        }
+
+        for (name, desc) in sorted(materials) {
+            let at = format!("materials.{}", name);
+            match desc {
+                MaterialDesc::Lambertian { albedo, texture, uv } => {
+                    match (albedo, texture) {
+                        (Some(a), None) => self.finite(&at, "albedo", a),
+                        (None, Some(t)) if !textures.contains_key(t) => {
+                            self.fail(&at, "texture", format!("unknown texture '{}'", t));
+                        }
+                        (None, Some(_)) => {}
+                        _ => self.fail(&at, "", "give either an albedo or a texture"),
+                    }
+                    match (uv, texture) {
+                        (Some(uv), Some(_)) => self.uv(&at, "uv", uv),
+                        (Some(_), None) => self.fail(&at, "uv", "only lays out a texture, there's none"),
+                        (None, _) => {}
+                    }
+                }
+                MaterialDesc::Metal { albedo, fuzz } => {
+                    self.finite(&at, "albedo", albedo);
+                    self.finite(&at, "fuzz", &[*fuzz]);
+                }
+                MaterialDesc::Dielectric { ir } => self.positive(&at, "ir", *ir),
+                MaterialDesc::Light { color, intensity, temperature } => {
+                    self.finite(&at, "color", color);
+                    self.finite(&at, "intensity", &[*intensity]);
+                    if let Some(t) = temperature {
+                        self.positive(&at, "temperature", *t);
+                    }
+                }
+                MaterialDesc::ShadowCatcher { distance, .. } => self.positive(&at, "distance", *distance),
+                MaterialDesc::Mix { a, b, mask } => {
+                    for (field, m) in [("a", a), ("b", b)] {
+                        if !materials.contains_key(m) {
+                            self.fail(&at, field, format!("unknown material '{}'", m));
+                        } else if m == name {
+                            self.fail(&at, field, "a material can't be mixed from itself");
+                        }
+                    }
+                    match mask {
+                        MaskDesc::Texture { texture, .. } if !textures.contains_key(texture) => {
+                            self.fail(&at, "mask", format!("unknown texture '{}'", texture));
+                        }
+                        MaskDesc::Texture { uv, .. } => {
+                            if let Some(uv) = uv {
+                                self.uv(&at, "mask", uv);
+                            }
+                        }
+                        MaskDesc::Crevices { distance, .. } | MaskDesc::Edges { distance, .. } => self.positive(&at, "mask", *distance),
+                    }
+                }
+            }
+        }
+    }
+
+    fn fail(&mut self, at: &str, field: &'static str, message: impl Into<String>) {
+        self.problems.push(Problem { at: at.to_string(), field, message: message.into() });
+    }
+
+    fn finite(&mut self, at: &str, field: &'static str, values: &[Float]) {
+        if let Some(v) = values.iter().find(|v| !v.is_finite()) {
+            self.fail(at, field, format!("{} is not a number", v));
+        }
+    }
+
+    fn uv(&mut self, at: &str, field: &'static str, uv: &UvTransform) {
+        self.finite(at, field, &[uv.offset[0], uv.offset[1], uv.rotation]);
+        if let Some(t) = uv.tiling.iter().find(|t| **t == 0.0 || !t.is_finite()) {
+            self.fail(at, field, format!("tiling {} should be neither 0 nor infinite", t));
+        }
+    }
+
+    fn positive(&mut self, at: &str, field: &'static str, value: Float) {
+        if !(value > 0.0 && value.is_finite()) {
+            self.fail(at, field, format!("{} should be above 0", value));
+        }
+    }
+}
+
+fn sorted<T>(map: &HashMap<String, T>) -> Vec<(&String, &T)> {
+    let mut entries: Vec<_> = map.iter().collect();
+    entries.sort_by(|a, b| a.0.cmp(b.0));
+    entries
+}
+
+fn vec3(v: [Float; 3]) -> Vec3 {
+    Vec3::new(v[0], v[1], v[2])
+}
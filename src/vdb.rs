@@ -0,0 +1,661 @@
+use std::io::Read;
+use std::path::Path;
+use flate2::read::ZlibDecoder;
+use crate::error::{RendererError, Result};
+use crate::transform::{self, Matrix};
+use crate::{Float, Point3};
+
+// Float grids out of OpenVDB files, the smoke and fire caches simulations write out. Only what
+// volumes need is read: the tree of values and the transform from the voxels to the world,
+// the metadata is skipped. Files are read whole from the start, without the delayed loading
+// OpenVDB itself does, and with zip or blosc (lz4 and zlib) compression, in full or half
+// precision. NanoVDB files and grids of other types than float aren't read.
+#[derive(Debug)]
+pub struct Grid {
+    name: String,
+    // from index space, where the voxels are centered on whole numbers, to the file's world
+    // space
+    transform: Matrix,
+    background: f32,
+    // by their origin, sorted
+    root: Vec<([i32; 3], RootEntry)>,
+    // corners of the box around the active voxels in index space, None for an empty grid
+    bounds: Option<([i32; 3], [i32; 3])>,
+    max: f32,
+}
+
+#[derive(Debug)]
+enum RootEntry {
+    Tile(f32),
+    Child(Box<Upper>),
+}
+
+// the tree of OpenVDB's default float grids: 32³ lower nodes of 16³ leaves of 8³ voxels
+type Upper = Internal<Lower, 5>;
+type Lower = Internal<Leaf, 4>;
+
+const MAGIC: i64 = 0x56444220;
+// the first version that stores the compression of each grid and compresses the node masks
+const NODE_MASK_COMPRESSION: u32 = 222;
+
+const COMPRESS_ZIP: u32 = 1;
+const COMPRESS_ACTIVE_MASK: u32 = 2;
+const COMPRESS_BLOSC: u32 = 4;
+
+// how the inactive values of a node are written, see Reader::values
+const NO_MASK_OR_INACTIVE_VALS: u8 = 0;
+const NO_MASK_AND_ONE_INACTIVE_VAL: u8 = 2;
+const MASK_AND_NO_INACTIVE_VALS: u8 = 3;
+const MASK_AND_ONE_INACTIVE_VAL: u8 = 4;
+const MASK_AND_TWO_INACTIVE_VALS: u8 = 5;
+const NO_MASK_AND_ALL_VALS: u8 = 6;
+
+impl Grid {
+    // The grid called `name` in the file at `path`
+    pub fn load(path: &Path, name: &str) -> Result<Grid> {
+        if path.extension().is_some_and(|e| e == "nvdb") {
+            return Err(RendererError::Unsupported(format!(
+                "{}: NanoVDB files can't be read, convert the grid to an OpenVDB .vdb file", path.display())));
+        }
+        let bytes = std::fs::read(path).map_err(|e| RendererError::io(path, e))?;
+        let mut r = Reader::new(path, &bytes);
+        r.header()?;
+        let mut names = Vec::new();
+        for _ in 0..r.u32()? {
+            let unique = r.string()?;
+            // copies of a name get a suffix after a record separator
+            let grid_name = unique.split('\u{1e}').next().unwrap_or("").to_string();
+            let kind = r.string()?;
+            let parent = r.string()?;
+            let grid_pos = r.i64()?;
+            let _block_pos = r.i64()?;
+            let end_pos = r.i64()?;
+            if grid_name == name {
+                if !parent.is_empty() {
+                    return Err(RendererError::Unsupported(format!(
+                        "{}: grid '{}' is an instance of '{}', instanced grids can't be read", path.display(), name, parent)));
+                }
+                let half = kind.ends_with("_HalfFloat");
+                let kind = kind.trim_end_matches("_HalfFloat");
+                if kind != "Tree_float_5_4_3" {
+                    return Err(RendererError::Unsupported(format!(
+                        "{}: grid '{}' is a {}, only float grids can be read", path.display(), name, kind)));
+                }
+                r.seek(grid_pos)?;
+                r.half = half;
+                return r.grid(name);
+            }
+            names.push(format!("'{}'", grid_name));
+            r.seek(end_pos)?;
+        }
+        let names = if names.is_empty() { "none".to_string() } else { names.join(", ") };
+        Err(RendererError::Scene(format!("{} has no grid '{}', it has {}", path.display(), name, names)))
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn transform(&self) -> &Matrix {
+        &self.transform
+    }
+
+    // the index space corners of the box the grid has values in, grown by a voxel for the
+    // interpolation
+    pub fn bounds(&self) -> Option<(Point3, Point3)> {
+        self.bounds.map(|(lo, hi)| {
+            let corner = |c: [i32; 3], by: Float| Point3::new(c[0] as Float + by, c[1] as Float + by, c[2] as Float + by);
+            (corner(lo, -1.0), corner(hi, 1.0))
+        })
+    }
+
+    // the largest value anywhere, for the majorant of a volume
+    pub fn max(&self) -> Float {
+        self.max as Float
+    }
+
+    // the value of the voxel at `at`, the background outside the tree
+    pub fn value(&self, at: [i32; 3]) -> f32 {
+        let origin = at.map(|c| c & !((1 << Upper::TOTAL) - 1));
+        match self.root.binary_search_by_key(&origin, |(o, _)| *o) {
+            Ok(i) => match &self.root[i].1 {
+                RootEntry::Tile(value) => *value,
+                RootEntry::Child(node) => node.value(at),
+            },
+            Err(_) => self.background,
+        }
+    }
+
+    // trilinear between the voxels around the index space point `p`
+    pub fn sample(&self, p: Point3) -> Float {
+        let base = [p.x().floor(), p.y().floor(), p.z().floor()];
+        let f = [p.x() - base[0], p.y() - base[1], p.z() - base[2]];
+        let base = base.map(|c| c as i32);
+        let mut sum = 0.0;
+        for corner in 0..8 {
+            let mut weight = 1.0;
+            let mut at = base;
+            for axis in 0..3 {
+                if corner & (1 << axis) == 0 {
+                    weight *= 1.0 - f[axis];
+                } else {
+                    weight *= f[axis];
+                    at[axis] += 1;
+                }
+            }
+            if weight > 0.0 {
+                sum += weight * self.value(at) as Float;
+            }
+        }
+        sum
+    }
+}
+
+trait Node: Sized {
+    // log2 of the side of the node in voxels
+    const TOTAL: u32;
+
+    fn topology(r: &mut Reader, origin: [i32; 3]) -> Result<Self>;
+    fn buffers(&mut self, r: &mut Reader) -> Result<()>;
+    fn value(&self, at: [i32; 3]) -> f32;
+    // grows the box around the active voxels and the largest value by the node's
+    fn extent(&self, extent: &mut Extent);
+}
+
+#[derive(Debug)]
+struct Internal<C, const LOG2: u32> {
+    origin: [i32; 3],
+    value_mask: Vec<u64>,
+    // the tiles, where there are no children
+    values: Vec<f32>,
+    children: Vec<Option<Box<C>>>,
+}
+
+#[derive(Debug)]
+struct Leaf {
+    origin: [i32; 3],
+    value_mask: Vec<u64>,
+    values: Vec<f32>,
+}
+
+impl<C: Node, const LOG2: u32> Internal<C, LOG2> {
+    const SIZE: usize = 1 << (3 * LOG2);
+
+    fn offset(at: [i32; 3]) -> usize {
+        let mask = (1 << (LOG2 + C::TOTAL)) - 1;
+        let [x, y, z] = at.map(|c| ((c & mask) >> C::TOTAL) as usize);
+        (x << (2 * LOG2)) | (y << LOG2) | z
+    }
+
+    fn child_origin(&self, n: usize) -> [i32; 3] {
+        let side = (1 << LOG2) - 1;
+        let at = [n >> (2 * LOG2), (n >> LOG2) & side, n & side];
+        [0, 1, 2].map(|axis| self.origin[axis] + ((at[axis] as i32) << C::TOTAL))
+    }
+}
+
+impl<C: Node, const LOG2: u32> Node for Internal<C, LOG2> {
+    const TOTAL: u32 = LOG2 + C::TOTAL;
+
+    fn topology(r: &mut Reader, origin: [i32; 3]) -> Result<Self> {
+        let child_mask = r.mask(Self::SIZE)?;
+        let value_mask = r.mask(Self::SIZE)?;
+        let values = r.values(Self::SIZE, &value_mask)?;
+        let mut node = Internal { origin, value_mask, values, children: (0..Self::SIZE).map(|_| None).collect() };
+        for n in (0..Self::SIZE).filter(|&n| on(&child_mask, n)) {
+            node.children[n] = Some(Box::new(C::topology(r, node.child_origin(n))?));
+        }
+        Ok(node)
+    }
+
+    fn buffers(&mut self, r: &mut Reader) -> Result<()> {
+        self.children.iter_mut().flatten().try_for_each(|child| child.buffers(r))
+    }
+
+    fn value(&self, at: [i32; 3]) -> f32 {
+        let n = Self::offset(at);
+        match &self.children[n] {
+            Some(child) => child.value(at),
+            None => self.values[n],
+        }
+    }
+
+    fn extent(&self, extent: &mut Extent) {
+        for (n, child) in self.children.iter().enumerate() {
+            match child {
+                Some(child) => child.extent(extent),
+                None => {
+                    let origin = self.child_origin(n);
+                    let side = (1 << C::TOTAL) - 1;
+                    extent.include(origin, origin.map(|c| c + side), self.values[n], on(&self.value_mask, n));
+                }
+            }
+        }
+    }
+}
+
+impl Node for Leaf {
+    const TOTAL: u32 = 3;
+
+    fn topology(r: &mut Reader, origin: [i32; 3]) -> Result<Self> {
+        Ok(Leaf { origin, value_mask: r.mask(512)?, values: Vec::new() })
+    }
+
+    fn buffers(&mut self, r: &mut Reader) -> Result<()> {
+        // the mask once more, the same as in the topology
+        self.value_mask = r.mask(512)?;
+        self.values = r.values(512, &self.value_mask)?;
+        Ok(())
+    }
+
+    fn value(&self, at: [i32; 3]) -> f32 {
+        let [x, y, z] = at.map(|c| (c & 7) as usize);
+        self.values[(x << 6) | (y << 3) | z]
+    }
+
+    fn extent(&self, extent: &mut Extent) {
+        for (n, &value) in self.values.iter().enumerate() {
+            let at = [(n >> 6) as i32, ((n >> 3) & 7) as i32, (n & 7) as i32];
+            let voxel = [0, 1, 2].map(|axis| self.origin[axis] + at[axis]);
+            extent.include(voxel, voxel, value, on(&self.value_mask, n));
+        }
+    }
+}
+
+#[derive(Default)]
+struct Extent {
+    bounds: Option<([i32; 3], [i32; 3])>,
+    max: f32,
+}
+
+impl Extent {
+    // inactive values still count for the largest value, the interpolation reaches them
+    fn include(&mut self, lo: [i32; 3], hi: [i32; 3], value: f32, active: bool) {
+        self.max = self.max.max(value);
+        if active {
+            self.bounds = Some(match self.bounds {
+                None => (lo, hi),
+                Some((a, b)) => ([0, 1, 2].map(|i| a[i].min(lo[i])), [0, 1, 2].map(|i| b[i].max(hi[i]))),
+            });
+        }
+    }
+}
+
+fn on(mask: &[u64], n: usize) -> bool {
+    mask[n >> 6] >> (n & 63) & 1 != 0
+}
+
+// Reads the little-endian values of a file in memory, along with what the values of a grid
+// depend on: its compression, its background value and whether it was saved in half precision
+struct Reader<'a> {
+    path: &'a Path,
+    bytes: &'a [u8],
+    at: usize,
+    compression: u32,
+    background: f32,
+    half: bool,
+}
+
+impl<'a> Reader<'a> {
+    fn new(path: &'a Path, bytes: &'a [u8]) -> Reader<'a> {
+        Reader { path, bytes, at: 0, compression: 0, background: 0.0, half: false }
+    }
+
+    fn error(&self, message: impl std::fmt::Display) -> RendererError {
+        RendererError::decode(self.path, message)
+    }
+
+    fn take(&mut self, n: usize) -> Result<&'a [u8]> {
+        let bytes = self.bytes.get(self.at..self.at.saturating_add(n))
+            .ok_or_else(|| self.error(format!("the file ends at byte {}, {} more were expected", self.bytes.len(), n)))?;
+        self.at += n;
+        Ok(bytes)
+    }
+
+    fn seek(&mut self, to: i64) -> Result<()> {
+        if to < 0 || to as usize > self.bytes.len() {
+            return Err(self.error(format!("offset {} is outside the file", to)));
+        }
+        self.at = to as usize;
+        Ok(())
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N]> {
+        Ok(self.take(N)?.try_into().unwrap_or([0; N]))
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.array::<1>()?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.array()?))
+    }
+
+    fn i32(&mut self) -> Result<i32> {
+        Ok(i32::from_le_bytes(self.array()?))
+    }
+
+    fn i64(&mut self) -> Result<i64> {
+        Ok(i64::from_le_bytes(self.array()?))
+    }
+
+    fn f32(&mut self) -> Result<f32> {
+        Ok(f32::from_le_bytes(self.array()?))
+    }
+
+    fn f64(&mut self) -> Result<f64> {
+        Ok(f64::from_le_bytes(self.array()?))
+    }
+
+    fn vec3d(&mut self) -> Result<[Float; 3]> {
+        Ok([self.f64()? as Float, self.f64()? as Float, self.f64()? as Float])
+    }
+
+    fn string(&mut self) -> Result<String> {
+        let n = self.u32()? as usize;
+        Ok(String::from_utf8_lossy(self.take(n)?).into_owned())
+    }
+
+    fn coord(&mut self) -> Result<[i32; 3]> {
+        Ok([self.i32()?, self.i32()?, self.i32()?])
+    }
+
+    // the header and the file's metadata, up to the number of grids
+    fn header(&mut self) -> Result<()> {
+        if self.i64()? != MAGIC {
+            return Err(self.error("not an OpenVDB file"));
+        }
+        let version = self.u32()?;
+        if version < NODE_MASK_COMPRESSION {
+            return Err(RendererError::Unsupported(format!(
+                "{}: OpenVDB file version {} is too old, resave it with OpenVDB 2 or later", self.path.display(), version)));
+        }
+        let _library = (self.u32()?, self.u32()?);
+        if self.u8()? == 0 {
+            return Err(RendererError::Unsupported(format!("{}: files without grid offsets can't be read", self.path.display())));
+        }
+        let _uuid = self.take(36)?;
+        self.metadata()
+    }
+
+    // skips a map of metadata, every value says how long it is
+    fn metadata(&mut self) -> Result<()> {
+        for _ in 0..self.u32()? {
+            let _name = self.string()?;
+            let _kind = self.string()?;
+            let size = self.u32()? as usize;
+            self.take(size)?;
+        }
+        Ok(())
+    }
+
+    // the grid starting here
+    fn grid(&mut self, name: &str) -> Result<Grid> {
+        self.compression = self.u32()?;
+        self.metadata()?;
+        let transform = self.transform()?;
+        let buffers = self.i32()?;
+        if buffers != 1 {
+            return Err(self.error(format!("grid '{}' has {} buffers, only 1 is supported", name, buffers)));
+        }
+
+        // the topology of the tree, the root's tiles and children, then the leaves' values
+        self.background = self.f32()?;
+        let tiles = self.u32()?;
+        let children = self.u32()?;
+        let mut root = Vec::new();
+        let mut extent = Extent::default();
+        for _ in 0..tiles {
+            let origin = self.coord()?;
+            let value = self.f32()?;
+            let active = self.u8()? != 0;
+            let side = (1 << Upper::TOTAL) - 1;
+            extent.include(origin, origin.map(|c| c + side), value, active);
+            root.push((origin, RootEntry::Tile(value)));
+        }
+        for _ in 0..children {
+            let origin = self.coord()?;
+            root.push((origin, RootEntry::Child(Box::new(Upper::topology(self, origin)?))));
+        }
+        for (_, entry) in &mut root {
+            if let RootEntry::Child(node) = entry {
+                node.buffers(self)?;
+                node.extent(&mut extent);
+            }
+        }
+        root.sort_by_key(|(origin, _)| *origin);
+        Ok(Grid { name: name.to_string(), transform, background: self.background, root, bounds: extent.bounds, max: extent.max })
+    }
+
+    // The map from index to world space. OpenVDB's matrices multiply row vectors, the
+    // transpose of the ones here.
+    fn transform(&mut self) -> Result<Matrix> {
+        let kind = self.string()?;
+        let scale_translate = |scale: [Float; 3], t: [Float; 3]| [
+            [scale[0], 0.0, 0.0, t[0]],
+            [0.0, scale[1], 0.0, t[1]],
+            [0.0, 0.0, scale[2], t[2]],
+            [0.0, 0.0, 0.0, 1.0],
+        ];
+        match kind.as_str() {
+            "UniformScaleMap" | "ScaleMap" => {
+                let scale = self.vec3d()?;
+                // voxel size, the inverse scale and two more derived from it
+                self.take(4 * 24)?;
+                Ok(scale_translate(scale, [0.0; 3]))
+            }
+            "UniformScaleTranslateMap" | "ScaleTranslateMap" => {
+                let t = self.vec3d()?;
+                let scale = self.vec3d()?;
+                self.take(4 * 24)?;
+                Ok(scale_translate(scale, t))
+            }
+            "TranslationMap" => Ok(scale_translate([1.0; 3], self.vec3d()?)),
+            "AffineMap" => {
+                let mut m = [[0.0; 4]; 4];
+                for row in m.iter_mut() {
+                    for v in row.iter_mut() {
+                        *v = self.f64()? as Float;
+                    }
+                }
+                Ok(transform::transpose(&m))
+            }
+            _ => Err(RendererError::Unsupported(format!("{}: grids with a {} can't be read", self.path.display(), kind))),
+        }
+    }
+
+    fn mask(&mut self, bits: usize) -> Result<Vec<u64>> {
+        (0..bits / 64).map(|_| Ok(u64::from_le_bytes(self.array()?))).collect()
+    }
+
+    // The `count` values of a node. Inactive values may have been left out, to be put back
+    // from the background or one or two values saved along with a mask saying which goes
+    // where.
+    fn values(&mut self, count: usize, value_mask: &[u64]) -> Result<Vec<f32>> {
+        let metadata = self.u8()?;
+        let background = self.background;
+        let mut inactive = [if metadata == NO_MASK_OR_INACTIVE_VALS { background } else { -background }, background];
+        if matches!(metadata, NO_MASK_AND_ONE_INACTIVE_VAL | MASK_AND_ONE_INACTIVE_VAL | MASK_AND_TWO_INACTIVE_VALS) {
+            inactive[0] = self.f32()?;
+        }
+        if metadata == MASK_AND_TWO_INACTIVE_VALS {
+            inactive[1] = self.f32()?;
+        }
+        let selection = match metadata {
+            MASK_AND_NO_INACTIVE_VALS | MASK_AND_ONE_INACTIVE_VAL | MASK_AND_TWO_INACTIVE_VALS => Some(self.mask(count)?),
+            _ => None,
+        };
+        let stored = if self.compression & COMPRESS_ACTIVE_MASK != 0 && metadata != NO_MASK_AND_ALL_VALS {
+            (0..count).filter(|&n| on(value_mask, n)).count()
+        } else {
+            count
+        };
+        let stored_values: Vec<f32> = if self.half {
+            self.data(2 * stored)?.chunks_exact(2)
+                .map(|b| half::f16::from_le_bytes([b[0], b[1]]).to_f32())
+                .collect()
+        } else {
+            self.data(4 * stored)?.chunks_exact(4)
+                .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+                .collect()
+        };
+        if stored == count {
+            return Ok(stored_values);
+        }
+        let mut active = stored_values.into_iter();
+        Ok((0..count)
+            .map(|n| {
+                if on(value_mask, n) {
+                    active.next().unwrap_or(background)
+                } else {
+                    inactive[selection.as_ref().map_or(0, |s| on(s, n) as usize)]
+                }
+            })
+            .collect())
+    }
+
+    // `n` bytes, compressed the way the grid is
+    fn data(&mut self, n: usize) -> Result<Vec<u8>> {
+        if self.compression & (COMPRESS_BLOSC | COMPRESS_ZIP) == 0 {
+            return Ok(self.take(n)?.to_vec());
+        }
+        // negative sizes are for data that didn't get any smaller and was saved as it is
+        let size = self.i64()?;
+        let data = self.take(size.unsigned_abs() as usize)?;
+        let bytes = if size <= 0 {
+            data.to_vec()
+        } else if self.compression & COMPRESS_BLOSC != 0 {
+            blosc(data).map_err(|e| self.error(e))?
+        } else {
+            inflate(data).map_err(|e| self.error(e))?
+        };
+        if bytes.len() != n {
+            return Err(self.error(format!("{} bytes of values where {} were expected", bytes.len(), n)));
+        }
+        Ok(bytes)
+    }
+}
+
+fn inflate(data: &[u8]) -> std::result::Result<Vec<u8>, String> {
+    let mut bytes = Vec::new();
+    ZlibDecoder::new(data).read_to_end(&mut bytes).map_err(|e| e.to_string())?;
+    Ok(bytes)
+}
+
+// Decompresses a blosc 1 buffer: a header, then blocks compressed on their own, each split into
+// a stream for every byte of the values when they were shuffled
+fn blosc(data: &[u8]) -> std::result::Result<Vec<u8>, String> {
+    const SHUFFLE: u8 = 0x1;
+    const MEMCPYED: u8 = 0x2;
+    const BITSHUFFLE: u8 = 0x4;
+    const DONT_SPLIT: u8 = 0x10;
+    let short = || "blosc data ends early".to_string();
+    let word = |at: usize| data.get(at..at + 4).map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as usize).ok_or_else(short);
+    if data.len() < 16 {
+        return Err(short());
+    }
+    let (flags, typesize) = (data[2], (data[3] as usize).max(1));
+    let (nbytes, blocksize) = (word(4)?, word(8)?);
+    if nbytes == 0 {
+        return Ok(Vec::new());
+    }
+    if flags & MEMCPYED != 0 {
+        return data.get(16..16 + nbytes).map(<[u8]>::to_vec).ok_or_else(short);
+    }
+    if flags & BITSHUFFLE != 0 {
+        return Err("bit shuffled blosc data isn't supported".to_string());
+    }
+    let compressor = flags >> 5;
+    if compressor != 1 && compressor != 3 {
+        return Err(format!("blosc compressor {} isn't supported, only lz4 and zlib", compressor));
+    }
+    if blocksize == 0 {
+        return Err("blosc block size is 0".to_string());
+    }
+    let blocks = nbytes.div_ceil(blocksize);
+    let mut out = vec![0; nbytes];
+    for b in 0..blocks {
+        let start = word(16 + 4 * b)?;
+        let size = blocksize.min(nbytes - b * blocksize);
+        let leftover = size < blocksize;
+        let splits = if flags & DONT_SPLIT == 0 && typesize <= 16 && blocksize / typesize >= 128 && !leftover { typesize } else { 1 };
+        let mut block = vec![0; size];
+        let mut at = start;
+        for part in block.chunks_mut(size / splits) {
+            let compressed = word(at)?;
+            at += 4;
+            let src = data.get(at..at + compressed).ok_or_else(short)?;
+            at += compressed;
+            if compressed == part.len() {
+                part.copy_from_slice(src);
+            } else if compressor == 1 {
+                let n = lz4_flex::block::decompress_into(src, part).map_err(|e| e.to_string())?;
+                if n != part.len() {
+                    return Err(short());
+                }
+            } else {
+                let inflated = inflate(src)?;
+                part.copy_from_slice(inflated.get(..part.len()).ok_or_else(short)?);
+            }
+        }
+        let dest = &mut out[b * blocksize..b * blocksize + size];
+        if flags & SHUFFLE != 0 && typesize > 1 {
+            // byte j of every value together, then byte j + 1, what's left over at the end
+            let n = size / typesize;
+            for i in 0..n {
+                for j in 0..typesize {
+                    dest[i * typesize + j] = block[j * n + i];
+                }
+            }
+            dest[n * typesize..].copy_from_slice(&block[n * typesize..]);
+        } else {
+            dest.copy_from_slice(&block);
+        }
+    }
+    Ok(out)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Both files hold the same grid 'density', zip compressed in full precision and blosc (lz4,
+    // shuffled) compressed in half precision, with the inactive values left out: a leaf at the
+    // origin with (1, 2, 3) = 1.5 and (7, 7, 7) = 2, a leaf at (8, 16, 0) with (9, 17, 4) = 0.75
+    // and the inactive (10, 18, 5) = 0.625, an active tile of 0.25 over (0..7, 0..7, 120..127)
+    // and an inactive root tile of 0.5 far off. Voxels are half a unit, moved by (1, 2, 3).
+    fn load(name: &str) -> Grid {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/vdb").join(name);
+        Grid::load(&path, "density").unwrap()
+    }
+
+    fn check(grid: &Grid) {
+        for (at, value) in [([1, 2, 3], 1.5), ([7, 7, 7], 2.0), ([9, 17, 4], 0.75), ([10, 18, 5], 0.625),
+                            ([3, 3, 124], 0.25), ([2, 2, 2], 0.0), ([100, 100, 100], 0.0), ([-1, 0, 0], 0.0)] {
+            assert_eq!(grid.value(at), value, "voxel {:?}", at);
+        }
+        let (lo, hi) = grid.bounds().unwrap();
+        assert_eq!((lo.to_array(), hi.to_array()), ([-1.0, -1.0, 2.0], [10.0, 18.0, 128.0]));
+        assert_eq!(grid.max(), 2.0);
+        assert_eq!(transform::point(grid.transform(), Point3::new(2.0, 2.0, 2.0)).to_array(), [2.0, 3.0, 4.0]);
+    }
+
+    #[test]
+    fn zip_compressed() {
+        check(&load("zip.vdb"));
+    }
+
+    #[test]
+    fn blosc_compressed_half() {
+        check(&load("blosc.vdb"));
+    }
+
+    #[test]
+    fn missing_grid() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/vdb/zip.vdb");
+        let error = Grid::load(&path, "temperature").unwrap_err().to_string();
+        assert!(error.ends_with("has no grid 'temperature', it has 'density'"), "{}", error);
+    }
+}
@@ -1,11 +1,12 @@
 use std::fmt::{Display, Formatter};
 use std::iter::Sum;
 use std::ops::{Add, AddAssign, Div, DivAssign, Index, IndexMut, Mul, MulAssign, Range, Sub, SubAssign};
-use rand::Rng;
+use crate::random;
+use crate::float::Float;
 
-#[derive(Clone, Copy, Default)]
+#[derive(Clone, Copy, Default, Debug)]
 pub struct Vec3 {
-    e: [f64; 3]
+    e: [Float; 3]
 }
 
 impl Sum for Vec3 {
@@ -20,29 +21,33 @@ pub type Point3 = Vec3;
 pub type Color = Vec3;
 
 impl Vec3 {
-    pub fn new (e0: f64, e1: f64, e2: f64) -> Vec3 {
+    pub fn new (e0: Float, e1: Float, e2: Float) -> Vec3 {
         Vec3 {
             e: [e0, e1, e2]
         }
     }
 
-    pub fn x(self) -> f64 {
+    pub fn x(self) -> Float {
         self[0]
     }
 
-    pub fn y(self) -> f64 {
+    pub fn y(self) -> Float {
         self[1]
     }
 
-    pub fn z(self) -> f64 {
+    pub fn z(self) -> Float {
         self[2]
     }
 
-    pub fn dot(self, other: Vec3) -> f64 {
+    pub fn to_array(self) -> [Float; 3] {
+        self.e
+    }
+
+    pub fn dot(self, other: Vec3) -> Float {
         self[0] * other[0] + self[1] * other[1] + self[2] * other[2]
     }
 
-    pub fn length(self) -> f64 {
+    pub fn length(self) -> Float {
         self.dot(self).sqrt()
     }
 
@@ -56,6 +61,11 @@ impl Vec3 {
         }
     }
 
+    // largest absolute coordinate
+    pub fn max_abs(self) -> Float {
+        self[0].abs().max(self[1].abs()).max(self[2].abs())
+    }
+
     pub fn normalized(self) -> Vec3 {
         self / self.length()
     }
@@ -63,19 +73,25 @@ impl Vec3 {
     // specifically for colors
     pub fn color_rgb(self, samples_per_pixel: u32) -> (u8, u8, u8) {
         (
-            (256.0 * (self[0]/(samples_per_pixel as f64)).sqrt().clamp(0.0, 0.999)) as u8,
-            (256.0 * (self[1]/(samples_per_pixel as f64)).sqrt().clamp(0.0, 0.999)) as u8,
-            (256.0 * (self[2]/(samples_per_pixel as f64)).sqrt().clamp(0.0, 0.999)) as u8
+            (256.0 * (self[0]/(samples_per_pixel as Float)).sqrt().clamp(0.0, 0.999)) as u8,
+            (256.0 * (self[1]/(samples_per_pixel as Float)).sqrt().clamp(0.0, 0.999)) as u8,
+            (256.0 * (self[2]/(samples_per_pixel as Float)).sqrt().clamp(0.0, 0.999)) as u8
         )
     }
 
-    // -- random vectors -- to emulate diffuse rays (for matte materials)
+    pub fn color_rgb16(self, samples_per_pixel: u32) -> (u16, u16, u16) {
+        (
+            (65536.0 * (self[0]/(samples_per_pixel as Float)).sqrt().clamp(0.0, 0.99999)) as u16,
+            (65536.0 * (self[1]/(samples_per_pixel as Float)).sqrt().clamp(0.0, 0.99999)) as u16,
+            (65536.0 * (self[2]/(samples_per_pixel as Float)).sqrt().clamp(0.0, 0.99999)) as u16
+        )
+    }
 
-    pub fn rand(r: Range<f64>) -> Vec3 {
-        let mut rng = rand::thread_rng();
+    // -- random vectors -- to emulate diffuse rays (for matte materials)
 
+    pub fn rand(r: Range<Float>) -> Vec3 {
         Vec3 {
-            e: [rng.gen_range(r.clone()), rng.gen_range(r.clone()), rng.gen_range(r.clone())]
+            e: [random::gen_range(r.clone()), random::gen_range(r.clone()), random::gen_range(r.clone())]
         }
     }
 
@@ -98,7 +114,7 @@ impl Vec3 {
     }
 
     pub fn near_zero(self) -> bool {
-        const EPS: f64 = 1.0e-8;
+        const EPS: Float = 1.0e-8;
         self[0].abs() < EPS && self[1].abs() < EPS && self[2].abs() < EPS
     }
 
@@ -106,7 +122,7 @@ impl Vec3 {
         self - 2.0*self.dot(n)*n
     }
 
-    pub fn refract(self, n: Vec3, eta_rat: f64) -> Vec3 {
+    pub fn refract(self, n: Vec3, eta_rat: Float) -> Vec3 {
         let cos_theta = ((-1.0) * self).dot(n).min(1.0);
         let r_out_perp = eta_rat * (self + cos_theta*n);
         let r_out_parallel = -(1.0 - r_out_perp.length().powi(2)).abs().sqrt()*n;
@@ -114,10 +130,8 @@ impl Vec3 {
     }
 
     pub fn rand_in_unit_disk() -> Vec3 {
-        let mut rng = rand::thread_rng();
-
         loop {
-            let p = Vec3::new(rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0), 0.0);
+            let p = Vec3::new(random::gen_range(-1.0..1.0), random::gen_range(-1.0..1.0), 0.0);
             if p.length() < 1.0 {
                 return p;
             }
@@ -132,15 +146,15 @@ impl Display for Vec3 {
 }
 
 impl Index<usize> for Vec3 {
-    type Output = f64;
+    type Output = Float;
 
-    fn index(&self, index: usize) -> &f64 {
+    fn index(&self, index: usize) -> &Float {
         &self.e[index]
     }
 }
 
 impl IndexMut<usize> for Vec3 {
-    fn index_mut(&mut self, index: usize) -> &mut f64 {
+    fn index_mut(&mut self, index: usize) -> &mut Float {
         &mut self.e[index]
     }
 }
@@ -156,7 +170,7 @@ impl Add for Vec3 {
 }
 
 impl AddAssign for Vec3 {
-    fn add_assign(&mut self, other: Vec3) -> () {
+    fn add_assign(&mut self, other: Vec3) {
         *self = Vec3 {
             e: [self[0] + other[0], self[1] + other[1], self[2] + other[2]]
         };
@@ -174,25 +188,25 @@ impl Sub for Vec3 {
 }
 
 impl SubAssign for Vec3 {
-    fn sub_assign(&mut self, other: Vec3) -> () {
+    fn sub_assign(&mut self, other: Vec3) {
         *self = Vec3 {
             e: [self[0] - other[0], self[1] - other[1], self[2] - other[2]]
         };
     }
 }
 
-impl Mul<f64> for Vec3 {
+impl Mul<Float> for Vec3 {
     type Output = Vec3;
 
-    fn mul(self, other: f64) -> Vec3 {
+    fn mul(self, other: Float) -> Vec3 {
         Vec3 {
             e: [self[0] * other, self[1] * other, self[2] * other]
         }
     }
 }
 
-impl MulAssign<f64> for Vec3 {
-    fn mul_assign(&mut self, other: f64) -> () {
+impl MulAssign<Float> for Vec3 {
+    fn mul_assign(&mut self, other: Float) {
         *self = Vec3 {
             e: [self[0] * other, self[1] * other, self[2] * other]
         };
@@ -210,14 +224,14 @@ impl Mul<Vec3> for Vec3 {
 }
 
 impl MulAssign<Vec3> for Vec3 {
-    fn mul_assign(&mut self, other: Vec3) -> () {
+    fn mul_assign(&mut self, other: Vec3) {
         *self = Vec3 {
             e: [self[0] * other[0], self[1] * other[1], self[2] * other[2]]
         };
     }
 }
 
-impl Mul<Vec3> for f64 {
+impl Mul<Vec3> for Float {
     type Output = Vec3;
 
     fn mul(self, other: Vec3) -> Vec3 {
@@ -227,18 +241,18 @@ impl Mul<Vec3> for f64 {
     }
 }
 
-impl Div<f64> for Vec3 {
+impl Div<Float> for Vec3 {
     type Output = Vec3;
 
-    fn div(self, other: f64) -> Vec3 {
+    fn div(self, other: Float) -> Vec3 {
         Vec3 {
             e: [self[0] / other, self[1] / other, self[2] / other]
         }
     }
 }
 
-impl DivAssign<f64> for Vec3 {
-    fn div_assign(&mut self, other: f64) -> () {
+impl DivAssign<Float> for Vec3 {
+    fn div_assign(&mut self, other: Float) {
         *self = Vec3 {
             e: [self[0] / other, self[1] / other, self[2] / other]
         };
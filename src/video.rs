@@ -0,0 +1,60 @@
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use raytracer_test::framebuffer::Framebuffer;
+use raytracer_test::colorspace::Encoding;
+use raytracer_test::{RendererError, Result};
+
+// Encodes frames into a video by piping raw RGB into an ffmpeg child process
+pub struct VideoEncoder {
+    child: Child,
+    stdin: ChildStdin,
+    path: PathBuf,
+}
+
+impl VideoEncoder {
+    // Whether the path names a video container we know how to encode
+    pub fn is_video(path: &Path) -> bool {
+        matches!(path.extension().and_then(|e| e.to_str()), Some("mp4") | Some("webm"))
+    }
+
+    pub fn new(path: &Path, width: u32, height: u32, fps: f64, bitrate: Option<&str>) -> Result<VideoEncoder> {
+        let codec: &[&str] = match path.extension().and_then(|e| e.to_str()) {
+            Some("webm") => &["-c:v", "libvpx-vp9"],
+            // yuv420p is what most players expect from H.264
+            _ => &["-c:v", "libx264", "-pix_fmt", "yuv420p"],
+        };
+
+        let mut cmd = Command::new("ffmpeg");
+        cmd.args(["-y", "-loglevel", "error"])
+            .args(["-f", "rawvideo", "-pix_fmt", "rgb24"])
+            .args(["-s", &format!("{}x{}", width, height)])
+            .args(["-r", &fps.to_string()])
+            .args(["-i", "-"])
+            .args(codec);
+        if let Some(b) = bitrate {
+            cmd.args(["-b:v", b]);
+        }
+        cmd.arg(path).stdin(Stdio::piped());
+
+        let mut child = cmd.spawn().map_err(|e| RendererError::io(Path::new("ffmpeg"), e))?;
+        let stdin = child.stdin.take().expect("stdin is piped");
+        Ok(VideoEncoder { child, stdin, path: path.to_path_buf() })
+    }
+
+    // shown through the OCIO view when there is one, see output::WriteOptions
+    pub fn push_frame(&mut self, data: &Framebuffer, encoding: Encoding) -> Result<()> {
+        self.stdin.write_all(&data.to_rgb8(encoding)).map_err(|e| RendererError::io(&self.path, e))
+    }
+
+    pub fn finish(self) -> Result<()> {
+        // closing stdin tells ffmpeg the stream has ended
+        let VideoEncoder { mut child, stdin, path } = self;
+        drop(stdin);
+        let status = child.wait().map_err(|e| RendererError::io(Path::new("ffmpeg"), e))?;
+        if !status.success() {
+            return Err(RendererError::encode(&path, format!("ffmpeg failed with {}", status)));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,173 @@
+use std::sync::Arc;
+use crate::aabb::Aabb;
+use crate::error::{RendererError, Result};
+use crate::hit::{HitRecord, HittableList};
+use crate::material::Scatter;
+use crate::scene_file::{Exporter, MaterialDesc};
+use crate::{Color, Float, Hit, Point3, Ray};
+
+// Which rays see which objects. Every ray is in one trace group: camera rays in "camera",
+// bounces in "diffuse", "specular" or "transmission" depending on how they left the surface,
+// unless their material puts them in a group of its own (see Grouped). Objects are seen by
+// every group unless they're given a list (see Visible), so a background card can show up for
+// the camera only, or a stand-in sphere only for the rays coming off one material.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct TraceGroup(u8);
+
+impl TraceGroup {
+    pub const CAMERA: TraceGroup = TraceGroup(0);
+    pub const DIFFUSE: TraceGroup = TraceGroup(1);
+    pub const SPECULAR: TraceGroup = TraceGroup(2);
+    pub const TRANSMISSION: TraceGroup = TraceGroup(3);
+
+    // one of the above rather than a scene's own
+    pub fn is_built_in(self) -> bool {
+        (self.0 as usize) < BUILT_IN.len()
+    }
+}
+
+const BUILT_IN: [&str; 4] = ["camera", "diffuse", "specular", "transmission"];
+
+// the groups an object is seen by, with their names for saving the scene
+#[derive(Clone, Debug)]
+pub struct Visibility {
+    bits: u64,
+    names: Vec<String>,
+}
+
+impl Visibility {
+    pub fn contains(&self, group: TraceGroup) -> bool {
+        self.bits & (1 << group.0) != 0
+    }
+
+    pub fn names(&self) -> &[String] {
+        &self.names
+    }
+}
+
+// The group names of a scene. The built-in groups are always there, others are added as
+// they're first used, up to 64 in all.
+#[derive(Clone, Debug)]
+pub struct TraceGroups {
+    names: Vec<String>,
+}
+
+impl TraceGroups {
+    pub fn new() -> TraceGroups {
+        TraceGroups {
+            names: BUILT_IN.iter().map(|n| n.to_string()).collect(),
+        }
+    }
+
+    pub fn group(&mut self, name: &str) -> Result<TraceGroup> {
+        if let Some(i) = self.names.iter().position(|n| n == name) {
+            return Ok(TraceGroup(i as u8));
+        }
+        if self.names.len() == 64 {
+            return Err(RendererError::Scene(format!("trace group '{}': a scene can't have more than 64 groups", name)));
+        }
+        self.names.push(name.to_string());
+        Ok(TraceGroup(self.names.len() as u8 - 1))
+    }
+
+    pub fn name(&self, group: TraceGroup) -> &str {
+        &self.names[group.0 as usize]
+    }
+
+    // seen by the rays of the named groups and nothing else
+    pub fn visibility(&mut self, names: &[String]) -> Result<Visibility> {
+        let mut bits = 0;
+        for name in names {
+            bits |= 1 << self.group(name)?.0;
+        }
+        Ok(Visibility { bits, names: names.to_vec() })
+    }
+}
+
+impl Default for TraceGroups {
+    fn default() -> TraceGroups {
+        TraceGroups::new()
+    }
+}
+
+// An object only some of the rays see, see TraceGroup
+#[derive(Debug)]
+pub struct Visible {
+    object: Arc<dyn Hit>,
+    visibility: Visibility,
+}
+
+impl Visible {
+    pub fn new(object: Arc<dyn Hit>, visibility: Visibility) -> Visible {
+        Visible {
+            object,
+            visibility,
+        }
+    }
+}
+
+impl Hit for Visible {
+    fn hit(&self, r: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord> {
+        if !self.visibility.contains(r.group()) {
+            return None;
+        }
+        self.object.hit(r, t_min, t_max)
+    }
+
+    fn bounding_box(&self, time0: Float, time1: Float) -> Option<Aabb> {
+        self.object.bounding_box(time0, time1)
+    }
+
+    fn export(&self, out: &mut Exporter) -> Result<()> {
+        let first = out.object_count();
+        self.object.export(out)?;
+        out.visible_since(first, self.visibility.names());
+        Ok(())
+    }
+}
+
+// A material whose scattered rays go into `group` instead of the one the material picks
+#[derive(Debug)]
+pub struct Grouped {
+    material: Arc<dyn Scatter>,
+    group: TraceGroup,
+    name: String,
+}
+
+impl Grouped {
+    pub fn new(material: Arc<dyn Scatter>, group: TraceGroup, groups: &TraceGroups) -> Grouped {
+        Grouped {
+            material,
+            group,
+            name: groups.name(group).to_string(),
+        }
+    }
+}
+
+impl Scatter for Grouped {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Color, Ray)> {
+        self.material.scatter(r_in, rec).map(|(attenuation, scattered)| (attenuation, scattered.with_group(self.group)))
+    }
+
+    fn emitted(&self, u: Float, v: Float, p: Point3) -> Color {
+        self.material.emitted(u, v, p)
+    }
+
+    fn is_light(&self) -> bool {
+        self.material.is_light()
+    }
+
+    fn export(&self, out: &mut Exporter) -> Result<MaterialDesc> {
+        self.material.export(out)
+    }
+
+    fn trace_group(&self) -> Option<&str> {
+        Some(&self.name)
+    }
+
+    // what's picked goes into the group too
+    fn pick(&self, rec: &HitRecord, world: &HittableList, time: Float) -> Option<Arc<dyn Scatter>> {
+        let material = self.material.pick(rec, world, time)?;
+        Some(Arc::new(Grouped { material, group: self.group, name: self.name.clone() }))
+    }
+}
@@ -0,0 +1,207 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use crate::aabb::Aabb;
+use crate::blackbody;
+use crate::colorspace::WorkingSpace;
+use crate::error::{RendererError, Result};
+use crate::hit::HitRecord;
+use crate::material::Scatter;
+use crate::scene_file::{Exporter, ObjectDesc};
+use crate::transform::{self, Matrix};
+use crate::vdb::Grid;
+use crate::visibility::TraceGroup;
+use crate::{random, Color, Float, Hit, Point3, Ray, Vec3};
+
+// Smoke, clouds and fire out of the density grid of an OpenVDB file (see vdb::Grid). Rays go
+// through it by delta tracking: free flights as if the whole volume were as dense as its
+// densest voxel, each ending in a real collision with the odds of how dense it actually is
+// there. A ray that collides hits the volume at that point and scatters off it every way alike,
+// so the volume is a surface to the rest of the renderer, found at a different distance by
+// every ray; shadow rays get through it as often as light would.
+//
+// With a temperature grid the volume also glows where it's hot, with the color and brightness
+// of a black body: fire and explosions. Denser parts glow brighter, as more of the rays end in
+// them.
+#[derive(Debug)]
+pub struct Volume {
+    file: PathBuf,
+    density: Grid,
+    // how much of the light is lost per scene unit where the grid is 1
+    scale: Float,
+    // from the grid's world space to the scene's, None for where the file puts it
+    placement: Option<Matrix>,
+    // from the scene to the grid's index space
+    to_index: Matrix,
+    // index space corners of the box the grid has values in
+    bounds: Option<(Point3, Point3)>,
+    // how much is lost per scene unit in the densest voxel
+    majorant: Float,
+    mat: Arc<Medium>,
+}
+
+impl Volume {
+    pub fn new(file: &Path, density: Grid, scale: Float, albedo: Color) -> Volume {
+        let to_index = transform::inverse(density.transform()).unwrap_or(transform::IDENTITY);
+        Volume {
+            file: file.to_path_buf(),
+            bounds: density.bounds(),
+            majorant: scale * density.max().max(0.0),
+            density,
+            scale,
+            placement: None,
+            to_index,
+            mat: Arc::new(Medium { albedo, fire: None }),
+        }
+    }
+
+    // glows with `emission` times a black body at `kelvin` times the temperature grid, its
+    // colors in `space`
+    pub fn with_fire(mut self, temperature: Grid, emission: Float, kelvin: Float, space: WorkingSpace) -> Result<Volume> {
+        let colors = blackbody::Table::new().in_space(space);
+        let fire = Fire::new(temperature, self.placement.as_ref(), emission, kelvin, colors)?;
+        self.mat = Arc::new(Medium { albedo: self.mat.albedo, fire: Some(fire) });
+        Ok(self)
+    }
+
+    // moves the volume by `m` from where the file puts it, fire and all
+    pub fn with_transform(mut self, m: &Matrix) -> Result<Volume> {
+        let world = transform::mul(m, self.density.transform());
+        self.to_index = transform::inverse(&world)
+            .ok_or_else(|| RendererError::Scene(format!("transform {:?} can't be inverted", m)))?;
+        self.placement = Some(*m);
+        if let Some(fire) = &self.mat.fire {
+            let fire = Fire::new(fire.temperature.clone(), Some(m), fire.emission, fire.kelvin, fire.colors.clone())?;
+            self.mat = Arc::new(Medium { albedo: self.mat.albedo, fire: Some(fire) });
+        }
+        Ok(self)
+    }
+}
+
+impl Hit for Volume {
+    fn hit(&self, r: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord> {
+        let (lo, hi) = self.bounds?;
+        // the ray in index space, where it goes through the same points at the same t
+        let o = transform::point(&self.to_index, r.origin());
+        let d = transform::vector(&self.to_index, r.direction());
+        let (mut t0, mut t1) = (t_min, t_max);
+        for axis in 0..3 {
+            let inv = 1.0 / d[axis];
+            let (a, b) = ((lo[axis] - o[axis]) * inv, (hi[axis] - o[axis]) * inv);
+            let (a, b) = if inv < 0.0 { (b, a) } else { (a, b) };
+            t0 = t0.max(a);
+            t1 = t1.min(b);
+        }
+        // per unit of t rather than per scene unit
+        let majorant = self.majorant * r.direction().length();
+        if t0 >= t1 || majorant <= 0.0 {
+            return None;
+        }
+        let mut t = t0;
+        loop {
+            t -= (1.0 - random::gen::<Float>()).ln() / majorant;
+            if t >= t1 {
+                return None;
+            }
+            let density = self.scale * self.density.sample(o + t * d).max(0.0);
+            if random::gen::<Float>() * self.majorant < density {
+                break;
+            }
+        }
+        Some(HitRecord {
+            p: r.at(t),
+            // there's no surface, rays leave from the point itself
+            normal: -1.0 * r.direction().normalized(),
+            mat: self.mat.clone(),
+            t,
+            u: 0.0,
+            v: 0.0,
+            front_face: true,
+            error: 0.0,
+        })
+    }
+
+    // the box of the grid's values, placed into the scene
+    fn bounding_box(&self, _time0: Float, _time1: Float) -> Option<Aabb> {
+        let (lo, hi) = self.bounds?;
+        Some(Aabb::new(lo, hi).transformed(&transform::inverse(&self.to_index)?))
+    }
+
+    fn export(&self, out: &mut Exporter) -> Result<()> {
+        let fire = self.mat.fire.as_ref();
+        out.object(ObjectDesc::Volume {
+            name: None,
+            file: self.file.clone(),
+            grid: self.density.name().to_string(),
+            temperature: fire.map(|f| f.temperature.name().to_string()),
+            density: self.scale,
+            albedo: self.mat.albedo.to_array(),
+            emission: fire.map_or(1.0, |f| f.emission),
+            kelvin: fire.map_or(1000.0, |f| f.kelvin),
+            transform: self.placement,
+            hidden: false,
+            keyframes: Vec::new(),
+            visible_to: None,
+        });
+        Ok(())
+    }
+}
+
+// What a volume is made of: the share of the light it scatters rather than absorbs, and the
+// fire in it
+#[derive(Debug)]
+pub struct Medium {
+    albedo: Color,
+    fire: Option<Fire>,
+}
+
+#[derive(Debug)]
+struct Fire {
+    temperature: Arc<Grid>,
+    // from the scene to the temperature grid's index space
+    to_index: Matrix,
+    emission: Float,
+    kelvin: Float,
+    colors: blackbody::Table,
+}
+
+impl Fire {
+    fn new(temperature: impl Into<Arc<Grid>>, placement: Option<&Matrix>, emission: Float, kelvin: Float,
+           colors: blackbody::Table) -> Result<Fire> {
+        let temperature = temperature.into();
+        let world = transform::mul(placement.unwrap_or(&transform::IDENTITY), temperature.transform());
+        let to_index = transform::inverse(&world)
+            .ok_or_else(|| RendererError::Scene(format!("the transform of temperature grid '{}' can't be inverted", temperature.name())))?;
+        Ok(Fire { temperature, to_index, emission, kelvin, colors })
+    }
+
+    // what a black body at `t` kelvin gives off
+    fn blackbody(&self, t: Float) -> Color {
+        if t <= 0.0 {
+            return Color::new(0.0, 0.0, 0.0);
+        }
+        blackbody::brightness(t) * self.colors.color(t)
+    }
+}
+
+impl Scatter for Medium {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Color, Ray)> {
+        let scattered = rec.spawn_ray(Vec3::rand_in_unit_sphere().normalized())
+            .with_time(r_in.time())
+            .with_group(TraceGroup::DIFFUSE);
+        Some((self.albedo, scattered))
+    }
+
+    fn emitted(&self, _u: Float, _v: Float, p: Point3) -> Color {
+        match &self.fire {
+            Some(fire) => {
+                let t = fire.temperature.sample(transform::point(&fire.to_index, p)) * fire.kelvin;
+                fire.emission * fire.blackbody(t)
+            }
+            None => Color::new(0.0, 0.0, 0.0),
+        }
+    }
+
+    fn is_light(&self) -> bool {
+        self.fire.as_ref().is_some_and(|fire| fire.emission > 0.0)
+    }
+}
@@ -0,0 +1,84 @@
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+use raytracer_test::output::Format;
+use raytracer_test::{inspect, overlay, overrides};
+use raytracer_test::render::{Renderer, Settings};
+use raytracer_test::scene_file::SceneFile;
+use raytracer_test::{Float, RendererError, Result};
+use crate::cli::Args;
+
+const POLL: Duration = Duration::from_millis(250);
+
+// Re-renders the scene file every time it's saved, until killed. Each change gets a quick
+// preview at a sixteenth of the samples first, then the full render, both written over the
+// output so an image viewer that reloads on change follows along. The previews get the
+// --overlay drawn over them, the full render is left as it is. A scene that fails to load is
+// reported and waited out.
+pub fn watch(scene: &Path, output: &Path, format: Format, args: &Args) -> Result<()> {
+    let renderer = crate::renderer(args)?;
+    let mut seen = None;
+    let mut missing = false;
+    log::info!("Watching {} for changes", scene.display());
+
+    loop {
+        // editors that save to another file and rename it over the scene leave it missing
+        // for a moment, it's reported once and looked for again
+        let modified = match modified(scene) {
+            Ok(modified) => modified,
+            Err(e) => {
+                if !missing {
+                    log::warn!("{}", e);
+                }
+                missing = true;
+                thread::sleep(POLL);
+                continue;
+            }
+        };
+        missing = false;
+        if seen != Some(modified) {
+            seen = Some(modified);
+            if let Err(e) = render(&renderer, scene, modified, output, format, args) {
+                log::error!("{}", e);
+            }
+        }
+        thread::sleep(POLL);
+    }
+}
+
+fn modified(path: &Path) -> Result<SystemTime> {
+    std::fs::metadata(path)
+        .and_then(|m| m.modified())
+        .map_err(|e| RendererError::io(path, e))
+}
+
+fn render(renderer: &Renderer, path: &Path, modified: SystemTime, output: &Path, format: Format, args: &Args) -> Result<()> {
+    let mut file = SceneFile::load(path)?;
+    if let Some((up, unit_scale)) = args.conversion() {
+        file.convert(up, unit_scale);
+    }
+    let file = overrides::apply(file, &args.overrides)?;
+    let mut settings = file.settings();
+    if let Some(seed) = args.seed {
+        settings.seed = seed;
+    }
+    let scene = file.build_with_shutter(0.0, (args.shutter / args.fps) as Float, (args.rolling_shutter / args.fps) as Float,
+                                       settings.aspect_ratio())?;
+
+    let preview = Settings { samples_per_pixel: (settings.samples_per_pixel / 16).max(1), ..settings };
+    for (pass, settings) in [("preview", preview), ("full", settings)] {
+        // saved again in the meantime, the next round starts over
+        if self::modified(path)? != modified {
+            return Ok(());
+        }
+        let start = Instant::now();
+        let mut data = renderer.render(&scene, &settings);
+        if pass == "preview" && !args.overlay.is_empty() {
+            overlay::draw(&mut data, &scene.camera, &inspect::inspect(&file)?, &args.overlay, args.coc);
+        }
+        crate::write(output, &data, format, args, &crate::write_options(args, format)?)?;
+        log::info!("{} render ({} spp) written to {} in {:.1}s",
+                   pass, settings.samples_per_pixel, output.display(), start.elapsed().as_secs_f64());
+    }
+    Ok(())
+}
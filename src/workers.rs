@@ -0,0 +1,192 @@
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Condvar, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+// Jobs shared out among the render threads, columns or tiles. Each worker has a queue of its
+// own, dealt the jobs in turn so the first ones all start together, and works through it from
+// the front. One that runs out takes the back half of the longest queue left, so nobody sits
+// idle while another thread still has a backlog, and the threads rarely touch the same lock.
+//
+// Stealing only moves jobs nobody has started on, and near the end one worker can still be
+// left with a slow job while the others sit idle. A queue made `with_handover` keeps workers
+// that run out waiting as long as others are busy, and a busy worker that sees `wants_work`
+// can `give` part of its job away as a job of its own.
+//
+// It also keeps count of how each worker spent the render: how long it was busy, on how many
+// jobs, and how long before the end it ran out of work. `report` logs that once the workers
+// are done, to see whether a render waited on a few slow jobs at the end.
+pub struct WorkQueue<T> {
+    queues: Vec<Mutex<VecDeque<T>>>,
+    usage: Vec<Mutex<Usage>>,
+    // workers not done yet
+    running: AtomicUsize,
+    start: Instant,
+    handover: bool,
+    // workers in the middle of a job, who might still give some of it away
+    busy: Mutex<usize>,
+    ready: Condvar,
+    // workers out of jobs waiting for one to be given, looked at without the lock
+    waiting: AtomicUsize,
+}
+
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Usage {
+    pub jobs: usize,
+    pub busy: Duration,
+    // since the queue was made, None while the worker is still at it
+    pub finished: Option<Duration>,
+}
+
+impl<T> WorkQueue<T> {
+    pub fn new(jobs: impl IntoIterator<Item = T>, workers: usize) -> WorkQueue<T> {
+        let workers = workers.max(1);
+        let mut queues: Vec<VecDeque<T>> = (0..workers).map(|_| VecDeque::new()).collect();
+        for (i, job) in jobs.into_iter().enumerate() {
+            queues[i % workers].push_back(job);
+        }
+        WorkQueue {
+            queues: queues.into_iter().map(Mutex::new).collect(),
+            usage: (0..workers).map(|_| Mutex::new(Usage::default())).collect(),
+            running: AtomicUsize::new(workers),
+            start: Instant::now(),
+            handover: false,
+            busy: Mutex::new(0),
+            ready: Condvar::new(),
+            waiting: AtomicUsize::new(0),
+        }
+    }
+
+    // workers that run out wait for the busy ones to give them part of their jobs, see `give`
+    pub fn with_handover(mut self) -> WorkQueue<T> {
+        self.handover = true;
+        self
+    }
+
+    pub fn workers(&self) -> usize {
+        self.queues.len()
+    }
+
+    // the next job for `worker`, its own or taken from the longest queue; None when there's
+    // nothing left anywhere
+    pub fn take(&self, worker: usize) -> Option<T> {
+        if let Some(job) = self.queues[worker].lock().unwrap().pop_front() {
+            return Some(job);
+        }
+        loop {
+            let (longest, len) = self.queues.iter().enumerate()
+                .map(|(i, q)| (i, q.lock().unwrap().len()))
+                .max_by_key(|&(_, len)| len)?;
+            if len == 0 {
+                return None;
+            }
+            // the back half, the part its owner would have got to last
+            let mut stolen = {
+                let mut victim = self.queues[longest].lock().unwrap();
+                let keep = victim.len() / 2;
+                victim.split_off(keep)
+            };
+            // emptied in the meantime, look again
+            let Some(job) = stolen.pop_front() else { continue };
+            self.queues[worker].lock().unwrap().extend(stolen);
+            return Some(job);
+        }
+    }
+
+    // Runs `job` on every job `worker` gets until there are none left or it returns false,
+    // keeping the time. True for the last worker to finish.
+    pub fn run(&self, worker: usize, mut job: impl FnMut(T) -> bool) -> bool {
+        while let Some(next) = self.next(worker) {
+            let start = Instant::now();
+            let go_on = {
+                let _busy = Busy::new(self);
+                job(next)
+            };
+            let mut usage = self.usage[worker].lock().unwrap();
+            usage.jobs += 1;
+            usage.busy += start.elapsed();
+            if !go_on {
+                break;
+            }
+        }
+        self.usage[worker].lock().unwrap().finished = Some(self.start.elapsed());
+        self.running.fetch_sub(1, Ordering::AcqRel) == 1
+    }
+
+    // Whether a worker is waiting for a job to be given, with `with_handover`
+    pub fn wants_work(&self) -> bool {
+        self.waiting.load(Ordering::Relaxed) > 0
+    }
+
+    // Hands `job`, part of what `worker` is busy with, to a waiting worker, first in line
+    // before any job nobody has started on
+    pub fn give(&self, worker: usize, job: T) {
+        self.queues[worker].lock().unwrap().push_front(job);
+        // under the lock, so a worker about to wait sees the job or gets woken
+        let _busy = self.busy.lock().unwrap();
+        self.ready.notify_one();
+    }
+
+    // take, or with `with_handover` waiting while others are busy and might give a job away
+    fn next(&self, worker: usize) -> Option<T> {
+        if !self.handover {
+            return self.take(worker);
+        }
+        let mut busy = self.busy.lock().unwrap();
+        loop {
+            if let Some(job) = self.take(worker) {
+                return Some(job);
+            }
+            if *busy == 0 {
+                return None;
+            }
+            self.waiting.fetch_add(1, Ordering::Relaxed);
+            busy = self.ready.wait(busy).unwrap();
+            self.waiting.fetch_sub(1, Ordering::Relaxed);
+        }
+    }
+
+    pub fn usage(&self) -> Vec<Usage> {
+        self.usage.iter().map(|u| *u.lock().unwrap()).collect()
+    }
+
+    // How busy each worker was, at debug level, and at info level how much of the threads'
+    // time went to waiting for the last ones to finish
+    pub fn report(&self) {
+        let usage = self.usage();
+        let end = usage.iter().filter_map(|u| u.finished).max().unwrap_or_default();
+        if end.is_zero() {
+            return;
+        }
+        for (i, u) in usage.iter().enumerate() {
+            let idle = end.saturating_sub(u.finished.unwrap_or(end));
+            log::debug!("thread {}: {} jobs, busy {:.0}% of the time, out of work {:.2}s before the end",
+                        i, u.jobs, 100.0 * u.busy.as_secs_f64() / end.as_secs_f64(), idle.as_secs_f64());
+        }
+        let busy: f64 = usage.iter().map(|u| u.busy.as_secs_f64()).sum();
+        log::info!("threads busy {:.0}% of the time", 100.0 * busy / (end.as_secs_f64() * usage.len() as f64));
+    }
+}
+
+// a worker counted as busy until it's dropped, panics included, so the others don't wait on
+// it forever
+struct Busy<'a, T> {
+    queue: &'a WorkQueue<T>,
+}
+
+impl<'a, T> Busy<'a, T> {
+    fn new(queue: &'a WorkQueue<T>) -> Busy<'a, T> {
+        *queue.busy.lock().unwrap() += 1;
+        Busy { queue }
+    }
+}
+
+impl<T> Drop for Busy<'_, T> {
+    fn drop(&mut self) {
+        let mut busy = self.queue.busy.lock().unwrap_or_else(PoisonError::into_inner);
+        *busy -= 1;
+        if *busy == 0 {
+            self.queue.ready.notify_all();
+        }
+    }
+}